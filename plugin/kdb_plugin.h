@@ -0,0 +1,56 @@
+/*
+ * deet 插件接口（C ABI，版本 1）。
+ *
+ * 把插件编译为共享库放到 ~/.kdb/plugins/ 下，调试器启动时加载。插件导出
+ *
+ *     const struct kdb_plugin *kdb_plugin_v1(void);
+ *
+ * 返回的结构在插件加载期间必须一直有效（通常是一个静态变量）。不需要的钩子设为 NULL。
+ *
+ *     gcc -shared -fPIC -o ~/.kdb/plugins/hello.so hello.c
+ */
+#ifndef KDB_PLUGIN_H
+#define KDB_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define KDB_PLUGIN_ABI_VERSION 1
+
+#define KDB_STOP_STOPPED 0
+#define KDB_STOP_EXITED 1
+#define KDB_STOP_SIGNALED 2
+
+struct kdb_context {
+    /* 被调试进程的 pid，没有正在运行的进程时为 0 */
+    int pid;
+    /* 读取被调试进程 [addr, addr + len) 的内存到 buf，成功返回 0 */
+    int (*read_memory)(const struct kdb_context *ctx, uint64_t addr, uint8_t *buf, size_t len);
+    /* 调试器内部使用 */
+    const void *inferior;
+};
+
+struct kdb_stop_event {
+    int kind;       /* KDB_STOP_* */
+    int signal;     /* 停止或终止进程的信号，正常退出时为 0 */
+    int exit_code;
+    uint64_t rip;
+    const char *function; /* 没有调试信息时为 NULL */
+    const char *file;     /* 没有调试信息时为 NULL */
+    uint32_t line;
+};
+
+struct kdb_plugin {
+    uint32_t abi_version; /* 必须为 KDB_PLUGIN_ABI_VERSION */
+    const char *name;
+    /* 插件提供的命令名，以 NULL 结尾；与内置命令重名时不生效 */
+    const char *const *commands;
+    /* 执行插件命令，args 是命令名之后的文本；返回非 0 表示失败 */
+    int (*run_command)(const char *command, const char *args, const struct kdb_context *ctx);
+    /* 被调试进程停止或退出时调用 */
+    void (*on_stop)(const struct kdb_stop_event *event, const struct kdb_context *ctx);
+    /* 把 type_name 类型的值格式化到 buf（以 NUL 结尾），处理了该类型时返回非 0 */
+    int (*pretty_print)(const char *type_name, uint64_t value, char *buf, size_t len);
+};
+
+#endif
@@ -0,0 +1,427 @@
+//! 被调试进程的底层操作接口。
+//!
+//! `Inferior` 的单步、断点和回溯逻辑只通过 `Backend` 访问寄存器、内存和停止事件：
+//! 实际调试时使用基于 ptrace 的 `PtraceBackend`，自动化测试时可以换成按脚本回放的 `MockBackend`，
+//! 不需要真的启动子进程。
+
+use crate::inferior::Status;
+
+use nix::libc::{self, user_fpregs_struct, user_regs_struct};
+use nix::sys::ptrace;
+use nix::sys::signal::{self, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+#[cfg(test)]
+use std::collections::{HashMap, VecDeque};
+use std::mem::size_of;
+use std::process::Child;
+
+/// 最近一次信号停止的 siginfo 中调试器关心的字段
+#[derive(Debug, Clone, Copy)]
+pub struct SigInfo {
+    pub signo: i32,
+    pub code: i32,
+    /// SIGSEGV / SIGBUS 等故障信号的出错地址
+    pub addr: usize,
+}
+
+pub trait Backend {
+    fn pid(&self) -> Pid;
+
+    /// 恢复运行，可选地向进程投递一个信号
+    fn cont(&mut self, signal: Option<Signal>) -> Result<(), nix::Error>;
+
+    /// 单步执行一条指令，可选地向进程投递一个信号
+    fn step(&mut self, signal: Option<Signal>) -> Result<(), nix::Error>;
+
+    /// 恢复运行到下一次系统调用的入口或出口（PTRACE_SYSCALL），可选地向进程投递一个信号
+    fn syscall(&mut self, signal: Option<Signal>) -> Result<(), nix::Error>;
+
+    /// 等待进程的下一次状态变化
+    fn wait(&mut self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error>;
+
+    fn kill(&mut self) -> Result<(), std::io::Error>;
+
+    /// 停止跟踪，进程从当前停止的位置继续运行
+    fn detach(&mut self) -> Result<(), nix::Error>;
+
+    /// 读取 addr 处的一个 word，addr 需要按 word 对齐
+    fn read_word(&self, addr: usize) -> Result<u64, nix::Error>;
+
+    /// 写入 addr 处的一个 word，addr 需要按 word 对齐
+    fn write_word(&mut self, addr: usize, word: u64) -> Result<(), nix::Error>;
+
+    fn getregs(&self) -> Result<user_regs_struct, nix::Error>;
+
+    fn setregs(&mut self, regs: user_regs_struct) -> Result<(), nix::Error>;
+
+    /// x87 和 SSE 寄存器（st0-st7、xmm0-xmm15）
+    fn getfpregs(&self) -> Result<user_fpregs_struct, nix::Error>;
+
+    /// 最近一次信号停止的 siginfo
+    fn siginfo(&self) -> Result<SigInfo, nix::Error>;
+
+    /// 读取调试寄存器 DR0-DR7
+    fn debug_register(&self, index: usize) -> Result<u64, nix::Error>;
+
+    fn set_debug_register(&mut self, index: usize, value: u64) -> Result<(), nix::Error>;
+}
+
+/// struct user 中第 index 个调试寄存器的偏移，用于 PTRACE_PEEKUSER / PTRACE_POKEUSER
+fn debug_register_offset(index: usize) -> usize {
+    std::mem::offset_of!(libc::user, u_debugreg) + index * size_of::<u64>()
+}
+
+/// 通过 ptrace 控制一个真实的进程：调试器启动的子进程，或者 `attach` 上的已有进程
+pub struct PtraceBackend {
+    pid: Pid,
+    /// 调试器启动的子进程；attach 上的进程为 None
+    child: Option<Child>,
+}
+
+impl PtraceBackend {
+    pub fn new(child: Child) -> PtraceBackend {
+        PtraceBackend {
+            pid: Pid::from_raw(child.id() as i32),
+            child: Some(child),
+        }
+    }
+
+    /// 用 PTRACE_ATTACH 跟踪已经在运行的进程，进程会收到一个 SIGSTOP 停下
+    pub fn attach(pid: Pid) -> Result<PtraceBackend, nix::Error> {
+        ptrace::attach(pid)?;
+        Ok(PtraceBackend { pid, child: None })
+    }
+
+    /// 被调试进程 fork 出的子进程，PTRACE_O_TRACEFORK 已经让内核自动跟踪它
+    pub fn forked(pid: Pid) -> PtraceBackend {
+        PtraceBackend { pid, child: None }
+    }
+}
+
+impl Backend for PtraceBackend {
+    fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    fn cont(&mut self, signal: Option<Signal>) -> Result<(), nix::Error> {
+        ptrace::cont(self.pid(), signal)
+    }
+
+    fn step(&mut self, signal: Option<Signal>) -> Result<(), nix::Error> {
+        ptrace::step(self.pid(), signal)
+    }
+
+    fn syscall(&mut self, signal: Option<Signal>) -> Result<(), nix::Error> {
+        ptrace::syscall(self.pid(), signal)
+    }
+
+    fn wait(&mut self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
+        Ok(match waitpid(self.pid(), options)? {
+            WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
+            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
+            WaitStatus::Stopped(_pid, signal) => {
+                let regs = ptrace::getregs(self.pid())?;
+                Status::Stopped(signal, regs.rip as usize)
+            }
+            WaitStatus::PtraceEvent(_pid, _signal, libc::PTRACE_EVENT_EXEC) => {
+                let regs = ptrace::getregs(self.pid())?;
+                Status::Exec(regs.rip as usize)
+            }
+            WaitStatus::PtraceEvent(_pid, _signal, event)
+                if event == libc::PTRACE_EVENT_FORK || event == libc::PTRACE_EVENT_VFORK =>
+            {
+                let child = Pid::from_raw(ptrace::getevent(self.pid())? as i32);
+                Status::Fork(child, event == libc::PTRACE_EVENT_VFORK)
+            }
+            WaitStatus::PtraceEvent(_pid, _signal, libc::PTRACE_EVENT_VFORK_DONE) => {
+                Status::VforkDone
+            }
+            WaitStatus::PtraceSyscall(_pid) => Status::Syscall,
+            other => panic!("waitpid returned unexpected status: {:?}", other),
+        })
+    }
+
+    fn kill(&mut self) -> Result<(), std::io::Error> {
+        match self.child.as_mut() {
+            Some(child) => child.kill(),
+            None => signal::kill(self.pid, Signal::SIGKILL).map_err(std::io::Error::other),
+        }
+    }
+
+    fn detach(&mut self) -> Result<(), nix::Error> {
+        ptrace::detach(self.pid, None)
+    }
+
+    fn read_word(&self, addr: usize) -> Result<u64, nix::Error> {
+        ptrace::read(self.pid(), addr as ptrace::AddressType).map(|word| word as u64)
+    }
+
+    fn write_word(&mut self, addr: usize, word: u64) -> Result<(), nix::Error> {
+        ptrace::write(
+            self.pid(),
+            addr as ptrace::AddressType,
+            word as *mut std::ffi::c_void,
+        )
+    }
+
+    fn getregs(&self) -> Result<user_regs_struct, nix::Error> {
+        ptrace::getregs(self.pid())
+    }
+
+    fn setregs(&mut self, regs: user_regs_struct) -> Result<(), nix::Error> {
+        ptrace::setregs(self.pid(), regs)
+    }
+
+    fn getfpregs(&self) -> Result<user_fpregs_struct, nix::Error> {
+        // nix 没有封装 PTRACE_GETFPREGS
+        let mut regs: user_fpregs_struct = unsafe { std::mem::zeroed() };
+        let ret = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETFPREGS,
+                self.pid().as_raw(),
+                std::ptr::null_mut::<libc::c_void>(),
+                &mut regs as *mut user_fpregs_struct,
+            )
+        };
+        if ret < 0 {
+            Err(nix::Error::last())
+        } else {
+            Ok(regs)
+        }
+    }
+
+    fn siginfo(&self) -> Result<SigInfo, nix::Error> {
+        ptrace::getsiginfo(self.pid()).map(|info| SigInfo {
+            signo: info.si_signo,
+            code: info.si_code,
+            addr: unsafe { info.si_addr() } as usize,
+        })
+    }
+
+    fn debug_register(&self, index: usize) -> Result<u64, nix::Error> {
+        // PTRACE_PEEKUSER 的返回值就是数据，需要先清空 errno 才能区分 -1 和出错
+        unsafe { nix::errno::Errno::clear() };
+        let value = unsafe {
+            libc::ptrace(
+                libc::PTRACE_PEEKUSER,
+                self.pid().as_raw(),
+                debug_register_offset(index),
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        };
+        if value == -1 && nix::errno::errno() != 0 {
+            Err(nix::Error::last())
+        } else {
+            Ok(value as u64)
+        }
+    }
+
+    fn set_debug_register(&mut self, index: usize, value: u64) -> Result<(), nix::Error> {
+        let ret = unsafe {
+            libc::ptrace(
+                libc::PTRACE_POKEUSER,
+                self.pid().as_raw(),
+                debug_register_offset(index),
+                value as usize,
+            )
+        };
+        if ret < 0 {
+            Err(nix::Error::last())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// 进程恢复运行后，下一次 wait 时回放的事件
+#[cfg(test)]
+pub struct MockStop {
+    pub status: Status,
+    /// 停止时的寄存器；为 None 时只更新 rip（取自 `Status::Stopped`）
+    pub regs: Option<user_regs_struct>,
+    /// 停止信号的 si_code，例如 int3 为 SI_KERNEL（0x80），单步为 TRAP_TRACE（2）
+    pub si_code: i32,
+    /// 故障信号的出错地址
+    pub si_addr: usize,
+}
+
+/// 按脚本回放的假进程，用于在测试中驱动调试器逻辑。
+///
+/// 内存是稀疏的 word 表，读取未映射的地址返回 EFAULT；每次 `cont` / `step` 之后的 `wait`
+/// 依次取出一个 `MockStop`，脚本用完后进程视为正常退出。
+#[cfg(test)]
+pub struct MockBackend {
+    regs: user_regs_struct,
+    memory: HashMap<usize, u64>,
+    stops: VecDeque<MockStop>,
+    siginfo: SigInfo,
+    debug_registers: [u64; 8],
+    exited: bool,
+}
+
+#[cfg(test)]
+impl MockBackend {
+    pub fn new(regs: user_regs_struct) -> MockBackend {
+        MockBackend {
+            regs,
+            memory: HashMap::new(),
+            stops: VecDeque::new(),
+            siginfo: SigInfo {
+                signo: 0,
+                code: 0,
+                addr: 0,
+            },
+            debug_registers: [0; 8],
+            exited: false,
+        }
+    }
+
+    /// 全部为 0 的寄存器组，方便脚本只设置关心的几个寄存器
+    pub fn zeroed_regs() -> user_regs_struct {
+        // user_regs_struct 只包含整数字段，全 0 是合法的值
+        unsafe { std::mem::zeroed() }
+    }
+
+    /// 把 bytes 映射到 addr 开始的内存，不满一个 word 的部分补 0
+    pub fn map_memory(&mut self, addr: usize, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            let word_addr = (addr + i) & !(size_of::<u64>() - 1);
+            let shift = 8 * (addr + i - word_addr);
+            let word = self.memory.entry(word_addr).or_insert(0);
+            *word = (*word & !(0xff << shift)) | ((*byte as u64) << shift);
+        }
+    }
+
+    /// 追加一个停止事件
+    pub fn push_stop(&mut self, status: Status, regs: Option<user_regs_struct>, si_code: i32) {
+        self.stops.push_back(MockStop {
+            status,
+            regs,
+            si_code,
+            si_addr: 0,
+        });
+    }
+
+    /// 追加一次在 rip 处访问 addr 引起的 SIGSEGV（SEGV_ACCERR）
+    pub fn push_fault(&mut self, rip: usize, addr: usize) {
+        self.stops.push_back(MockStop {
+            status: Status::Stopped(Signal::SIGSEGV, rip),
+            regs: None,
+            si_code: 2,
+            si_addr: addr,
+        });
+    }
+}
+
+#[cfg(test)]
+impl Backend for MockBackend {
+    fn pid(&self) -> Pid {
+        Pid::from_raw(0)
+    }
+
+    fn cont(&mut self, _signal: Option<Signal>) -> Result<(), nix::Error> {
+        if self.exited {
+            return Err(nix::Error::Sys(nix::errno::Errno::ESRCH));
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, signal: Option<Signal>) -> Result<(), nix::Error> {
+        self.cont(signal)
+    }
+
+    fn syscall(&mut self, signal: Option<Signal>) -> Result<(), nix::Error> {
+        self.cont(signal)
+    }
+
+    fn wait(&mut self, _options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
+        if self.exited {
+            return Err(nix::Error::Sys(nix::errno::Errno::ECHILD));
+        }
+        let stop = self.stops.pop_front().unwrap_or(MockStop {
+            status: Status::Exited(0),
+            regs: None,
+            si_code: 0,
+            si_addr: 0,
+        });
+        match stop.status {
+            Status::Stopped(signal, rip) => {
+                match stop.regs {
+                    Some(regs) => self.regs = regs,
+                    None => self.regs.rip = rip as u64,
+                }
+                self.siginfo = SigInfo {
+                    signo: signal as i32,
+                    code: stop.si_code,
+                    addr: stop.si_addr,
+                };
+            }
+            Status::Exec(rip) => self.regs.rip = rip as u64,
+            Status::Syscall | Status::Fork(..) | Status::VforkDone => {}
+            Status::Exited(_) | Status::Signaled(_) => self.exited = true,
+        }
+        Ok(stop.status)
+    }
+
+    fn kill(&mut self) -> Result<(), std::io::Error> {
+        self.stops.clear();
+        self.stops.push_back(MockStop {
+            status: Status::Signaled(Signal::SIGKILL),
+            regs: None,
+            si_code: 0,
+            si_addr: 0,
+        });
+        Ok(())
+    }
+
+    fn detach(&mut self) -> Result<(), nix::Error> {
+        self.stops.clear();
+        self.exited = true;
+        Ok(())
+    }
+
+    fn read_word(&self, addr: usize) -> Result<u64, nix::Error> {
+        self.memory
+            .get(&addr)
+            .copied()
+            .ok_or(nix::Error::Sys(nix::errno::Errno::EFAULT))
+    }
+
+    fn write_word(&mut self, addr: usize, word: u64) -> Result<(), nix::Error> {
+        match self.memory.get_mut(&addr) {
+            Some(value) => {
+                *value = word;
+                Ok(())
+            }
+            None => Err(nix::Error::Sys(nix::errno::Errno::EFAULT)),
+        }
+    }
+
+    fn getregs(&self) -> Result<user_regs_struct, nix::Error> {
+        Ok(self.regs)
+    }
+
+    fn setregs(&mut self, regs: user_regs_struct) -> Result<(), nix::Error> {
+        self.regs = regs;
+        Ok(())
+    }
+
+    fn getfpregs(&self) -> Result<user_fpregs_struct, nix::Error> {
+        // 脚本不模拟浮点寄存器，全部为 0
+        Ok(unsafe { std::mem::zeroed() })
+    }
+
+    fn siginfo(&self) -> Result<SigInfo, nix::Error> {
+        Ok(self.siginfo)
+    }
+
+    fn debug_register(&self, index: usize) -> Result<u64, nix::Error> {
+        Ok(self.debug_registers[index])
+    }
+
+    fn set_debug_register(&mut self, index: usize, value: u64) -> Result<(), nix::Error> {
+        self.debug_registers[index] = value;
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+//! 断点管理。
+//!
+//! 每个断点在创建时分配一个单调递增的编号，之后设置或删除其它断点都不会改变它，
+//! 因此命令里的断点编号始终指向同一个断点。断点可以归入分组，以便整组启用、禁用或删除。
+//!
+//! 一个源码行可能对应多个地址（内联展开到多个函数中），这时一个断点有多个位置：每个地址各有一项，
+//! 编号和设置都相同，按编号启用、禁用或删除时作用于全部位置，`@N` 数的是所有位置的命中次数之和。
+
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Clone, Debug)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub addr: usize,
+    /// 设置断点时的位置（`break` 的参数），exec 之后据此在新程序中重新解析地址
+    pub location: Option<String>,
+    pub orig_byte: u8,
+    /// 禁用的断点保留在列表中，但不会写入 int3
+    pub enabled: bool,
+    pub group: Option<String>,
+    /// `if-caller`：只在调用链中有这个函数时停下
+    pub caller: Option<String>,
+    /// `@N`：只在本次运行中第 N 次命中时停下
+    pub nth_hit: Option<usize>,
+    /// `if <expr>`：只在表达式为真时停下
+    pub condition: Option<String>,
+    /// 本次运行中命中的次数
+    pub hits: usize,
+    /// 命中时自动执行的调试器命令（`commands ... end`）
+    pub commands: Vec<String>,
+}
+
+pub struct BreakpointManager {
+    /// 按地址索引，命中断点时用 rip 查找
+    breakpoints: HashMap<usize, Breakpoint>,
+    next_id: usize,
+    groups: BTreeSet<String>,
+}
+
+impl BreakpointManager {
+    pub fn new() -> BreakpointManager {
+        BreakpointManager {
+            breakpoints: HashMap::new(),
+            next_id: 0,
+            groups: BTreeSet::new(),
+        }
+    }
+
+    /// 在 addr 处新建断点并返回它的编号；该地址已有断点时返回 Err(已有断点的编号)
+    pub fn insert(&mut self, addr: usize) -> Result<usize, usize> {
+        if let Some(bp) = self.breakpoints.get(&addr) {
+            return Err(bp.id);
+        }
+        let id = self.allocate_id();
+        self.breakpoints.insert(
+            addr,
+            Breakpoint {
+                id,
+                addr,
+                location: None,
+                orig_byte: 0,
+                enabled: true,
+                group: None,
+                caller: None,
+                nth_hit: None,
+                condition: None,
+                hits: 0,
+                commands: Vec::new(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// 给编号为 id 的断点增加一个位置，设置从已有的位置复制；该地址已有断点时返回 Err(已有断点的编号)
+    pub fn add_location(&mut self, id: usize, addr: usize) -> Result<(), usize> {
+        if let Some(bp) = self.breakpoints.get(&addr) {
+            return Err(bp.id);
+        }
+        let sibling = match self.breakpoints.values().find(|bp| bp.id == id) {
+            Some(sibling) => sibling.clone(),
+            None => return Ok(()),
+        };
+        self.breakpoints.insert(
+            addr,
+            Breakpoint {
+                addr,
+                orig_byte: 0,
+                hits: 0,
+                ..sibling
+            },
+        );
+        Ok(())
+    }
+
+    /// 分配一个编号但不新建断点；监视点与断点共用编号
+    pub fn allocate_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    pub fn get_mut(&mut self, addr: usize) -> Option<&mut Breakpoint> {
+        self.breakpoints.get_mut(&addr)
+    }
+
+    /// 返回 addr 处已启用（即已写入 int3）的断点
+    pub fn get_enabled(&self, addr: usize) -> Option<&Breakpoint> {
+        self.breakpoints.get(&addr).filter(|bp| bp.enabled)
+    }
+
+    /// 编号为 id 的断点的全部位置，按地址排序；没有这个编号时为空
+    pub fn locations(&self, id: usize) -> Vec<usize> {
+        let mut addrs: Vec<usize> = self
+            .breakpoints
+            .values()
+            .filter(|bp| bp.id == id)
+            .map(|bp| bp.addr)
+            .collect();
+        addrs.sort_unstable();
+        addrs
+    }
+
+    /// 记录 addr 处的一次命中，返回这个断点所有位置的命中次数之和
+    pub fn record_hit(&mut self, addr: usize) -> usize {
+        let id = match self.breakpoints.get_mut(&addr) {
+            Some(bp) => {
+                bp.hits += 1;
+                bp.id
+            }
+            None => return 0,
+        };
+        self.breakpoints
+            .values()
+            .filter(|bp| bp.id == id)
+            .map(|bp| bp.hits)
+            .sum()
+    }
+
+    pub fn remove(&mut self, addr: usize) -> Option<Breakpoint> {
+        self.breakpoints.remove(&addr)
+    }
+
+    /// 把断点移动到新的地址，返回因新地址上已有断点而被丢弃的断点
+    pub fn relocate(&mut self, moves: &[(usize, usize)]) -> Vec<Breakpoint> {
+        let moved: Vec<(Breakpoint, usize)> = moves
+            .iter()
+            .filter_map(|(old, new)| self.breakpoints.remove(old).map(|bp| (bp, *new)))
+            .collect();
+        let mut dropped = Vec::new();
+        for (mut bp, new) in moved {
+            if self.breakpoints.contains_key(&new) {
+                dropped.push(bp);
+                continue;
+            }
+            bp.addr = new;
+            self.breakpoints.insert(new, bp);
+        }
+        dropped
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.breakpoints.values()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Breakpoint> {
+        self.breakpoints.values_mut()
+    }
+
+    /// 创建分组，已存在时返回 false
+    pub fn create_group(&mut self, name: &str) -> bool {
+        self.groups.insert(name.to_string())
+    }
+
+    pub fn has_group(&self, name: &str) -> bool {
+        self.groups.contains(name)
+    }
+
+    pub fn remove_group(&mut self, name: &str) -> bool {
+        self.groups.remove(name)
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = &String> {
+        self.groups.iter()
+    }
+
+    /// 分组中所有断点的地址，按编号排序
+    pub fn group_members(&self, name: &str) -> Vec<usize> {
+        let mut members: Vec<&Breakpoint> = self
+            .breakpoints
+            .values()
+            .filter(|bp| bp.group.as_deref() == Some(name))
+            .collect();
+        members.sort_by_key(|bp| bp.id);
+        members.iter().map(|bp| bp.addr).collect()
+    }
+}
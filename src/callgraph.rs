@@ -0,0 +1,130 @@
+//! 调用图记录：`callgraph start` 到 `callgraph stop` 之间实际发生的函数调用。
+//!
+//! 在目标程序每个有调试信息的函数入口写入 int3，命中时从栈顶的返回地址得到调用者，记下一条
+//! 调用者 → 被调用者的边并自动继续运行。int3 只在被调试进程运行期间存在：每次停下交给用户之前
+//! 全部撤掉，继续运行时重新写入，因此单步、`next` 等命令看不到它们。结果可以导出为 Graphviz DOT
+//! （文件名以 `.dot` 结尾时）或 JSON。
+
+use crate::inferior::Inferior;
+
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+
+/// 一个函数入口上的 int3
+struct Entry {
+    function: String,
+    /// 写入 int3 之前的字节；没有写入时为 None
+    orig_byte: Option<u8>,
+}
+
+pub struct CallGraph {
+    /// 按入口地址索引
+    entries: HashMap<usize, Entry>,
+    /// (调用者, 被调用者) → 调用次数
+    edges: BTreeMap<(String, String), usize>,
+}
+
+impl CallGraph {
+    /// functions 是要记录的函数的 (入口地址, 名字)
+    pub fn new(functions: impl Iterator<Item = (usize, String)>) -> CallGraph {
+        CallGraph {
+            entries: functions
+                .map(|(addr, function)| {
+                    (
+                        addr,
+                        Entry {
+                            function,
+                            orig_byte: None,
+                        },
+                    )
+                })
+                .collect(),
+            edges: BTreeMap::new(),
+        }
+    }
+
+    pub fn function_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 在所有入口写入 int3；skip 为 true 的地址（已有用户断点）不写，命中用户断点时由调用者记录
+    pub fn arm(&mut self, inferior: &mut Inferior, skip: impl Fn(usize) -> bool) {
+        for (addr, entry) in self.entries.iter_mut() {
+            if entry.orig_byte.is_none() && !skip(*addr) {
+                entry.orig_byte = inferior.write_byte(*addr, 0xcc).ok();
+            }
+        }
+    }
+
+    /// 撤掉所有 int3
+    pub fn disarm(&mut self, inferior: &mut Inferior) {
+        for (addr, entry) in self.entries.iter_mut() {
+            if let Some(orig_byte) = entry.orig_byte.take() {
+                let _ = inferior.write_byte(*addr, orig_byte);
+            }
+        }
+    }
+
+    /// 进程 exec 了别的程序：旧程序的入口已经不存在，不再写入 int3，已记录的边保留
+    pub fn forget_entries(&mut self) {
+        self.entries.clear();
+    }
+
+    /// 已写入 int3 的入口在 addr 处时返回原来的字节
+    pub fn armed_at(&self, addr: usize) -> Option<u8> {
+        self.entries.get(&addr).and_then(|entry| entry.orig_byte)
+    }
+
+    /// addr 是某个被记录函数的入口时返回函数名
+    pub fn function_at(&self, addr: usize) -> Option<&str> {
+        self.entries.get(&addr).map(|entry| entry.function.as_str())
+    }
+
+    pub fn record(&mut self, caller: String, callee: String) {
+        *self.edges.entry((caller, callee)).or_insert(0) += 1;
+    }
+
+    /// 打印所有边，按调用次数从多到少
+    pub fn print(&self) {
+        let calls: usize = self.edges.values().sum();
+        println!("{} call(s), {} distinct edge(s):", calls, self.edges.len());
+        let mut edges: Vec<_> = self.edges.iter().collect();
+        edges.sort_by(|a, b| b.1.cmp(a.1));
+        for ((caller, callee), count) in edges {
+            println!("  {:>8}  {} -> {}", count, caller, callee);
+        }
+    }
+
+    /// 按文件名选择格式导出
+    pub fn export(&self, path: &str) -> std::io::Result<()> {
+        let contents = if path.ends_with(".dot") {
+            self.to_dot()
+        } else {
+            self.to_json().to_string()
+        };
+        std::fs::write(path, contents)
+    }
+
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph callgraph {\n    node [shape=box];\n");
+        for ((caller, callee), count) in &self.edges {
+            dot.push_str(&format!(
+                "    {:?} -> {:?} [label=\"{}\"];\n",
+                caller, callee, count
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let edges: Vec<serde_json::Value> = self
+            .edges
+            .iter()
+            .map(|((caller, callee), count)| {
+                json!({ "caller": caller, "callee": callee, "count": count })
+            })
+            .collect();
+        json!({ "edges": edges })
+    }
+}
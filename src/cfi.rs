@@ -0,0 +1,182 @@
+//! 基于 .eh_frame 的调用帧信息（CFI）。
+//!
+//! 回溯时优先用 CFI 计算调用者的寄存器：即使停在函数序言中（rbp 尚未压栈）或者停在不维护帧指针的
+//! 库函数里，也能得到正确的返回地址。
+
+use gimli::UnwindSection;
+use object::{Object, ObjectSection};
+use std::fs;
+
+/// 回溯需要跟踪的寄存器
+#[derive(Debug, Clone, Copy)]
+pub struct UnwindRegs {
+    pub rip: u64,
+    pub rsp: u64,
+    pub rbp: u64,
+}
+
+/// `info frame` 显示的寄存器
+const SAVED_REGISTERS: &[(gimli::Register, &str)] = &[
+    (gimli::X86_64::RBX, "rbx"),
+    (gimli::X86_64::RBP, "rbp"),
+    (gimli::X86_64::R12, "r12"),
+    (gimli::X86_64::R13, "r13"),
+    (gimli::X86_64::R14, "r14"),
+    (gimli::X86_64::R15, "r15"),
+    (gimli::X86_64::RA, "rip"),
+];
+
+/// 一帧在栈上的布局
+pub struct FrameLayout {
+    pub cfa: u64,
+    /// (寄存器名, 保存位置)
+    pub saved: Vec<(&'static str, u64)>,
+}
+
+pub struct CallFrameInfo {
+    eh_frame: Vec<u8>,
+    eh_frame_addr: u64,
+    text_addr: u64,
+    endian: gimli::RunTimeEndian,
+}
+
+impl CallFrameInfo {
+    /// 读取 ELF 文件的 .eh_frame 节，没有该节时返回 None
+    pub fn from_file(path: &str) -> Option<CallFrameInfo> {
+        let file = fs::File::open(path).ok()?;
+        let mmap = unsafe { memmap::Mmap::map(&file).ok()? };
+        CallFrameInfo::from_image(&mmap)
+    }
+
+    /// 与 from_file 相同，ELF 映像已经在内存中（从被调试进程读出的 vDSO）
+    pub fn from_image(image: &[u8]) -> Option<CallFrameInfo> {
+        let object = object::File::parse(image).ok()?;
+        let eh_frame = object.section_by_name(".eh_frame")?;
+        let text_addr = object
+            .section_by_name(".text")
+            .map(|s| s.address())
+            .unwrap_or(0);
+        Some(CallFrameInfo {
+            eh_frame: eh_frame.data().into_owned(),
+            eh_frame_addr: eh_frame.address(),
+            text_addr,
+            endian: if object.is_little_endian() {
+                gimli::RunTimeEndian::Little
+            } else {
+                gimli::RunTimeEndian::Big
+            },
+        })
+    }
+
+    /// 直接使用一段 .eh_frame 的内容，测试中用手工构造的 CFI 代替 ELF 文件
+    #[cfg(test)]
+    pub fn from_eh_frame(eh_frame: Vec<u8>, eh_frame_addr: u64) -> CallFrameInfo {
+        CallFrameInfo {
+            eh_frame,
+            eh_frame_addr,
+            text_addr: 0,
+            endian: gimli::RunTimeEndian::Little,
+        }
+    }
+
+    /// addr（文件中的地址）所属 FDE 的 CIE 是否带 'S' 增强，即信号处理返回跳板（如 __restore_rt）
+    pub fn is_signal_frame(&self, addr: u64) -> bool {
+        let eh_frame = gimli::EhFrame::new(&self.eh_frame, self.endian);
+        let bases = gimli::BaseAddresses::default()
+            .set_eh_frame(self.eh_frame_addr)
+            .set_text(self.text_addr);
+        match eh_frame.fde_for_address(&bases, addr, gimli::EhFrame::cie_from_offset) {
+            Ok(fde) => fde.cie().is_signal_trampoline(),
+            Err(_) => false,
+        }
+    }
+
+    /// addr（文件中的地址）所属 FDE 覆盖的函数起始地址
+    pub fn function_start(&self, addr: u64) -> Option<u64> {
+        let eh_frame = gimli::EhFrame::new(&self.eh_frame, self.endian);
+        let bases = gimli::BaseAddresses::default()
+            .set_eh_frame(self.eh_frame_addr)
+            .set_text(self.text_addr);
+        eh_frame
+            .fde_for_address(&bases, addr, gimli::EhFrame::cie_from_offset)
+            .ok()
+            .map(|fde| fde.initial_address())
+    }
+
+    /// file_pc 处的 CFI 规则行，以及按 regs 算出的 CFA；规则无法求值时返回 None
+    fn row_and_cfa(
+        &self,
+        file_pc: u64,
+        regs: &UnwindRegs,
+    ) -> Option<(
+        gimli::UnwindTableRow<gimli::EndianSlice<'_, gimli::RunTimeEndian>>,
+        u64,
+    )> {
+        let eh_frame = gimli::EhFrame::new(&self.eh_frame, self.endian);
+        let bases = gimli::BaseAddresses::default()
+            .set_eh_frame(self.eh_frame_addr)
+            .set_text(self.text_addr);
+        let mut ctx = gimli::UninitializedUnwindContext::new();
+        let row = eh_frame
+            .unwind_info_for_address(&bases, &mut ctx, file_pc, gimli::EhFrame::cie_from_offset)
+            .ok()?;
+        let cfa = match row.cfa() {
+            gimli::CfaRule::RegisterAndOffset { register, offset } => {
+                let base = if *register == gimli::X86_64::RSP {
+                    regs.rsp
+                } else if *register == gimli::X86_64::RBP {
+                    regs.rbp
+                } else {
+                    return None;
+                };
+                (base as i64 + offset) as u64
+            }
+            gimli::CfaRule::Expression(_) => return None,
+        };
+        Some((row, cfa))
+    }
+
+    /// file_pc 处的帧布局：CFA，以及保存在栈上的被调用者保存寄存器和返回地址的位置
+    pub fn frame_layout(&self, file_pc: u64, regs: &UnwindRegs) -> Option<FrameLayout> {
+        let (row, cfa) = self.row_and_cfa(file_pc, regs)?;
+        let saved = SAVED_REGISTERS
+            .iter()
+            .filter_map(|(register, name)| match row.register(*register) {
+                gimli::RegisterRule::Offset(offset) => Some((*name, (cfa as i64 + offset) as u64)),
+                _ => None,
+            })
+            .collect();
+        Some(FrameLayout { cfa, saved })
+    }
+
+    /// 根据 `file_pc`（文件中的地址）处的 CFI 规则计算调用者的寄存器。
+    /// 没有覆盖该地址的 FDE、或规则过于复杂（如 PLT 使用的 CFA 表达式）时返回 Ok(None)；
+    /// 返回地址未定义（最外层帧）时调用者的 rip 为 0
+    pub fn unwind<E, F>(
+        &self,
+        file_pc: u64,
+        regs: &UnwindRegs,
+        read_word: &F,
+    ) -> Result<Option<UnwindRegs>, E>
+    where
+        F: Fn(usize) -> Result<u64, E>,
+    {
+        let (row, cfa) = match self.row_and_cfa(file_pc, regs) {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+
+        let rip = match row.register(gimli::X86_64::RA) {
+            gimli::RegisterRule::Undefined => 0,
+            gimli::RegisterRule::Offset(offset) => read_word((cfa as i64 + offset) as usize)?,
+            _ => return Ok(None),
+        };
+        let rbp = match row.register(gimli::X86_64::RBP) {
+            gimli::RegisterRule::Undefined | gimli::RegisterRule::SameValue => regs.rbp,
+            gimli::RegisterRule::Offset(offset) => read_word((cfa as i64 + offset) as usize)?,
+            gimli::RegisterRule::ValOffset(offset) => (cfa as i64 + offset) as u64,
+            _ => return Ok(None),
+        };
+        Ok(Some(UnwindRegs { rip, rsp: cfa, rbp }))
+    }
+}
@@ -1,27 +1,202 @@
-use crate::debugger_command::DebuggerCommand;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
-use crate::inferior::Inferior;
+use crate::callgraph::CallGraph;
+use crate::cfi::{CallFrameInfo, FrameLayout, UnwindRegs};
+use crate::debugger_command::{BreakOptions, DebuggerCommand};
+use crate::deltas::Snapshot;
+use crate::dwarf_data::{DwarfData, Encoding, Error as DwarfError, Line, Member};
+use crate::elf_info::ElfSummary;
+use crate::examine::{self, Format, Spec};
+use crate::expr::{Environment, Expr, Value};
+use crate::fault_inject::{self, Fault, FaultInjector};
+use crate::gimli_wrapper::{evaluate_location, ExpressionContext};
 use crate::inferior::Status;
+use crate::inferior::{
+    self, unwind_register, watch_ranges, FollowFork, Frame, FrameArguments, Inferior,
+    LaunchOptions, Scheduler, WatchScope, Watchpoint, WatchpointManager,
+};
+use crate::minidump::{Error as MinidumpError, Minidump};
+use crate::monitor::{Interval, Monitors};
+use crate::net;
+use crate::output_pipe::{split_pipeline, OutputPipe};
+use crate::plt_catch::{self, PltCatch, PltCatches};
+use crate::plugins::{PluginManager, StopKind, StopLocation};
+use crate::registers;
+use crate::run_compare::{RunRecord, StopRecord};
+use crate::semantic::SymbolIndex;
+use crate::signals::SignalTable;
+use crate::symbols::SharedLibraries;
+use crate::syscall_trace::{self, SyscallTracer};
+use crate::threads;
+use crate::timeline::Timeline;
 use nix::sys::signal;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
 use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
 
-use crate::inferior::Breakpoint;
+use crate::breakpoints::{Breakpoint, BreakpointManager};
+
+const PAGE_SIZE: usize = 4096;
+const SYS_MPROTECT: u64 = 10;
+const PROT_NONE: u64 = 0;
+/// compare-runs 每次运行最多记录的断点停止次数，防止断点在循环里时一直运行下去
+const MAX_RECORDED_STOPS: usize = 1000;
+/// suggest-fix 发给 LLM 的源代码在出问题的行前后各取多少行
+const FIX_CONTEXT_LINES: usize = 10;
+const DEFAULT_PROMPT: &str = "(kdb) ";
+/// `ask` 发给 LLM 的源代码在当前行前后各取多少行
+const ASK_CONTEXT_LINES: usize = 5;
+/// `ask` 为每个非空指针变量读取它指向的多少字节
+const ASK_MEMORY_BYTES: usize = 64;
+/// `context` 显示的源代码在当前行前后各取多少行
+const CONTEXT_SOURCE_LINES: usize = 2;
+/// `context` 显示的栈帧数
+const CONTEXT_FRAMES: usize = 3;
+/// `find-func` 列出的函数数
+const FIND_FUNC_RESULTS: usize = 5;
+/// System V ABI 中 rsp 之下函数可以直接使用的红区大小
+const RED_ZONE: usize = 128;
+/// `context` 显示的寄存器
+const CONTEXT_REGISTERS: &[&str] = &[
+    "rip", "rsp", "rbp", "rax", "rbx", "rcx", "rdx", "rsi", "rdi",
+];
+
+/// 对整个断点分组执行的操作
+enum GroupAction {
+    Enable,
+    Disable,
+    Delete,
+}
 
 pub struct Debugger {
     target: String,
-    history_path: String,
+    /// 历史文件所在的目录不能创建时为 None，这时不保存历史
+    history_path: Option<String>,
     readline: Editor<()>,
     inferior: Option<Inferior>,
     debug_data: DwarfData,
-    pub break_point: HashMap<usize, Breakpoint>,
+    /// 目标程序的 .eh_frame，用于回溯
+    target_cfi: Option<CallFrameInfo>,
+    /// debug_data 和 target_cfi 来自的程序；被调试进程 exec 了别的程序后与 target 不同
+    symbols_path: String,
+    core: Option<Minidump>,
+    /// 通过 restore 主动写入过的内存范围，compare-sections 不把它们算作异常修改
+    patched_ranges: Vec<(usize, usize)>,
+    /// 已加载共享库的按需符号索引
+    libraries: SharedLibraries,
+    pub break_point: BreakpointManager,
+    /// `watch` 设置的硬件监视点
+    watchpoints: WatchpointManager,
+    /// 当前命令的输出管道（`cmd | shell 命令`），读取下一条命令前关闭
+    output_pipe: Option<OutputPipe>,
+    /// 等待执行的输入行：一次粘贴的多行文本，以及断点命中时排队的 `commands`
+    pending_lines: VecDeque<String>,
+    /// `hook-stop` 的命令，每次停止时排到输入队列最前面
+    hook_stop: Vec<String>,
+    /// `hook-exited` 的命令，被调试进程结束时排到输入队列最前面
+    hook_exited: Vec<String>,
+    /// 最近设置的断点地址，`commands` 作用于它
+    last_breakpoint: Option<usize>,
+    signals: SignalTable,
+    /// 停在按策略需要转交的信号上时为该信号，下一次 continue 把它交给进程
+    pending_signal: Option<signal::Signal>,
+    /// 本次运行中通过 `guard` 放置的保护页
+    guards: Vec<GuardPage>,
+    /// 当前的被调试进程运行在 Valgrind 的 gdbserver 下（`target valgrind`）
+    under_valgrind: bool,
+    /// 本次会话中被调试进程运行和停止的时间线
+    timeline: Timeline,
+    /// 从 `~/.kdb/plugins` 加载的插件
+    plugins: PluginManager,
+    /// `set cwd` 设置的被调试进程工作目录，None 表示继承调试器的工作目录
+    cwd: Option<PathBuf>,
+    /// 启动被调试进程时的其它设置（`set preload`）
+    launch: LaunchOptions,
+    /// 回溯中显示哪些参数的值
+    frame_arguments: FrameArguments,
+    /// `display` 添加的变量，每次停止时显示
+    displays: Vec<String>,
+    /// `set $name = <expr>` 设置的便利变量（名字不带 `$`）
+    convenience: HashMap<String, Value>,
+    /// `print` 的结果，`$1` 是第一个
+    history_values: Vec<Value>,
+    /// `frame` / `up` / `down` 选中的外层帧：层号和回溯出的寄存器。None 表示最内层帧，每次停止后恢复
+    selected_frame: Option<(usize, UnwindRegs)>,
+    /// 每次停止时显示 `context` 摘要而不只是当前行（`set context on`）
+    auto_context: bool,
+    /// 上一次 `x` 的格式，以及它显示的内存之后的地址（不带地址的 `x` 从这里继续）
+    examine: (Spec, Option<usize>),
+    /// `set print deltas on` 时上一次停止的快照；关闭时为 None 且不再记录
+    deltas: Option<Option<Snapshot>>,
+    /// 上一次 `run` 的参数，不带参数的 `run` 沿用它们
+    run_args: Vec<String>,
+    /// 本次会话中 `run` 的次数
+    run_count: usize,
+    /// 有被调试进程时退出、删除全部断点之前要求确认（`set confirm off` 关闭）
+    confirm_dangerous: bool,
+    /// `set prompt` 设置的提示符，显示前替换其中的占位符
+    prompt: String,
+    /// `nb` 设置断点之前显示解析出的位置并要求确认（`set llm.confirm off` 关闭）
+    llm_confirm: bool,
+    /// `callgraph start` 之后记录的函数调用
+    callgraph: Option<CallGraph>,
+    /// `inject-fault` 设置的故障注入，重新运行时沿用
+    faults: FaultInjector,
+    /// `catch plt` 等待第一次调用的延迟绑定函数，重新运行时沿用
+    plt_catches: PltCatches,
+    /// `monitor` 定期打印的表达式，重新运行时沿用
+    monitors: Monitors,
+    /// 每条命令执行完后显示耗时（`maint time on`）
+    maint_time: bool,
+    /// `maint time on` 时当前命令开始执行的时间
+    command_started: Option<Instant>,
+    /// `trace syscalls` 的设置：只记录的调用名（为空时全部）和输出文件。重新运行时沿用
+    syscall_trace: Option<(Vec<String>, Option<String>)>,
+    /// 统计套接字流量（`set net-tracking on`），重新运行时沿用
+    net_tracking: bool,
+    /// `set follow-fork-mode`，默认跟随父进程
+    follow_fork: FollowFork,
+    /// 批处理模式（`--batch`）：退出时不再确认，有断言失败时调试器以非零状态退出
+    batch: bool,
+    /// 失败的 `assert-exit-code` 和 `assert-reaches` 的个数
+    failed_assertions: usize,
+    /// 本次运行的结束状态（Exited 或 Signaled），进程还没有结束时为 None
+    exit_status: Option<Status>,
+    /// 本次运行中停下过的地址，命中断点时是断点的地址
+    reached: HashSet<usize>,
+}
+
+/// `break` 的参数匹配到的一个位置
+struct BreakLocation {
+    /// 一个源码行在多个函数中有代码时有多个地址，它们属于同一个断点
+    addrs: Vec<usize>,
+    /// 只匹配这一个位置的 `break` 参数，记录在断点上，重新加载符号时据此再次解析
+    spec: String,
+    /// 在选择菜单中显示的描述
+    description: String,
+}
+
+/// 变量的值在哪里
+enum VariablePlace {
+    /// 在内存中：变量的起始地址和大小
+    Memory(usize, usize),
+    /// 在寄存器中或由位置表达式算出，没有地址
+    Value(Vec<u8>),
+}
+
+/// `guard` 命令保护的缓冲区，以及紧跟在它后面被设为不可访问的页
+struct GuardPage {
+    buffer: usize,
+    len: usize,
+    page: usize,
 }
 
 impl Debugger {
-    /// Initializes the debugger.
-    pub fn new(target: &str) -> Debugger {
+    /// Initializes the debugger. `core` is an optional minidump to inspect post-mortem; `batch`
+    /// makes assertion failures set the exit status returned by `run`.
+    pub fn new(target: &str, core: Option<&str>, batch: bool) -> Debugger {
         // TODO (milestone 3): initialize the DwarfData
         let debug_data = match DwarfData::from_file(target) {
             Ok(val) => {
@@ -41,10 +216,37 @@ impl Debugger {
             }
         };
 
-        let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
+        let core = core.map(|path| match Minidump::from_file(path) {
+            Ok(dump) => dump,
+            Err(MinidumpError::ErrorOpeningFile) => {
+                println!("Could not open file {}", path);
+                std::process::exit(1);
+            }
+            Err(MinidumpError::FormatError(err)) => {
+                println!("Could not load minidump {}: {}", path, err);
+                std::process::exit(1);
+            }
+        });
+
+        if crate::home::home_dir().is_none() {
+            match crate::home::base_dir() {
+                Some(dir) => println!(
+                    "Warning: HOME is not set; keeping history and caches in {}",
+                    dir.display()
+                ),
+                None => println!(
+                    "Warning: HOME is not set and {} is not a private directory; \
+                     history, plugins, LLM config and caches are disabled",
+                    crate::home::temp_dir().display()
+                ),
+            }
+        }
+        let history_path = history_path_for(target);
         let mut readline = Editor::<()>::new();
-        // Attempt to load history from ~/.deet_history if it exists
-        let _ = readline.load_history(&history_path);
+        // Attempt to load this target's history if it exists
+        if let Some(history_path) = &history_path {
+            let _ = readline.load_history(history_path);
+        }
 
         Debugger {
             target: target.to_string(),
@@ -52,319 +254,421 @@ impl Debugger {
             readline,
             inferior: None,
             debug_data,
-            break_point: HashMap::new(),
+            target_cfi: CallFrameInfo::from_file(target),
+            symbols_path: target.to_string(),
+            core,
+            patched_ranges: Vec::new(),
+            libraries: SharedLibraries::new(),
+            break_point: BreakpointManager::new(),
+            watchpoints: WatchpointManager::default(),
+            output_pipe: None,
+            pending_lines: VecDeque::new(),
+            hook_stop: Vec::new(),
+            hook_exited: Vec::new(),
+            last_breakpoint: None,
+            signals: SignalTable::new(),
+            pending_signal: None,
+            guards: Vec::new(),
+            under_valgrind: false,
+            timeline: Timeline::new(),
+            plugins: PluginManager::load(),
+            cwd: None,
+            launch: LaunchOptions::default(),
+            frame_arguments: FrameArguments::default(),
+            displays: Vec::new(),
+            convenience: HashMap::new(),
+            history_values: Vec::new(),
+            selected_frame: None,
+            auto_context: false,
+            examine: (Spec::default(), None),
+            deltas: None,
+            prompt: DEFAULT_PROMPT.to_string(),
+            confirm_dangerous: true,
+            run_args: Vec::new(),
+            run_count: 0,
+            llm_confirm: true,
+            callgraph: None,
+            faults: FaultInjector::default(),
+            plt_catches: PltCatches::default(),
+            monitors: Monitors::default(),
+            syscall_trace: None,
+            net_tracking: false,
+            follow_fork: FollowFork::Parent,
+            batch,
+            failed_assertions: 0,
+            exit_status: None,
+            reached: HashSet::new(),
+            maint_time: false,
+            command_started: None,
         }
     }
 
-    pub fn run(&mut self) {
+    /// 读取并执行命令直到退出，返回调试器的退出状态
+    pub fn run(&mut self) -> i32 {
+        self.print_core_info();
         loop {
             match self.get_next_command() {
                 DebuggerCommand::Run(args) => {
-                    if self.inferior.is_some() {
-                        println!(
-                            "Killing running inferior (pid {})",
-                            self.inferior.as_ref().unwrap().pid()
-                        );
-                        let _ = self.inferior.as_mut().unwrap().kill();
-                        self.inferior = None;
+                    if self.start_run(args) {
+                        let status = self.continue_inferior();
+                        self.report_status(status);
                     }
-                    if let Some(inferior) =
-                        Inferior::new(&self.target, &args, &mut self.break_point)
-                    {
-                        // Create the inferior
-                        self.inferior = Some(inferior);
-                        match self.inferior.as_mut().unwrap().continue_run(None) {
-                            Ok(Status::Exited(code)) => {
-                                println!("Child exited (status {})", code);
-                                self.inferior = None;
-                            }
-                            Ok(Status::Signaled(signal)) => {
-                                println!("Child exited (signal {})", signal);
-                                self.inferior = None;
-                            }
-                            Ok(Status::Stopped(signal, rip)) => {
-                                println!("Child stopped (signal {})", signal);
-                                self.print_stopped_info(rip);
+                }
+                DebuggerCommand::RunUntilFail(limit, args) => self.run_until_fail(limit, &args),
+                DebuggerCommand::AssertExitCode(code) => self.assert_exit_code(code),
+                DebuggerCommand::AssertReaches(location) => self.assert_reaches(&location),
+                DebuggerCommand::SuggestFix(note) => self.suggest_fix(note.as_deref()),
+                DebuggerCommand::Ask(question) => self.ask(&question),
+                DebuggerCommand::FindFunc(description) => self.find_function(&description),
+                DebuggerCommand::LlmReload => {
+                    let (cleared, config) = crate::llm::reload();
+                    println!("Cleared {} cached natural-language breakpoint(s)", cleared);
+                    match config {
+                        Ok(config) => print_llm_config(&config),
+                        Err(e) => println!("{}", e),
+                    }
+                }
+                DebuggerCommand::LlmStatus => match crate::llm::load_config() {
+                    Ok(config) => {
+                        print_llm_config(&config);
+                        match crate::llm::ping(&config) {
+                            Ok(models) => {
+                                println!("API key is valid ({} model(s) available)", models)
                             }
-                            Err(e) => println!("Error continuing inferior: {}", e),
+                            Err(e) => println!("{}", e),
                         }
-                    } else {
-                        println!("Error starting subprocess");
+                    }
+                    Err(e) => println!("{}", e),
+                },
+                DebuggerCommand::SetCwd(dir) => match fs::canonicalize(&dir) {
+                    Ok(path) if path.is_dir() => {
+                        self.cwd = Some(path);
+                        self.print_cwd();
+                    }
+                    Ok(_) => println!("{} is not a directory", dir),
+                    Err(e) => println!("{}: {}", dir, e),
+                },
+                DebuggerCommand::SetPreload(libs) => self.set_preload(&libs),
+                DebuggerCommand::SetAffinity(cpus) => self.set_affinity(cpus),
+                DebuggerCommand::SetScheduler(scheduler) => self.set_scheduler(scheduler),
+                DebuggerCommand::SetFrameArguments(mode) => self.frame_arguments = mode,
+                DebuggerCommand::SetAutoContext(on) => self.auto_context = on,
+                DebuggerCommand::Examine(spec, addr) => self.examine_memory(&spec, addr.as_deref()),
+                DebuggerCommand::InfoRegisters(names) => self.print_registers(&names),
+                DebuggerCommand::SetRegister(name, text) => self.set_register(&name, &text),
+                DebuggerCommand::SetPrintDeltas(on) => self.deltas = on.then_some(None),
+                DebuggerCommand::SetLlmConfirm(on) => self.llm_confirm = on,
+                DebuggerCommand::SetConfirm(on) => self.confirm_dangerous = on,
+                DebuggerCommand::SetPrompt(prompt) => self.prompt = prompt,
+                DebuggerCommand::SetLlmLog(path) => match crate::llm::set_log(path.clone()) {
+                    Ok(()) => match path {
+                        Some(path) => println!("Logging LLM requests to {}", path),
+                        None => println!("LLM request logging is off"),
+                    },
+                    Err(e) => println!("{}", e),
+                },
+                DebuggerCommand::SetNetTracking(on) => {
+                    if on && self.under_valgrind {
+                        println!("net-tracking is not supported under valgrind");
+                        continue;
+                    }
+                    self.net_tracking = on;
+                    if let Some(inferior) = self.inferior.as_mut() {
+                        inferior.set_net_tracking(on);
                     }
                 }
-                DebuggerCommand::Continue => {
+                DebuggerCommand::SetFollowFork(mode) => {
+                    if self.under_valgrind {
+                        println!("follow-fork-mode is not supported under valgrind");
+                        continue;
+                    }
+                    self.follow_fork = mode;
                     if let Some(inferior) = self.inferior.as_mut() {
-                        use nix::sys::ptrace;
-                        let mut regs = ptrace::getregs(inferior.pid()).unwrap();
-                        let rip = regs.rip as usize;
-                        let bp_addr = rip - 1;
-
-                        if let Some(bp) = self.break_point.get(&bp_addr) {
-                            // We are stopped at a breakpoint. We need to step over it.
-                            // 1. Restore original instruction
-                            inferior.write_byte(bp_addr, bp.orig_byte).unwrap();
-                            // 2. Rewind instruction pointer
-                            regs.rip = bp_addr as u64;
-                            ptrace::setregs(inferior.pid(), regs).unwrap();
-                            // 3. Single step
-                            match inferior.step() {
-                                Ok(Status::Stopped(signal::Signal::SIGTRAP, _)) => {
-                                    // 4. Restore breakpoint
-                                    inferior.write_byte(bp_addr, 0xcc).unwrap();
-                                }
-                                Ok(status) => {
-                                    // Child stopped for other reason during step (e.g. exit)
-                                    // We should probably handle this, but for now just print status
-                                    println!("Child stopped during step (status {:?})", status); // This might not compile if debug is not derived
-                                    match status {
-                                        Status::Exited(code) => {
-                                            println!("Child exited (status {})", code);
-                                            self.inferior = None;
-                                            continue;
-                                        }
-                                        Status::Signaled(signal) => {
-                                            println!("Child exited (signal {})", signal);
-                                            self.inferior = None;
-                                            continue;
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                Err(e) => {
-                                    println!("Error stepping inferior: {}", e);
-                                    continue;
+                        inferior.set_follow_fork(mode);
+                    }
+                }
+                DebuggerCommand::InfoNet => self.print_net_info(),
+                DebuggerCommand::InfoThreads => self.print_threads(),
+                DebuggerCommand::InfoBreakpoints => self.print_breakpoints(),
+                DebuggerCommand::MaintLineTable(file) => {
+                    if !self.debug_data.print_line_table(file.as_deref()) {
+                        println!("No source file named {}", file.unwrap_or_default());
+                    }
+                }
+                DebuggerCommand::MaintPrintSymbols => {
+                    let stats = self.debug_data.load_stats();
+                    println!(
+                        "Symbols for {}: index {} in {:.3} ms, addr2line context in {:.3} ms",
+                        self.symbols_path,
+                        if stats.from_cache {
+                            "read from cache"
+                        } else {
+                            "parsed"
+                        },
+                        stats.index_time.as_secs_f64() * 1000.0,
+                        stats.addr2line_time.as_secs_f64() * 1000.0
+                    );
+                    self.debug_data.print();
+                }
+                DebuggerCommand::MaintTime(on) => self.maint_time = on,
+                DebuggerCommand::Context => match self.inferior.as_ref().map(|i| i.getregs()) {
+                    Some(Ok(regs)) => self.print_context(regs.rip as usize),
+                    Some(Err(e)) => println!("Error reading registers: {}", e),
+                    None => println!("The program is not being run."),
+                },
+                DebuggerCommand::Display(Some(expression)) => {
+                    self.displays.push(expression);
+                    if self.inferior.is_some() {
+                        self.print_display(self.displays.len() - 1);
+                    }
+                }
+                DebuggerCommand::Display(None) => {
+                    for index in 0..self.displays.len() {
+                        self.print_display(index);
+                    }
+                }
+                DebuggerCommand::InfoDisplay => self.print_display_list(),
+                DebuggerCommand::Plugin(name, args) => {
+                    self.plugins
+                        .run_command(&name, &args, self.inferior.as_ref())
+                }
+                DebuggerCommand::ExportTimeline(file) => match self.timeline.export(&file) {
+                    Ok(()) => println!("Timeline written to {}", file),
+                    Err(e) => println!("Error writing {}: {}", file, e),
+                },
+                DebuggerCommand::Journal(count) => self.timeline.print_journal(count),
+                DebuggerCommand::CompareRuns(vars, first, second) => {
+                    self.compare_runs(&vars, &first, &second)
+                }
+                DebuggerCommand::TargetValgrind(prog, args) => {
+                    if !same_file(&prog, &self.target) {
+                        println!(
+                            "{} is not the loaded target; start deet with {} to debug it under valgrind",
+                            prog, prog
+                        );
+                    } else if self.start_under_valgrind(&args) {
+                        let status = self.continue_inferior();
+                        self.report_status(status);
+                    }
+                }
+                DebuggerCommand::Continue(count) => {
+                    if self.inferior.is_some() {
+                        let mut status = self.resume_inferior();
+                        for _ in 1..count {
+                            match status {
+                                Ok(Status::Stopped(signal::Signal::SIGTRAP, _))
+                                    if self.at_breakpoint() =>
+                                {
+                                    status = self.resume_inferior();
                                 }
+                                _ => break,
                             }
                         }
-
-                        match inferior.continue_run(None) {
-                            Ok(Status::Exited(code)) => {
-                                println!("Child exited (status {})", code);
-                                self.inferior = None;
-                            }
-                            Ok(Status::Signaled(signal)) => {
-                                println!("Child exited (signal {})", signal);
-                                self.inferior = None;
-                            }
-                            Ok(Status::Stopped(signal, rip)) => {
-                                println!("Child stopped (signal {})", signal);
-                                self.print_stopped_info(rip);
-                            }
-                            Err(e) => println!("Error continuing inferior: {}", e),
-                        }
+                        self.report_status(status);
                     } else {
                         println!("No inferior to continue");
                     }
                 }
                 DebuggerCommand::Backtrace => {
-                    if let Some(inferior) = self.inferior.as_mut() {
-                        match inferior.print_backtrace(&self.debug_data) {
-                            Ok(_) => (),
-                            Err(e) => println!("Error printing backtrace: {}", e),
+                    if self.inferior.is_some() {
+                        self.print_inferior_backtrace();
+                    } else if let Some(core) = self.core.as_ref() {
+                        match core.crash_context() {
+                            Some(ctx) => {
+                                if let Err(e) = crate::inferior::print_frames(
+                                    &self.debug_data,
+                                    self.target_cfi.as_ref(),
+                                    None,
+                                    UnwindRegs {
+                                        rip: ctx.rip,
+                                        rsp: ctx.rsp,
+                                        rbp: ctx.rbp,
+                                    },
+                                    self.frame_arguments,
+                                    |addr| core.read_word(addr),
+                                ) {
+                                    println!("Error printing backtrace: {}", e);
+                                }
+                            }
+                            None => println!("Minidump contains no threads"),
                         }
                     } else {
                         println!("No inferior to print backtrace");
                     }
                 }
-                DebuggerCommand::Break(args) => {
-                    let addr = if args.starts_with("*") {
-                        // Raw address: break *0x4005b8
-                        parse_address(&args[1..])
-                    } else if let Ok(line_number) = args.parse::<usize>() {
-                        // Line number: break 15
-                        self.debug_data.get_addr_for_line(None, line_number)
-                    } else {
-                        // Function name: break func1
-                        self.debug_data.get_addr_for_function(None, &args)
-                    };
-
-                    if let Some(addr) = addr {
-                        let mut bp = Breakpoint { addr, orig_byte: 0 };
-                        self.break_point.insert(addr, bp.clone());
-                        println!(
-                            "Set breakpoint {} at {:#x}",
-                            self.break_point.len() - 1,
-                            addr
-                        );
-                        if let Some(inferior) = self.inferior.as_mut() {
-                            match inferior.write_byte(addr, 0xcc) {
-                                Ok(orig_byte) => {
-                                    bp.orig_byte = orig_byte;
-                                    self.break_point.insert(addr, bp);
-                                }
-                                Err(e) => {
-                                    println!("Error setting breakpoint at {:#x}: {}", addr, e)
-                                }
-                            }
+                DebuggerCommand::Break(args, options) => {
+                    if let Some(group) = &options.group {
+                        if !self.break_point.has_group(group) {
+                            println!("No breakpoint group named {}", group);
+                            continue;
                         }
+                    }
+                    if let Some(Err(e)) = options.condition.as_deref().map(crate::expr::parse) {
+                        println!("Invalid condition: {}", e);
+                        continue;
+                    }
+                    let mut locations = self.resolve_locations(&args);
+                    if locations.len() > 1 {
+                        locations = self.choose_locations(locations);
+                    } else if let Some(location) = locations.first_mut() {
+                        location.spec = args.clone();
                     } else {
                         println!("Unable to set breakpoint: {}", args);
                     }
+                    for location in locations {
+                        self.set_breakpoint(&location.addrs, &location.spec, &options);
+                    }
+                }
+                DebuggerCommand::Next(count) => self.step_lines(count, Self::next_line),
+                DebuggerCommand::Step(count) => self.step_lines(count, Self::step_line),
+                DebuggerCommand::UntilExpr(text) => self.until_expression(&text),
+                DebuggerCommand::WhoWrites(var_name) => self.who_writes(&var_name),
+                DebuggerCommand::Monitor(text, interval) => self.add_monitor(text, interval),
+                DebuggerCommand::MonitorList => self.print_monitors(),
+                DebuggerCommand::MonitorOff(None) => {
+                    println!("Deleted {} monitor(s)", self.monitors.monitors.len());
+                    self.monitors.monitors.clear();
+                }
+                DebuggerCommand::MonitorOff(Some(id)) => {
+                    if !self.monitors.remove(id) {
+                        println!("No monitor number {}.", id);
+                    }
                 }
-                DebuggerCommand::Next => {
+                DebuggerCommand::Watch(var_name) => self.watch(&var_name),
+                DebuggerCommand::TraceSyscalls(filter, output) => {
+                    self.trace_syscalls(filter, output)
+                }
+                DebuggerCommand::TraceSyscallsOff => {
+                    if self.syscall_trace.take().is_none() {
+                        println!("System calls are not being traced");
+                        continue;
+                    }
                     if let Some(inferior) = self.inferior.as_mut() {
-                        use nix::sys::ptrace;
-                        // 获取当前行号（只比较行号数字，不比较地址）
-                        let regs = ptrace::getregs(inferior.pid()).unwrap();
-                        let current_line_number = self
-                            .debug_data
-                            .get_line_from_addr(regs.rip as usize)
-                            .map(|l| l.number);
-
-                        loop {
-                            // 在单步前检查是否停在断点上
-                            let mut regs = ptrace::getregs(inferior.pid()).unwrap();
-                            let rip = regs.rip as usize;
-                            let bp_addr = rip - 1;
-
-                            if let Some(bp) = self.break_point.get(&bp_addr) {
-                                // 恢复原始字节、回退 rip、单步、重设断点
-                                inferior.write_byte(bp_addr, bp.orig_byte).unwrap();
-                                regs.rip = bp_addr as u64;
-                                ptrace::setregs(inferior.pid(), regs).unwrap();
-                                match inferior.step() {
-                                    Ok(Status::Stopped(signal::Signal::SIGTRAP, _)) => {
-                                        inferior.write_byte(bp_addr, 0xcc).unwrap();
-                                    }
-                                    Ok(Status::Exited(code)) => {
-                                        println!("Child exited (status {})", code);
-                                        self.inferior = None;
-                                        break;
-                                    }
-                                    Ok(Status::Signaled(signal)) => {
-                                        println!("Child exited (signal {})", signal);
-                                        self.inferior = None;
-                                        break;
-                                    }
-                                    Ok(Status::Stopped(_, rip)) => {
-                                        self.print_stopped_info(rip);
-                                        break;
-                                    }
-                                    Err(e) => {
-                                        println!("Error stepping inferior: {}", e);
-                                        break;
-                                    }
-                                }
-                            } else {
-                                // 正常单步
-                                match inferior.step() {
-                                    Ok(Status::Stopped(_, rip)) => {
-                                        let new_line_number = self
-                                            .debug_data
-                                            .get_line_from_addr(rip)
-                                            .map(|l| l.number);
-                                        // 如果行号变了（或者从 None 变成了 Some），就停下来
-                                        if new_line_number != current_line_number
-                                            && new_line_number.is_some()
-                                        {
-                                            self.print_stopped_info(rip);
-                                            break;
-                                        }
-                                        // 行号没变或者还在无行号区域，继续步进
-                                    }
-                                    Ok(Status::Exited(code)) => {
-                                        println!("Child exited (status {})", code);
-                                        self.inferior = None;
-                                        break;
-                                    }
-                                    Ok(Status::Signaled(signal)) => {
-                                        println!("Child exited (signal {})", signal);
-                                        self.inferior = None;
-                                        break;
-                                    }
-                                    Err(e) => {
-                                        println!("Error stepping inferior: {}", e);
-                                        break;
-                                    }
-                                }
-                            }
+                        inferior.set_syscall_tracer(None);
+                    }
+                    println!("Stopped tracing system calls");
+                }
+                DebuggerCommand::InjectFault(function, errno, every) => {
+                    self.add_fault(function, errno, every)
+                }
+                DebuggerCommand::InjectFaultList => self.print_faults(),
+                DebuggerCommand::InjectFaultOff(function) => match function {
+                    Some(function) => {
+                        if self.faults.remove(&function) {
+                            println!("Deleted fault injection for {}", function);
+                        } else {
+                            println!("No fault injected into {}", function);
                         }
-                    } else {
-                        println!("No inferior to step");
-                    }
-                }
-                DebuggerCommand::Print(var_name) => {
-                    if let Some(inferior) = self.inferior.as_ref() {
-                        use crate::dwarf_data::Location;
-                        use nix::sys::ptrace;
-                        let regs = ptrace::getregs(inferior.pid()).unwrap();
-                        let rip = regs.rip as usize;
-                        let rbp = regs.rbp as i64;
-
-                        if let Some(var) = self.debug_data.get_variable_by_name(rip, &var_name) {
-                            let addr = match &var.location {
-                                Location::Address(a) => *a,
-                                Location::FramePointerOffset(offset) => {
-                                    // DW_OP_fbreg 基于 CFA，x86-64 上 CFA = rbp + 16
-                                    (rbp + 16 + (*offset as i64)) as usize
-                                }
-                            };
-                            match ptrace::read(inferior.pid(), addr as ptrace::AddressType) {
-                                Ok(value) => {
-                                    let value = value as u64;
-                                    let type_name = &var.entity_type.name;
-                                    let size = var.entity_type.size;
-                                    // 根据大小截断值
-                                    let masked = match size {
-                                        1 => value & 0xff,
-                                        2 => value & 0xffff,
-                                        4 => value & 0xffff_ffff,
-                                        _ => value,
-                                    };
-                                    println!("{} = {} ({})", var_name, masked, type_name);
-                                }
-                                Err(e) => println!("Error reading variable '{}': {}", var_name, e),
-                            }
+                    }
+                    None => {
+                        self.faults.faults.clear();
+                        println!("Deleted all fault injections");
+                    }
+                },
+                DebuggerCommand::CatchPlt(name) => self.add_plt_catch(name),
+                DebuggerCommand::CatchPltList => self.print_plt_catches(),
+                DebuggerCommand::CatchPltOff(name) => match name {
+                    Some(name) => {
+                        if self.plt_catches.remove(&name) {
+                            println!("Deleted catch plt {}", name);
                         } else {
-                            println!("Variable '{}' not found in current scope", var_name);
+                            println!("No catch plt for {}", name);
+                        }
+                    }
+                    None => {
+                        self.plt_catches.catches.clear();
+                        println!("Deleted all catch plt");
+                    }
+                },
+                DebuggerCommand::CallgraphStart => {
+                    if self.callgraph.is_some() {
+                        println!("Call graph recording is already running");
+                        continue;
+                    }
+                    let bias = self.debug_data.load_bias();
+                    let functions = self.debug_data.files().iter().flat_map(|file| {
+                        file.functions
+                            .iter()
+                            .filter(|func| func.text_length > 0)
+                            .map(move |func| (func.address + bias, func.name.clone()))
+                    });
+                    let callgraph = CallGraph::new(functions);
+                    println!(
+                        "Recording calls to {} function(s); use 'callgraph stop' to finish",
+                        callgraph.function_count()
+                    );
+                    self.callgraph = Some(callgraph);
+                }
+                DebuggerCommand::CallgraphStop(file) => match self.callgraph.take() {
+                    Some(callgraph) => {
+                        callgraph.print();
+                        if let Some(file) = file {
+                            match callgraph.export(&file) {
+                                Ok(()) => println!("Call graph written to {}", file),
+                                Err(e) => println!("Error writing {}: {}", file, e),
+                            }
                         }
+                    }
+                    None => println!("Call graph recording is not running"),
+                },
+                DebuggerCommand::Finish => {
+                    if self.inferior.is_some() {
+                        self.finish();
                     } else {
-                        println!("No inferior running");
+                        println!("The program is not being run.");
+                    }
+                }
+                DebuggerCommand::Print(text) => self.print_value(&text),
+                DebuggerCommand::SetConvenience(name, text) => {
+                    if registers::is_register(&name) {
+                        self.set_register(&name, &text);
+                        continue;
+                    }
+                    if name == "_" {
+                        println!("$_ is the last value in the history and cannot be assigned");
+                        continue;
+                    }
+                    match crate::expr::parse(&text).and_then(|expr| self.evaluate(&expr)) {
+                        Ok(value) => {
+                            println!("${} = {}", name, value);
+                            self.convenience.insert(name, value);
+                        }
+                        Err(e) => println!("{}", e),
                     }
                 }
                 DebuggerCommand::NaturalBreak(description) => {
                     println!("正在解析自然语言断点: \"{}\" ...", description);
                     match crate::llm::parse_with_fallback(&description, &self.debug_data) {
                         Ok(spec) => {
-                            let addr = match &spec {
+                            let location = match &spec {
                                 crate::llm::BreakpointSpec::Line { file, line } => {
                                     println!(
                                         "LLM 解析结果: 行号断点 (文件: {:?}, 行: {})",
                                         file, line
                                     );
-                                    self.debug_data.get_addr_for_line(file.as_deref(), *line)
+                                    match file {
+                                        Some(file) => format!("{}:{}", file, line),
+                                        None => line.to_string(),
+                                    }
                                 }
                                 crate::llm::BreakpointSpec::Function { name } => {
                                     println!("LLM 解析结果: 函数断点 (函数: {})", name);
-                                    self.debug_data.get_addr_for_function(None, name)
+                                    name.clone()
                                 }
                                 crate::llm::BreakpointSpec::Address { addr } => {
                                     println!("LLM 解析结果: 地址断点 (地址: {:#x})", addr);
-                                    Some(*addr)
+                                    format!("*{:#x}", addr)
                                 }
                             };
 
-                            if let Some(addr) = addr {
-                                let mut bp = Breakpoint { addr, orig_byte: 0 };
-                                self.break_point.insert(addr, bp.clone());
-                                println!(
-                                    "Set breakpoint {} at {:#x}",
-                                    self.break_point.len() - 1,
-                                    addr
-                                );
-                                if let Some(inferior) = self.inferior.as_mut() {
-                                    match inferior.write_byte(addr, 0xcc) {
-                                        Ok(orig_byte) => {
-                                            bp.orig_byte = orig_byte;
-                                            self.break_point.insert(addr, bp);
-                                        }
-                                        Err(e) => {
-                                            println!(
-                                                "Error setting breakpoint at {:#x}: {}",
-                                                addr, e
-                                            )
-                                        }
-                                    }
+                            let addrs = self.resolve_location(&location);
+                            if let Some(&addr) = addrs.first() {
+                                if !self.llm_confirm || self.confirm_llm_breakpoint(addr) {
+                                    self.set_breakpoint(
+                                        &addrs,
+                                        &location,
+                                        &BreakOptions::default(),
+                                    );
                                 }
                             } else {
                                 println!("无法将 LLM 解析结果映射到有效地址: {:?}", spec);
@@ -375,99 +679,5036 @@ impl Debugger {
                         }
                     }
                 }
-                DebuggerCommand::Quit => {
+                DebuggerCommand::NaturalBreakFromBug(report) => self.break_from_bug(&report),
+                DebuggerCommand::InfoElf => {
+                    for summary in self.loaded_elf_files() {
+                        summary.print_summary();
+                    }
+                }
+                DebuggerCommand::InfoSections => {
+                    for summary in self.loaded_elf_files() {
+                        summary.print_sections();
+                    }
+                }
+                DebuggerCommand::Frame(level) => {
+                    let current = self.selected_frame.map_or(0, |(level, _)| level);
+                    self.select_frame(level.unwrap_or(current));
+                }
+                DebuggerCommand::Up(count) => {
+                    let current = self.selected_frame.map_or(0, |(level, _)| level);
+                    self.select_frame(current + count);
+                }
+                DebuggerCommand::Down(count) => {
+                    let current = self.selected_frame.map_or(0, |(level, _)| level);
+                    match current.checked_sub(count) {
+                        Some(level) => self.select_frame(level),
+                        None => println!("Bottom (innermost) frame selected; you cannot go down."),
+                    }
+                }
+                DebuggerCommand::InfoFrame => {
                     if self.inferior.is_some() {
+                        if let Err(e) = self.print_frame_info() {
+                            println!("Error reading frame: {}", e);
+                        }
+                    } else {
+                        println!("No stack.");
+                    }
+                }
+                DebuggerCommand::InfoSharedLibrary => {
+                    self.refresh_libraries();
+                    if self.libraries.libraries().is_empty() {
+                        println!("No shared libraries loaded at this time.");
+                    }
+                    for lib in self.libraries.libraries() {
+                        let preloaded = self
+                            .launch
+                            .preload
+                            .iter()
+                            .any(|path| path.as_os_str() == lib.path.as_str());
                         println!(
-                            "Killing running inferior (pid {})",
-                            self.inferior.as_ref().unwrap().pid()
+                            "{:#x}-{:#x}  {}{}",
+                            lib.start,
+                            lib.end,
+                            lib.path,
+                            if preloaded { "  (preloaded)" } else { "" }
                         );
-                        let _ = self.inferior.as_mut().unwrap().kill();
-
-                        self.inferior = None;
+                        println!(
+                            "    build-id: {}",
+                            lib.build_id.as_deref().unwrap_or("(none)")
+                        );
+                        println!("    symbols:  {}", lib.symbols_status());
                     }
-                    return;
+                }
+                DebuggerCommand::DumpMemory(file, start, end) => {
+                    match (self.evaluate_address(&start), self.evaluate_address(&end)) {
+                        (Ok(start), Ok(end)) if start < end => self.dump_memory(&file, start, end),
+                        (Err(e), _) | (_, Err(e)) => println!("{}", e),
+                        _ => println!("Invalid address range: {} {}", start, end),
+                    }
+                }
+                DebuggerCommand::Restore(file, addr) => match self.evaluate_address(&addr) {
+                    Ok(addr) => self.restore_memory(&file, addr),
+                    Err(e) => println!("{}", e),
+                },
+                DebuggerCommand::CompareSections => self.compare_sections(),
+                DebuggerCommand::Handle(signals, actions) => {
+                    if !actions.is_empty() {
+                        for signal in &signals {
+                            if *signal == signal::Signal::SIGTRAP {
+                                println!("SIGTRAP is used by the debugger and cannot be changed");
+                                continue;
+                            }
+                            self.signals.apply(*signal, &actions);
+                        }
+                    }
+                    self.signals.print(&signals);
+                }
+                DebuggerCommand::Guard(addr, len) => {
+                    match (self.evaluate_address(&addr), self.evaluate_length(&len)) {
+                        (Ok(addr), Ok(len)) if len > 0 => self.set_guard(addr, len),
+                        (Err(e), _) | (_, Err(e)) => println!("{}", e),
+                        _ => println!("Invalid buffer: {} {}", addr, len),
+                    }
+                }
+                DebuggerCommand::GroupCreate(name) => {
+                    if self.break_point.create_group(&name) {
+                        println!("Created breakpoint group {}", name);
+                    } else {
+                        println!("Breakpoint group {} already exists", name);
+                    }
+                }
+                DebuggerCommand::GroupEnable(name) => self.update_group(&name, GroupAction::Enable),
+                DebuggerCommand::GroupDisable(name) => {
+                    self.update_group(&name, GroupAction::Disable)
+                }
+                DebuggerCommand::GroupDelete(name) => self.update_group(&name, GroupAction::Delete),
+                DebuggerCommand::GroupList => self.print_groups(),
+                DebuggerCommand::Delete(ids) => {
+                    if ids.is_empty()
+                        && self.break_point.iter().next().is_none()
+                        && self.watchpoints.watchpoints.is_empty()
+                    {
+                        println!("No breakpoints to delete");
+                    } else if !ids.is_empty()
+                        || !self.confirm_dangerous
+                        || self.confirm("Delete all breakpoints? (y or n) ", false)
+                    {
+                        for addr in self.breakpoints_by_id(&ids) {
+                            self.delete_breakpoint(addr);
+                        }
+                        self.delete_watchpoints(&ids);
+                    }
+                }
+                DebuggerCommand::Enable(ids) => {
+                    for addr in self.breakpoints_by_id(&ids) {
+                        self.set_breakpoint_enabled(addr, true);
+                    }
+                    self.explain_watchpoint_toggle(&ids);
+                }
+                DebuggerCommand::Disable(ids) => {
+                    for addr in self.breakpoints_by_id(&ids) {
+                        self.set_breakpoint_enabled(addr, false);
+                    }
+                    self.explain_watchpoint_toggle(&ids);
+                }
+                DebuggerCommand::History(pattern) => self.print_history(pattern.as_deref()),
+                DebuggerCommand::HookStop(body) => {
+                    print_hook_change("hook-stop", &body);
+                    self.hook_stop = body;
+                }
+                DebuggerCommand::HookExited(body) => {
+                    print_hook_change("hook-exited", &body);
+                    self.hook_exited = body;
+                }
+                DebuggerCommand::Commands(body) => match self
+                    .last_breakpoint
+                    .and_then(|addr| self.break_point.get_mut(addr))
+                {
+                    Some(bp) => {
+                        let id = bp.id;
+                        for addr in self.break_point.locations(id) {
+                            self.break_point.get_mut(addr).unwrap().commands = body.clone();
+                        }
+                    }
+                    None => println!("No breakpoints specified."),
+                },
+                DebuggerCommand::Attach(pid) => self.attach(pid),
+                DebuggerCommand::Detach => self.detach(),
+                DebuggerCommand::Kill => match self.inferior.take() {
+                    Some(mut inferior) => {
+                        let _ = inferior.kill();
+                        println!("[Inferior (pid {}) killed]", inferior.pid());
+                        self.timeline
+                            .event(format!("killed pid {}", inferior.pid()));
+                        self.selected_frame = None;
+                        self.expire_watchpoints();
+                    }
+                    None => println!("The program is not being run."),
+                },
+                DebuggerCommand::Quit => {
+                    if let Some(pid) = self.inferior.as_ref().map(|inferior| inferior.pid()) {
+                        // 再次按 Ctrl+D 时直接退出，输入来自管道时也不会停在这里
+                        let prompt = format!(
+                            "A debugging session is active (pid {}). Quit anyway? (y or n) ",
+                            pid
+                        );
+                        if self.confirm_dangerous && !self.batch && !self.confirm(&prompt, true) {
+                            println!("Not confirmed.");
+                            continue;
+                        }
+                    }
+                    self.end_inferior();
+                    return self.exit_code();
                 }
             }
         }
     }
 
-    /// 打印停止时的位置信息和源代码行
-    fn print_stopped_info(&self, rip: usize) {
-        let line = self.debug_data.get_line_from_addr(rip);
-        let function = self.debug_data.get_function_from_addr(rip);
-        if let (Some(line), Some(function)) = (&line, function) {
-            println!("Stopped at {} {}", function, line);
+    /// 调试器的退出状态：批处理模式下有断言失败时为 1
+    fn exit_code(&self) -> i32 {
+        if !self.batch || self.failed_assertions == 0 {
+            return 0;
+        }
+        println!("{} assertion(s) failed", self.failed_assertions);
+        1
+    }
+
+    /// 记录一个断言的结果
+    fn report_assertion(&mut self, passed: bool, message: String) {
+        if passed {
+            println!("Assertion passed: {}", message);
         } else {
-            println!("Stopped at {:#x}", rip);
+            println!("Assertion failed: {}", message);
+            self.failed_assertions += 1;
         }
-        // 打印对应的源代码行
-        if let Some(line) = &line {
-            self.print_source(&line.file, line.number);
+    }
+
+    /// `assert-exit-code <n>`：本次运行的进程以状态 n 退出
+    fn assert_exit_code(&mut self, code: i32) {
+        let (passed, message) = match self.exit_status {
+            Some(Status::Exited(status)) if status == code => {
+                (true, format!("exited with status {}", code))
+            }
+            Some(Status::Exited(status)) => (
+                false,
+                format!("expected exit status {}, got {}", code, status),
+            ),
+            Some(Status::Signaled(signal)) => (
+                false,
+                format!("expected exit status {}, killed by {}", code, signal),
+            ),
+            _ if self.inferior.is_some() => (
+                false,
+                format!(
+                    "expected exit status {}, the program is still running",
+                    code
+                ),
+            ),
+            _ => (
+                false,
+                format!(
+                    "expected exit status {}, the program has not been run",
+                    code
+                ),
+            ),
+        };
+        self.report_assertion(passed, message);
+    }
+
+    /// `assert-reaches <location>`：本次运行中进程曾在 location 处停下
+    fn assert_reaches(&mut self, location: &str) {
+        let addrs = self.resolve_location(location);
+        if addrs.is_empty() {
+            self.report_assertion(false, format!("location {} not found", location));
+            return;
         }
+        // 行号和函数名按源码行比较：单步停在行首时与断点解析出的地址不一定相同
+        let line_of = |addr: usize| {
+            self.debug_data
+                .get_line_from_addr(addr)
+                .map(|line| (line.file, line.number))
+        };
+        let lines: Vec<(String, usize)> = if location.starts_with('*') {
+            Vec::new()
+        } else {
+            addrs.iter().filter_map(|addr| line_of(*addr)).collect()
+        };
+        let passed = self.reached.iter().any(|addr| {
+            addrs.contains(addr) || line_of(*addr).is_some_and(|line| lines.contains(&line))
+        });
+        let message = if passed {
+            format!("reached {}", location)
+        } else {
+            format!("never stopped at {}", location)
+        };
+        self.report_assertion(passed, message);
     }
 
-    /// 读取源文件并打印指定行号的代码
-    fn print_source(&self, file_path: &str, line_number: usize) {
-        match fs::read_to_string(file_path) {
-            Ok(contents) => {
-                let lines: Vec<&str> = contents.lines().collect();
-                if line_number >= 1 && line_number <= lines.len() {
-                    println!("{:<4} {}", line_number, lines[line_number - 1]);
+    /// 加载 minidump 后打印崩溃概要：线程数、导致崩溃的信号以及崩溃位置
+    fn print_core_info(&mut self) {
+        if let Some(core) = self.core.as_ref() {
+            println!("Loaded minidump with {} threads", core.threads().len());
+            if let Some(crash) = core.crash() {
+                match crash.signal {
+                    Some(signal) => println!(
+                        "Program terminated with signal {} (thread {})",
+                        signal, crash.thread_id
+                    ),
+                    None => println!("Program terminated (thread {})", crash.thread_id),
                 }
+            } else if let Some(thread) = core.threads().first() {
+                println!("No exception stream, showing thread {}", thread.thread_id);
             }
-            Err(_) => {
-                // 无法读取源文件，静默跳过
+            if let Some(ctx) = core.crash_context() {
+                self.print_stopped_info(ctx.rip as usize);
             }
         }
     }
 
-    /// This function prompts the user to enter a command, and continues re-prompting until the user
-    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
-    ///
-    /// You don't need to read, understand, or modify this function.
-    fn get_next_command(&mut self) -> DebuggerCommand {
-        loop {
-            // Print prompt and get next line of user input
-            match self.readline.readline("(kdb) ") {
-                Err(ReadlineError::Interrupted) => {
-                    // User pressed ctrl+c. We're going to ignore it
-                    println!("Type \"quit\" to exit");
-                }
-                Err(ReadlineError::Eof) => {
-                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
-                    return DebuggerCommand::Quit;
-                }
-                Err(err) => {
-                    panic!("Unexpected I/O error: {:?}", err);
-                }
-                Ok(line) => {
-                    if line.trim().len() == 0 {
-                        continue;
-                    }
-                    self.readline.add_history_entry(line.as_str());
-                    if let Err(err) = self.readline.save_history(&self.history_path) {
-                        println!(
-                            "Warning: failed to save history file at {}: {}",
-                            self.history_path, err
-                        );
-                    }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
-                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
-                        return cmd;
-                    } else {
-                        println!("Unrecognized command.");
+    /// 根据进程当前的内存映射更新共享库索引
+    fn refresh_libraries(&mut self) {
+        if let Some(inferior) = self.inferior.as_ref() {
+            if let Ok(maps) = crate::proc_maps::read_maps(inferior.pid()) {
+                self.libraries.refresh(inferior.pid(), &maps, &self.target);
+            }
+        }
+    }
+
+    /// 读取 [addr, addr + len) 的内存，断点处是原始字节而不是 0xcc
+    fn read_original_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = self.inferior.as_ref().unwrap().read_memory(addr, len)?;
+        for bp in self.break_point.iter().filter(|bp| bp.enabled) {
+            if bp.addr >= addr && bp.addr < addr + len {
+                bytes[bp.addr - addr] = bp.orig_byte;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// `x/<count><format><size> <addr>`：地址可以是表达式、寄存器或变量（数组和结构体取它的地址）
+    fn examine_memory(&mut self, spec: &str, addr: Option<&str>) {
+        if self.inferior.is_none() {
+            println!("The program is not being run.");
+            return;
+        }
+        let spec = match Spec::parse(spec, &self.examine.0) {
+            Ok(spec) => spec,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let start = match addr {
+            Some(text) => {
+                // 数组和结构体不能在表达式中求值
+                let addr = match self.evaluate_address(text) {
+                    Err(e) if is_variable_path(text) => self.variable_address(text).map_err(|_| e),
+                    addr => addr,
+                };
+                match addr {
+                    Ok(start) => start,
+                    Err(e) => {
+                        println!("{}", e);
+                        return;
                     }
                 }
             }
+            None => match self.examine.1 {
+                Some(next) => next,
+                None => {
+                    println!("Argument required (starting display address).");
+                    return;
+                }
+            },
+        };
+        let (rows, next) = if spec.format == Format::Str {
+            examine::read_strings(start, spec.count, |addr, len| {
+                self.read_original_memory(addr, len)
+            })
+        } else {
+            // Spec::parse 已经限制了 count * size 的大小
+            let len = spec.count * spec.size;
+            self.read_original_memory(start, len).map(|bytes| {
+                (
+                    examine::format_units(start, &bytes, &spec),
+                    start.wrapping_add(len),
+                )
+            })
+        }
+        .unwrap_or_else(|e| {
+            println!("Cannot access memory at {:#x}: {}", start, e);
+            (Vec::new(), start)
+        });
+        for (addr, text) in rows {
+            println!(
+                "{:#x}{}:\t{}",
+                addr,
+                self.annotate_value(&Value::Int(addr as i64)),
+                text
+            );
         }
+        self.examine = (spec, Some(next));
     }
-}
 
-fn parse_address(addr: &str) -> Option<usize> {
-    let addr_without_0x = if addr.to_lowercase().starts_with("0x") {
-        &addr[2..]
-    } else {
-        &addr
-    };
-    usize::from_str_radix(addr_without_0x, 16).ok()
+    /// 把 [start, end) 的内存写入文件。断点处写出的是原始字节而不是 0xcc
+    fn dump_memory(&self, file: &str, start: usize, end: usize) {
+        if self.inferior.is_none() {
+            println!("No inferior running");
+            return;
+        }
+        let bytes = match self.read_original_memory(start, end - start) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                println!("Error reading memory at {:#x}: {}", start, e);
+                return;
+            }
+        };
+        match fs::write(file, &bytes) {
+            Ok(_) => println!("Wrote {} bytes from {:#x} to {}", bytes.len(), start, file),
+            Err(e) => println!("Error writing {}: {}", file, e),
+        }
+    }
+
+    /// 把文件内容写回 addr 处的内存。覆盖到的断点会记录新的原始字节并重新写入 0xcc
+    fn restore_memory(&mut self, file: &str, addr: usize) {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => {
+                println!("No inferior running");
+                return;
+            }
+        };
+        let mut bytes = match fs::read(file) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                println!("Error reading {}: {}", file, e);
+                return;
+            }
+        };
+        let end = addr + bytes.len();
+        for bp in self.break_point.iter_mut().filter(|bp| bp.enabled) {
+            if bp.addr >= addr && bp.addr < end {
+                bp.orig_byte = bytes[bp.addr - addr];
+                bytes[bp.addr - addr] = 0xcc;
+            }
+        }
+        match inferior.write_memory(addr, &bytes) {
+            Ok(_) => {
+                println!(
+                    "Restored {} bytes from {} at {:#x}",
+                    bytes.len(),
+                    file,
+                    addr
+                );
+                self.patched_ranges.push((addr, end));
+            }
+            Err(e) => println!("Error writing memory at {:#x}: {}", addr, e),
+        }
+    }
+
+    /// 校验内存中的代码节是否与磁盘上的可执行文件一致。
+    /// 断点字节按原始字节比较，restore 写入过的范围不参与比较
+    fn compare_sections(&self) {
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => {
+                println!("No inferior running");
+                return;
+            }
+        };
+        let summary = match ElfSummary::from_file(&self.target) {
+            Ok(summary) => summary,
+            Err(e) => {
+                println!("Could not read ELF file {}", e);
+                return;
+            }
+        };
+        for section in summary.sections.iter().filter(|s| s.is_code()) {
+            let start = section.address as usize + self.debug_data.load_bias();
+            let end = start + section.size as usize;
+            let file_bytes = match summary.read_section(section) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    println!("Section {}: {}", section.name, e);
+                    continue;
+                }
+            };
+            let mut mem_bytes = match inferior.read_memory(start, file_bytes.len()) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    println!("Section {}: error reading memory: {}", section.name, e);
+                    continue;
+                }
+            };
+            for bp in self.break_point.iter().filter(|bp| bp.enabled) {
+                if bp.addr >= start && bp.addr < end {
+                    mem_bytes[bp.addr - start] = bp.orig_byte;
+                }
+            }
+            let mut file_bytes = file_bytes;
+            for (patch_start, patch_end) in &self.patched_ranges {
+                for addr in (*patch_start).max(start)..(*patch_end).min(end) {
+                    mem_bytes[addr - start] = 0;
+                    file_bytes[addr - start] = 0;
+                }
+            }
+
+            let diffs: Vec<usize> = (0..file_bytes.len())
+                .filter(|i| file_bytes[*i] != mem_bytes[*i])
+                .collect();
+            print!(
+                "Section {}, range {:#x} -- {:#x}: ",
+                section.name, start, end
+            );
+            if diffs.is_empty() {
+                println!("matched (hash {:016x}).", fnv1a_hash(&mem_bytes));
+                continue;
+            }
+            println!(
+                "MIS-MATCHED! {} bytes differ (file hash {:016x}, memory hash {:016x})",
+                diffs.len(),
+                fnv1a_hash(&file_bytes),
+                fnv1a_hash(&mem_bytes)
+            );
+            for i in diffs.iter().take(16) {
+                println!(
+                    "    {:#x}: file {:02x}, memory {:02x}",
+                    start + i,
+                    file_bytes[*i],
+                    mem_bytes[*i]
+                );
+            }
+            if diffs.len() > 16 {
+                println!("    ... and {} more", diffs.len() - 16);
+            }
+        }
+    }
+
+    /// 返回目标程序以及（进程运行时）所有已映射共享库的 ELF 信息
+    fn loaded_elf_files(&self) -> Vec<ElfSummary> {
+        let mut files = vec![(self.target.clone(), None)];
+        if let Some(inferior) = self.inferior.as_ref() {
+            match crate::proc_maps::read_maps(inferior.pid()) {
+                Ok(maps) => {
+                    let target = fs::canonicalize(&self.target).ok();
+                    for mapped in crate::proc_maps::mapped_files(&maps) {
+                        let range = Some((mapped.start, mapped.end));
+                        if target.as_deref() == Some(std::path::Path::new(&mapped.path)) {
+                            files[0].1 = range;
+                        } else {
+                            files.push((mapped.path, range));
+                        }
+                    }
+                }
+                Err(e) => println!("Warning: could not read memory map: {}", e),
+            }
+        }
+        let mut summaries = Vec::new();
+        for (path, range) in files {
+            match ElfSummary::from_file(&path) {
+                Ok(mut summary) => {
+                    summary.mapped_range = range;
+                    summaries.push(summary);
+                }
+                Err(e) => println!("Could not read ELF file {}", e),
+            }
+        }
+        summaries
+    }
+
+    /// 打印停止时的位置信息和源代码行
+    fn print_stopped_info(&mut self, rip: usize) {
+        if self.auto_context {
+            self.print_context(rip);
+        } else {
+            let line = self.print_location(rip);
+            // 打印对应的源代码行
+            if let Some(line) = &line {
+                self.print_source(&line.file, line.number);
+            }
+            for index in 0..self.displays.len() {
+                self.print_display(index);
+            }
+        }
+        if self.deltas.is_some() {
+            self.print_deltas(rip);
+        }
+        self.queue_breakpoint_commands();
+        // 与 gdb 相同，hook-stop 在断点的 commands 之前执行
+        queue_lines(&mut self.pending_lines, &self.hook_stop);
+    }
+
+    /// 记录这次停止的快照，列出与上一次停止相比改变了的寄存器和局部变量
+    fn print_deltas(&mut self, rip: usize) {
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => return,
+        };
+        let regs = match inferior.getregs() {
+            Ok(regs) => regs,
+            Err(_) => return,
+        };
+        // 刚命中断点时 rip 位于 int3 之后，按断点地址查找所在的帧和作用域
+        let pc = match inferior.trapped_breakpoint(&self.break_point) {
+            Ok(Some(bp_addr)) => bp_addr,
+            _ => rip,
+        };
+        let frame = self.debug_data.get_function_from_addr(pc).map(|function| {
+            let unwind_regs = UnwindRegs {
+                rip: pc as u64,
+                rsp: regs.rsp,
+                rbp: regs.rbp,
+            };
+            let cfa = self
+                .target_cfi
+                .as_ref()
+                .and_then(|cfi| {
+                    cfi.frame_layout((pc - self.debug_data.load_bias()) as u64, &unwind_regs)
+                })
+                .map(|layout| layout.cfa)
+                .unwrap_or(regs.rbp + 16);
+            (function, cfa)
+        });
+        let locals = self
+            .debug_data
+            .get_local_variables(pc)
+            .iter()
+            .map(|var| {
+                let value = match self.read_selected(&var.name) {
+                    Ok((selected, bytes)) => selected.format_in(&bytes),
+                    Err(e) => e,
+                };
+                (var.name.clone(), value)
+            })
+            .collect();
+        let snapshot = Snapshot::new(&regs, frame, locals);
+        if let Some(Some(previous)) = &self.deltas {
+            for line in snapshot.changes_since(previous) {
+                println!("{}", line);
+            }
+        }
+        self.deltas = Some(Some(snapshot));
+    }
+
+    /// 打印 "Stopped at 函数 文件:行"，返回 rip 所在的行。停在 vDSO 中时标出所在的函数
+    fn print_location(&mut self, rip: usize) -> Option<Line> {
+        let line = self.debug_data.get_line_from_addr(rip);
+        let function = self.debug_data.get_function_from_addr(rip);
+        if let (Some(line), Some(function)) = (&line, function) {
+            println!("Stopped at {} {}", function, line);
+        } else {
+            self.refresh_libraries();
+            match self.libraries.kernel_stub(rip) {
+                Some((area, function)) => {
+                    println!("Stopped at {:#x} <{}: {}>", rip, area, function)
+                }
+                None => println!("Stopped at {:#x}", rip),
+            }
+        }
+        line
+    }
+
+    /// 按 `N: 变量 = 值` 的格式显示第 index 个 display 表达式
+    fn print_display(&self, index: usize) {
+        match self.read_variable(&self.displays[index]) {
+            Ok(value) => println!("{}: {}", index + 1, value),
+            Err(e) => println!("{}: {}", index + 1, e),
+        }
+    }
+
+    /// `info display`：列出 display 表达式和监视点，但不求值。两者都在重新运行后保留，
+    /// 全局变量的监视点按表达式在新的进程中重新解析
+    fn print_display_list(&self) {
+        if self.displays.is_empty() {
+            println!("There are no auto-display expressions now.");
+        } else {
+            println!("Auto-display expressions now in effect:");
+            println!("{:<5}Expression", "Num");
+            for (index, expression) in self.displays.iter().enumerate() {
+                println!("{:<5}{}", index + 1, expression);
+            }
+        }
+        if self.watchpoints.watchpoints.is_empty() {
+            return;
+        }
+        println!("Watchpoints:");
+        println!("{:<5}{:<20}{:<7}Expression", "Num", "Address", "Scope");
+        for watchpoint in &self.watchpoints.watchpoints {
+            println!(
+                "{:<5}{:<#20x}{:<7}{}",
+                watchpoint.id,
+                watchpoint.addr,
+                if watchpoint.scope.is_some() {
+                    "local"
+                } else {
+                    "global"
+                },
+                watchpoint.expression
+            );
+        }
+    }
+
+    /// 显示 `nb` 解析出的断点位置和前后几行源代码，询问是否设置。LLM 给出的行号可能是编造的，
+    /// 映射到的地址不一定在用户想要的地方
+    fn confirm_llm_breakpoint(&mut self, addr: usize) -> bool {
+        let line = self.debug_data.get_line_from_addr(addr);
+        match (&line, self.debug_data.get_function_from_addr(addr)) {
+            (Some(line), Some(function)) => {
+                println!(
+                    "Breakpoint would be set at {:#x}: {} {}",
+                    addr, function, line
+                )
+            }
+            _ => println!("Breakpoint would be set at {:#x}", addr),
+        }
+        if let Some(line) = &line {
+            print_source_around(line);
+        }
+        let confirmed = self.confirm("Set this breakpoint? (y or n) ", false);
+        if !confirmed {
+            println!("Breakpoint not set");
+        }
+        confirmed
+    }
+
+    /// 询问 y/n，直到得到其中一个回答；输入结束（Ctrl+D）时返回 on_eof，Ctrl+C 视为 n
+    fn confirm(&mut self, prompt: &str, on_eof: bool) -> bool {
+        loop {
+            match self.read_line(prompt) {
+                Ok(answer) => match answer.trim() {
+                    "y" | "yes" => return true,
+                    "n" | "no" => return false,
+                    _ => println!("Please answer y or n."),
+                },
+                Err(ReadlineError::Eof) => return on_eof,
+                Err(_) => return false,
+            }
+        }
+    }
+
+    /// `nb --from-bug`：请 LLM 根据错误报告建议断点和监视的变量，逐项显示并询问是否采用
+    fn break_from_bug(&mut self, report: &str) {
+        println!("正在根据错误报告请求断点建议 ...");
+        let suggestions = match crate::llm::suggest_breakpoints(report, &self.debug_data) {
+            Ok(suggestions) if suggestions.is_empty() => {
+                println!("No suggestions");
+                return;
+            }
+            Ok(suggestions) => suggestions,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let count = suggestions.len();
+        for (index, suggestion) in suggestions.into_iter().enumerate() {
+            let (description, reason, addr) = match &suggestion {
+                crate::llm::Suggestion::Break { location, reason } => {
+                    let addr = self.resolve_location(location).first().copied();
+                    let place = match addr.and_then(|addr| self.debug_data.get_line_from_addr(addr))
+                    {
+                        Some(line) => format!(" ({})", line),
+                        None => String::new(),
+                    };
+                    (format!("break {}{}", location, place), reason, addr)
+                }
+                crate::llm::Suggestion::Watch { variable, reason } => {
+                    (format!("watch {}", variable), reason, None)
+                }
+            };
+            println!("[{}/{}] {}", index + 1, count, description);
+            if !reason.is_empty() {
+                println!("      {}", reason);
+            }
+            if let crate::llm::Suggestion::Break { location, .. } = &suggestion {
+                if addr.is_none() {
+                    println!("      Skipped: no code at {}", location);
+                    continue;
+                }
+            }
+            let answer = match self.read_line("Accept? (y, n or q) ") {
+                Ok(answer) => answer,
+                Err(_) => break,
+            };
+            match answer.trim() {
+                "y" | "yes" => match &suggestion {
+                    crate::llm::Suggestion::Break { location, .. } => {
+                        let addrs = self.resolve_location(location);
+                        self.set_breakpoint(&addrs, location, &BreakOptions::default());
+                    }
+                    crate::llm::Suggestion::Watch { variable, .. } => self.watch(variable),
+                },
+                "q" | "quit" => break,
+                _ => {}
+            }
+        }
+    }
+
+    /// `context`：一次显示停止位置、前后几行源代码、常用寄存器、栈顶几帧和所有 display 表达式
+    fn print_context(&mut self, rip: usize) {
+        let line = self.print_location(rip);
+        if let Some(line) = &line {
+            println!("--- source ---");
+            print_source_around(line);
+        }
+
+        if let Some(Ok(mut regs)) = self.inferior.as_ref().map(|i| i.getregs()) {
+            // 刚命中断点时 rip 位于 int3 之后，显示断点本身的地址
+            if let Ok(Some(bp_addr)) = self
+                .inferior
+                .as_ref()
+                .unwrap()
+                .trapped_breakpoint(&self.break_point)
+            {
+                regs.rip = bp_addr as u64;
+            }
+            println!("--- registers ---");
+            for row in CONTEXT_REGISTERS.chunks(3) {
+                let cells: Vec<String> = row
+                    .iter()
+                    .map(|name| {
+                        let value = registers::get(&regs, name).unwrap();
+                        format!(
+                            "{} {:#018x}{}",
+                            name,
+                            value,
+                            self.annotate_value(&Value::Int(value as i64))
+                        )
+                    })
+                    .collect();
+                println!("{}", cells.join("  "));
+            }
+        }
+
+        self.refresh_libraries();
+        if let Some(inferior) = self.inferior.as_ref() {
+            if let Ok(frames) = inferior.backtrace(
+                &self.debug_data,
+                self.target_cfi.as_ref(),
+                &mut self.libraries,
+                &self.break_point,
+                self.frame_arguments,
+            ) {
+                println!("--- stack ---");
+                for (i, frame) in frames.iter().take(CONTEXT_FRAMES).enumerate() {
+                    println!("#{} {}", i, frame);
+                }
+                if frames.len() > CONTEXT_FRAMES {
+                    println!("... {} more", frames.len() - CONTEXT_FRAMES);
+                }
+            }
+        }
+
+        if !self.displays.is_empty() {
+            println!("--- displays ---");
+            for index in 0..self.displays.len() {
+                self.print_display(index);
+            }
+        }
+    }
+
+    /// 读取源文件并打印指定行号的代码
+    fn print_source(&self, file_path: &str, line_number: usize) {
+        match fs::read_to_string(file_path) {
+            Ok(contents) => {
+                let lines: Vec<&str> = contents.lines().collect();
+                if line_number >= 1 && line_number <= lines.len() {
+                    println!("{:<4} {}", line_number, lines[line_number - 1]);
+                }
+            }
+            Err(_) => {
+                // 无法读取源文件，静默跳过
+            }
+        }
+    }
+
+    /// 读取当前作用域中的变量，返回 `name = value (type)`
+    fn read_variable(&self, var_name: &str) -> Result<String, String> {
+        let (text, _) = self.show_variable(var_name)?;
+        Ok(format!("{} = {}", var_name, text))
+    }
+
+    /// 读取变量，返回 `value (type)` 以及它在表达式中的值（只有标量有）
+    fn show_variable(&self, var_name: &str) -> Result<(String, Option<Value>), String> {
+        let (selected, bytes) = self.read_selected(var_name)?;
+        let type_name = &selected.member_type.name;
+        let text = selected
+            .scalar_in(&bytes)
+            .filter(|_| selected.member_type.size <= 8)
+            .and_then(|value| self.plugins.pretty_print(type_name, value))
+            .unwrap_or_else(|| selected.format_in(&bytes));
+        Ok((
+            format!("{} ({})", text, type_name),
+            selected.value_in(&bytes),
+        ))
+    }
+
+    /// `print`：标量结果依次记入值历史，显示为 `$N = ...`，之后的表达式可以用 `$N` 或 `$_` 引用它
+    fn print_value(&mut self, text: &str) {
+        let result = if is_variable_path(text) {
+            self.show_variable(text)
+        } else {
+            crate::expr::parse(text)
+                .and_then(|expr| self.evaluate(&expr))
+                .map(|value| (value.to_string(), Some(value)))
+        };
+        match result {
+            Ok((shown, Some(value))) => {
+                self.history_values.push(value);
+                println!(
+                    "${} = {}{}",
+                    self.history_values.len(),
+                    shown,
+                    self.annotate_value(&value)
+                );
+            }
+            Ok((shown, None)) => println!("{} = {}", text, shown),
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    /// 变量在表达式中的值，只能是标量
+    fn variable_value(&self, var_name: &str) -> Result<Value, String> {
+        let (selected, bytes) = self.read_selected(var_name)?;
+        selected.value_in(&bytes).ok_or_else(|| {
+            format!(
+                "'{}' has type {}, which cannot be used in an expression",
+                var_name, selected.member_type.name
+            )
+        })
+    }
+
+    /// 在当前停止位置求表达式的值
+    fn evaluate(&self, expr: &Expr) -> Result<Value, String> {
+        expr.evaluate(self)
+    }
+
+    /// 表达式中 `$` 开头的名字：值历史（`$N`、`$_`）、寄存器或便利变量。停在断点上时 `$rip` / `$pc`
+    /// 是断点的地址；选中了外层帧时 rip、rsp、rbp 取该帧回溯出的值
+    fn dollar_value(&self, name: &str) -> Result<Value, String> {
+        if name == "_" {
+            return self
+                .history_values
+                .last()
+                .copied()
+                .ok_or_else(|| "History is empty".to_string());
+        }
+        if let Ok(index) = name.parse::<usize>() {
+            return index
+                .checked_sub(1)
+                .and_then(|index| self.history_values.get(index))
+                .copied()
+                .ok_or_else(|| format!("History has no value ${}", index));
+        }
+        if !registers::is_register(name) {
+            return self
+                .convenience
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("Unknown register or convenience variable '${}'", name));
+        }
+        let regs = self.frame_registers()?;
+        Ok(Value::Int(registers::get(&regs, name).unwrap() as i64))
+    }
+
+    /// 用户看到的寄存器：停在断点上时 rip 是断点的地址；选中了外层帧时 rip、rsp、rbp 取该帧回溯出的值
+    fn frame_registers(&self) -> Result<nix::libc::user_regs_struct, String> {
+        let inferior = self
+            .inferior
+            .as_ref()
+            .ok_or_else(|| "No inferior running".to_string())?;
+        let mut regs = inferior.getregs().map_err(|e| e.to_string())?;
+        match self.selected_frame {
+            Some((_, frame)) => {
+                regs.rip = frame.rip;
+                regs.rsp = frame.rsp;
+                regs.rbp = frame.rbp;
+            }
+            None => {
+                if let Ok(Some(bp_addr)) = inferior.trapped_breakpoint(&self.break_point) {
+                    regs.rip = bp_addr as u64;
+                }
+            }
+        }
+        Ok(regs)
+    }
+
+    /// `info registers [<name>...]`：不带参数时显示全部通用寄存器、rip、eflags 和段寄存器
+    fn print_registers(&self, names: &[String]) {
+        let regs = match self.frame_registers() {
+            Ok(regs) => regs,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let names: Vec<&str> = if names.is_empty() {
+            registers::DISPLAYED.to_vec()
+        } else {
+            names
+                .iter()
+                .map(|name| name.trim_start_matches('$'))
+                .collect()
+        };
+        for name in names {
+            let value = match registers::get(&regs, name) {
+                Some(value) => value,
+                None => {
+                    println!("Invalid register '{}'", name);
+                    continue;
+                }
+            };
+            // 与 gdb 相同：地址寄存器附加所在的函数，eflags 显示置位的标志，其它显示有符号十进制
+            let natural = match name {
+                "rip" | "pc" | "rsp" | "sp" | "rbp" | "fp" => format!(
+                    "{:#x}{}",
+                    value,
+                    self.annotate_value(&Value::Int(value as i64))
+                ),
+                "eflags" => registers::format_eflags(value),
+                _ => (value as i64).to_string(),
+            };
+            println!("{:<15}{:<19}{}", name, format!("{:#x}", value), natural);
+        }
+    }
+
+    /// `set reg <name> <value>` / `set $<name> = <value>`：修改最内层帧的寄存器，之后继续运行时生效
+    fn set_register(&mut self, name: &str, text: &str) {
+        if self.inferior.is_none() {
+            println!("The program is not being run.");
+            return;
+        }
+        if !registers::is_register(name) {
+            println!("Invalid register '{}'", name);
+            return;
+        }
+        if let Some((level, _)) = self.selected_frame {
+            println!(
+                "Frame {} is selected; registers can only be changed in frame 0",
+                level
+            );
+            return;
+        }
+        let value = match crate::expr::parse(text).and_then(|expr| self.evaluate(&expr)) {
+            Ok(Value::Int(value)) => value as u64,
+            Ok(Value::Float(_)) => {
+                println!("${} is an integer register", name);
+                return;
+            }
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let inferior = self.inferior.as_mut().unwrap();
+        // 停在断点上时 rip 按惯例位于 int3 之后，继续运行时先执行断点处原来的指令。
+        // 跳到另一个断点时保持这个惯例，否则会执行它的 int3 或从指令中间开始执行
+        let trapped = matches!(inferior.trapped_breakpoint(&self.break_point), Ok(Some(_)));
+        let raw = if matches!(name, "rip" | "pc")
+            && trapped
+            && self.break_point.get_enabled(value as usize).is_some()
+        {
+            value + 1
+        } else {
+            value
+        };
+        let result = inferior.getregs().and_then(|mut regs| {
+            registers::set(&mut regs, name, raw);
+            inferior.setregs(regs)
+        });
+        match result {
+            Ok(()) => {
+                println!("${} = {:#x}", name, value);
+                self.timeline.event(format!("set ${} = {:#x}", name, value));
+            }
+            Err(e) => println!("Error setting ${}: {}", name, e),
+        }
+    }
+
+    /// 表达式中 `&变量` 的值：变量（或选中的成员）的起始地址
+    fn variable_address(&self, var_name: &str) -> Result<usize, String> {
+        match self.locate_selected(var_name)? {
+            (selected, _) if selected.bit_size.is_some() => Err(format!(
+                "Cannot take the address of bit-field '{}'",
+                var_name
+            )),
+            (selected, VariablePlace::Memory(addr, _)) => Ok(addr + selected.bit_position / 8),
+            (_, VariablePlace::Value(_)) => Err(format!("'{}' is not in memory", var_name)),
+        }
+    }
+
+    /// 命令中的地址参数：十六进制数（可以省略 `0x`）或表达式，例如 `$rsp+0x40`、`&buf + i*8`
+    fn evaluate_address(&self, text: &str) -> Result<usize, String> {
+        if let Some(addr) = parse_address(text) {
+            return Ok(addr);
+        }
+        match self.evaluate(&crate::expr::parse(text)?)? {
+            Value::Int(addr) => Ok(addr as usize),
+            Value::Float(_) => Err(format!("Address '{}' is not an integer", text)),
+        }
+    }
+
+    /// 命令中的长度参数：十进制或 `0x` 开头的十六进制数，或表达式，例如 `n * 8`
+    fn evaluate_length(&self, text: &str) -> Result<usize, String> {
+        if let Some(len) = parse_length(text) {
+            return Ok(len);
+        }
+        match self.evaluate(&crate::expr::parse(text)?)? {
+            Value::Int(len) if len >= 0 => Ok(len as usize),
+            _ => Err(format!("Length '{}' is not a non-negative integer", text)),
+        }
+    }
+
+    /// 找到 var_name（可带 `file.c::` 前缀和 `.成员` 路径）选中的部分，返回它以及所在变量的全部字节
+    fn read_selected(&self, var_name: &str) -> Result<(Member, Vec<u8>), String> {
+        let (selected, place) = self.locate_selected(var_name)?;
+        match place {
+            VariablePlace::Memory(addr, size) => {
+                match self.inferior.as_ref().unwrap().read_memory(addr, size) {
+                    Ok(bytes) => Ok((selected, bytes)),
+                    Err(e) => Err(format!("Error reading variable '{}': {}", var_name, e)),
+                }
+            }
+            VariablePlace::Value(bytes) => Ok((selected, bytes)),
+        }
+    }
+
+    /// 与 read_selected 相同，但返回所在变量的位置而不读取它
+    fn locate_selected(&self, var_name: &str) -> Result<(Member, VariablePlace), String> {
+        use crate::dwarf_data::Location;
+        let inferior = self
+            .inferior
+            .as_ref()
+            .ok_or_else(|| "No inferior running".to_string())?;
+        let regs = inferior.getregs().unwrap();
+        // 选中了外层帧时按它回溯出的寄存器读取；它的 rip 是返回地址，作用域和位置列表按 call 指令内的
+        // 地址查找。否则停在断点上时 rip 已越过 int3，按断点地址查找
+        let (rip, frame) = match self.selected_frame {
+            Some((_, frame)) => (frame.rip as usize - 1, frame),
+            None => {
+                let rip = match inferior.trapped_breakpoint(&self.break_point) {
+                    Ok(Some(bp_addr)) => bp_addr,
+                    _ => regs.rip as usize,
+                };
+                let frame = UnwindRegs {
+                    rip: rip as u64,
+                    rsp: regs.rsp,
+                    rbp: regs.rbp,
+                };
+                (rip, frame)
+            }
+        };
+        // 位置列表和 CFI 使用文件中的地址
+        let bias = self.debug_data.load_bias();
+        let register = |register| match self.selected_frame {
+            Some(_) => unwind_register(&frame, register),
+            None => inferior.dwarf_register(register),
+        };
+
+        // file.c::counter：区分不同编译单元中同名的 static 变量
+        let (file, path) = match var_name.rsplit_once("::") {
+            Some((file, path)) => (Some(file), path),
+            None => (None, var_name),
+        };
+        // var.member.member：逐级选择结构体或联合的成员
+        let mut path = path.split('.');
+        let base_name = path.next().unwrap_or_default();
+        let var = match file {
+            Some(file) => self
+                .debug_data
+                .get_file_variable(file, base_name)
+                .ok_or_else(|| format!("No variable '{}' in file {}", base_name, file))?,
+            None => self
+                .debug_data
+                .get_variable_by_name(rip, base_name)
+                .ok_or_else(|| format!("Variable '{}' not found in current scope", base_name))?,
+        };
+        let mut selected = Member::whole(&var.entity_type);
+        for member_name in path {
+            let member = selected
+                .member_type
+                .find_member(member_name)
+                .ok_or_else(|| {
+                    format!(
+                        "There is no member named {} in {}",
+                        member_name, selected.member_type.name
+                    )
+                })?;
+            selected = Member {
+                bit_position: selected.bit_position + member.bit_position,
+                ..member
+            };
+        }
+        let location = match &var.location {
+            Location::List(entries) => {
+                match entries.iter().find(|entry| entry.covers(rip - bias)) {
+                    Some(entry) => entry.location.as_ref().ok_or_else(|| {
+                        format!(
+                            "Variable '{}' has an unsupported location expression at {:#x}",
+                            var_name, rip
+                        )
+                    })?,
+                    None => {
+                        let ranges: Vec<String> = entries
+                            .iter()
+                            .filter(|entry| !matches!(entry.location, Some(Location::OptimizedOut)))
+                            .map(|entry| {
+                                format!("{:#x}-{:#x}", entry.begin + bias, entry.end + bias)
+                            })
+                            .collect();
+                        return Err(if ranges.is_empty() {
+                            format!("{} = <optimized out>", var_name)
+                        } else {
+                            format!(
+                                "{} = <optimized out> (available at {})",
+                                var_name,
+                                ranges.join(", ")
+                            )
+                        });
+                    }
+                }
+            }
+            location => location,
+        };
+        // 大小未知的类型按一个 word 读取
+        if selected.member_type.size == 0 {
+            selected.member_type.size = 8;
+        }
+        let size = var.entity_type.size.max(selected.member_type.size);
+        // DW_OP_fbreg 基于 CFA：优先用 CFI 计算，没有 CFI 时按 rbp + 16
+        let caller = self.target_cfi.as_ref().map(|cfi| {
+            cfi.unwind((rip - bias) as u64, &frame, &|addr| {
+                inferior.read_word(addr)
+            })
+        });
+        let cfa = match caller {
+            Some(Ok(Some(caller))) => caller.rsp,
+            _ => frame.rbp + 16,
+        };
+        let addr = match location {
+            Location::Address(a) => *a + bias,
+            Location::FramePointerOffset(offset) => (cfa as i64 + *offset as i64) as usize,
+            Location::Register(number) => {
+                let mut bytes = register(*number).ok_or_else(|| match self.selected_frame {
+                    Some((level, _)) => format!(
+                        "Variable '{}' is in register {}, which is not saved in frame {}",
+                        var_name, number, level
+                    ),
+                    None => format!(
+                        "Variable '{}' is in register {}, which is not supported",
+                        var_name, number
+                    ),
+                })?;
+                bytes.resize(size, 0);
+                return Ok((selected, VariablePlace::Value(bytes)));
+            }
+            Location::Expression(expression, encoding) => {
+                let ctx = ExpressionContext {
+                    register: &register,
+                    read_memory: &|addr, len| inferior.read_memory(addr, len).ok(),
+                    cfa,
+                };
+                return match evaluate_location(expression, *encoding, size, &ctx) {
+                    Ok(Some(bytes)) => Ok((selected, VariablePlace::Value(bytes))),
+                    Ok(None) => Err(format!("{} = <optimized out>", var_name)),
+                    Err(e) => Err(format!("Error reading variable '{}': {}", var_name, e)),
+                };
+            }
+            Location::TlsOffset(offset) => {
+                // 只处理可执行文件自己的 TLS 块；fs_base 是当前线程的线程指针
+                let block = self.debug_data.tls_offset().ok_or_else(|| {
+                    format!(
+                        "Variable '{}' is thread-local but the target has no TLS block",
+                        var_name
+                    )
+                })?;
+                (regs.fs_base as usize).wrapping_sub(block) + offset
+            }
+            Location::OptimizedOut => return Err(format!("{} = <optimized out>", var_name)),
+            // 位置列表的项本身不会是位置列表
+            Location::List(_) => {
+                return Err(format!("Variable '{}' has an invalid location", var_name))
+            }
+        };
+        Ok((selected, VariablePlace::Memory(addr, size)))
+    }
+
+    /// `until-expr`：逐行执行（越过函数调用），直到表达式为真。途中命中断点、收到信号或进程结束时停下
+    fn until_expression(&mut self, text: &str) {
+        let expr = match crate::expr::parse(text) {
+            Ok(expr) => expr,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        if self.inferior.is_none() {
+            println!("The program is not being run.");
+            return;
+        }
+        // 开始时就必须能求值，否则拼错的变量名会让进程一直运行下去
+        match self.evaluate(&expr) {
+            Ok(value) if value.is_true() => {
+                println!("'{}' is already true", text);
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
+
+        self.timeline.resumed("until-expr");
+        let mut steps = 0;
+        let status = loop {
+            let status = self.step_to_next_line();
+            steps += 1;
+            match status {
+                Ok(Status::Stopped(signal::Signal::SIGTRAP, _)) if !self.at_breakpoint() => {
+                    // 离开变量所在的作用域时求值失败，当作条件不成立继续执行
+                    if self.evaluate(&expr).map(|v| v.is_true()).unwrap_or(false) {
+                        println!("'{}' became true after {} line(s)", text, steps);
+                        break status;
+                    }
+                }
+                Ok(Status::Exec(_)) => {
+                    self.handle_exec();
+                    let status = self.continue_inferior();
+                    self.report_status(status);
+                    return;
+                }
+                status => break status,
+            }
+        };
+        self.record_step_hit(&status);
+        self.record_stop(&status);
+        match status {
+            Ok(Status::Stopped(signal::Signal::SIGTRAP, rip)) if !self.at_breakpoint() => {
+                self.print_stopped_info(rip)
+            }
+            Err(e) => println!("Error stepping inferior: {}", e),
+            status => self.report_status(status),
+        }
+    }
+
+    /// `who-writes`：在变量上设置硬件写监视点并继续运行，每次写入时记录写入位置和调用栈后自动继续，
+    /// 进程因别的原因停下（断点、信号、退出）时撤掉监视点，按调用栈汇总所有写入者
+    fn who_writes(&mut self, var_name: &str) {
+        if self.inferior.is_none() {
+            println!("The program is not being run.");
+            return;
+        }
+        let (selected, var_addr, var_size) = match self.locate_selected(var_name) {
+            Ok((selected, VariablePlace::Memory(addr, size))) => (selected, addr, size),
+            Ok((_, VariablePlace::Value(_))) => {
+                println!("'{}' is not in memory and cannot be watched", var_name);
+                return;
+            }
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let (start, len) = watched_bytes(&selected, var_addr);
+        let ranges = watch_ranges(start, len);
+        // `watch` 占用的调试寄存器不能动
+        let free = self.watchpoints.free_slots();
+        if ranges.len() > free.len() {
+            println!(
+                "'{}' is too large to watch ({} bytes need {} debug registers, {} available)",
+                var_name,
+                len,
+                ranges.len(),
+                free.len()
+            );
+            return;
+        }
+        let slots = &free[..ranges.len()];
+        let inferior = self.inferior.as_mut().unwrap();
+        for (i, (addr, size)) in ranges.iter().enumerate() {
+            if let Err(e) = inferior.set_write_watch(slots[i], *addr, *size) {
+                println!("Error setting watchpoint on {}: {}", var_name, e);
+                for slot in &slots[..i] {
+                    let _ = inferior.clear_watch(*slot);
+                }
+                return;
+            }
+        }
+        println!(
+            "Watching writes to {} ({} bytes at {:#x}), continuing until the program stops",
+            var_name, len, start
+        );
+
+        let read_value = |inferior: &Inferior| {
+            inferior
+                .read_memory(var_addr, var_size)
+                .map(|bytes| selected.format_in(&bytes))
+                .unwrap_or_else(|_| "<unreadable>".to_string())
+        };
+        let mut old_value = read_value(self.inferior.as_ref().unwrap());
+        // 按调用栈汇总：(各帧的描述, 写入次数)
+        let mut writers: Vec<(Vec<String>, usize)> = Vec::new();
+        let mut writes = 0;
+        let status = loop {
+            let status = self.resume_inferior();
+            let hit = match (&status, self.inferior.as_mut()) {
+                (Ok(Status::Stopped(signal::Signal::SIGTRAP, _)), Some(inferior)) => {
+                    inferior.triggered_watch(slots).ok().flatten().is_some()
+                }
+                _ => false,
+            };
+            if !hit {
+                break status;
+            }
+            writes += 1;
+            self.refresh_libraries();
+            let inferior = self.inferior.as_ref().unwrap();
+            let new_value = read_value(inferior);
+            // 监视点在写入指令执行之后触发，rip 是下一条指令
+            let frames: Vec<String> = inferior
+                .backtrace(
+                    &self.debug_data,
+                    self.target_cfi.as_ref(),
+                    &mut self.libraries,
+                    &self.break_point,
+                    FrameArguments::None,
+                )
+                .map(|frames| frames.iter().map(|frame| frame.to_string()).collect())
+                .unwrap_or_default();
+            println!(
+                "Write {}: {} -> {} at {}",
+                writes,
+                old_value,
+                new_value,
+                frames.first().map(String::as_str).unwrap_or("??")
+            );
+            old_value = new_value;
+            match writers.iter_mut().find(|(stack, _)| *stack == frames) {
+                Some((_, count)) => *count += 1,
+                None => writers.push((frames, 1)),
+            }
+        };
+        if let Some(inferior) = self.inferior.as_mut() {
+            for slot in slots {
+                let _ = inferior.clear_watch(*slot);
+            }
+        }
+
+        println!(
+            "{} write(s) to {} from {} code path(s):",
+            writes,
+            var_name,
+            writers.len()
+        );
+        for (stack, count) in &writers {
+            println!("  {} time(s):", count);
+            for frame in stack {
+                println!("    {}", frame);
+            }
+        }
+        self.report_status(status);
+    }
+
+    /// `watch <var>`：在变量上设置硬件写监视点，之后每次运行到它的值改变时停下，显示旧值和新值。
+    /// 选中的帧中的局部变量在这个帧返回时删除监视点
+    fn watch(&mut self, var_name: &str) {
+        if self.inferior.is_none() {
+            println!("The program is not being run.");
+            return;
+        }
+        let (selected, var_addr, var_size) = match self.locate_selected(var_name) {
+            Ok((selected, VariablePlace::Memory(addr, size))) => (selected, addr, size),
+            Ok((_, VariablePlace::Value(_))) => {
+                println!("'{}' is not in memory and cannot be watched", var_name);
+                return;
+            }
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let (addr, len) = watched_bytes(&selected, var_addr);
+        let needed = watch_ranges(addr, len).len();
+        let available = self.watchpoints.free_slots().len();
+        if needed > available {
+            println!(
+                "Cannot watch '{}': {} bytes need {} debug registers, {} available",
+                var_name, len, needed, available
+            );
+            return;
+        }
+        let scope = match self.watch_scope(addr) {
+            Ok(scope) => scope,
+            Err(e) => {
+                println!("Error finding the frame of {}: {}", var_name, e);
+                return;
+            }
+        };
+        let inferior = self.inferior.as_mut().unwrap();
+        let value = match inferior.read_memory(var_addr, var_size) {
+            Ok(value) => value,
+            Err(e) => {
+                println!("Error reading variable '{}': {}", var_name, e);
+                return;
+            }
+        };
+        let id = self.break_point.allocate_id();
+        let watchpoint = Watchpoint {
+            id,
+            expression: var_name.to_string(),
+            member: selected,
+            var_addr,
+            var_size,
+            addr,
+            len,
+            slots: Vec::new(),
+            value,
+            hits: 0,
+            scope,
+        };
+        match self.watchpoints.insert(inferior, watchpoint) {
+            Ok(()) => println!("Hardware watchpoint {}: {}", id, var_name),
+            Err(e) => println!("Cannot watch '{}': {}", var_name, e),
+        }
+    }
+
+    /// addr 在选中的帧的栈上时返回这个帧的 CFA 和返回地址，否则（全局或 static 变量）返回 None
+    fn watch_scope(&self, addr: usize) -> Result<Option<WatchScope>, nix::Error> {
+        let inferior = self.inferior.as_ref().unwrap();
+        let regs = match self.selected_frame {
+            Some((_, regs)) => regs,
+            None => inferior.unwind_regs(&self.break_point)?,
+        };
+        let cfa = self
+            .target_cfi
+            .as_ref()
+            .and_then(|cfi| cfi.frame_layout(regs.rip - self.debug_data.load_bias() as u64, &regs))
+            .map(|layout| layout.cfa)
+            .unwrap_or(regs.rbp + 16) as usize;
+        // 叶函数的局部变量可以放在 rsp 之下 128 字节的红区中
+        let stack_bottom = (regs.rsp as usize).saturating_sub(RED_ZONE);
+        if addr < stack_bottom || addr >= cfa {
+            return Ok(None);
+        }
+        let return_addr = inferior.read_word(cfa - 8)? as usize;
+        Ok(Some(WatchScope::new(cfa, return_addr)))
+    }
+
+    /// 删除编号在 ids 中的监视点，ids 为空时删除全部
+    fn delete_watchpoints(&mut self, ids: &[usize]) {
+        let all: Vec<usize> = self
+            .watchpoints
+            .watchpoints
+            .iter()
+            .map(|watchpoint| watchpoint.id)
+            .filter(|id| ids.is_empty() || ids.contains(id))
+            .collect();
+        for id in all {
+            self.watchpoints.remove(self.inferior.as_mut(), id);
+        }
+    }
+
+    /// 监视点不能单独启用或禁用，对其中的监视点编号给出提示
+    fn explain_watchpoint_toggle(&self, ids: &[usize]) {
+        for id in ids {
+            if self.watchpoints.find_id(*id).is_some() {
+                println!(
+                    "Watchpoint {} cannot be enabled or disabled; use `delete {}`",
+                    id, id
+                );
+            }
+        }
+    }
+
+    /// 新的进程或 exec 之后的新程序：全局变量的监视点按表达式重新解析地址，程序重新编译或换成了
+    /// 别的程序时变量可能已经移动；解析不到时删除监视点。局部变量的帧已经不存在，它们的监视点也被删除。
+    /// 之后由 `rearm_watchpoints` 写入调试寄存器
+    fn resolve_watchpoints(&mut self) {
+        for mut watchpoint in std::mem::take(&mut self.watchpoints.watchpoints) {
+            let id = watchpoint.id;
+            if watchpoint.scope.is_some() {
+                println!(
+                    "Watchpoint {} deleted: the frame of {} no longer exists",
+                    id, watchpoint.expression
+                );
+                continue;
+            }
+            match self.locate_selected(&watchpoint.expression) {
+                Ok((selected, VariablePlace::Memory(var_addr, var_size))) => {
+                    let (addr, len) = watched_bytes(&selected, var_addr);
+                    watchpoint.member = selected;
+                    watchpoint.var_addr = var_addr;
+                    watchpoint.var_size = var_size;
+                    watchpoint.addr = addr;
+                    watchpoint.len = len;
+                    self.watchpoints.watchpoints.push(watchpoint);
+                }
+                Ok((_, VariablePlace::Value(_))) => println!(
+                    "Watchpoint {} deleted: '{}' is not in memory",
+                    id, watchpoint.expression
+                ),
+                Err(e) => println!("Watchpoint {} deleted: {}", id, e),
+            }
+        }
+    }
+
+    /// 新的被调试进程：全局变量的监视点重新读取初始值并写入调试寄存器
+    fn rearm_watchpoints(&mut self) {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => return,
+        };
+        for mut watchpoint in std::mem::take(&mut self.watchpoints.watchpoints) {
+            watchpoint.slots.clear();
+            watchpoint.hits = 0;
+            watchpoint.value = inferior
+                .read_memory(watchpoint.var_addr, watchpoint.var_size)
+                .unwrap_or_default();
+            let id = watchpoint.id;
+            if let Err(e) = self.watchpoints.insert(inferior, watchpoint) {
+                println!("Watchpoint {} deleted: {}", id, e);
+            }
+        }
+    }
+
+    /// 处理监视点引起的 SIGTRAP（rip 是停止时的 rip）。返回 None 时调用者应该继续运行：
+    /// 硬件监视点触发但值没有改变（写入了相同的值），或者停在返回地址的 int3 上但帧还没有返回
+    /// （递归调用中更深的一层返回）。否则返回停止位置，停在 int3 上时已回退到 int3 的地址
+    fn watch_trap(&mut self, rip: usize) -> Result<Option<usize>, nix::Error> {
+        if self.watchpoints.watchpoints.is_empty() {
+            return Ok(Some(rip));
+        }
+        let slots = self.watchpoints.used_slots();
+        let inferior = self.inferior.as_mut().unwrap();
+        if inferior.triggered_watch(&slots)?.is_some() {
+            let changed = self.watchpoints.watchpoints.iter().any(|watchpoint| {
+                inferior
+                    .read_memory(watchpoint.var_addr, watchpoint.var_size)
+                    .is_ok_and(|value| value != watchpoint.value)
+            });
+            return Ok(changed.then_some(rip));
+        }
+        let addr = rip - 1;
+        let orig_byte = match self.watchpoints.armed_at(addr) {
+            Some(orig_byte) => orig_byte,
+            None => return Ok(Some(rip)),
+        };
+        let mut regs = inferior.getregs()?;
+        regs.rip = addr as u64;
+        inferior.setregs(regs)?;
+        let left = self.watchpoints.watchpoints.iter().any(|watchpoint| {
+            watchpoint.scope.as_ref().is_some_and(|scope| {
+                scope.return_addr == addr && regs.rsp as usize >= scope.frame_cfa
+            })
+        });
+        if left {
+            // 由 record_stop 删除离开作用域的监视点
+            return Ok(Some(addr));
+        }
+        // 临时恢复原指令，单步越过后再写回 int3
+        inferior.write_byte(addr, orig_byte)?;
+        let status = inferior.step(None);
+        let status = skip_quiet_signals(inferior, &self.signals, status, true)?;
+        inferior.write_byte(addr, 0xcc)?;
+        match status {
+            Status::Stopped(signal::Signal::SIGTRAP, _) => Ok(None),
+            _ => Ok(Some(addr)),
+        }
+    }
+
+    /// 每次停止时重新读取监视点的值，打印改变了的监视点
+    fn report_watchpoints(&mut self) {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) if !self.watchpoints.watchpoints.is_empty() => inferior,
+            _ => return,
+        };
+        // 单步期间触发的记录已经没有用了
+        let _ = inferior.triggered_watch(&self.watchpoints.used_slots());
+        for watchpoint in self.watchpoints.watchpoints.iter_mut() {
+            let value = match inferior.read_memory(watchpoint.var_addr, watchpoint.var_size) {
+                Ok(value) if value != watchpoint.value => value,
+                _ => continue,
+            };
+            watchpoint.hits += 1;
+            println!();
+            println!(
+                "Hardware watchpoint {}: {}",
+                watchpoint.id, watchpoint.expression
+            );
+            println!();
+            println!("Old value = {}", watchpoint.format(&watchpoint.value));
+            println!("New value = {}", watchpoint.format(&value));
+            watchpoint.value = value;
+        }
+    }
+
+    /// 删除已经离开作用域的局部变量监视点：所在的帧已经返回（rsp 回到了 CFA 之上），或者进程已经结束
+    fn expire_watchpoints(&mut self) {
+        let rsp = self
+            .inferior
+            .as_ref()
+            .and_then(|inferior| inferior.getregs().ok())
+            .map(|regs| regs.rsp as usize);
+        let expired: Vec<usize> = self
+            .watchpoints
+            .watchpoints
+            .iter()
+            .filter(|watchpoint| {
+                watchpoint
+                    .scope
+                    .as_ref()
+                    .is_some_and(|scope| rsp.is_none_or(|rsp| rsp >= scope.frame_cfa))
+            })
+            .map(|watchpoint| watchpoint.id)
+            .collect();
+        for id in expired {
+            self.watchpoints.remove(self.inferior.as_mut(), id);
+            println!();
+            println!(
+                "Watchpoint {} deleted because the program has left the block in\nwhich its expression is valid.",
+                id
+            );
+        }
+    }
+
+    /// `info frame`：最内层帧的 CFA、返回地址、保存的寄存器和所在函数的地址区间。
+    /// 没有覆盖当前地址的 CFI 时按帧指针布局（CFA = rbp + 16）推算
+    fn print_frame_info(&mut self) -> Result<(), nix::Error> {
+        let inferior = self.inferior.as_ref().unwrap();
+        let (level, regs) = match self.selected_frame {
+            Some(selected) => selected,
+            None => (0, inferior.unwind_regs(&self.break_point)?),
+        };
+        let read_word = |addr| inferior.read_word(addr);
+        let cfi = self.target_cfi.as_ref();
+        let bias = self.debug_data.load_bias() as u64;
+        let layout = cfi.and_then(|cfi| cfi.frame_layout(regs.rip - bias, &regs));
+        let from_cfi = layout.is_some();
+        let layout = layout.unwrap_or_else(|| FrameLayout {
+            cfa: regs.rbp + 16,
+            saved: vec![("rbp", regs.rbp), ("rip", regs.rbp + 8)],
+        });
+        let saved_rip = layout
+            .saved
+            .iter()
+            .find(|(name, _)| *name == "rip")
+            .map(|(_, addr)| read_word(*addr as usize))
+            .transpose()?;
+
+        println!("Stack level {}, frame at {:#x}:", level, layout.cfa);
+        let location = match (
+            self.debug_data.get_function_from_addr(regs.rip as usize),
+            self.debug_data.get_line_from_addr(regs.rip as usize),
+        ) {
+            (Some(function), Some(line)) => format!(" in {} ({})", function, line),
+            (Some(function), None) => format!(" in {}", function),
+            _ => String::new(),
+        };
+        match saved_rip {
+            Some(saved_rip) => println!(
+                " rip = {:#x}{}; saved rip = {:#x}{}",
+                regs.rip,
+                location,
+                saved_rip,
+                self.annotate_value(&Value::Int(saved_rip as i64))
+            ),
+            None => println!(" rip = {:#x}{}", regs.rip, location),
+        }
+        // 调用者的 CFA：按 CFI 恢复调用者的寄存器后，用返回地址所在的 call 指令再查一次
+        let caller = match cfi {
+            Some(cfi) => cfi.unwind(regs.rip - bias, &regs, &read_word)?,
+            None => None,
+        };
+        if let Some(caller_cfa) = caller
+            .filter(|caller| caller.rip != 0)
+            .and_then(|caller| cfi?.frame_layout(caller.rip - 1 - bias, &caller))
+            .map(|caller_layout| caller_layout.cfa)
+        {
+            println!(" called by frame at {:#x}", caller_cfa);
+        }
+        if let Some((start, end)) = self.debug_data.get_function_range(regs.rip as usize) {
+            println!(" function range {:#x}-{:#x}", start, end);
+        }
+        println!(
+            " stack {:#x}-{:#x} ({} bytes), rbp = {:#x}",
+            regs.rsp,
+            layout.cfa,
+            layout.cfa.saturating_sub(regs.rsp),
+            regs.rbp
+        );
+        if !from_cfi {
+            println!(" (no CFI for this address, assuming a frame pointer layout)");
+        }
+        println!(" Saved registers:");
+        let saved: Vec<String> = layout
+            .saved
+            .iter()
+            .map(|(name, addr)| format!("{} at {:#x}", name, addr))
+            .collect();
+        println!("  {}", saved.join(", "));
+        Ok(())
+    }
+
+    /// 打印被调试进程当前的调用栈
+    fn print_inferior_backtrace(&mut self) {
+        self.refresh_libraries();
+        if let Some(inferior) = self.inferior.as_mut() {
+            if let Err(e) = inferior.print_backtrace(
+                &self.debug_data,
+                self.target_cfi.as_ref(),
+                &mut self.libraries,
+                &self.break_point,
+                self.frame_arguments,
+            ) {
+                println!("Error printing backtrace: {}", e);
+            }
+        }
+    }
+
+    /// `run` 的启动部分：记录参数和运行次数后启动被调试进程，成功时返回 true。
+    /// 不带参数时沿用上一次的参数
+    fn start_run(&mut self, args: Vec<String>) -> bool {
+        let args = if args.is_empty() {
+            self.run_args.clone()
+        } else {
+            args
+        };
+        self.run_args = args.clone();
+        self.run_count += 1;
+        let command_line = std::iter::once(self.program_path())
+            .chain(args.iter().cloned())
+            .collect::<Vec<String>>()
+            .join(" ");
+        println!(
+            "Starting program: {} (run #{})",
+            command_line, self.run_count
+        );
+        self.start_inferior(&args)
+    }
+
+    /// 启动新的被调试进程（已有进程会先被杀掉），成功时返回 true
+    fn start_inferior(&mut self, args: &Vec<String>) -> bool {
+        self.reset_inferior();
+        self.print_cwd();
+        self.timeline
+            .event(format!("run {}", args.join(" ")).trim().to_string());
+        let target = self.program_path();
+        self.print_launch_options();
+        match Inferior::new(&target, args, self.cwd.as_deref(), &self.launch) {
+            Some(mut inferior) => {
+                inferior.set_syscall_tracer(self.syscall_tracer());
+                inferior.set_net_tracking(self.net_tracking);
+                inferior.set_follow_fork(self.follow_fork);
+                inferior.set_timer_interrupt(self.monitors.interrupt_flag());
+                self.inferior = Some(inferior);
+                let bias = self.target_load_bias();
+                self.apply_load_bias(bias);
+                self.faults
+                    .reset(entry_point(&target).map(|entry| entry.wrapping_add(bias)));
+                self.inferior
+                    .as_mut()
+                    .unwrap()
+                    .arm_breakpoints(&mut self.break_point);
+                self.resolve_watchpoints();
+                self.rearm_watchpoints();
+                true
+            }
+            None => {
+                println!("Error starting subprocess");
+                false
+            }
+        }
+    }
+
+    /// 开始记录系统调用，已经在记录时替换原来的设置。指定了文件时先清空它
+    fn trace_syscalls(&mut self, filter: Vec<String>, output: Option<String>) {
+        if self.under_valgrind {
+            println!("trace syscalls is not supported under valgrind");
+            return;
+        }
+        if let Some(name) = filter.iter().find(|name| !syscall_trace::is_known(name)) {
+            println!("Unknown system call '{}'", name);
+            return;
+        }
+        if let Some(path) = &output {
+            if let Err(e) = fs::File::create(path) {
+                println!("Error opening {}: {}", path, e);
+                return;
+            }
+        }
+        let calls = if filter.is_empty() {
+            "all system calls".to_string()
+        } else {
+            filter.join(", ")
+        };
+        match &output {
+            Some(path) => println!("Tracing {} to {}", calls, path),
+            None => println!("Tracing {}", calls),
+        }
+        self.syscall_trace = Some((filter, output));
+        let tracer = self.syscall_tracer();
+        if let Some(inferior) = self.inferior.as_mut() {
+            inferior.set_syscall_tracer(tracer);
+        }
+    }
+
+    /// 按 `trace syscalls` 的设置为被调试进程创建记录器；输出追加到文件末尾，重新运行时不覆盖
+    fn syscall_tracer(&self) -> Option<SyscallTracer> {
+        let (filter, output) = self.syscall_trace.as_ref()?;
+        let output = match output {
+            Some(path) => match fs::OpenOptions::new().create(true).append(true).open(path) {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    println!("Error opening {}: {}", path, e);
+                    return None;
+                }
+            },
+            None => None,
+        };
+        Some(SyscallTracer::new(filter.clone(), output))
+    }
+
+    /// `info net`：列出打开的套接字；统计流量时附上上次停止以来收发的字节数
+    fn print_net_info(&mut self) {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => {
+                println!("The program is not being run.");
+                return;
+            }
+        };
+        let sockets = match net::read_sockets(inferior.pid()) {
+            Ok(sockets) => sockets,
+            Err(e) => {
+                println!("Error reading sockets: {}", e);
+                return;
+            }
+        };
+        if sockets.is_empty() {
+            println!("No open sockets.");
+            return;
+        }
+        let traffic = inferior.net_traffic();
+        print!(
+            "{:<4} {:<5} {:<24} {:<24} {:<12}",
+            "fd", "proto", "local", "peer", "state"
+        );
+        match traffic {
+            Some(_) => println!(" {:>10} {:>10}", "sent", "received"),
+            None => println!(),
+        }
+        for socket in &sockets {
+            print!(
+                "{:<4} {:<5} {:<24} {:<24} {:<12}",
+                socket.fd, socket.protocol, socket.local, socket.peer, socket.state
+            );
+            match &traffic {
+                Some(traffic) => {
+                    let (sent, received) = traffic.get(socket.inode);
+                    println!(" {:>10} {:>10}", sent, received);
+                }
+                None => println!(),
+            }
+        }
+        if traffic.is_none() {
+            println!("Use 'set net-tracking on' to count bytes sent and received between stops");
+        }
+    }
+
+    /// `info threads`：被跟踪的线程标 `*`，其余线程只有阻塞在系统调用中时才知道所在函数
+    fn print_threads(&mut self) {
+        let (pid, rip) = match self.inferior.as_ref() {
+            Some(inferior) => (inferior.pid(), inferior.getregs().ok().map(|regs| regs.rip)),
+            None => {
+                println!("The program is not being run.");
+                return;
+            }
+        };
+        let threads = match threads::read_threads(pid) {
+            Ok(threads) => threads,
+            Err(e) => {
+                println!("Error reading threads: {}", e);
+                return;
+            }
+        };
+        println!("  {:<8} {:<16} {:<16} Frame", "Id", "Name", "State");
+        for thread in &threads {
+            let traced = thread.tid == pid.as_raw();
+            let pc = if traced {
+                rip.map(|rip| rip as usize)
+            } else {
+                thread.pc
+            };
+            let frame = match pc {
+                Some(pc) => self.describe_code_address(pc),
+                None if thread.state == 'R' => "(running)".to_string(),
+                None => "-".to_string(),
+            };
+            println!(
+                "{} {:<8} {:<16} {:<16} {}",
+                if traced { '*' } else { ' ' },
+                thread.tid,
+                thread.name,
+                format!("{} ({})", thread.state, thread.state_description()),
+                frame
+            );
+        }
+    }
+
+    /// 与 `describe_location` 相同，没有调试信息时再按共享库的符号表查找函数名
+    fn describe_code_address(&mut self, addr: usize) -> String {
+        if self.debug_data.get_function_from_addr(addr).is_some() {
+            return self.describe_location(addr);
+        }
+        self.refresh_libraries();
+        match self.libraries.symbolize(addr) {
+            Some(symbol) => symbol.to_string(),
+            None => format!("{:#x}", addr),
+        }
+    }
+
+    /// 在 Valgrind 下启动目标程序。断点在这种模式下不生效
+    fn start_under_valgrind(&mut self, args: &[String]) -> bool {
+        self.reset_inferior();
+        self.print_cwd();
+        if self.break_point.iter().any(|bp| bp.enabled) {
+            println!(
+                "Breakpoints are not inserted under valgrind; stops come from Memcheck errors"
+            );
+        }
+        self.timeline.event(
+            format!("run under valgrind {}", args.join(" "))
+                .trim()
+                .to_string(),
+        );
+        let target = self.program_path();
+        self.print_launch_options();
+        match Inferior::under_valgrind(&target, args, self.cwd.as_deref(), &self.launch) {
+            Some(inferior) => {
+                self.inferior = Some(inferior);
+                self.under_valgrind = true;
+                true
+            }
+            None => {
+                println!("Error starting valgrind");
+                false
+            }
+        }
+    }
+
+    /// 启动被调试进程时使用的程序路径。设置了工作目录时改用绝对路径，
+    /// 否则相对路径会相对新的工作目录查找
+    fn program_path(&self) -> String {
+        match &self.cwd {
+            Some(_) => fs::canonicalize(&self.target)
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| self.target.clone()),
+            None => self.target.clone(),
+        }
+    }
+
+    /// `set preload`：库的路径在设置时转成绝对路径，之后 `set cwd` 不影响它们
+    fn set_preload(&mut self, libs: &[String]) {
+        let mut preload = Vec::new();
+        for lib in libs {
+            match fs::canonicalize(lib) {
+                Ok(path) if path.is_file() => preload.push(path),
+                Ok(_) => {
+                    println!("{} is not a file", lib);
+                    return;
+                }
+                Err(e) => {
+                    println!("{}: {}", lib, e);
+                    return;
+                }
+            }
+        }
+        self.launch.preload = preload;
+        if self.launch.preload.is_empty() {
+            println!("No libraries will be preloaded.");
+        } else if self.inferior.is_some() {
+            println!("The preload list takes effect on the next run.");
+        }
+    }
+
+    /// `set affinity`：只接受调试器自己可以使用的 CPU，否则启动时 sched_setaffinity 会失败
+    fn set_affinity(&mut self, mut cpus: Vec<usize>) {
+        let available = inferior::available_cpus();
+        if let Some(cpu) = cpus.iter().find(|cpu| !available.contains(cpu)) {
+            println!(
+                "CPU {} is not available; usable CPUs: {}",
+                cpu,
+                join_values(&available)
+            );
+            return;
+        }
+        cpus.sort_unstable();
+        cpus.dedup();
+        self.launch.affinity = cpus;
+        if self.launch.affinity.is_empty() {
+            println!("The program will run on any CPU.");
+        } else {
+            println!(
+                "The program will be pinned to CPU {} on the next run.",
+                join_values(&self.launch.affinity)
+            );
+        }
+    }
+
+    /// `set scheduler`：实时策略的优先级必须在内核允许的范围内
+    fn set_scheduler(&mut self, scheduler: Option<Scheduler>) {
+        if let Some(scheduler) = scheduler {
+            let (min, max) = unsafe {
+                (
+                    libc::sched_get_priority_min(scheduler.policy()),
+                    libc::sched_get_priority_max(scheduler.policy()),
+                )
+            };
+            if scheduler.priority() < min || scheduler.priority() > max {
+                println!(
+                    "Priority must be between {} and {} for this policy",
+                    min, max
+                );
+                return;
+            }
+        }
+        self.launch.scheduler = scheduler;
+        match scheduler {
+            Some(scheduler) => println!("The program will run with {} on the next run.", scheduler),
+            None => println!("The program will inherit the debugger's scheduling policy."),
+        }
+    }
+
+    /// 启动时提示注入的库、CPU 绑定和调度策略，并记入时间线
+    fn print_launch_options(&mut self) {
+        if !self.launch.preload.is_empty() {
+            let libs: Vec<String> = self
+                .launch
+                .preload
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            println!("Preloading {}.", libs.join(", "));
+            self.timeline.event(format!("preload {}", libs.join(" ")));
+        }
+        if !self.launch.affinity.is_empty() {
+            let cpus = join_values(&self.launch.affinity);
+            println!("Pinned to CPU {}.", cpus);
+            self.timeline.event(format!("affinity {}", cpus));
+        }
+        if let Some(scheduler) = self.launch.scheduler {
+            println!("Scheduling policy {}.", scheduler);
+            self.timeline.event(format!("scheduler {}", scheduler));
+        }
+    }
+
+    /// 打印被调试进程实际使用的工作目录
+    fn print_cwd(&self) {
+        let cwd = match &self.cwd {
+            Some(cwd) => Some(cwd.clone()),
+            None => std::env::current_dir().ok(),
+        };
+        if let Some(cwd) = cwd {
+            println!("Working directory {}.", cwd.display());
+        }
+    }
+
+    /// 结束正在运行的被调试进程，并清空与这次运行相关的状态
+    fn reset_inferior(&mut self) {
+        self.end_inferior();
+        self.expire_watchpoints();
+        self.patched_ranges.clear();
+        self.libraries.clear();
+        for bp in self.break_point.iter_mut() {
+            bp.hits = 0;
+        }
+        self.guards.clear();
+        self.under_valgrind = false;
+        self.pending_signal = None;
+        self.exit_status = None;
+        self.reached.clear();
+        // 上一次运行 exec 了别的程序时换回目标程序的符号
+        let target = self.target.clone();
+        self.load_symbols(&target);
+        self.faults.reset(entry_point(&target));
+        self.plt_catches.reset();
+        self.monitors.reset();
+        if let Some(previous) = self.deltas.as_mut() {
+            *previous = None;
+        }
+    }
+
+    /// 结束正在运行的被调试进程；`attach` 上的进程不是调试器启动的，只停止跟踪，让它继续运行
+    fn end_inferior(&mut self) {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => return,
+        };
+        if inferior.attached() {
+            self.detach();
+            if self.inferior.is_none() {
+                return;
+            }
+        }
+        let inferior = self.inferior.as_mut().unwrap();
+        println!("Killing running inferior (pid {})", inferior.pid());
+        let _ = inferior.kill();
+        self.inferior = None;
+    }
+
+    /// `attach <pid>`：跟踪已经在运行的进程。调试信息按需从 /proc/<pid>/exe 重新加载，
+    /// 进程运行的就是目标程序时沿用已经加载的符号
+    fn attach(&mut self, pid: i32) {
+        self.reset_inferior();
+        let mut inferior = match Inferior::attach(nix::unistd::Pid::from_raw(pid)) {
+            Some(inferior) => inferior,
+            None => return,
+        };
+        let exe = format!("/proc/{}/exe", pid);
+        // 程序文件被删除或替换后只能通过 /proc/<pid>/exe 读取
+        let path = fs::read_link(&exe)
+            .ok()
+            .filter(|path| path.is_file())
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or(exe);
+        if !self.load_symbols(&path) {
+            println!(
+                "Symbols of {} are used for process {}",
+                self.symbols_path, pid
+            );
+        }
+        // 进程早已过了程序入口，共享库也已经加载，故障注入的函数在下面直接解析
+        self.faults.reset(None);
+        inferior.set_syscall_tracer(self.syscall_tracer());
+        inferior.set_net_tracking(self.net_tracking);
+        inferior.set_follow_fork(self.follow_fork);
+        inferior.set_timer_interrupt(self.monitors.interrupt_flag());
+        let rip = inferior.getregs().map(|regs| regs.rip as usize).ok();
+        self.inferior = Some(inferior);
+        let bias = self.target_load_bias();
+        self.apply_load_bias(bias);
+        self.inferior
+            .as_mut()
+            .unwrap()
+            .arm_breakpoints(&mut self.break_point);
+        self.resolve_watchpoints();
+        self.rearm_watchpoints();
+        self.resolve_faults(true);
+        println!("Attached to process {} ({})", pid, path);
+        self.timeline.event(format!("attach {}", pid));
+        if let Some(rip) = rip {
+            self.print_stopped_info(rip);
+        }
+    }
+
+    /// `detach`：撤掉断点和监视点后停止跟踪，被调试进程从停下的位置继续运行
+    fn detach(&mut self) {
+        if self.under_valgrind {
+            println!("detach is not supported under valgrind");
+            return;
+        }
+        let mut inferior = match self.inferior.take() {
+            Some(inferior) => inferior,
+            None => {
+                println!("The program is not being run.");
+                return;
+            }
+        };
+        let pid = inferior.pid();
+        // 调试寄存器留在进程中时，写入被监视的变量会让不再被跟踪的进程收到 SIGTRAP 而终止
+        for watchpoint in self.watchpoints.watchpoints.iter_mut() {
+            for slot in watchpoint.slots.drain(..) {
+                let _ = inferior.clear_watch(slot);
+            }
+        }
+        if let Err(e) = inferior.detach(&self.break_point) {
+            println!("Error detaching from process {}: {}", pid, e);
+            self.inferior = Some(inferior);
+            self.rearm_watchpoints();
+            return;
+        }
+        println!("[Inferior (pid {}) detached]", pid);
+        if !self.guards.is_empty() {
+            println!("Guard pages stay inaccessible in the detached process");
+        }
+        self.timeline.event(format!("detached pid {}", pid));
+        self.selected_frame = None;
+        self.expire_watchpoints();
+    }
+
+    /// 让停止的被调试进程继续运行。停在断点上时先恢复原指令单步越过它，再重新写入 int3
+    fn resume_inferior(&mut self) -> Result<Status, nix::Error> {
+        if let Some(status) = self.step_over_breakpoint()? {
+            return Ok(status);
+        }
+        self.continue_inferior()
+    }
+
+    /// 停在断点上（或 rip 处有断点）时，临时恢复原指令单步越过它再写回 int3；单步期间进程退出或
+    /// 收到了其它信号时返回该状态
+    fn step_over_breakpoint(&mut self) -> Result<Option<Status>, nix::Error> {
+        let inferior = self.inferior.as_mut().unwrap();
+        match step_over_breakpoint(inferior, &self.signals, &self.break_point)? {
+            // 新的程序里没有这个断点
+            Some(Status::Exec(_)) => {
+                self.handle_exec();
+                Ok(None)
+            }
+            status => Ok(status),
+        }
+    }
+
+    /// 继续运行被调试进程，按信号策略不需要停下的信号会直接转交给进程，
+    /// 命中条件不满足的断点时越过它继续运行
+    fn continue_inferior(&mut self) -> Result<Status, nix::Error> {
+        self.timeline.resumed("continue");
+        if let Some(traffic) = self.inferior.as_mut().unwrap().net_traffic() {
+            traffic.reset();
+        }
+        // 在返回时结束定时线程
+        let _timer = match self.under_valgrind {
+            false => {
+                let pid = self.inferior.as_ref().unwrap().pid();
+                self.monitors.start_timer(pid)
+            }
+            true => None,
+        };
+        loop {
+            let under_valgrind = self.under_valgrind;
+            if let Some(callgraph) = self.callgraph.as_mut().filter(|_| !under_valgrind) {
+                let breakpoints = &self.break_point;
+                callgraph.arm(self.inferior.as_mut().unwrap(), |addr| {
+                    breakpoints.get_enabled(addr).is_some()
+                });
+            }
+            if !under_valgrind {
+                let breakpoints = &self.break_point;
+                self.faults.arm(self.inferior.as_mut().unwrap(), |addr| {
+                    breakpoints.get_enabled(addr).is_some()
+                });
+                self.plt_catches
+                    .arm(self.inferior.as_mut().unwrap(), |addr| {
+                        breakpoints.get_enabled(addr).is_some()
+                    });
+                self.watchpoints
+                    .arm(self.inferior.as_mut().unwrap(), |addr| {
+                        breakpoints.get_enabled(addr).is_some()
+                    });
+            }
+            let inferior = self.inferior.as_mut().unwrap();
+            let status = inferior.continue_run(self.pending_signal.take());
+            let mut status = skip_quiet_signals(inferior, &self.signals, status, false);
+            while let Ok(Status::Exec(_)) = status {
+                if let Some(callgraph) = self.callgraph.as_mut() {
+                    callgraph.forget_entries();
+                }
+                self.handle_exec();
+                let inferior = self.inferior.as_mut().unwrap();
+                let resumed = inferior.continue_run(None);
+                status = skip_quiet_signals(inferior, &self.signals, resumed, false);
+            }
+            if let Ok(Status::Stopped(signal::Signal::SIGSTOP, _)) = status {
+                if self.inferior.as_ref().unwrap().take_timer_interrupt() {
+                    let due = self.monitors.due_by_time();
+                    self.sample_monitors(&due);
+                    continue;
+                }
+            }
+            if let Ok(Status::Stopped(..)) = status {
+                let due = self.monitors.count_stop();
+                self.sample_monitors(&due);
+            }
+            if let Ok(Status::Stopped(signal::Signal::SIGTRAP, rip)) = status {
+                match self.inject_fault(rip) {
+                    Ok(true) => continue,
+                    Ok(false) => {}
+                    Err(e) => status = Err(e),
+                }
+            }
+            if let Ok(Status::Stopped(signal::Signal::SIGTRAP, rip)) = status {
+                match self.record_call(rip) {
+                    Ok(true) => continue,
+                    Ok(false) => {}
+                    Err(e) => status = Err(e),
+                }
+            }
+            if let Ok(Status::Stopped(signal::Signal::SIGTRAP, rip)) = status {
+                match self.watch_trap(rip) {
+                    Ok(Some(rip)) => status = Ok(Status::Stopped(signal::Signal::SIGTRAP, rip)),
+                    Ok(None) => continue,
+                    Err(e) => status = Err(e),
+                }
+            }
+            if let Ok(Status::Stopped(signal::Signal::SIGTRAP, _)) = status {
+                if !self.breakpoint_condition_met() {
+                    match self.step_over_breakpoint() {
+                        Ok(None) => continue,
+                        Ok(Some(stopped)) => status = Ok(stopped),
+                        Err(e) => status = Err(e),
+                    }
+                }
+            }
+            if let Ok(Status::Stopped(signal::Signal::SIGTRAP, rip)) = status {
+                match self.plt_trap(rip) {
+                    Ok(true) => status = Ok(Status::Stopped(signal::Signal::SIGTRAP, rip - 1)),
+                    Ok(false) => {}
+                    Err(e) => status = Err(e),
+                }
+            }
+            if let (Some(callgraph), Some(inferior)) =
+                (self.callgraph.as_mut(), self.inferior.as_mut())
+            {
+                callgraph.disarm(inferior);
+            }
+            if let Some(inferior) = self.inferior.as_mut() {
+                self.faults.disarm(inferior);
+                self.plt_catches.disarm(inferior);
+                self.watchpoints.disarm(inferior);
+            }
+            self.record_stop(&status);
+            return status;
+        }
+    }
+
+    /// 记录录制调用图时在函数入口的停止。停在调用图自己的 int3 上时越过它并返回 true，
+    /// 调用者应该继续运行；停在函数入口的用户断点上时只记录，返回 false
+    fn record_call(&mut self, rip: usize) -> Result<bool, nix::Error> {
+        let callgraph = match self.callgraph.as_ref() {
+            Some(callgraph) => callgraph,
+            None => return Ok(false),
+        };
+        let inferior = self.inferior.as_ref().unwrap();
+        let entry = rip - 1;
+        let (callee, orig_byte) = match (callgraph.function_at(entry), callgraph.armed_at(entry)) {
+            (Some(callee), orig_byte) if orig_byte.is_some() || self.at_breakpoint() => {
+                (callee.to_string(), orig_byte)
+            }
+            _ => return Ok(false),
+        };
+        // 函数入口处栈顶就是返回地址，它前面的 call 指令所在的函数就是调用者
+        let return_addr = inferior.read_word(inferior.getregs()?.rsp as usize)? as usize;
+        let caller = match self.debug_data.get_function_from_addr(return_addr - 1) {
+            Some(caller) => caller,
+            None => {
+                self.refresh_libraries();
+                self.libraries
+                    .symbolize(return_addr - 1)
+                    .map(|symbol| symbol.function)
+                    .unwrap_or_else(|| format!("{:#x}", return_addr))
+            }
+        };
+        self.callgraph.as_mut().unwrap().record(caller, callee);
+        let orig_byte = match orig_byte {
+            Some(orig_byte) => orig_byte,
+            None => return Ok(false),
+        };
+        // 临时恢复原指令，单步越过后再写回 int3
+        let inferior = self.inferior.as_mut().unwrap();
+        let mut regs = inferior.getregs()?;
+        regs.rip = entry as u64;
+        inferior.setregs(regs)?;
+        inferior.write_byte(entry, orig_byte)?;
+        let status = inferior.step(None);
+        let status = skip_quiet_signals(inferior, &self.signals, status, true)?;
+        inferior.write_byte(entry, 0xcc)?;
+        match status {
+            Status::Stopped(signal::Signal::SIGTRAP, _) => Ok(true),
+            // 单步期间收到了其它信号：当作普通的停止交给调用者
+            _ => Ok(false),
+        }
+    }
+
+    /// `inject-fault <function>`：同名的设置被替换。进程已经运行时立即在已加载的库中查找函数
+    fn add_fault(&mut self, function: String, errno: Option<i32>, every: usize) {
+        let errno = errno.unwrap_or_else(|| fault_inject::default_errno(&function));
+        self.faults.remove(&function);
+        let fault = Fault::new(function, errno, every);
+        let result = match fault.failure_value() {
+            0 => "NULL".to_string(),
+            value => (value as i64).to_string(),
+        };
+        println!(
+            "{}() will return {} with errno {} on {}",
+            fault.function,
+            result,
+            fault_inject::errno_name(errno),
+            fault_inject::describe_calls(every)
+        );
+        self.faults.faults.push(fault);
+        if self.inferior.is_some() {
+            self.resolve_faults(false);
+        }
+    }
+
+    /// `catch plt <name>`：在目标程序的 PLT 中找到 name 的解析桩。已经绑定或者程序在启动时
+    /// 绑定所有符号时提示这次运行不会停下
+    fn add_plt_catch(&mut self, name: String) {
+        let slot = match plt_catch::find_slot(&self.symbols_path, &name) {
+            Ok(Some(slot)) => slot.shifted(self.debug_data.load_bias()),
+            Ok(None) => {
+                println!("No PLT entry for {} in {}", name, self.symbols_path);
+                return;
+            }
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        println!(
+            "Catchpoint (plt {}): the first call stops at the resolver stub {:#x} (GOT slot {:#x})",
+            name, slot.stub, slot.got
+        );
+        if plt_catch::binds_now(&self.symbols_path) {
+            println!(
+                "{} binds all symbols at startup (BIND_NOW); the catchpoint will not trigger",
+                self.symbols_path
+            );
+        } else if std::env::var_os("LD_BIND_NOW").is_some_and(|value| !value.is_empty()) {
+            println!("LD_BIND_NOW is set; the catchpoint will not trigger");
+        }
+        if let Some(inferior) = self.inferior.as_ref() {
+            match inferior.read_word(slot.got) {
+                Ok(bound) if bound as usize != slot.stub => println!(
+                    "{} is already bound to {:#x}; the catchpoint triggers on the next run",
+                    name, bound
+                ),
+                _ => {}
+            }
+        }
+        self.plt_catches.remove(&name);
+        self.plt_catches.catches.push(PltCatch::new(name, slot));
+    }
+
+    fn print_plt_catches(&mut self) {
+        if self.plt_catches.catches.is_empty() {
+            println!("No catch plt set.");
+            return;
+        }
+        // describe_code_address 可能需要刷新共享库列表，先把要显示的内容取出来
+        let catches: Vec<_> = self
+            .plt_catches
+            .catches
+            .iter()
+            .map(|catch| (catch.name.clone(), catch.slot, catch.first_call))
+            .collect();
+        for (name, slot, first_call) in catches {
+            let state = match first_call {
+                Some(return_addr) => format!(
+                    "first called from {}",
+                    self.describe_code_address(return_addr - 1)
+                ),
+                None => "not called yet in this run".to_string(),
+            };
+            println!(
+                "{}: GOT slot {:#x}, resolver stub {:#x}; {}",
+                name, slot.got, slot.stub, state
+            );
+        }
+    }
+
+    /// 停在 `catch plt` 的解析桩上时把 rip 退回桩的地址、记下调用者并返回 true。
+    /// 这个桩之后不再写入 int3，原来的指令照常执行，由动态链接器完成绑定
+    fn plt_trap(&mut self, rip: usize) -> Result<bool, nix::Error> {
+        let stub = rip - 1;
+        let index = match self.plt_catches.armed_at(stub) {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+        let inferior = self.inferior.as_mut().unwrap();
+        let mut regs = inferior.getregs()?;
+        regs.rip = stub as u64;
+        inferior.setregs(regs)?;
+        // 调用经 PLT 跳到桩，栈顶仍是调用者的返回地址
+        let return_addr = inferior.read_word(regs.rsp as usize)? as usize;
+        let catch = &mut self.plt_catches.catches[index];
+        catch.first_call = Some(return_addr);
+        let (name, got) = (catch.name.clone(), catch.slot.got);
+        println!();
+        println!(
+            "Catchpoint (plt {}): first call to {}, not yet resolved by the dynamic linker",
+            name, name
+        );
+        println!("GOT slot {:#x} still points to the resolver stub", got);
+        println!(
+            "Called from {}",
+            self.describe_code_address(return_addr - 1)
+        );
+        Ok(true)
+    }
+
+    /// `monitor <expr> every ...`：进程已经运行时立即求值一次作为起点
+    fn add_monitor(&mut self, text: String, interval: Interval) {
+        let expr = match crate::expr::parse(&text) {
+            Ok(expr) => expr,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let description = interval.describe();
+        let id = self.monitors.add(text.clone(), expr, interval);
+        println!("Monitor {}: {} {}", id, text, description);
+        if self.inferior.is_some() {
+            let index = self.monitors.monitors.len() - 1;
+            self.sample_monitors(&[index]);
+        }
+    }
+
+    fn print_monitors(&self) {
+        if self.monitors.monitors.is_empty() {
+            println!("No monitors.");
+            return;
+        }
+        for monitor in &self.monitors.monitors {
+            match monitor.last {
+                Some(value) => println!(
+                    "{}: {} {}, last value {}",
+                    monitor.id,
+                    monitor.expression,
+                    monitor.interval.describe(),
+                    value
+                ),
+                None => println!(
+                    "{}: {} {}",
+                    monitor.id,
+                    monitor.expression,
+                    monitor.interval.describe()
+                ),
+            }
+        }
+    }
+
+    /// 对下标在 indices 中的监视求值并打印，局部变量在最内层帧中查找
+    fn sample_monitors(&mut self, indices: &[usize]) {
+        if indices.is_empty() {
+            return;
+        }
+        self.selected_frame = None;
+        for index in indices {
+            let monitor = &self.monitors.monitors[*index];
+            let line = match self.evaluate(&monitor.expr) {
+                Ok(value) => {
+                    let sample = monitor.describe_sample(&value);
+                    self.monitors.monitors[*index].last = Some(value);
+                    sample
+                }
+                Err(e) => e,
+            };
+            let monitor = &self.monitors.monitors[*index];
+            println!("[monitor {}] {} = {}", monitor.id, monitor.expression, line);
+        }
+    }
+
+    fn print_faults(&self) {
+        if self.faults.faults.is_empty() {
+            println!("No faults are injected.");
+            return;
+        }
+        for fault in &self.faults.faults {
+            println!(
+                "{}(): errno {} on {}; {} call(s), {} failed in this run",
+                fault.function,
+                fault_inject::errno_name(fault.errno),
+                fault_inject::describe_calls(fault.every),
+                fault.calls,
+                fault.injected
+            );
+        }
+    }
+
+    /// 在已加载的共享库中解析故障注入的函数。libraries_loaded 为 false 且进程还没有加载共享库时，
+    /// 到达程序入口后会再解析一次，这里不提示找不到的函数
+    fn resolve_faults(&mut self, libraries_loaded: bool) {
+        self.refresh_libraries();
+        let libraries = &self.libraries;
+        let unresolved = self.faults.resolve(|name| libraries.lookup_function(name));
+        if libraries_loaded || !libraries.libraries().is_empty() {
+            for function in unresolved {
+                println!(
+                    "inject-fault: no function named {} in the loaded libraries",
+                    function
+                );
+            }
+        }
+    }
+
+    /// 停在故障注入的 int3 上时处理这次调用并返回 true，调用者应该继续运行
+    fn inject_fault(&mut self, rip: usize) -> Result<bool, nix::Error> {
+        let addr = rip - 1;
+        let inferior = self.inferior.as_mut().unwrap();
+        if self.faults.reached_entry(inferior, addr) {
+            let mut regs = inferior.getregs()?;
+            regs.rip = addr as u64;
+            inferior.setregs(regs)?;
+            self.resolve_faults(true);
+            return Ok(true);
+        }
+        let (index, orig_byte) = match self.faults.armed_at(addr) {
+            Some(armed) => armed,
+            None => return Ok(false),
+        };
+        let fault = &mut self.faults.faults[index];
+        fault.calls += 1;
+        if !fault.calls.is_multiple_of(fault.every) {
+            // 这次调用正常执行：临时恢复原指令，单步越过后再写回 int3
+            let mut regs = inferior.getregs()?;
+            regs.rip = addr as u64;
+            inferior.setregs(regs)?;
+            inferior.write_byte(addr, orig_byte)?;
+            let status = inferior.step(None);
+            let status = skip_quiet_signals(inferior, &self.signals, status, true)?;
+            inferior.write_byte(addr, 0xcc)?;
+            return match status {
+                Status::Stopped(signal::Signal::SIGTRAP, _) => Ok(true),
+                _ => Ok(false),
+            };
+        }
+        fault.injected += 1;
+        let (function, errno, calls, failure) = (
+            fault.function.clone(),
+            fault.errno,
+            fault.calls,
+            fault.failure_value(),
+        );
+        let errno_set = match self.libraries.lookup_function("__errno_location") {
+            Some(errno_location) => self.set_errno(errno_location, addr, errno)?,
+            None => false,
+        };
+        // 模拟 ret：函数入口处栈顶就是返回地址
+        let inferior = self.inferior.as_mut().unwrap();
+        let mut regs = inferior.getregs()?;
+        regs.rip = inferior.read_word(regs.rsp as usize)?;
+        regs.rsp += 8;
+        regs.rax = failure;
+        inferior.setregs(regs)?;
+        let result = match failure {
+            0 => "NULL".to_string(),
+            value => (value as i64).to_string(),
+        };
+        let message = if errno_set {
+            format!(
+                "Injected fault: {}() returned {} with errno {} (call {})",
+                function,
+                result,
+                fault_inject::errno_name(errno),
+                calls
+            )
+        } else {
+            format!(
+                "Injected fault: {}() returned {} (call {}; errno not set)",
+                function, result, calls
+            )
+        };
+        println!("{}", message);
+        self.timeline.event(message);
+        Ok(true)
+    }
+
+    /// 在被调试进程中调用 `__errno_location` 并写入 errno，返回是否成功。trap 是一个已写入 int3
+    /// 的地址，用作这次调用的返回地址；调用结束后寄存器恢复原样
+    fn set_errno(
+        &mut self,
+        errno_location: usize,
+        trap: usize,
+        errno: i32,
+    ) -> Result<bool, nix::Error> {
+        let inferior = self.inferior.as_mut().unwrap();
+        let saved = inferior.getregs()?;
+        let mut regs = saved;
+        // 函数入口处 rsp 为 16n+8；留出 8 字节再压入返回地址，保持相同的对齐
+        regs.rsp -= 16;
+        inferior.write_memory(regs.rsp as usize, &(trap as u64).to_le_bytes())?;
+        regs.rip = errno_location as u64;
+        inferior.setregs(regs)?;
+        let status = inferior.continue_run(None);
+        let status = skip_quiet_signals(inferior, &self.signals, status, false)?;
+        let errno_addr = inferior.getregs()?.rax as usize;
+        inferior.setregs(saved)?;
+        match status {
+            Status::Stopped(signal::Signal::SIGTRAP, rip) if rip == trap + 1 => {
+                inferior.write_memory(errno_addr, &errno.to_le_bytes())?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// 停在断点上时记录一次命中，并判断它的条件是否满足；没有停在断点上或断点没有条件时为 true
+    fn breakpoint_condition_met(&mut self) -> bool {
+        let inferior = self.inferior.as_ref().unwrap();
+        let addr = match inferior.trapped_breakpoint(&self.break_point) {
+            Ok(Some(addr)) => addr,
+            _ => return true,
+        };
+        // 条件为假的命中不计入命中次数，`@N` 数的是条件为真的命中
+        let bp = self.break_point.get_mut(addr).unwrap();
+        if let Some(condition) = bp.condition.clone() {
+            let id = bp.id;
+            // 条件在断点所在的帧中求值，上次停止时选中的外层帧已经失效
+            self.selected_frame = None;
+            match crate::expr::parse(&condition).and_then(|expr| self.evaluate(&expr)) {
+                Ok(value) if !value.is_true() => return false,
+                Ok(_) => {}
+                Err(e) => {
+                    println!("Error in testing condition for breakpoint {}: {}", id, e);
+                    return true;
+                }
+            }
+        }
+        let hits = self.break_point.record_hit(addr);
+        let bp = self.break_point.get_mut(addr).unwrap();
+        if bp.nth_hit.is_some_and(|n| hits != n) {
+            return false;
+        }
+        let caller = match &bp.caller {
+            Some(caller) => caller.clone(),
+            None => return true,
+        };
+        self.refresh_libraries();
+        let inferior = self.inferior.as_ref().unwrap();
+        let frames = match inferior.backtrace(
+            &self.debug_data,
+            self.target_cfi.as_ref(),
+            &mut self.libraries,
+            &self.break_point,
+            FrameArguments::None,
+        ) {
+            Ok(frames) => frames,
+            // 无法回溯时宁可停下，由用户判断
+            Err(_) => return true,
+        };
+        // 第一帧是断点所在的函数本身
+        frames.iter().skip(1).any(|frame| match frame {
+            Frame::Source { function, .. } => *function == caller,
+            Frame::Library(symbol) => symbol.function == caller,
+            _ => false,
+        })
+    }
+
+    /// 被调试进程调用了 execve：改用新程序的符号，在新的进程映像中重新写入断点。
+    /// 与启动时一样，先按新映像的加载偏移移动断点再写入
+    fn handle_exec(&mut self) {
+        let pid = self.inferior.as_ref().unwrap().pid();
+        let path = fs::read_link(format!("/proc/{}/exe", pid))
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| self.target.clone());
+        println!("Process {} is executing new program: {}", pid, path);
+        self.timeline.event(format!("exec {}", path));
+        // 旧映像中的补丁、保护页和共享库都已不存在
+        self.patched_ranges.clear();
+        self.libraries.clear();
+        self.guards.clear();
+        let bias = if self.load_symbols(&path) {
+            let bias = self.target_load_bias();
+            self.apply_load_bias(bias);
+            self.inferior
+                .as_mut()
+                .unwrap()
+                .arm_breakpoints(&mut self.break_point);
+            // exec 清空了调试寄存器
+            self.resolve_watchpoints();
+            self.rearm_watchpoints();
+            bias
+        } else {
+            println!("Breakpoints are not inserted into {}", path);
+            0
+        };
+        self.faults
+            .reset(entry_point(&path).map(|entry| entry.wrapping_add(bias)));
+        self.plt_catches.reset();
+    }
+
+    /// 被调试进程中符号所属程序的加载偏移。非 PIE 的程序和读不到内存映射时为 0
+    fn target_load_bias(&self) -> usize {
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => return 0,
+        };
+        let maps = match crate::proc_maps::read_maps(inferior.pid()) {
+            Ok(maps) => maps,
+            Err(e) => {
+                println!("Warning: could not read memory map: {}", e);
+                return 0;
+            }
+        };
+        crate::proc_maps::mapped_files(&maps)
+            .iter()
+            .find(|mapped| same_file(&mapped.path, &self.symbols_path))
+            .and_then(|mapped| crate::symbols::load_bias(&mapped.path, mapped.start))
+            .unwrap_or(0)
+    }
+
+    /// 换用新的加载偏移：目标程序映像中的断点、`catch plt` 和全局变量的监视点随映像一起移动，
+    /// 共享库中的和映像之外的地址不变。只在 int3 没有写入时调用
+    fn apply_load_bias(&mut self, bias: usize) {
+        let old = self.debug_data.load_bias();
+        if bias == old {
+            return;
+        }
+        let delta = bias.wrapping_sub(old);
+        // 映像占用的文件地址范围
+        let image = ElfSummary::from_file(&self.symbols_path)
+            .ok()
+            .and_then(|elf| {
+                let sections = elf.sections.iter().filter(|section| section.address != 0);
+                let start = sections.clone().map(|section| section.address).min()?;
+                let end = sections
+                    .map(|section| section.address + section.size)
+                    .max()?;
+                Some(start as usize..end as usize)
+            })
+            .unwrap_or(0..0);
+        let in_image = |addr: usize| image.contains(&addr.wrapping_sub(old));
+        let moves: Vec<(usize, usize)> = self
+            .break_point
+            .iter()
+            .map(|bp| bp.addr)
+            .filter(|addr| in_image(*addr))
+            .map(|addr| (addr, addr.wrapping_add(delta)))
+            .collect();
+        self.break_point.relocate(&moves);
+        if let Some(last) = self.last_breakpoint.filter(|last| in_image(*last)) {
+            self.last_breakpoint = Some(last.wrapping_add(delta));
+        }
+        self.plt_catches.shift(delta);
+        for watchpoint in self.watchpoints.watchpoints.iter_mut() {
+            if watchpoint.scope.is_none() && in_image(watchpoint.var_addr) {
+                watchpoint.var_addr = watchpoint.var_addr.wrapping_add(delta);
+                watchpoint.addr = watchpoint.addr.wrapping_add(delta);
+            }
+        }
+        self.debug_data.set_load_bias(bias);
+        if bias != 0 {
+            println!("{} is loaded at offset {:#x}", self.symbols_path, bias);
+        }
+    }
+
+    /// 改用 path 的调试信息，并按设置时的位置重新解析断点；解析不到的断点被禁用。
+    /// 无法读取 path 的调试信息时保留原来的符号并返回 false
+    fn load_symbols(&mut self, path: &str) -> bool {
+        if path == self.symbols_path || same_file(path, &self.symbols_path) {
+            return true;
+        }
+        match DwarfData::from_file(path) {
+            Ok(debug_data) => self.debug_data = debug_data,
+            Err(_) => {
+                println!("Could not load debugging symbols from {}", path);
+                return false;
+            }
+        }
+        self.target_cfi = CallFrameInfo::from_file(path);
+        self.symbols_path = path.to_string();
+
+        let mut specs: Vec<(usize, String)> = self
+            .break_point
+            .iter()
+            .filter_map(|bp| bp.location.clone().map(|location| (bp.id, location)))
+            .collect();
+        specs.sort();
+        specs.dedup();
+        // 新程序中断点的位置数可能不同：按地址顺序逐个移动，多出的旧位置删除，缺少的位置之后补上
+        let mut moves = Vec::new();
+        let mut removed = Vec::new();
+        let mut added = Vec::new();
+        let mut unresolved = Vec::new();
+        for (id, location) in specs {
+            let old = self.break_point.locations(id);
+            let new = self.resolve_location(&location);
+            if new.is_empty() {
+                unresolved.push((old, id, location));
+                continue;
+            }
+            moves.extend(
+                old.iter()
+                    .zip(&new)
+                    .filter(|(old, new)| old != new)
+                    .map(|(old, new)| (*old, *new)),
+            );
+            removed.extend(old.iter().skip(new.len()).copied());
+            added.extend(new.iter().skip(old.len()).map(|addr| (id, *addr)));
+        }
+        for (addrs, id, location) in unresolved {
+            let mut disabled = false;
+            for addr in addrs {
+                let bp = self.break_point.get_mut(addr).unwrap();
+                // 进程映像已经换掉，不需要撤掉旧的 int3
+                disabled |= bp.enabled;
+                bp.enabled = false;
+            }
+            if disabled {
+                println!(
+                    "Breakpoint {} ({}) is not in {}; disabled",
+                    id, location, path
+                );
+            }
+        }
+        for addr in removed {
+            self.break_point.remove(addr);
+        }
+        for bp in self.break_point.relocate(&moves) {
+            println!(
+                "Breakpoint {} ({}) resolves to an address that already has a breakpoint; deleted",
+                bp.id,
+                bp.location.as_deref().unwrap_or_default()
+            );
+        }
+        if let Some(last) = self.last_breakpoint {
+            self.last_breakpoint = moves
+                .iter()
+                .find(|(old, _)| *old == last)
+                .map(|(_, new)| *new)
+                .or(Some(last))
+                .filter(|addr| self.break_point.get_mut(*addr).is_some());
+        }
+        for (id, addr) in added {
+            if let Err(existing) = self.break_point.add_location(id, addr) {
+                println!(
+                    "Breakpoint {} has a location at {:#x}, which already has breakpoint {}; skipped",
+                    id, addr, existing
+                );
+            }
+        }
+        // `catch plt` 的槽位和解析桩也是新程序里的
+        let mut catches = std::mem::take(&mut self.plt_catches.catches);
+        catches.retain_mut(|catch| match plt_catch::find_slot(path, &catch.name) {
+            Ok(Some(slot)) => {
+                catch.slot = slot;
+                true
+            }
+            _ => {
+                println!(
+                    "catch plt {}: no PLT entry in {}; deleted",
+                    catch.name, path
+                );
+                false
+            }
+        });
+        self.plt_catches.catches = catches;
+        true
+    }
+
+    /// `next N` / `step N`：执行 N 次 step，只打印最后一次停止的位置
+    fn step_lines(&mut self, count: usize, step: fn(&mut Self) -> Result<Status, nix::Error>) {
+        if self.inferior.is_none() {
+            println!("No inferior to step");
+            return;
+        }
+        let mut status = step(self);
+        for _ in 1..count {
+            match status {
+                Ok(Status::Stopped(signal::Signal::SIGTRAP, _)) => status = step(self),
+                _ => break,
+            }
+        }
+        match status {
+            Ok(Status::Stopped(signal::Signal::SIGTRAP, rip)) => self.print_stopped_info(rip),
+            Err(e) => println!("Error stepping inferior: {}", e),
+            status => self.report_status(status),
+        }
+    }
+
+    /// 执行一次 `next`：单步直到源码行号改变（只比较行号数字，不比较地址）。
+    /// 正常结束时返回 Stopped(SIGTRAP, rip)，进程退出或收到其它信号时提前返回
+    fn next_line(&mut self) -> Result<Status, nix::Error> {
+        self.timeline.resumed("next");
+        let status = self.step_to_next_line();
+        if let Ok(Status::Exec(_)) = status {
+            // 原来的函数已经不存在了，在新程序中继续运行
+            self.handle_exec();
+            return self.continue_inferior();
+        }
+        self.record_step_hit(&status);
+        self.record_stop(&status);
+        status
+    }
+
+    /// 执行一次 `step`：单步直到源码行号或所在函数改变。进入有调试信息的函数时越过它的序言，
+    /// 停在函数体的第一行；没有调试信息的函数（PLT、共享库）整个跳过
+    fn step_line(&mut self) -> Result<Status, nix::Error> {
+        self.timeline.resumed("step");
+        let rip = self.inferior.as_ref().unwrap().getregs()?.rip as usize;
+        let mut line_number = self.debug_data.get_line_from_addr(rip).map(|l| l.number);
+        let mut function = self.debug_data.get_function_from_addr(rip);
+        let status = 'step: loop {
+            if let Some(status) = self.step_instruction(true)? {
+                break Ok(status);
+            }
+            let rip = self.inferior.as_ref().unwrap().getregs()?.rip as usize;
+            let new_line_number = self.debug_data.get_line_from_addr(rip).map(|l| l.number);
+            let new_function = self.debug_data.get_function_from_addr(rip);
+            // 刚进入函数：单步越过序言，停在与函数断点相同的位置。单行函数的函数体与函数头在同一行，
+            // 不能等行号改变
+            let range = self
+                .debug_data
+                .get_function_range(rip)
+                .filter(|(start, _)| *start == rip);
+            if let Some((start, end)) = range {
+                let body = self.debug_data.get_prologue_end(rip).unwrap_or(rip);
+                let mut pc = rip;
+                while pc != body && pc >= start && pc < end {
+                    if let Some(status) = self.step_instruction(false)? {
+                        break 'step Ok(status);
+                    }
+                    pc = self.inferior.as_ref().unwrap().getregs()?.rip as usize;
+                }
+                break Ok(Status::Stopped(signal::Signal::SIGTRAP, pc));
+            }
+            // 返回到调用者的语句中间：与 gdb 相同，执行完调用者的这一行再停下
+            let mid_line = self
+                .debug_data
+                .get_line_range(rip)
+                .is_some_and(|(start, _)| start != rip);
+            if new_function != function && mid_line {
+                line_number = new_line_number;
+                function = new_function;
+                continue;
+            }
+            if new_line_number.is_some()
+                && (new_line_number != line_number || new_function != function)
+            {
+                break Ok(Status::Stopped(signal::Signal::SIGTRAP, rip));
+            }
+        };
+        if let Ok(Status::Exec(_)) = status {
+            self.handle_exec();
+            return self.continue_inferior();
+        }
+        self.record_step_hit(&status);
+        self.record_stop(&status);
+        status
+    }
+
+    /// 先单步一条指令；如果仍在当前语句的地址区间内，就在区间的出口放临时断点后继续运行，
+    /// 而不是逐条指令单步。区间的出口是函数中其它语句的起始地址和当前函数的返回地址；
+    /// 无法确定出口（没有行号或 CFI）时退回逐条单步
+    fn step_to_next_line(&mut self) -> Result<Status, nix::Error> {
+        let regs = self.inferior.as_ref().unwrap().getregs()?;
+        let current_line_number = self
+            .debug_data
+            .get_line_from_addr(regs.rip as usize)
+            .map(|l| l.number);
+
+        loop {
+            if let Some(status) = self.step_instruction(false)? {
+                return Ok(status);
+            }
+            let inferior = self.inferior.as_mut().unwrap();
+            let mut rip = inferior.getregs()?.rip as usize;
+            let line_number = self.debug_data.get_line_from_addr(rip).map(|l| l.number);
+            if line_number != current_line_number && line_number.is_some() {
+                return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip));
+            }
+            if let Some((exits, frame_cfa)) = self.statement_exits(rip)? {
+                let inferior = self.inferior.as_mut().unwrap();
+                if let Some(status) = run_to_exits(
+                    inferior,
+                    &self.signals,
+                    &self.break_point,
+                    self.target_cfi.as_ref(),
+                    self.debug_data.load_bias(),
+                    &exits,
+                    frame_cfa,
+                )? {
+                    return Ok(status);
+                }
+                rip = inferior.getregs()?.rip as usize;
+            }
+
+            let new_line_number = self.debug_data.get_line_from_addr(rip).map(|l| l.number);
+            // 如果行号变了（或者从 None 变成了 Some），就停下来
+            if new_line_number != current_line_number && new_line_number.is_some() {
+                return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip));
+            }
+            // 行号没变或者还在无行号区域，继续步进
+        }
+    }
+
+    /// 单步执行一条指令，遇到 call 时运行到被调用函数返回；into_calls 为 true 时进入有调试信息的函数。
+    /// 返回 None 表示正常停在下一条指令上；进程退出、收到信号或命中断点时返回该停止
+    fn step_instruction(&mut self, into_calls: bool) -> Result<Option<Status>, nix::Error> {
+        let inferior = self.inferior.as_mut().unwrap();
+        let mut regs = inferior.getregs()?;
+        // 在单步前检查是否刚执行了断点的 int3 或停在断点上：恢复原始字节、回退 rip，单步后重设断点
+        let trapped = inferior.breakpoint_to_step_over(&self.break_point)?;
+        if let Some(bp_addr) = trapped {
+            let bp = self.break_point.get_enabled(bp_addr).unwrap();
+            inferior.write_byte(bp_addr, bp.orig_byte)?;
+            regs.rip = bp_addr as u64;
+            inferior.setregs(regs)?;
+        }
+        let is_call = inferior
+            .read_memory(regs.rip as usize, 3)
+            .map(|code| is_call_instruction(&code))
+            .unwrap_or(false);
+
+        let status = inferior.step(None);
+        match skip_quiet_signals(inferior, &self.signals, status, true)? {
+            Status::Stopped(signal::Signal::SIGTRAP, _) => {}
+            status => return Ok(Some(status)),
+        }
+        if let Some(bp_addr) = trapped {
+            inferior.write_byte(bp_addr, 0xcc)?;
+        }
+
+        // 执行了 call 时运行到返回地址，跳过被调用的函数
+        let after = inferior.getregs()?;
+        let rsp = after.rsp;
+        if is_call
+            && into_calls
+            && self
+                .debug_data
+                .get_function_from_addr(after.rip as usize)
+                .is_some()
+        {
+            return Ok(None);
+        }
+        if is_call && rsp == regs.rsp - 8 {
+            let return_addr = inferior.read_word(rsp as usize)? as usize;
+            return run_to_return(
+                inferior,
+                &self.signals,
+                &self.break_point,
+                return_addr,
+                regs.rsp,
+            );
+        }
+        Ok(None)
+    }
+
+    /// `finish`：运行到当前函数返回，打印返回值
+    fn finish(&mut self) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let pc = match inferior.trapped_breakpoint(&self.break_point) {
+            Ok(Some(bp_addr)) => Ok(bp_addr),
+            Ok(None) => inferior.getregs().map(|regs| regs.rip as usize),
+            Err(e) => Err(e),
+        };
+        let pc = match pc {
+            Ok(pc) => pc,
+            Err(e) => {
+                println!("Error finishing: {}", e);
+                return;
+            }
+        };
+        let caller = match self.frame_caller(pc) {
+            Some(Ok(Some(caller))) if caller.rip != 0 => caller,
+            Some(Err(e)) => {
+                println!("Error finishing: {}", e);
+                return;
+            }
+            _ => {
+                println!("\"finish\" not meaningful: cannot find the caller of the current frame");
+                return;
+            }
+        };
+        let function = self
+            .debug_data
+            .get_function_from_addr(pc)
+            .unwrap_or_else(|| format!("{:#x}", pc));
+        println!("Run till exit from {}", function);
+        let return_type = self.debug_data.get_return_type(pc).cloned();
+
+        self.timeline.resumed("finish");
+        let status = self.run_to_caller(caller);
+        if let Ok(Status::Exec(_)) = status {
+            self.handle_exec();
+            let status = self.continue_inferior();
+            self.report_status(status);
+            return;
+        }
+        self.record_step_hit(&status);
+        self.record_stop(&status);
+        match status {
+            Ok(Status::Stopped(signal::Signal::SIGTRAP, rip)) if rip == caller.rip as usize => {
+                self.print_stopped_info(rip);
+                if let Some(return_type) = return_type {
+                    match self.inferior.as_ref().unwrap().return_value(&return_type) {
+                        Ok(bytes) => println!(
+                            "Value returned is {} ({})",
+                            return_type.format_bytes(&bytes),
+                            return_type.name
+                        ),
+                        Err(e) => println!("Error reading return value: {}", e),
+                    }
+                }
+            }
+            status => self.report_status(status),
+        }
+    }
+
+    /// 运行到当前帧返回到 caller；正常返回时停在返回地址上
+    fn run_to_caller(&mut self, caller: UnwindRegs) -> Result<Status, nix::Error> {
+        let returned = Status::Stopped(signal::Signal::SIGTRAP, caller.rip as usize);
+        // 停在断点上时先单步越过它；如果这一步就是 ret，已经回到了调用者
+        if self
+            .inferior
+            .as_ref()
+            .unwrap()
+            .breakpoint_to_step_over(&self.break_point)?
+            .is_some()
+        {
+            if let Some(status) = self.step_instruction(false)? {
+                return Ok(status);
+            }
+            let regs = self.inferior.as_ref().unwrap().getregs()?;
+            if regs.rip == caller.rip && regs.rsp == caller.rsp {
+                return Ok(returned);
+            }
+        }
+        let inferior = self.inferior.as_mut().unwrap();
+        Ok(run_to_return(
+            inferior,
+            &self.signals,
+            &self.break_point,
+            caller.rip as usize,
+            caller.rsp,
+        )?
+        .unwrap_or(returned))
+    }
+
+    /// rip 所在语句区间的出口地址，以及当前帧的 CFA（用来区分递归调用中的同一地址）。
+    /// rip 没有行号信息或者无法通过 CFI 得到返回地址时返回 None
+    fn statement_exits(&self, rip: usize) -> Result<Option<(Vec<usize>, u64)>, nix::Error> {
+        let (start, end) = match self.debug_data.get_line_range(rip) {
+            Some(range) => range,
+            None => return Ok(None),
+        };
+        let caller = match self.frame_caller(rip) {
+            Some(caller) => caller?,
+            None => return Ok(None),
+        };
+        let caller = match caller {
+            Some(caller) if caller.rip != 0 => caller,
+            _ => return Ok(None),
+        };
+        let mut exits: Vec<usize> = self
+            .debug_data
+            .get_function_line_addrs(rip)
+            .into_iter()
+            .filter(|addr| *addr < start || *addr >= end)
+            .collect();
+        exits.push(caller.rip as usize);
+        Ok(Some((exits, caller.rsp)))
+    }
+
+    /// `find-func`：按描述在本地语义索引中列出最相似的几个函数
+    fn find_function(&self, description: &str) {
+        let index = SymbolIndex::build(&self.debug_data);
+        let matches = index.search(description, FIND_FUNC_RESULTS);
+        if matches.is_empty() {
+            println!("No function matches '{}'", description);
+            return;
+        }
+        for (entry, score) in matches {
+            let location = format!("{}:{}", entry.file, entry.line);
+            if entry.summary.is_empty() {
+                println!("{:.2}  {} ({})", score, entry.name, location);
+            } else {
+                println!(
+                    "{:.2}  {} ({}) - {}",
+                    score, entry.name, location, entry.summary
+                );
+            }
+        }
+    }
+
+    /// 选中第 level 层栈帧并显示它，之后 `print`、表达式和 `info frame` 都作用于这一帧
+    fn select_frame(&mut self, level: usize) {
+        if self.inferior.is_none() {
+            println!("No stack.");
+            return;
+        }
+        self.refresh_libraries();
+        let inferior = self.inferior.as_ref().unwrap();
+        let frames = match inferior.frames(
+            &self.debug_data,
+            self.target_cfi.as_ref(),
+            &mut self.libraries,
+            &self.break_point,
+            self.frame_arguments,
+        ) {
+            Ok(frames) => frames,
+            Err(e) => {
+                println!("Error reading frames: {}", e);
+                return;
+            }
+        };
+        match frames.into_iter().nth(level) {
+            Some((frame, regs)) => {
+                self.selected_frame = if level == 0 {
+                    None
+                } else {
+                    Some((level, regs))
+                };
+                println!("#{} {}", level, frame);
+            }
+            None => println!("No frame at level {}", level),
+        }
+    }
+
+    /// 用目标程序的 CFI 计算当前停在 pc 处的帧的调用者；没有 CFI 时返回 None
+    fn frame_caller(&self, pc: usize) -> Option<Result<Option<UnwindRegs>, nix::Error>> {
+        let cfi = self.target_cfi.as_ref()?;
+        let inferior = self.inferior.as_ref().unwrap();
+        Some(frame_caller(cfi, self.debug_data.load_bias(), inferior, pc))
+    }
+
+    /// 把停止事件通知给插件
+    fn notify_plugins(&self, status: &Result<Status, nix::Error>) {
+        let (kind, location) = match status {
+            Ok(Status::Exited(code)) => (StopKind::Exited(*code), None),
+            Ok(Status::Signaled(signal)) => (StopKind::Signaled(*signal as i32), None),
+            Ok(Status::Stopped(signal, rip)) => {
+                let location = match (
+                    self.debug_data.get_function_from_addr(*rip),
+                    self.debug_data.get_line_from_addr(*rip),
+                ) {
+                    (Some(function), Some(line)) => Some(StopLocation {
+                        function,
+                        file: line.file,
+                        line: line.number,
+                    }),
+                    _ => None,
+                };
+                (StopKind::Stopped(*signal as i32, *rip as u64), location)
+            }
+            Ok(Status::Exec(_)) | Err(_) => return,
+            Ok(Status::Syscall) | Ok(Status::Fork(..)) | Ok(Status::VforkDone) => {
+                unreachable!("system call and fork stops are handled by Inferior")
+            }
+        };
+        self.plugins
+            .notify_stop(kind, location, self.inferior.as_ref());
+    }
+
+    /// `next`、`step`、`finish` 等命令途中停在用户断点上时记录一次命中；
+    /// `continue` 在 `breakpoint_condition_met` 中记录
+    fn record_step_hit(&mut self, status: &Result<Status, nix::Error>) {
+        if let (Ok(Status::Stopped(signal::Signal::SIGTRAP, _)), Some(inferior)) =
+            (status, self.inferior.as_ref())
+        {
+            if let Ok(Some(addr)) = inferior.trapped_breakpoint(&self.break_point) {
+                self.break_point.record_hit(addr);
+            }
+        }
+    }
+
+    /// 把一次停止记入时间线
+    fn record_stop(&mut self, status: &Result<Status, nix::Error>) {
+        self.pending_signal = match status {
+            Ok(Status::Stopped(signal, _)) if self.signals.policy(*signal).pass => Some(*signal),
+            _ => None,
+        };
+        let (kind, reason, exited) = match status {
+            Ok(Status::Exited(code)) => ("exit", format!("exited (status {})", code), true),
+            Ok(Status::Signaled(signal)) => ("signal", format!("killed by {}", signal), true),
+            Ok(Status::Stopped(signal, rip)) => {
+                let breakpoint = self
+                    .inferior
+                    .as_ref()
+                    .and_then(|inferior| inferior.trapped_breakpoint(&self.break_point).ok())
+                    .flatten()
+                    .and_then(|addr| self.break_point.get_enabled(addr));
+                match breakpoint {
+                    Some(bp) => (
+                        "breakpoint",
+                        format!(
+                            "breakpoint {} at {}",
+                            bp.id,
+                            self.describe_location(bp.addr)
+                        ),
+                        false,
+                    ),
+                    None if *signal == signal::Signal::SIGTRAP => (
+                        "stop",
+                        format!("reached {}", self.describe_location(*rip)),
+                        false,
+                    ),
+                    None => (
+                        "signal",
+                        format!("{} at {}", signal, self.describe_location(*rip)),
+                        false,
+                    ),
+                }
+            }
+            Ok(Status::Exec(rip)) => ("exec", format!("exec, entry at {:#x}", rip), false),
+            Ok(Status::Syscall) | Ok(Status::Fork(..)) | Ok(Status::VforkDone) => {
+                unreachable!("system call and fork stops are handled by Inferior")
+            }
+            Err(e) => ("error", format!("error: {}", e), true),
+        };
+        match status {
+            Ok(Status::Stopped(_, rip)) => {
+                let breakpoint = self
+                    .inferior
+                    .as_ref()
+                    .and_then(|inferior| inferior.trapped_breakpoint(&self.break_point).ok())
+                    .flatten();
+                self.reached.insert(breakpoint.unwrap_or(*rip));
+            }
+            Ok(status) if exited => self.exit_status = Some(*status),
+            _ => {}
+        }
+        self.timeline.stopped(kind, reason, exited);
+        self.selected_frame = None;
+        self.report_watchpoints();
+        self.expire_watchpoints();
+    }
+
+    /// 打印 run / continue 之后的进程状态，进程结束时清空 inferior
+    fn report_status(&mut self, status: Result<Status, nix::Error>) {
+        self.notify_plugins(&status);
+        match status {
+            Ok(Status::Exited(code)) => {
+                println!("Child exited (status {})", code);
+                self.inferior = None;
+                queue_lines(&mut self.pending_lines, &self.hook_exited);
+            }
+            Ok(Status::Signaled(signal)) => {
+                println!("Child exited (signal {})", signal);
+                self.inferior = None;
+                queue_lines(&mut self.pending_lines, &self.hook_exited);
+            }
+            Ok(Status::Stopped(signal, rip)) => {
+                println!("Child stopped (signal {})", signal);
+                if signal == signal::Signal::SIGSEGV {
+                    self.report_guard_fault();
+                }
+                self.print_stopped_info(rip);
+                // Memcheck 发现错误时 gdbserver 报告 SIGTRAP，错误描述已由 Valgrind 打印
+                if self.under_valgrind && signal == signal::Signal::SIGTRAP {
+                    println!("Valgrind reported an error");
+                    self.print_inferior_backtrace();
+                }
+            }
+            Ok(Status::Exec(rip)) => {
+                self.handle_exec();
+                self.print_stopped_info(rip);
+            }
+            Ok(Status::Syscall) | Ok(Status::Fork(..)) | Ok(Status::VforkDone) => {
+                unreachable!("system call and fork stops are handled by Inferior")
+            }
+            Err(e) => println!("Error continuing inferior: {}", e),
+        }
+    }
+
+    /// 用 mprotect 把 [addr, addr + len) 之后的第一个整页设为不可访问，越界写入它时进程会收到 SIGSEGV。
+    /// 缓冲区末尾到页边界之间的越界访问无法捕获
+    fn set_guard(&mut self, addr: usize, len: usize) {
+        if self.under_valgrind {
+            println!("guard is not supported under valgrind");
+            return;
+        }
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => {
+                println!("The program is not being run.");
+                return;
+            }
+        };
+        let end = match addr.checked_add(len) {
+            Some(end) => end,
+            None => {
+                println!("Invalid buffer: {:#x} {}", addr, len);
+                return;
+            }
+        };
+        let page = (end + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        match inferior.inject_syscall(SYS_MPROTECT, &[page as u64, PAGE_SIZE as u64, PROT_NONE]) {
+            Ok(0) => {
+                println!(
+                    "Guard page at {:#x} ({} bytes after the end of buffer {:#x})",
+                    page,
+                    page - end,
+                    addr
+                );
+                self.guards.push(GuardPage {
+                    buffer: addr,
+                    len,
+                    page,
+                });
+            }
+            Ok(ret) => println!(
+                "Error protecting page {:#x}: {}",
+                page,
+                nix::errno::Errno::from_i32(-ret as i32).desc()
+            ),
+            Err(e) => println!("Error protecting page {:#x}: {}", page, e),
+        }
+    }
+
+    /// SIGSEGV 的出错地址落在保护页内时，报告是哪个缓冲区越界以及越界了多少
+    fn report_guard_fault(&self) {
+        let info = match self.inferior.as_ref().map(|inferior| inferior.siginfo()) {
+            Some(Ok(info)) => info,
+            _ => return,
+        };
+        for guard in &self.guards {
+            if info.addr >= guard.page && info.addr < guard.page + PAGE_SIZE {
+                println!(
+                    "Buffer overflow: access to {:#x} is {} bytes past the end of buffer {:#x} ({} bytes)",
+                    info.addr,
+                    info.addr - (guard.buffer + guard.len),
+                    guard.buffer,
+                    guard.len
+                );
+            }
+        }
+    }
+
+    /// 选中帧中用来查找函数、源代码行和变量作用域的地址。外层帧的 rip 是返回地址，
+    /// 取 call 指令内的地址；最内层帧刚命中断点时取断点地址
+    fn selected_pc(&self) -> Result<usize, String> {
+        let inferior = self
+            .inferior
+            .as_ref()
+            .ok_or_else(|| "The program is not being run.".to_string())?;
+        match self.selected_frame {
+            Some((_, frame)) => Ok(frame.rip as usize - 1),
+            None => inferior
+                .unwind_regs(&self.break_point)
+                .map(|regs| regs.rip as usize)
+                .map_err(|e| format!("Error reading registers: {}", e)),
+        }
+    }
+
+    /// 停止的原因：`stopped at a breakpoint`、`stopped by SIGSEGV` 等
+    fn stop_reason(&self) -> String {
+        if self.at_breakpoint() {
+            return "stopped at a breakpoint".to_string();
+        }
+        match self
+            .inferior
+            .as_ref()
+            .and_then(|inferior| inferior.siginfo().ok())
+            .and_then(|info| signal::Signal::try_from(info.signo).ok())
+        {
+            Some(signal) => format!("stopped by {}", signal),
+            None => "stopped".to_string(),
+        }
+    }
+
+    /// `ask`：收集选中帧的位置、附近的源代码、局部变量和非空指针指向的内存，请 LLM 回答问题，
+    /// 再打印回答和它引用的依据
+    fn ask(&mut self, question: &str) {
+        let pc = match self.selected_pc() {
+            Ok(pc) => pc,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let level = self.selected_frame.map_or(0, |(level, _)| level);
+        let mut context = format!("程序 {}，选中第 {} 层帧", self.stop_reason(), level);
+        match (
+            self.debug_data.get_function_from_addr(pc),
+            self.debug_data.get_line_from_addr(pc),
+        ) {
+            (Some(function), Some(line)) => {
+                context.push_str(&format!("，位于 {} {}\n", function, line))
+            }
+            _ => context.push_str(&format!("，位于 {:#x}\n", pc)),
+        }
+
+        let line = self.debug_data.get_line_from_addr(pc);
+        let mut source_lines = 0;
+        if let Some(source) = line
+            .as_ref()
+            .and_then(|line| fs::read_to_string(&line.file).ok())
+        {
+            let line = line.as_ref().unwrap();
+            context.push_str(&format!("\n源文件 {}:\n", line.file));
+            let first = line.number.saturating_sub(ASK_CONTEXT_LINES).max(1);
+            for (number, text) in source
+                .lines()
+                .enumerate()
+                .map(|(i, text)| (i + 1, text))
+                .skip(first - 1)
+                .take(2 * ASK_CONTEXT_LINES + 1)
+            {
+                let marker = if number == line.number { ">" } else { " " };
+                context.push_str(&format!("{}{:>5}  {}\n", marker, number, text));
+                source_lines += 1;
+            }
+        }
+
+        let variables: Vec<(String, bool)> = self
+            .debug_data
+            .get_local_variables(pc)
+            .iter()
+            .map(|var| {
+                (
+                    var.name.clone(),
+                    var.entity_type.encoding == Encoding::Address,
+                )
+            })
+            .collect();
+        let mut pointers = Vec::new();
+        context.push_str("\n局部变量:\n");
+        for (name, is_pointer) in &variables {
+            match self.show_variable(name) {
+                Ok((text, value)) => {
+                    context.push_str(&format!("{} = {}\n", name, text));
+                    if let (true, Some(Value::Int(addr))) = (is_pointer, value) {
+                        if addr != 0 {
+                            pointers.push((name, addr as usize));
+                        }
+                    }
+                }
+                Err(e) => context.push_str(&format!("{}: {}\n", name, e)),
+            }
+        }
+
+        let inferior = self.inferior.as_ref().unwrap();
+        let mut snippets = 0;
+        for (name, addr) in pointers {
+            // 指针可能是野指针，读不到的内存直接写明
+            match inferior.read_memory(addr, ASK_MEMORY_BYTES) {
+                Ok(bytes) => {
+                    context.push_str(&format!(
+                        "\n*{} ({:#x} 起的 {} 字节):\n",
+                        name,
+                        addr,
+                        bytes.len()
+                    ));
+                    for (row, chunk) in bytes.chunks(16).enumerate() {
+                        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+                        context.push_str(&format!("{:#x}: {}\n", addr + row * 16, hex.join(" ")));
+                    }
+                    snippets += 1;
+                }
+                Err(e) => {
+                    context.push_str(&format!("\n*{} ({:#x}): 无法读取: {}\n", name, addr, e))
+                }
+            }
+        }
+
+        println!(
+            "Asking with {} source line(s), {} local(s) and {} memory snippet(s) ...",
+            source_lines,
+            variables.len(),
+            snippets
+        );
+        match crate::llm::ask(&context, question) {
+            Ok(answer) => {
+                println!("{}", answer.text);
+                if answer.evidence.is_empty() {
+                    println!("Evidence: none cited");
+                } else {
+                    println!("Evidence:");
+                    for item in &answer.evidence {
+                        println!("  {}", item);
+                    }
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    /// `suggest-fix`：把停止原因、调用栈和出问题处的源代码交给 LLM，打印它给出的补丁。
+    /// 补丁只用于预览，不会写入任何文件
+    fn suggest_fix(&mut self, note: Option<&str>) {
+        if self.inferior.is_none() {
+            println!("The program is not being run.");
+            return;
+        }
+        self.refresh_libraries();
+        let inferior = self.inferior.as_ref().unwrap();
+        let frames = match inferior.backtrace(
+            &self.debug_data,
+            self.target_cfi.as_ref(),
+            &mut self.libraries,
+            &self.break_point,
+            self.frame_arguments,
+        ) {
+            Ok(frames) => frames,
+            Err(e) => {
+                println!("Error printing backtrace: {}", e);
+                return;
+            }
+        };
+        // 最内层有源码的帧通常就是需要修改的地方
+        let line = match frames.iter().find_map(|frame| match frame {
+            Frame::Source { line, .. } => Some(line),
+            _ => None,
+        }) {
+            Some(line) => line,
+            None => {
+                println!("No frame with source information to fix");
+                return;
+            }
+        };
+        let source = match fs::read_to_string(&line.file) {
+            Ok(source) => source,
+            Err(e) => {
+                println!("Error reading {}: {}", line.file, e);
+                return;
+            }
+        };
+
+        let mut context = format!("程序 {}\n\n调用栈:\n", self.stop_reason());
+        for frame in &frames {
+            context.push_str(&format!("{}\n", frame));
+        }
+        context.push_str(&format!("\n源文件 {}:\n", line.file));
+        let first = line.number.saturating_sub(FIX_CONTEXT_LINES).max(1);
+        for (number, text) in source
+            .lines()
+            .enumerate()
+            .map(|(i, text)| (i + 1, text))
+            .skip(first - 1)
+            .take(2 * FIX_CONTEXT_LINES + 1)
+        {
+            let marker = if number == line.number { ">" } else { " " };
+            context.push_str(&format!("{}{:>5}  {}\n", marker, number, text));
+        }
+        if let Some(note) = note {
+            context.push_str(&format!("\n问题描述: {}\n", note));
+        }
+
+        println!("正在请求修复建议 ...");
+        match crate::llm::suggest_fix(&context) {
+            Ok(diff) => {
+                println!("Suggested fix (not applied):");
+                println!("{}", diff);
+            }
+            Err(e) => println!("Failed to get a fix suggestion: {}", e),
+        }
+    }
+
+    /// `compare-runs`：用两组参数各运行一次，自动越过断点并记录，然后总结两次运行的差异
+    fn compare_runs(&mut self, vars: &[String], first: &[String], second: &[String]) {
+        if self.break_point.iter().all(|bp| !bp.enabled) {
+            println!("No breakpoints are enabled; only the results of the runs will be compared");
+        }
+        let first = match self.record_run(vars, first) {
+            Some(record) => record,
+            None => return,
+        };
+        let second = match self.record_run(vars, second) {
+            Some(record) => record,
+            None => return,
+        };
+        println!("Run A {}, run B {}", first.outcome, second.outcome);
+
+        match crate::llm::summarize_run_differences(&first.transcript(), &second.transcript()) {
+            Ok(summary) => println!("{}", summary.trim()),
+            Err(e) => {
+                println!(
+                    "LLM unavailable ({}); comparing structurally",
+                    e.lines().next().unwrap_or_default().trim_end_matches(':')
+                );
+                let differences = crate::run_compare::structural_diff(&first, &second);
+                if differences.is_empty() {
+                    println!("No behavioral differences between the two runs");
+                }
+                for difference in differences {
+                    println!("  - {}", difference);
+                }
+            }
+        }
+    }
+
+    /// 运行一次目标程序直到结束，记录每次断点停止的位置和 vars 的值；无法启动时返回 None
+    fn record_run(&mut self, vars: &[String], args: &[String]) -> Option<RunRecord> {
+        let mut record = RunRecord::new(args);
+        if !self.start_inferior(&args.to_vec()) {
+            return None;
+        }
+        let mut status = self.continue_inferior();
+        loop {
+            match status {
+                Ok(Status::Stopped(signal::Signal::SIGTRAP, _)) if self.at_breakpoint() => {
+                    if record.stops.len() == MAX_RECORDED_STOPS {
+                        record.outcome =
+                            format!("was still running after {} stops", MAX_RECORDED_STOPS);
+                        break;
+                    }
+                    let addr = self
+                        .inferior
+                        .as_ref()
+                        .and_then(|inferior| inferior.trapped_breakpoint(&self.break_point).ok())
+                        .flatten()
+                        .unwrap();
+                    let values = vars
+                        .iter()
+                        .map(|var| self.read_variable(var).unwrap_or_else(|e| e))
+                        .collect();
+                    record.stops.push(StopRecord {
+                        location: self.describe_location(addr),
+                        values,
+                    });
+                    status = self.resume_inferior();
+                }
+                Ok(Status::Exited(code)) => {
+                    record.outcome = format!("exited with status {}", code);
+                    break;
+                }
+                Ok(Status::Signaled(signal)) => {
+                    record.outcome = format!("was killed by {}", signal);
+                    break;
+                }
+                Ok(Status::Stopped(signal, rip)) => {
+                    record.outcome =
+                        format!("stopped by {} at {}", signal, self.describe_location(rip));
+                    break;
+                }
+                Ok(Status::Exec(rip)) => {
+                    record.outcome = format!("stopped after exec at {:#x}", rip);
+                    break;
+                }
+                Ok(Status::Syscall) | Ok(Status::Fork(..)) | Ok(Status::VforkDone) => {
+                    unreachable!("system call and fork stops are handled by Inferior")
+                }
+                Err(e) => {
+                    record.outcome = format!("failed: {}", e);
+                    break;
+                }
+            }
+        }
+        if let Some(mut inferior) = self.inferior.take() {
+            let _ = inferior.kill();
+        }
+        Some(record)
+    }
+
+    /// 值是目标程序中的代码地址时返回 ` <函数+偏移 at 文件:行>`，否则返回空字符串
+    fn annotate_value(&self, value: &Value) -> String {
+        match value {
+            Value::Int(addr) if *addr > 0 => self
+                .debug_data
+                .annotate_address(*addr as usize)
+                .map(|annotation| format!(" {}", annotation))
+                .unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+
+    /// 地址对应的 `函数 文件:行号`，没有调试信息时返回地址本身
+    fn describe_location(&self, addr: usize) -> String {
+        let line = self.debug_data.get_line_from_addr(addr);
+        let function = self.debug_data.get_function_from_addr(addr);
+        match (line, function) {
+            (Some(line), Some(function)) => format!("{} {}", function, line),
+            _ => format!("{:#x}", addr),
+        }
+    }
+
+    /// `run --until-fail`：反复运行目标程序，自动越过断点，直到某次运行崩溃、停在断点以外的地方或
+    /// 停在带 `if` 条件的断点上（条件就是失败条件），然后保留那次运行供检查；limit 为运行次数上限
+    fn run_until_fail(&mut self, limit: Option<usize>, args: &Vec<String>) {
+        let mut iteration = 0;
+        while limit != Some(iteration) {
+            iteration += 1;
+            if !self.start_run(args.clone()) {
+                return;
+            }
+            let mut status = self.continue_inferior();
+            loop {
+                match status {
+                    Ok(Status::Stopped(signal::Signal::SIGTRAP, _)) if self.at_breakpoint() => {
+                        if let Some((id, condition)) = self.met_condition() {
+                            println!(
+                                "Run {} failed: breakpoint {} condition '{}' is true",
+                                iteration, id, condition
+                            );
+                            self.report_status(status);
+                            return;
+                        }
+                        status = self.resume_inferior();
+                    }
+                    Ok(Status::Exited(_)) => {
+                        self.report_status(status);
+                        break;
+                    }
+                    _ => {
+                        println!("Run {} failed", iteration);
+                        self.report_status(status);
+                        return;
+                    }
+                }
+            }
+        }
+        println!("No failure in {} runs", iteration);
+    }
+
+    /// 在 addrs 处设置一个断点（可选地归入分组、带调用者条件）；被调试进程正在运行时立即写入 int3。
+    /// 已有断点的地址被跳过，其余地址都是这个断点的位置
+    fn set_breakpoint(&mut self, addrs: &[usize], location: &str, options: &BreakOptions) {
+        let mut id = None;
+        for &addr in addrs {
+            // 第一个位置带上设置，之后的位置从它复制
+            let result = match id {
+                Some(id) => self.break_point.add_location(id, addr),
+                None => self.break_point.insert(addr).map(|new_id| {
+                    let bp = self.break_point.get_mut(addr).unwrap();
+                    bp.location = Some(location.to_string());
+                    bp.group = options.group.clone();
+                    bp.caller = options.caller.clone();
+                    bp.nth_hit = options.nth_hit;
+                    bp.condition = options.condition.clone();
+                    id = Some(new_id);
+                }),
+            };
+            if let Err(existing) = result {
+                println!("Breakpoint {} is already set at {:#x}", existing, addr);
+            }
+        }
+        let id = match id {
+            Some(id) => id,
+            None => return,
+        };
+        let addrs = self.break_point.locations(id);
+        let addr = addrs[0];
+        self.last_breakpoint = Some(addr);
+        self.timeline.breakpoint(format!(
+            "set breakpoint {} at {:#x} ({})",
+            id, addr, location
+        ));
+        let mut conditions = Vec::new();
+        if addrs.len() > 1 {
+            conditions.push(format!("{} locations", addrs.len()));
+        }
+        if let Some(n) = options.nth_hit {
+            conditions.push(format!("only on call {}", n));
+        }
+        if let Some(caller) = &options.caller {
+            conditions.push(format!("only when called from {}", caller));
+        }
+        if let Some(condition) = &options.condition {
+            conditions.push(format!("only if {}", condition));
+        }
+        if conditions.is_empty() {
+            println!("Set breakpoint {} at {:#x}", id, addr);
+        } else {
+            println!(
+                "Set breakpoint {} at {:#x} ({})",
+                id,
+                addr,
+                conditions.join(", ")
+            );
+        }
+        if self.under_valgrind {
+            println!(
+                "Breakpoints are not inserted under valgrind; it takes effect on the next run"
+            );
+        } else if let Some(inferior) = self.inferior.as_mut() {
+            for addr in addrs {
+                match inferior.write_byte(addr, 0xcc) {
+                    Ok(orig_byte) => self.break_point.get_mut(addr).unwrap().orig_byte = orig_byte,
+                    Err(e) => println!("Error setting breakpoint at {:#x}: {}", addr, e),
+                }
+            }
+        }
+    }
+
+    /// 把 `break` 的参数解析为一个断点的全部地址，匹配到多个位置时取第一个；无法解析时为空
+    fn resolve_location(&self, location: &str) -> Vec<usize> {
+        self.resolve_locations(location)
+            .into_iter()
+            .next()
+            .map(|location| location.addrs)
+            .unwrap_or_default()
+    }
+
+    /// 把 `break` 的参数解析为全部匹配的位置：`*<addr>`、行号 `<line>` / `<file>:<line>`、
+    /// 函数名 `<func>` / `<file>:<func>`
+    fn resolve_locations(&self, location: &str) -> Vec<BreakLocation> {
+        let single = |addr: Option<usize>| {
+            addr.map(|addr| BreakLocation {
+                addrs: vec![addr],
+                spec: location.to_string(),
+                description: location.to_string(),
+            })
+            .into_iter()
+            .collect()
+        };
+        if let Some(addr) = location.strip_prefix('*') {
+            // Raw address or expression: break *0x4005b8, break *$rip+5
+            return single(match self.evaluate_address(addr.trim()) {
+                Ok(addr) => Some(addr),
+                Err(e) => {
+                    println!("{}", e);
+                    None
+                }
+            });
+        }
+        if let Some(symbol) = location.strip_prefix("-sym ") {
+            // ELF symbol: break -sym _ZN3foo3barEv
+            return single(
+                crate::symbols::lookup_text_symbol(&self.symbols_path, symbol.trim())
+                    .map(|addr| addr + self.debug_data.load_bias()),
+            );
+        }
+        let line_locations = |file: Option<&str>, line_number: usize| {
+            self.debug_data
+                .get_line_candidates(file, line_number)
+                .into_iter()
+                .map(|lines| BreakLocation {
+                    addrs: lines.iter().map(|line| line.address).collect(),
+                    spec: format!("{}:{}", lines[0].file, lines[0].number),
+                    description: format!("{}:{}", lines[0].file, lines[0].number),
+                })
+                .collect()
+        };
+        if let Ok(line_number) = location.parse::<usize>() {
+            // Line number: break 15
+            return line_locations(None, line_number);
+        }
+        let (file, name) = match location.rsplit_once(':') {
+            // C++ 的限定名 ns::func 不是 <file>:<func>
+            Some((file, name)) if !file.is_empty() && !file.ends_with(':') => (Some(file), name),
+            _ => (None, location),
+        };
+        if let (Some(file), Ok(line_number)) = (file, name.parse::<usize>()) {
+            // Line in file: break main.c:15，每个同名源文件中各有一个候选
+            return line_locations(Some(file), line_number);
+        }
+        // Function name: break func1 / break main.c:func1；同名的 static 函数和重载各是一个候选
+        let functions = self.debug_data.get_function_candidates(file, name);
+        if !functions.is_empty() {
+            return functions
+                .into_iter()
+                .map(|line| BreakLocation {
+                    addrs: vec![line.address],
+                    spec: format!("{}:{}", line.file, name),
+                    description: format!("{} at {}:{}", name, line.file, line.number),
+                })
+                .collect();
+        }
+        // 调试信息中没有的函数（汇编文件、没有 -g 编译的单元）退回 ELF 符号表
+        single(
+            crate::symbols::lookup_text_symbol(&self.symbols_path, location)
+                .map(|addr| addr + self.debug_data.load_bias()),
+        )
+    }
+
+    /// 列出编号菜单让用户选择一个、几个或全部位置；选择 0 或输入无效时不设置断点
+    fn choose_locations(&mut self, locations: Vec<BreakLocation>) -> Vec<BreakLocation> {
+        println!("[0] cancel");
+        println!("[1] all");
+        for (i, location) in locations.iter().enumerate() {
+            let others = match location.addrs.len() {
+                1 => String::new(),
+                count => format!(", {} locations", count),
+            };
+            println!(
+                "[{}] {} ({:#x}{})",
+                i + 2,
+                location.description,
+                location.addrs[0],
+                others
+            );
+        }
+        let line = match self.read_line("> ") {
+            Ok(line) => line,
+            Err(_) => {
+                println!("Cancelled");
+                return Vec::new();
+            }
+        };
+        let mut chosen = vec![false; locations.len()];
+        for choice in line.split(|c: char| c.is_whitespace() || c == ',') {
+            if choice.is_empty() {
+                continue;
+            }
+            match choice.parse::<usize>() {
+                Ok(0) => {
+                    println!("Cancelled");
+                    return Vec::new();
+                }
+                Ok(1) => chosen.iter_mut().for_each(|chosen| *chosen = true),
+                Ok(n) if n - 2 < locations.len() => chosen[n - 2] = true,
+                _ => {
+                    println!("Invalid choice: {}", choice);
+                    return Vec::new();
+                }
+            }
+        }
+        if !chosen.contains(&true) {
+            println!("Cancelled");
+        }
+        locations
+            .into_iter()
+            .zip(chosen)
+            .filter(|(_, chosen)| *chosen)
+            .map(|(location, _)| location)
+            .collect()
+    }
+
+    /// 启用或禁用 addr 处的断点，被调试进程正在运行时同步写入或撤掉 int3
+    fn set_breakpoint_enabled(&mut self, addr: usize, enabled: bool) {
+        let trapped_here = match self.inferior.as_ref() {
+            Some(inferior) => {
+                inferior
+                    .trapped_breakpoint(&self.break_point)
+                    .ok()
+                    .flatten()
+                    == Some(addr)
+            }
+            None => false,
+        };
+        let bp = match self.break_point.get_mut(addr) {
+            Some(bp) if bp.enabled != enabled => bp,
+            _ => return,
+        };
+        let under_valgrind = self.under_valgrind;
+        if let Some(inferior) = self.inferior.as_mut().filter(|_| !under_valgrind) {
+            if enabled {
+                match inferior.write_byte(addr, 0xcc) {
+                    Ok(orig_byte) => bp.orig_byte = orig_byte,
+                    Err(e) => {
+                        println!("Error setting breakpoint at {:#x}: {}", addr, e);
+                        return;
+                    }
+                }
+            } else if inferior.read_memory(addr, 1).ok() == Some(vec![0xcc]) {
+                // int3 没有成功写入时不能用记录的 orig_byte 覆盖原指令
+                if let Err(e) = inferior.write_byte(addr, bp.orig_byte) {
+                    println!("Error removing breakpoint at {:#x}: {}", addr, e);
+                    return;
+                }
+                // 正停在这个断点上时 rip 位于 int3 之后，回退到断点地址以便继续执行原指令
+                if trapped_here {
+                    if let Ok(mut regs) = inferior.getregs() {
+                        regs.rip = addr as u64;
+                        let _ = inferior.setregs(regs);
+                    }
+                }
+            }
+        }
+        bp.enabled = enabled;
+        let text = format!(
+            "{} breakpoint {} at {:#x}",
+            if enabled { "enabled" } else { "disabled" },
+            bp.id,
+            addr
+        );
+        self.timeline.breakpoint(text);
+    }
+
+    /// 删除 addr 处的断点
+    fn delete_breakpoint(&mut self, addr: usize) {
+        self.set_breakpoint_enabled(addr, false);
+        if let Some(bp) = self.break_point.get_mut(addr) {
+            let text = format!("deleted breakpoint {} at {:#x}", bp.id, addr);
+            self.timeline.breakpoint(text);
+        }
+        if let Some(bp) = self.break_point.remove(addr) {
+            if self.last_breakpoint == Some(addr) {
+                // 断点的其它位置仍然是上一个断点
+                self.last_breakpoint = self.break_point.locations(bp.id).first().copied();
+            }
+        }
+    }
+
+    /// 编号对应的断点的全部位置，不存在的编号打印提示后跳过；ids 为空时返回全部断点，按编号排序
+    fn breakpoints_by_id(&self, ids: &[usize]) -> Vec<usize> {
+        if ids.is_empty() {
+            let mut all: Vec<(usize, usize)> =
+                self.break_point.iter().map(|bp| (bp.id, bp.addr)).collect();
+            all.sort();
+            return all.into_iter().map(|(_, addr)| addr).collect();
+        }
+        ids.iter()
+            .flat_map(|id| {
+                let addrs = self.break_point.locations(*id);
+                if addrs.is_empty() && self.watchpoints.find_id(*id).is_none() {
+                    println!("No breakpoint number {}.", id);
+                }
+                addrs
+            })
+            .collect()
+    }
+
+    /// addrs 中的断点个数：同一个断点的多个位置只算一次
+    fn count_breakpoints(&self, addrs: &[usize]) -> usize {
+        let ids: HashSet<usize> = addrs
+            .iter()
+            .filter_map(|addr| self.break_point.iter().find(|bp| bp.addr == *addr))
+            .map(|bp| bp.id)
+            .collect();
+        ids.len()
+    }
+
+    /// 对分组中的每个断点执行启用、禁用或删除
+    fn update_group(&mut self, name: &str, action: GroupAction) {
+        if !self.break_point.has_group(name) {
+            println!("No breakpoint group named {}", name);
+            return;
+        }
+        let members = self.break_point.group_members(name);
+        let count = self.count_breakpoints(&members);
+        for addr in &members {
+            match action {
+                GroupAction::Enable => self.set_breakpoint_enabled(*addr, true),
+                GroupAction::Disable => self.set_breakpoint_enabled(*addr, false),
+                GroupAction::Delete => self.delete_breakpoint(*addr),
+            }
+        }
+        if let GroupAction::Delete = action {
+            self.break_point.remove_group(name);
+        }
+        println!(
+            "{} {} breakpoint(s) in group {}",
+            match action {
+                GroupAction::Enable => "Enabled",
+                GroupAction::Disable => "Disabled",
+                GroupAction::Delete => "Deleted",
+            },
+            count,
+            name
+        );
+    }
+
+    /// `info break`：按编号列出断点和监视点
+    fn print_breakpoints(&self) {
+        let mut breakpoints: Vec<_> = self.break_point.iter().collect();
+        if breakpoints.is_empty() && self.watchpoints.watchpoints.is_empty() {
+            println!("No breakpoints.");
+            return;
+        }
+        breakpoints.sort_by_key(|bp| (bp.id, bp.addr));
+        // 同一个断点的多个位置放在一起
+        let mut grouped: Vec<Vec<&Breakpoint>> = Vec::new();
+        for bp in breakpoints {
+            match grouped.last_mut() {
+                Some(locations) if locations[0].id == bp.id => locations.push(bp),
+                _ => grouped.push(vec![bp]),
+            }
+        }
+        println!("{:<5}{:<5}{:<20}{:<6}What", "Num", "Enb", "Address", "Hits");
+        let mut watchpoints = self.watchpoints.watchpoints.iter().peekable();
+        for locations in grouped.into_iter().map(Some).chain(std::iter::once(None)) {
+            let bp = locations.as_ref().map(|locations| locations[0]);
+            // 监视点与断点共用编号，按编号穿插在断点之间
+            while let Some(watchpoint) =
+                watchpoints.next_if(|watchpoint| bp.is_none_or(|bp| watchpoint.id < bp.id))
+            {
+                println!(
+                    "{:<5}{:<5}{:<#20x}{:<6}hw watchpoint {}",
+                    watchpoint.id, "y", watchpoint.addr, watchpoint.hits, watchpoint.expression
+                );
+                if let Some(scope) = &watchpoint.scope {
+                    println!("        valid in the frame at {:#x}", scope.frame_cfa);
+                }
+            }
+            let (bp, locations) = match (bp, locations) {
+                (Some(bp), Some(locations)) => (bp, locations),
+                _ => break,
+            };
+            let enabled = if bp.enabled { "y" } else { "n" };
+            if locations.len() == 1 {
+                println!(
+                    "{:<5}{:<5}{:<#20x}{:<6}{}",
+                    bp.id,
+                    enabled,
+                    bp.addr,
+                    bp.hits,
+                    self.describe_breakpoint(bp)
+                );
+            } else {
+                // 与 gdb 相同：一行汇总，之后每个位置一行，编号为 `<id>.<n>`
+                let hits: usize = locations.iter().map(|location| location.hits).sum();
+                println!(
+                    "{:<5}{:<5}{:<20}{:<6}{}",
+                    bp.id,
+                    enabled,
+                    "<MULTIPLE>",
+                    hits,
+                    bp.location.as_deref().unwrap_or_default()
+                );
+            }
+            if let Some(n) = bp.nth_hit {
+                println!("        stop only on hit {}", n);
+            }
+            if let Some(caller) = &bp.caller {
+                println!("        stop only if called from {}", caller);
+            }
+            if let Some(condition) = &bp.condition {
+                println!("        stop only if {}", condition);
+            }
+            if let Some(group) = &bp.group {
+                println!("        group {}", group);
+            }
+            for command in &bp.commands {
+                println!("        {}", command);
+            }
+            if locations.len() > 1 {
+                for (i, location) in locations.iter().enumerate() {
+                    println!(
+                        "{:<5}{:<5}{:<#20x}{:<6}{}",
+                        format!("{}.{}", bp.id, i + 1),
+                        enabled,
+                        location.addr,
+                        location.hits,
+                        self.describe_breakpoint(location)
+                    );
+                }
+            }
+        }
+    }
+
+    /// `info break` 的 What 列。位置在显示时用调试信息解析，共享库中的断点显示设置时的位置
+    fn describe_breakpoint(&self, bp: &Breakpoint) -> String {
+        match (
+            self.debug_data.get_function_from_addr(bp.addr),
+            self.debug_data.get_line_from_addr(bp.addr),
+        ) {
+            (Some(function), Some(line)) => format!("in {} at {}", function, line),
+            (Some(function), None) => format!("in {}", function),
+            _ => bp.location.clone().unwrap_or_default(),
+        }
+    }
+
+    fn print_groups(&self) {
+        let mut any = false;
+        for name in self.break_point.groups() {
+            any = true;
+            let members = self.break_point.group_members(name);
+            let enabled: Vec<usize> = members
+                .iter()
+                .copied()
+                .filter(|addr| self.break_point.get_enabled(*addr).is_some())
+                .collect();
+            println!(
+                "{}: {} breakpoint(s), {} enabled",
+                name,
+                self.count_breakpoints(&members),
+                self.count_breakpoints(&enabled)
+            );
+        }
+        if !any {
+            println!("No breakpoint groups");
+        }
+    }
+
+    /// 打印命令历史（编号从 1 开始），pattern 非空时只显示包含它的条目
+    fn print_history(&self, pattern: Option<&str>) {
+        for (i, entry) in self.readline.history().iter().enumerate() {
+            match pattern {
+                Some(pattern) if !entry.contains(pattern) => {}
+                _ => println!("{:>5}  {}", i + 1, entry),
+            }
+        }
+    }
+
+    /// 读取一行输入：优先取排队的行，否则从终端读取。
+    /// 粘贴的多行文本会作为一行返回，这里按行拆开，其余的行留到后面逐条执行
+    fn read_line(&mut self, prompt: &str) -> Result<String, ReadlineError> {
+        if let Some(line) = self.pending_lines.pop_front() {
+            return Ok(line);
+        }
+        let text = self.readline.readline(prompt)?;
+        let mut lines = text
+            .split(&['\n', '\r'][..])
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.to_string());
+        let first = lines.next().unwrap_or_default();
+        self.pending_lines.extend(lines);
+
+        for line in std::iter::once(&first).chain(self.pending_lines.iter()) {
+            if !line.trim().is_empty() {
+                self.readline.add_history_entry(line.as_str());
+            }
+        }
+        if let Some(history_path) = &self.history_path {
+            if let Err(err) = self.readline.save_history(history_path) {
+                println!(
+                    "Warning: failed to save history file at {}: {}",
+                    history_path, err
+                );
+            }
+        }
+        Ok(first)
+    }
+
+    /// 读取一条完整的命令，行尾的反斜杠表示命令在下一行继续
+    fn read_command_line(&mut self) -> Result<String, ReadlineError> {
+        let prompt = self.expand_prompt();
+        let mut line = self.read_line(&prompt)?;
+        while line.ends_with('\\') {
+            line.pop();
+            line.push_str(&self.read_line("> ")?);
+        }
+        Ok(line)
+    }
+
+    /// 把 `set prompt` 设置的提示符中的 `{state}`、`{func}`、`{thread}` 替换为当前的状态
+    fn expand_prompt(&self) -> String {
+        if !self.prompt.contains('{') {
+            return self.prompt.clone();
+        }
+        let (state, thread) = match self.inferior.as_ref() {
+            Some(inferior) => ("stopped", inferior.pid().to_string()),
+            None => ("none", "-".to_string()),
+        };
+        let function = self
+            .selected_pc()
+            .ok()
+            .and_then(|pc| self.debug_data.get_function_from_addr(pc))
+            .unwrap_or_else(|| "-".to_string());
+        self.prompt
+            .replace("{state}", state)
+            .replace("{func}", &function)
+            .replace("{thread}", &thread)
+    }
+
+    /// 读取 `commands` 的命令体，直到单独一行的 `end`
+    fn read_command_block(&mut self) -> Result<Vec<String>, ReadlineError> {
+        let mut body = Vec::new();
+        loop {
+            let line = self.read_line(">")?;
+            let line = line.trim();
+            if line == "end" {
+                return Ok(body);
+            }
+            if !line.is_empty() {
+                body.push(line.to_string());
+            }
+        }
+    }
+
+    /// 停在带 `if` 条件的断点上时返回断点编号和条件；continue 只在条件为真时停在这样的断点上
+    fn met_condition(&self) -> Option<(usize, String)> {
+        let addr = self
+            .inferior
+            .as_ref()?
+            .trapped_breakpoint(&self.break_point)
+            .ok()??;
+        let bp = self.break_point.get_enabled(addr)?;
+        Some((bp.id, bp.condition.clone()?))
+    }
+
+    /// 被调试进程是否停在刚命中的断点上
+    fn at_breakpoint(&self) -> bool {
+        match self.inferior.as_ref() {
+            Some(inferior) => inferior
+                .trapped_breakpoint(&self.break_point)
+                .ok()
+                .flatten()
+                .is_some(),
+            None => false,
+        }
+    }
+
+    /// 停在某个断点上时，把它的 `commands` 排到输入队列最前面
+    fn queue_breakpoint_commands(&mut self) {
+        let bp_addr = match self.inferior.as_ref() {
+            Some(inferior) => inferior
+                .trapped_breakpoint(&self.break_point)
+                .ok()
+                .flatten(),
+            None => None,
+        };
+        let breakpoints = &self.break_point;
+        if let Some(bp) = bp_addr.and_then(|addr| breakpoints.get_enabled(addr)) {
+            queue_lines(&mut self.pending_lines, &bp.commands);
+        }
+    }
+
+    /// This function prompts the user to enter a command, and continues re-prompting until the user
+    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
+    ///
+    /// You don't need to read, understand, or modify this function.
+    fn get_next_command(&mut self) -> DebuggerCommand {
+        // 上一条命令已经执行完，结束它的输出管道
+        self.output_pipe = None;
+        if let Some(started) = self.command_started.take() {
+            if self.maint_time {
+                println!(
+                    "Command execution time: {:.6} s",
+                    started.elapsed().as_secs_f64()
+                );
+            }
+        }
+        loop {
+            // Print prompt and get next line of user input
+            match self.read_command_line() {
+                Err(ReadlineError::Interrupted) => {
+                    // User pressed ctrl+c. We're going to ignore it
+                    println!("Type \"quit\" to exit");
+                }
+                Err(ReadlineError::Eof) => {
+                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
+                    return DebuggerCommand::Quit;
+                }
+                Err(err) => {
+                    panic!("Unexpected I/O error: {:?}", err);
+                }
+                Ok(line) => {
+                    if line.trim().len() == 0 {
+                        continue;
+                    }
+                    // `cmd | pipeline`：命令的输出交给 shell 管道处理
+                    let (command, pipeline) = split_pipeline(&line);
+                    let tokens: Vec<&str> = command.split_whitespace().collect();
+                    if tokens.is_empty() {
+                        println!("Unrecognized command.");
+                        continue;
+                    }
+                    // 无论命令本身是否合法，都要读完命令体，避免把它们当成普通命令执行
+                    let block = if ["commands", "hook-stop", "hook-exited"].contains(&tokens[0]) {
+                        match self.read_command_block() {
+                            Ok(block) => block,
+                            Err(_) => continue,
+                        }
+                    } else {
+                        Vec::new()
+                    };
+                    // 内置命令优先，其次是插件提供的命令
+                    let parsed = match DebuggerCommand::from_tokens(&tokens) {
+                        Some(cmd) => Some(cmd),
+                        None if self.plugins.has_command(tokens[0]) => Some(
+                            DebuggerCommand::Plugin(tokens[0].to_string(), tokens[1..].join(" ")),
+                        ),
+                        None => None,
+                    };
+                    if let Some(mut cmd) = parsed {
+                        if let DebuggerCommand::Commands(body)
+                        | DebuggerCommand::HookStop(body)
+                        | DebuggerCommand::HookExited(body) = &mut cmd
+                        {
+                            *body = block;
+                        }
+                        if let DebuggerCommand::SetPrompt(prompt) = &mut cmd {
+                            *prompt = prompt_argument(&line);
+                        }
+                        if let Some(pipeline) = pipeline {
+                            if pipeline.is_empty() {
+                                println!("Missing shell command after |");
+                                continue;
+                            }
+                            if cmd.starts_process() {
+                                // 被调试进程会继承重定向后的标准输出，导致管道无法结束
+                                println!("The output of {} cannot be piped", tokens[0]);
+                                continue;
+                            }
+                            match OutputPipe::start(pipeline) {
+                                Ok(pipe) => self.output_pipe = Some(pipe),
+                                Err(err) => {
+                                    println!("Error creating pipe: {}", err);
+                                    continue;
+                                }
+                            }
+                        }
+                        if self.maint_time {
+                            self.command_started = Some(Instant::now());
+                        }
+                        self.timeline.command(line.trim());
+                        return cmd;
+                    } else if !DebuggerCommand::is_command_name(tokens[0]) {
+                        // 已知命令的参数错误时 from_tokens 已经打印了用法
+                        let suggestions = DebuggerCommand::suggestions(tokens[0]);
+                        if suggestions.is_empty() {
+                            println!("Unrecognized command '{}'.", tokens[0]);
+                        } else {
+                            println!(
+                                "Unrecognized command '{}'. Did you mean {}?",
+                                tokens[0],
+                                suggestions
+                                    .iter()
+                                    .map(|name| format!("'{}'", name))
+                                    .collect::<Vec<_>>()
+                                    .join(" or ")
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 逗号分隔的列表，例如 CPU 编号
+fn join_values(values: &[usize]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// ELF 文件头中的程序入口地址
+fn entry_point(path: &str) -> Option<usize> {
+    ElfSummary::from_file(path)
+        .ok()
+        .map(|elf| elf.entry as usize)
+}
+
+impl Environment for Debugger {
+    fn value_of(&self, name: &str) -> Result<Value, String> {
+        match name.strip_prefix('$') {
+            Some(name) => self.dollar_value(name),
+            None => self.variable_value(name),
+        }
+    }
+
+    fn address_of(&self, name: &str) -> Result<usize, String> {
+        self.variable_address(name)
+    }
+
+    fn read_word(&self, addr: usize) -> Result<i64, String> {
+        let inferior = self
+            .inferior
+            .as_ref()
+            .ok_or_else(|| "No inferior running".to_string())?;
+        let bytes = inferior
+            .read_memory(addr, 8)
+            .map_err(|e| format!("Cannot access memory at {:#x}: {}", addr, e))?;
+        Ok(i64::from_le_bytes(
+            <[u8; 8]>::try_from(&bytes[..8]).unwrap(),
+        ))
+    }
+}
+
+/// `print` 的参数是变量（可带 `file.c::` 前缀和 `.成员` 路径）而不是表达式
+fn is_variable_path(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '.' || c == ':')
+}
+
+/// 把 lines 按顺序排到输入队列最前面
+fn queue_lines(pending_lines: &mut VecDeque<String>, lines: &[String]) {
+    for line in lines.iter().rev() {
+        pending_lines.push_front(line.clone());
+    }
+}
+
+fn print_hook_change(name: &str, body: &[String]) {
+    if body.is_empty() {
+        println!("{} removed", name);
+    } else {
+        println!("{} set ({} command(s))", name, body.len());
+    }
+}
+
+/// 硬件监视点要覆盖的字节范围 (起始地址, 长度)；位字段按所在的字节监视
+fn watched_bytes(selected: &Member, var_addr: usize) -> (usize, usize) {
+    let len = match selected.bit_size {
+        Some(bits) => (selected.bit_position % 8 + bits).div_ceil(8),
+        None => selected.member_type.size,
+    };
+    (var_addr + selected.bit_position / 8, len)
+}
+
+/// 显示 line 前后各 CONTEXT_SOURCE_LINES 行源代码，line 本身用 `=>` 标出
+fn print_source_around(line: &Line) {
+    if let Ok(contents) = fs::read_to_string(&line.file) {
+        let first = line.number.saturating_sub(CONTEXT_SOURCE_LINES).max(1);
+        for (i, text) in contents
+            .lines()
+            .enumerate()
+            .skip(first - 1)
+            .take(2 * CONTEXT_SOURCE_LINES + 1)
+        {
+            let marker = if i + 1 == line.number { "=>" } else { "  " };
+            println!("{} {:<4} {}", marker, i + 1, text);
+        }
+    }
+}
+
+/// `set prompt` 之后的原始文本：两端的双引号去掉，引号内的空格保留；没有参数时恢复默认提示符
+fn prompt_argument(line: &str) -> String {
+    let text = line.trim_start();
+    let text = text["set".len()..].trim_start();
+    let text = text["prompt".len()..].trim();
+    match text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+    {
+        Some(quoted) => quoted.to_string(),
+        None if text.is_empty() => DEFAULT_PROMPT.to_string(),
+        None => text.to_string(),
+    }
+}
+
+/// `llm reload` / `llm status` 显示的配置
+fn print_llm_config(config: &crate::llm::LlmConfig) {
+    println!("Config file: {}", config.path);
+    println!("API base:    {}", config.api_base);
+    println!("Model:       {}", config.model);
+    println!("API key:     {}", config.masked_key());
+    match crate::llm::log_path() {
+        Some(path) => println!("Log file:    {}", path),
+        None => println!("Log file:    off"),
+    }
+}
+
+/// 两个路径是否指向同一个文件
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// 解析十进制或 0x 开头的十六进制长度
+fn parse_length(len: &str) -> Option<usize> {
+    if len.to_lowercase().starts_with("0x") {
+        usize::from_str_radix(&len[2..], 16).ok()
+    } else {
+        len.parse().ok()
+    }
+}
+
+fn parse_address(addr: &str) -> Option<usize> {
+    let addr_without_0x = if addr.to_lowercase().starts_with("0x") {
+        &addr[2..]
+    } else {
+        &addr
+    };
+    usize::from_str_radix(addr_without_0x, 16).ok()
+}
+
+/// 按信号策略处理停止：不需要停下的信号直接转交给进程，然后继续运行（stepping 时继续单步），
+/// 直到遇到需要交给用户的停止或进程结束
+fn skip_quiet_signals(
+    inferior: &mut Inferior,
+    signals: &SignalTable,
+    mut status: Result<Status, nix::Error>,
+    stepping: bool,
+) -> Result<Status, nix::Error> {
+    while let Ok(Status::Stopped(signal, _)) = status {
+        let policy = signals.policy(signal);
+        if policy.stop {
+            break;
+        }
+        if policy.print {
+            println!("Program received signal {}", signal);
+        }
+        let pass = if policy.pass { Some(signal) } else { None };
+        status = if stepping {
+            inferior.step(pass)
+        } else {
+            inferior.continue_run(pass)
+        };
+    }
+    status
+}
+
+/// 停在断点上（或 rip 处有断点）时，临时恢复原指令单步越过它再写回 int3。返回 None 表示已越过
+/// 断点或者不在断点上；单步期间进程 exec、退出或收到了其它信号时返回该状态，exec 时不写回 int3
+fn step_over_breakpoint(
+    inferior: &mut Inferior,
+    signals: &SignalTable,
+    breakpoints: &BreakpointManager,
+) -> Result<Option<Status>, nix::Error> {
+    let bp_addr = match inferior.breakpoint_to_step_over(breakpoints)? {
+        Some(bp_addr) => bp_addr,
+        None => return Ok(None),
+    };
+    let bp = breakpoints.get_enabled(bp_addr).unwrap();
+    let mut regs = inferior.getregs()?;
+    inferior.write_byte(bp_addr, bp.orig_byte)?;
+    regs.rip = bp_addr as u64;
+    inferior.setregs(regs)?;
+    let status = inferior.step(None);
+    match skip_quiet_signals(inferior, signals, status, true)? {
+        Status::Stopped(signal::Signal::SIGTRAP, _) => {
+            inferior.write_byte(bp_addr, 0xcc)?;
+            Ok(None)
+        }
+        status => Ok(Some(status)),
+    }
+}
+
+/// code 开头是否是一条 call 指令（`call rel32`，或 `call r/m64`，可带 REX 前缀）
+fn is_call_instruction(code: &[u8]) -> bool {
+    let code = match code.first() {
+        Some(0x40..=0x4f) => &code[1..],
+        _ => code,
+    };
+    match code {
+        [0xe8, ..] => true,
+        [0xff, modrm, ..] => (modrm >> 3) & 7 == 2,
+        _ => false,
+    }
+}
+
+/// 在返回地址 return_addr 处放临时断点并继续运行，直到函数返回到栈指针为 frame_rsp 的调用者。返回 None 表示已回到调用者（rip 为返回地址）；
+/// 途中遇到用户断点、信号或进程结束时返回该停止
+fn run_to_return(
+    inferior: &mut Inferior,
+    signals: &SignalTable,
+    breakpoints: &BreakpointManager,
+    return_addr: usize,
+    frame_rsp: u64,
+) -> Result<Option<Status>, nix::Error> {
+    let orig_byte = inferior.write_byte(return_addr, 0xcc)?;
+    loop {
+        let status = inferior.continue_run(None);
+        let status = skip_quiet_signals(inferior, signals, status, false);
+        match status {
+            Ok(Status::Stopped(signal::Signal::SIGTRAP, rip)) if rip == return_addr + 1 => {
+                let mut regs = inferior.getregs()?;
+                if regs.rsp == frame_rsp {
+                    inferior.write_byte(return_addr, orig_byte)?;
+                    regs.rip = return_addr as u64;
+                    inferior.setregs(regs)?;
+                    return Ok(None);
+                }
+                if breakpoints.get_enabled(return_addr).is_some() {
+                    // 递归调用中命中了同一地址上的用户断点
+                    return Ok(Some(Status::Stopped(signal::Signal::SIGTRAP, rip)));
+                }
+                // 递归调用中的更深一层经过返回地址：越过临时断点继续运行
+                inferior.write_byte(return_addr, orig_byte)?;
+                regs.rip = return_addr as u64;
+                inferior.setregs(regs)?;
+                let status = inferior.step(None);
+                match skip_quiet_signals(inferior, signals, status, true)? {
+                    Status::Stopped(signal::Signal::SIGTRAP, _) => {
+                        inferior.write_byte(return_addr, 0xcc)?;
+                    }
+                    status => return Ok(Some(status)),
+                }
+            }
+            status => {
+                // 进程可能已经结束，恢复失败可以忽略
+                let _ = inferior.write_byte(return_addr, orig_byte);
+                return status.map(Some);
+            }
+        }
+    }
+}
+
+/// 用 CFI 计算停在 pc 处的帧的调用者，寄存器取自进程当前的 rsp 和 rbp
+fn frame_caller(
+    cfi: &CallFrameInfo,
+    load_bias: usize,
+    inferior: &Inferior,
+    pc: usize,
+) -> Result<Option<UnwindRegs>, nix::Error> {
+    let regs = inferior.getregs()?;
+    let regs = UnwindRegs {
+        rip: pc as u64,
+        rsp: regs.rsp,
+        rbp: regs.rbp,
+    };
+    cfi.unwind((pc - load_bias) as u64, &regs, &|addr| {
+        inferior.read_word(addr)
+    })
+}
+
+/// 在 exits 的每个地址放临时断点后继续运行，直到在 CFA 为 frame_cfa 的帧（或它的调用者）中到达
+/// 其中一个地址。返回 None 表示已停在出口上（rip 为出口地址）；途中遇到用户断点、信号或进程结束时
+/// 返回该停止。更深的递归调用经过出口时越过临时断点继续运行
+fn run_to_exits(
+    inferior: &mut Inferior,
+    signals: &SignalTable,
+    breakpoints: &BreakpointManager,
+    cfi: Option<&CallFrameInfo>,
+    load_bias: usize,
+    exits: &[usize],
+    frame_cfa: u64,
+) -> Result<Option<Status>, nix::Error> {
+    // 已有用户断点的地址不需要再放临时断点
+    let mut planted = Vec::new();
+    for &addr in exits {
+        if breakpoints.get_enabled(addr).is_some() || planted.iter().any(|(a, _)| *a == addr) {
+            continue;
+        }
+        match inferior.write_byte(addr, 0xcc) {
+            Ok(orig_byte) => planted.push((addr, orig_byte)),
+            Err(e) => {
+                remove_temporary_breakpoints(inferior, &planted);
+                return Err(e);
+            }
+        }
+    }
+
+    loop {
+        let status = inferior.continue_run(None);
+        let status = skip_quiet_signals(inferior, signals, status, false);
+        let (addr, orig_byte) = match status {
+            Ok(Status::Stopped(signal::Signal::SIGTRAP, rip)) => {
+                match planted.iter().find(|(addr, _)| *addr + 1 == rip) {
+                    Some(&temporary) => temporary,
+                    None => {
+                        // 命中用户断点或者单步以外的陷阱
+                        remove_temporary_breakpoints(inferior, &planted);
+                        return status.map(Some);
+                    }
+                }
+            }
+            status => {
+                remove_temporary_breakpoints(inferior, &planted);
+                return status.map(Some);
+            }
+        };
+
+        let mut regs = inferior.getregs()?;
+        // 返回地址处的 rsp 就是调用者的 CFA，其它出口在函数内部，按 CFI 计算所在帧的 CFA
+        let cfa = if addr == *exits.last().unwrap() {
+            Some(regs.rsp)
+        } else {
+            match cfi {
+                Some(cfi) => frame_caller(cfi, load_bias, inferior, addr)?.map(|caller| caller.rsp),
+                None => None,
+            }
+        };
+        regs.rip = addr as u64;
+        if cfa.map(|cfa| cfa >= frame_cfa).unwrap_or(true) {
+            remove_temporary_breakpoints(inferior, &planted);
+            inferior.setregs(regs)?;
+            return Ok(None);
+        }
+        // 递归调用中的更深一层经过出口：越过临时断点继续运行
+        inferior.write_byte(addr, orig_byte)?;
+        inferior.setregs(regs)?;
+        let status = inferior.step(None);
+        match skip_quiet_signals(inferior, signals, status, true) {
+            Ok(Status::Stopped(signal::Signal::SIGTRAP, _)) => {
+                inferior.write_byte(addr, 0xcc)?;
+            }
+            status => {
+                remove_temporary_breakpoints(inferior, &planted);
+                return status.map(Some);
+            }
+        }
+    }
+}
+
+/// 恢复临时断点处的原始字节；进程可能已经结束，恢复失败可以忽略
+fn remove_temporary_breakpoints(inferior: &mut Inferior, planted: &[(usize, u8)]) {
+    for &(addr, orig_byte) in planted {
+        let _ = inferior.write_byte(addr, orig_byte);
+    }
+}
+
+/// 每个目标程序使用单独的历史文件 `~/.deet_history.d/<程序名>-<路径哈希>`，
+/// 不同项目的命令不会混在一起。目录不能创建时返回 None
+fn history_path_for(target: &str) -> Option<String> {
+    let target_path = fs::canonicalize(target)
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| target.to_string());
+    let name = std::path::Path::new(&target_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let dir = crate::home::base_dir()?.join(".deet_history.d");
+    fs::create_dir_all(&dir).ok()?;
+    Some(format!(
+        "{}/{}-{:016x}",
+        dir.display(),
+        name,
+        fnv1a_hash(target_path.as_bytes())
+    ))
 }
+
+/// 64 位 FNV-1a 哈希
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,283 @@
+//! 用 `MockBackend` 回放的假进程测试单步、断点和回溯逻辑，不需要启动真正的子进程。
+
+use super::*;
+use crate::backend::MockBackend;
+use nix::libc::user_regs_struct;
+
+/// int3 引起的 SIGTRAP 的 si_code
+const SI_KERNEL: i32 = 0x80;
+/// 单步引起的 SIGTRAP 的 si_code
+const TRAP_TRACE: i32 = 2;
+
+const CODE: usize = 0x401000;
+const STACK: usize = 0x7ff000;
+
+fn regs(rip: usize, rsp: usize, rbp: usize) -> user_regs_struct {
+    let mut regs = MockBackend::zeroed_regs();
+    regs.rip = rip as u64;
+    regs.rsp = rsp as u64;
+    regs.rbp = rbp as u64;
+    regs
+}
+
+fn trap(rip: usize) -> Status {
+    Status::Stopped(signal::Signal::SIGTRAP, rip)
+}
+
+/// 在 addr 处设置断点并写入 int3
+fn plant(inferior: &mut Inferior, breakpoints: &mut BreakpointManager, addr: usize) {
+    breakpoints.insert(addr).unwrap();
+    let orig_byte = inferior.write_byte(addr, 0xcc).unwrap();
+    breakpoints.get_mut(addr).unwrap().orig_byte = orig_byte;
+}
+
+fn byte_at(inferior: &Inferior, addr: usize) -> u8 {
+    inferior.read_memory(addr, 1).unwrap()[0]
+}
+
+fn rip(inferior: &Inferior) -> usize {
+    inferior.getregs().unwrap().rip as usize
+}
+
+#[test]
+fn steps_over_the_breakpoint_it_stopped_on() {
+    let mut backend = MockBackend::new(regs(CODE, STACK, 0));
+    // push rbp; mov rbp, rsp
+    backend.map_memory(CODE, &[0x55, 0x48, 0x89, 0xe5, 0x90, 0x90, 0x90, 0x90]);
+    backend.push_stop(trap(CODE + 1), None, SI_KERNEL);
+    backend.push_stop(trap(CODE + 1), None, TRAP_TRACE);
+    let mut inferior = Inferior::with_backend(Box::new(backend));
+    let mut breakpoints = BreakpointManager::new();
+    plant(&mut inferior, &mut breakpoints, CODE);
+
+    assert_eq!(inferior.continue_run(None).unwrap(), trap(CODE + 1));
+    assert_eq!(
+        inferior.trapped_breakpoint(&breakpoints).unwrap(),
+        Some(CODE)
+    );
+    let stopped = step_over_breakpoint(&mut inferior, &SignalTable::new(), &breakpoints);
+    assert_eq!(stopped.unwrap(), None);
+    // 执行了原来的 push rbp，int3 已经写回
+    assert_eq!(rip(&inferior), CODE + 1);
+    assert_eq!(byte_at(&inferior, CODE), 0xcc);
+    assert_eq!(breakpoints.get_enabled(CODE).unwrap().orig_byte, 0x55);
+}
+
+#[test]
+fn steps_over_a_breakpoint_set_at_rip() {
+    let mut backend = MockBackend::new(regs(CODE, STACK, 0));
+    backend.map_memory(CODE, &[0x55, 0x48, 0x89, 0xe5, 0x90, 0x90, 0x90, 0x90]);
+    // 单步停在 CODE 上，之后才在这里设置断点
+    backend.push_stop(trap(CODE), None, TRAP_TRACE);
+    backend.push_stop(trap(CODE + 1), None, TRAP_TRACE);
+    let mut inferior = Inferior::with_backend(Box::new(backend));
+    assert_eq!(inferior.step(None).unwrap(), trap(CODE));
+    let mut breakpoints = BreakpointManager::new();
+    plant(&mut inferior, &mut breakpoints, CODE);
+
+    assert_eq!(inferior.trapped_breakpoint(&breakpoints).unwrap(), None);
+    assert_eq!(
+        inferior.breakpoint_to_step_over(&breakpoints).unwrap(),
+        Some(CODE)
+    );
+    let stopped = step_over_breakpoint(&mut inferior, &SignalTable::new(), &breakpoints);
+    assert_eq!(stopped.unwrap(), None);
+    assert_eq!(rip(&inferior), CODE + 1);
+    assert_eq!(byte_at(&inferior, CODE), 0xcc);
+}
+
+#[test]
+fn disabled_breakpoint_is_not_stepped_over() {
+    let mut backend = MockBackend::new(regs(CODE, STACK, 0));
+    backend.map_memory(CODE, &[0x55, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90]);
+    let inferior = Inferior::with_backend(Box::new(backend));
+    let mut breakpoints = BreakpointManager::new();
+    breakpoints.insert(CODE).unwrap();
+    breakpoints.get_mut(CODE).unwrap().enabled = false;
+    assert_eq!(
+        inferior.breakpoint_to_step_over(&breakpoints).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn recognizes_call_instructions() {
+    assert!(is_call_instruction(&[0xe8, 0x10, 0x00]));
+    // call *%rax，带 REX 前缀的 call *%r8
+    assert!(is_call_instruction(&[0xff, 0xd0, 0x90]));
+    assert!(is_call_instruction(&[0x41, 0xff, 0xd0]));
+    // jmp *%rax
+    assert!(!is_call_instruction(&[0xff, 0xe0, 0x90]));
+    assert!(!is_call_instruction(&[0x55, 0x48, 0x89]));
+}
+
+/// `next` 越过 `call`：在返回地址放临时断点，越过递归调用中更深一层经过返回地址的那次停止
+#[test]
+fn next_runs_to_the_return_address_of_its_own_frame() {
+    let call = CODE + 0x10;
+    let return_addr = call + 5;
+    let mut backend = MockBackend::new(regs(call, STACK, 0));
+    backend.map_memory(call, &[0xe8, 0xeb, 0xef, 0xff, 0xff, 0x89, 0x45, 0xfc]);
+    // 更深一层的递归调用经过返回地址：rsp 更低
+    let deeper = STACK - 0x40;
+    backend.push_stop(
+        trap(return_addr + 1),
+        Some(regs(return_addr + 1, deeper, 0)),
+        SI_KERNEL,
+    );
+    backend.push_stop(trap(return_addr + 3), None, TRAP_TRACE);
+    // 回到这一帧
+    backend.push_stop(
+        trap(return_addr + 1),
+        Some(regs(return_addr + 1, STACK, 0)),
+        SI_KERNEL,
+    );
+    let mut inferior = Inferior::with_backend(Box::new(backend));
+    let breakpoints = BreakpointManager::new();
+
+    let stopped = run_to_return(
+        &mut inferior,
+        &SignalTable::new(),
+        &breakpoints,
+        return_addr,
+        STACK as u64,
+    );
+    assert_eq!(stopped.unwrap(), None);
+    assert_eq!(rip(&inferior), return_addr);
+    // 临时断点已经撤掉
+    assert_eq!(byte_at(&inferior, return_addr), 0x89);
+}
+
+#[test]
+fn next_stops_at_a_user_breakpoint_in_the_callee() {
+    let call = CODE + 0x10;
+    let return_addr = call + 5;
+    let callee = CODE + 0x100;
+    let mut backend = MockBackend::new(regs(call, STACK, 0));
+    backend.map_memory(call, &[0xe8, 0xeb, 0x00, 0x00, 0x00, 0x89, 0x45, 0xfc]);
+    backend.map_memory(callee, &[0x55, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90]);
+    backend.push_stop(trap(callee + 1), None, SI_KERNEL);
+    let mut inferior = Inferior::with_backend(Box::new(backend));
+    let mut breakpoints = BreakpointManager::new();
+    plant(&mut inferior, &mut breakpoints, callee);
+
+    let stopped = run_to_return(
+        &mut inferior,
+        &SignalTable::new(),
+        &breakpoints,
+        return_addr,
+        STACK as u64,
+    );
+    assert_eq!(stopped.unwrap(), Some(trap(callee + 1)));
+    assert_eq!(
+        inferior.trapped_breakpoint(&breakpoints).unwrap(),
+        Some(callee)
+    );
+    assert_eq!(byte_at(&inferior, return_addr), 0x89);
+}
+
+/// 把 body 补齐到 8 字节对齐（包括前面的长度字段）并加上长度
+fn cfi_entry(mut body: Vec<u8>) -> Vec<u8> {
+    while !(4 + body.len()).is_multiple_of(8) {
+        // DW_CFA_nop
+        body.push(0);
+    }
+    let mut entry = (body.len() as u32).to_le_bytes().to_vec();
+    entry.extend(body);
+    entry
+}
+
+/// 手工构造的 .eh_frame：每个函数都是 `push rbp; mov rbp, rsp` 开头的标准序言，
+/// FDE 中的地址是 8 字节的绝对地址
+fn eh_frame(functions: &[(usize, usize)]) -> Vec<u8> {
+    // CIE：版本 1，增强 "zR"，代码对齐 1，数据对齐 -8，返回地址寄存器 16，指针编码 absptr；
+    // 初始规则 CFA = rsp + 8，返回地址在 CFA - 8
+    let mut eh_frame = cfi_entry(vec![
+        0, 0, 0, 0, 1, b'z', b'R', 0, 1, 0x78, 16, 1, 0x00, 0x0c, 7, 8, 0x90, 1,
+    ]);
+    for &(start, len) in functions {
+        // CIE 指针是这个字段到 CIE 开头的距离
+        let cie_pointer = (eh_frame.len() + 4) as u32;
+        let mut body = cie_pointer.to_le_bytes().to_vec();
+        body.extend(&(start as u64).to_le_bytes());
+        body.extend(&(len as u64).to_le_bytes());
+        body.extend(&[
+            0,    // 增强数据长度
+            0x41, // advance_loc 1：push rbp 之后
+            0x0e, 16, // CFA = rsp + 16
+            0x86, 2,    // rbp 保存在 CFA - 16
+            0x43, // advance_loc 3：mov rbp, rsp 之后
+            0x0d, 6, // CFA = rbp + 16
+        ]);
+        eh_frame.extend(cfi_entry(body));
+    }
+    eh_frame.extend(&[0, 0, 0, 0]);
+    eh_frame
+}
+
+/// 停在函数入口（rbp 还是调用者的）时按 CFI 回溯：帧指针链会跳过调用者，CFI 不会
+#[test]
+fn cfi_backtrace_from_a_function_entry() {
+    let callee = CODE;
+    let main = CODE + 0x100;
+    let call_site = main + 0x20;
+    let outer: usize = 0x7f00_0040_1234;
+    let cfi = CallFrameInfo::from_eh_frame(eh_frame(&[(callee, 0x20), (main, 0x40)]), 0);
+
+    // main 的帧：rbp 指向保存的 rbp（0，最外层），之上是 main 的返回地址
+    let main_rbp = STACK + 0x10;
+    let entry_rsp = STACK - 8;
+    let mut stack = Vec::new();
+    stack.extend(&(call_site as u64).to_le_bytes()); // entry_rsp：callee 的返回地址
+    stack.extend(&[0; 16]);
+    stack.extend(&0u64.to_le_bytes()); // main_rbp：main 保存的 rbp
+    stack.extend(&(outer as u64).to_le_bytes()); // main 的返回地址
+    let mut backend = MockBackend::new(regs(callee, entry_rsp, main_rbp));
+    backend.map_memory(entry_rsp, &stack);
+    let inferior = Inferior::with_backend(Box::new(backend));
+
+    let caller = frame_caller(&cfi, 0, &inferior, callee).unwrap().unwrap();
+    assert_eq!(caller.rip, call_site as u64);
+    assert_eq!(caller.rsp, STACK as u64);
+    assert_eq!(caller.rbp, main_rbp as u64);
+
+    // 调用者的 rip 是返回地址，按 call 指令内的地址查找 CFI
+    let read_word = |addr: usize| inferior.read_word(addr);
+    let outermost = cfi
+        .unwind(caller.rip - 1, &caller, &read_word)
+        .unwrap()
+        .unwrap();
+    assert_eq!(outermost.rip, outer as u64);
+    assert_eq!(outermost.rsp, (main_rbp + 16) as u64);
+    assert_eq!(outermost.rbp, 0);
+
+    // 帧指针链在这里会把 main 的返回地址当成 callee 的返回地址
+    assert_eq!(inferior.read_word(main_rbp + 8).unwrap(), outer as u64);
+    // CFI 不覆盖的地址
+    assert!(cfi
+        .unwind(outer as u64, &outermost, &read_word)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn fault_while_stepping_over_a_breakpoint_is_reported() {
+    let mut backend = MockBackend::new(regs(CODE, STACK, 0));
+    // mov (%rax), %eax
+    backend.map_memory(CODE, &[0x8b, 0x00, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90]);
+    backend.push_stop(trap(CODE + 1), None, SI_KERNEL);
+    backend.push_fault(CODE, 0);
+    let mut inferior = Inferior::with_backend(Box::new(backend));
+    let mut breakpoints = BreakpointManager::new();
+    plant(&mut inferior, &mut breakpoints, CODE);
+    inferior.continue_run(None).unwrap();
+
+    let stopped = step_over_breakpoint(&mut inferior, &SignalTable::new(), &breakpoints);
+    assert_eq!(
+        stopped.unwrap(),
+        Some(Status::Stopped(signal::Signal::SIGSEGV, CODE))
+    );
+    assert_eq!(inferior.siginfo().unwrap().addr, 0);
+    // 单步没有完成，原指令保留在内存中，下次继续运行时会重新执行它
+    assert_eq!(byte_at(&inferior, CODE), 0x8b);
+}
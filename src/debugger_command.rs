@@ -1,57 +1,1073 @@
+use crate::fault_inject::parse_errno;
+use crate::inferior::{FollowFork, FrameArguments, Scheduler};
+use crate::monitor::Interval;
+use crate::signals::{self, SignalAction};
+use nix::sys::signal::Signal;
+
+/// `break <location>` 之后的选项
+#[derive(Debug, Clone, Default)]
+pub struct BreakOptions {
+    /// `group <name>`：归入断点分组
+    pub group: Option<String>,
+    /// `if-caller <func>`：只在调用链中有 func 时停下，否则自动继续运行
+    pub caller: Option<String>,
+    /// `@N`：只在第 N 次调用时停下
+    pub nth_hit: Option<usize>,
+    /// `if <expr>`：只在表达式为真时停下，必须是最后一个选项
+    pub condition: Option<String>,
+}
+
 pub enum DebuggerCommand {
     Quit,
+    /// 结束被调试进程，但不退出调试器
+    Kill,
+    /// `attach <pid>`：跟踪已经在运行的进程
+    Attach(i32),
+    /// 停止跟踪被调试进程，让它继续运行
+    Detach,
     Run(Vec<String>),
-    Continue,
+    /// `run --until-fail [N] [args]`：反复重新运行，直到出现失败或达到 N 次
+    RunUntilFail(Option<usize>, Vec<String>),
+    /// `assert-exit-code <n>`：检查本次运行的进程是否以状态 n 退出
+    AssertExitCode(i32),
+    /// `assert-reaches <location>`：检查本次运行中进程是否在 location 处停下过
+    AssertReaches(String),
+    /// 继续运行，越过前 N - 1 次断点命中
+    Continue(usize),
     Backtrace,
-    Break(String),
+    /// `frame [N]`：选中第 N 层栈帧，省略 N 时显示当前选中的帧
+    Frame(Option<usize>),
+    /// `up [N]`：选中向外 N 层的调用者
+    Up(usize),
+    /// `down [N]`：选中向内 N 层的被调用者
+    Down(usize),
+    /// 断点位置，以及位置之后的选项
+    Break(String, BreakOptions),
     NaturalBreak(String),
-    Next,
+    /// 运行到当前函数返回，打印返回值
+    Finish,
+    /// 逐行执行直到表达式为真
+    UntilExpr(String),
+    /// `callgraph start`：开始记录函数调用
+    CallgraphStart,
+    /// `callgraph stop [file]`：停止记录，打印调用图并可选地导出
+    CallgraphStop(Option<String>),
+    /// `trace syscalls [name,...] [to <file>]`：记录系统调用，可以只记录列出的调用、写到文件
+    TraceSyscalls(Vec<String>, Option<String>),
+    /// `trace syscalls off`
+    TraceSyscallsOff,
+    /// `inject-fault <function> [errno] [every N]`：让函数的第 N、2N……次调用失败，errno 为 None 时取默认值
+    InjectFault(String, Option<i32>, usize),
+    /// `inject-fault`：列出所有故障注入
+    InjectFaultList,
+    /// `inject-fault off [function]`：删除一个或全部故障注入
+    InjectFaultOff(Option<String>),
+    /// `catch plt <name>`：第一次调用延迟绑定的库函数、动态链接器解析它时停下
+    CatchPlt(String),
+    /// `catch plt`：列出所有 `catch plt`
+    CatchPltList,
+    /// `catch plt off [<name>]`：删除一个或全部 `catch plt`
+    CatchPltOff(Option<String>),
+    /// `monitor <expr> every <n> stops|seconds`：运行期间定期打印表达式的值
+    Monitor(String, Interval),
+    /// `monitor`：列出所有定期打印的表达式
+    MonitorList,
+    /// `monitor off [n]`：删除一个或全部
+    MonitorOff(Option<usize>),
+    /// `who-writes <var>`：用硬件监视点记录所有写入变量的代码位置，直到进程因别的原因停下
+    WhoWrites(String),
+    /// `watch <var>`：在变量上设置硬件写监视点，值改变时停下
+    Watch(String),
+    /// 执行 N 次 next，只打印最后一次停止的位置
+    Next(usize),
+    /// 执行 N 次 step（进入被调用的函数），只打印最后一次停止的位置
+    Step(usize),
+    /// `print <var>|<expr>`
     Print(String),
+    InfoElf,
+    InfoSections,
+    InfoSharedLibrary,
+    /// `info net`：打开的套接字，以及上次停止以来的收发字节数
+    InfoNet,
+    /// `info threads`：每个线程的名字、内核状态和所在函数
+    InfoThreads,
+    /// `info break` / `ib`：列出所有断点
+    InfoBreakpoints,
+    /// 当前帧的 CFA、返回地址和保存的寄存器
+    InfoFrame,
+    DumpMemory(String, String, String),
+    Restore(String, String),
+    CompareSections,
+    /// 最近设置的断点命中时自动执行的命令，命令体由输入循环读取到 `end` 为止
+    Commands(Vec<String>),
+    /// `hook-stop`：被调试进程每次停下时自动执行的命令，命令体同样读取到 `end` 为止，为空时删除
+    HookStop(Vec<String>),
+    /// `hook-exited`：被调试进程退出或被信号杀死时自动执行的命令
+    HookExited(Vec<String>),
+    /// 列出命令历史，可选地只显示包含给定文本的条目
+    History(Option<String>),
+    /// `delete [n...]`：删除指定编号的断点，没有编号时删除全部
+    Delete(Vec<usize>),
+    /// `enable [n...]` / `disable [n...]`：没有编号时作用于全部断点
+    Enable(Vec<usize>),
+    Disable(Vec<usize>),
+    GroupCreate(String),
+    GroupEnable(String),
+    GroupDisable(String),
+    GroupDelete(String),
+    GroupList,
+    /// `guard <addr> <len>`：在缓冲区末尾之后放置不可访问的保护页
+    Guard(String, String),
+    /// `handle <signal>|all [actions]`、`info signals [<signal>]`：修改策略并显示这些信号，
+    /// 没有动作时只显示
+    Handle(Vec<Signal>, Vec<SignalAction>),
+    /// `target valgrind -- <prog> [args]`：在 Valgrind 的 gdbserver 下运行目标程序
+    TargetValgrind(String, Vec<String>),
+    /// `compare-runs [-p var]... <args A> -- <args B>`：观察的变量、两次运行的参数
+    CompareRuns(Vec<String>, Vec<String>, Vec<String>),
+    /// `find-func <描述>`：在本地语义索引中查找与描述最相似的函数
+    FindFunc(String),
+    /// `nb --from-bug <报告>`：请 LLM 根据错误报告建议断点，逐项确认后设置
+    NaturalBreakFromBug(String),
+    /// 请 LLM 针对当前停止位置给出修复补丁，可附带问题描述
+    SuggestFix(Option<String>),
+    /// `ask <问题>`：把当前帧的局部变量、指针指向的内存和源代码交给 LLM 回答问题
+    Ask(String),
+    /// `llm reload`：清空缓存并重新读取 LLM 配置
+    LlmReload,
+    /// `llm status`：显示使用的 LLM 配置，并用一次请求验证 api_key
+    LlmStatus,
+    /// 把会话时间线导出为 Chrome trace 或 speedscope 文件
+    ExportTimeline(String),
+    /// `journal [N]`：查看会话日志中最近的 N 条记录
+    Journal(usize),
+    /// 插件提供的命令：命令名、其后的参数文本（由输入循环而不是 from_tokens 产生）
+    Plugin(String, String),
+    /// `set cwd <dir>`：之后启动的被调试进程使用的工作目录
+    SetCwd(String),
+    /// `set print frame-arguments all|scalars|none`：回溯中显示哪些参数的值
+    SetFrameArguments(FrameArguments),
+    /// `set context on|off`：每次停止时是否显示 `context` 摘要
+    SetAutoContext(bool),
+    /// `x/<count><format><size> [<addr>]`：`/` 之后的部分（可以为空）和地址表达式，
+    /// 没有地址时接着上一次 `x` 显示的内存
+    Examine(String, Option<String>),
+    /// `info registers [<name>...]`：不带参数时显示全部寄存器
+    InfoRegisters(Vec<String>),
+    /// `set reg <name> <value>`：寄存器名（不带 `$`）和值的表达式
+    SetRegister(String, String),
+    /// `set print deltas on|off`：每次停止时是否列出与上一次停止相比改变了的寄存器和局部变量
+    SetPrintDeltas(bool),
+    /// `set llm.confirm on|off`：`nb` 设置断点之前是否要求确认
+    SetLlmConfirm(bool),
+    /// `set confirm on|off`：退出或删除全部断点之前是否要求确认
+    SetConfirm(bool),
+    /// `set prompt "<text>"`：提示符，参数由输入循环按原文填入
+    SetPrompt(String),
+    /// `set llm.log <file>|off`：把每次 LLM 请求记录到 JSONL 文件
+    SetLlmLog(Option<String>),
+    /// `set net-tracking on|off`：是否统计套接字收发的字节数
+    SetNetTracking(bool),
+    /// `set follow-fork-mode parent|child`：被调试进程 fork 之后跟随哪一个进程
+    SetFollowFork(FollowFork),
+    /// `set preload <lib.so>...`：下次运行时通过 LD_PRELOAD 注入的库；`set preload off` 时为空
+    SetPreload(Vec<String>),
+    /// `set affinity <cpu>[,<cpu>...]`：下次运行时把被调试进程绑定到这些 CPU；`set affinity off` 时为空
+    SetAffinity(Vec<usize>),
+    /// `set scheduler <policy> [priority]`：下次运行时的调度策略；`set scheduler off` 时为 None
+    SetScheduler(Option<Scheduler>),
+    /// `set $name = <expr>`：便利变量的名字（不带 `$`）和表达式
+    SetConvenience(String, String),
+    /// 当前位置的摘要：源代码、寄存器、栈顶几帧和 display 表达式的值
+    Context,
+    /// 添加一个每次停止时显示的变量；不带参数时显示全部
+    Display(Option<String>),
+    /// `info display`：按编号列出 display 表达式和监视点
+    InfoDisplay,
+    /// `maint info line-table [file]`：按地址顺序列出行号表
+    MaintLineTable(Option<String>),
+    /// `maint print symbols`：打印解析出的全部文件、函数、变量和行号，以及读取它们的耗时
+    MaintPrintSymbols,
+    /// `maint time on|off`：每条命令执行完后显示耗时
+    MaintTime(bool),
 }
 
+const TRACE_USAGE: &str =
+    "Usage: trace syscalls [<name>,<name>...] [to <file>] | trace syscalls off";
+
+const HANDLE_USAGE: &str = "Usage: handle <signal>|all [stop|nostop|print|noprint|pass|nopass]...";
+
+/// 所有命令名及别名，用于给拼错的命令提供建议
+const COMMAND_NAMES: &[&str] = &[
+    "q",
+    "quit",
+    "k",
+    "kill",
+    "attach",
+    "detach",
+    "assert-exit-code",
+    "assert-reaches",
+    "r",
+    "run",
+    "c",
+    "cont",
+    "continue",
+    "bt",
+    "back",
+    "backtrace",
+    "f",
+    "frame",
+    "up",
+    "down",
+    "b",
+    "break",
+    "n",
+    "next",
+    "s",
+    "step",
+    "fin",
+    "until-expr",
+    "who-writes",
+    "watch",
+    "callgraph",
+    "trace",
+    "inject-fault",
+    "catch",
+    "monitor",
+    "finish",
+    "p",
+    "print",
+    "nb",
+    "find-func",
+    "llm",
+    "i",
+    "ib",
+    "info",
+    "dump",
+    "x",
+    "export",
+    "journal",
+    "restore",
+    "set",
+    "compare-sections",
+    "compare-runs",
+    "suggest-fix",
+    "ask",
+    "commands",
+    "hook-stop",
+    "hook-exited",
+    "history",
+    "group",
+    "d",
+    "delete",
+    "enable",
+    "disable",
+    "guard",
+    "handle",
+    "target",
+    "context",
+    "display",
+    "mt",
+    "maint",
+    "maintenance",
+];
+
 impl DebuggerCommand {
+    pub fn is_command_name(name: &str) -> bool {
+        COMMAND_NAMES.contains(&name) || name.starts_with("x/")
+    }
+
+    /// 命令是否会启动新的进程。这些进程继承调试器的标准输出，输出不能交给管道
+    pub fn starts_process(&self) -> bool {
+        matches!(
+            self,
+            DebuggerCommand::Run(_)
+                | DebuggerCommand::RunUntilFail(..)
+                | DebuggerCommand::TargetValgrind(..)
+                | DebuggerCommand::CompareRuns(..)
+        )
+    }
+
+    /// 返回与 name 编辑距离最近的命令名（距离不超过 2）。过短的别名不参与匹配，否则几乎任何输入都会命中
+    pub fn suggestions(name: &str) -> Vec<&'static str> {
+        let mut best = Vec::new();
+        let mut best_distance = 3;
+        for candidate in COMMAND_NAMES.iter().filter(|c| c.len() > 2) {
+            let distance = edit_distance(name, candidate);
+            if distance < best_distance {
+                best_distance = distance;
+                best.clear();
+            }
+            if distance == best_distance {
+                best.push(*candidate);
+            }
+        }
+        best
+    }
+
     pub fn from_tokens(tokens: &Vec<&str>) -> Option<DebuggerCommand> {
         match tokens[0] {
             "q" | "quit" => Some(DebuggerCommand::Quit),
+            "k" | "kill" => Some(DebuggerCommand::Kill),
+            "attach" => match tokens.get(1).map(|pid| pid.parse::<i32>()) {
+                Some(Ok(pid)) if pid > 0 && tokens.len() == 2 => Some(DebuggerCommand::Attach(pid)),
+                _ => {
+                    println!("Usage: attach <pid>");
+                    None
+                }
+            },
+            "detach" => Some(DebuggerCommand::Detach),
+            "assert-exit-code" => match tokens.get(1).map(|code| code.parse::<i32>()) {
+                Some(Ok(code)) if tokens.len() == 2 => Some(DebuggerCommand::AssertExitCode(code)),
+                _ => {
+                    println!("Usage: assert-exit-code <n>");
+                    None
+                }
+            },
+            "assert-reaches" => {
+                if tokens.len() != 2 {
+                    println!("Usage: assert-reaches <location>");
+                    None
+                } else {
+                    Some(DebuggerCommand::AssertReaches(tokens[1].to_string()))
+                }
+            }
             "r" | "run" => {
+                if tokens.get(1) == Some(&"--until-fail") {
+                    let limit = tokens.get(2).and_then(|n| n.parse::<usize>().ok());
+                    let args_start = if limit.is_some() { 3 } else { 2 };
+                    let args = tokens[args_start..].to_vec();
+                    return Some(DebuggerCommand::RunUntilFail(
+                        limit,
+                        args.iter().map(|s| s.to_string()).collect(),
+                    ));
+                }
                 let args = tokens[1..].to_vec();
                 Some(DebuggerCommand::Run(
                     args.iter().map(|s| s.to_string()).collect(),
                 ))
             }
-            "c" | "cont" | "continue" => Some(DebuggerCommand::Continue),
+            "c" | "cont" | "continue" => match parse_count(tokens) {
+                Some(count) => Some(DebuggerCommand::Continue(count)),
+                None => {
+                    println!("Usage: c|continue [N]");
+                    None
+                }
+            },
             "bt" | "back" | "backtrace" => Some(DebuggerCommand::Backtrace),
-            "b" | "break" => {
+            "f" | "frame" => match tokens.get(1).map(|level| level.parse::<usize>()) {
+                None => Some(DebuggerCommand::Frame(None)),
+                Some(Ok(level)) if tokens.len() == 2 => Some(DebuggerCommand::Frame(Some(level))),
+                _ => {
+                    println!("Usage: f|frame [N]");
+                    None
+                }
+            },
+            "up" => match parse_count(tokens) {
+                Some(count) => Some(DebuggerCommand::Up(count)),
+                None => {
+                    println!("Usage: up [N]");
+                    None
+                }
+            },
+            "down" => match parse_count(tokens) {
+                Some(count) => Some(DebuggerCommand::Down(count)),
+                None => {
+                    println!("Usage: down [N]");
+                    None
+                }
+            },
+            "fin" | "finish" => Some(DebuggerCommand::Finish),
+            "until-expr" => {
                 if tokens.len() < 2 {
-                    println!("Usage: b|break <location>");
+                    println!("Usage: until-expr <expr>");
                     None
                 } else {
-                    let args = tokens[1..].to_vec();
-                    Some(DebuggerCommand::Break(
-                        args.iter().map(|s| s.to_string()).collect(),
+                    Some(DebuggerCommand::UntilExpr(tokens[1..].join(" ")))
+                }
+            }
+            "callgraph" => match (tokens.get(1).copied(), tokens.len()) {
+                (Some("start"), 2) => Some(DebuggerCommand::CallgraphStart),
+                (Some("stop"), 2) => Some(DebuggerCommand::CallgraphStop(None)),
+                (Some("stop"), 3) => {
+                    Some(DebuggerCommand::CallgraphStop(Some(tokens[2].to_string())))
+                }
+                _ => {
+                    println!("Usage: callgraph start | callgraph stop [<file>.dot|<file>.json]");
+                    None
+                }
+            },
+            "trace" => match tokens.get(1).copied() {
+                Some("syscalls") if tokens.get(2) == Some(&"off") && tokens.len() == 3 => {
+                    Some(DebuggerCommand::TraceSyscallsOff)
+                }
+                Some("syscalls") => {
+                    // `to <file>` 只能出现在最后
+                    let (names, output) = match tokens.iter().position(|token| *token == "to") {
+                        Some(pos) if pos + 2 == tokens.len() => {
+                            (&tokens[2..pos], Some(tokens[pos + 1].to_string()))
+                        }
+                        Some(_) => {
+                            println!("{}", TRACE_USAGE);
+                            return None;
+                        }
+                        None => (&tokens[2..], None),
+                    };
+                    let filter = names
+                        .iter()
+                        .flat_map(|names| names.split(','))
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                    Some(DebuggerCommand::TraceSyscalls(filter, output))
+                }
+                _ => {
+                    println!("{}", TRACE_USAGE);
+                    None
+                }
+            },
+            "monitor" => parse_monitor(tokens),
+            "catch" => match &tokens[1..] {
+                ["plt"] => Some(DebuggerCommand::CatchPltList),
+                ["plt", "off"] => Some(DebuggerCommand::CatchPltOff(None)),
+                ["plt", "off", name] => Some(DebuggerCommand::CatchPltOff(Some(name.to_string()))),
+                ["plt", name] => Some(DebuggerCommand::CatchPlt(name.to_string())),
+                _ => {
+                    println!("Usage: catch plt <name> | catch plt | catch plt off [<name>]");
+                    None
+                }
+            },
+            "inject-fault" => match tokens.get(1).copied() {
+                None => Some(DebuggerCommand::InjectFaultList),
+                Some("off") if tokens.len() <= 3 => Some(DebuggerCommand::InjectFaultOff(
+                    tokens.get(2).map(|function| function.to_string()),
+                )),
+                Some(function) => {
+                    let mut errno = None;
+                    let mut every = 1;
+                    let mut rest = &tokens[2..];
+                    if let Some(token) = rest.first().filter(|token| **token != "every") {
+                        match parse_errno(token) {
+                            Some(value) => errno = Some(value),
+                            None => {
+                                println!("Unknown errno '{}'", token);
+                                return None;
+                            }
+                        }
+                        rest = &rest[1..];
+                    }
+                    // `every 3` 或 `every 3rd`
+                    let nth = |n: &str| {
+                        n.trim_end_matches(|c: char| c.is_ascii_alphabetic())
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|n| *n > 0)
+                    };
+                    match rest {
+                        [] => {}
+                        ["every", n] if nth(n).is_some() => every = nth(n).unwrap(),
+                        _ => {
+                            println!("Usage: inject-fault <function> [errno] [every <N>] | inject-fault off [<function>]");
+                            return None;
+                        }
+                    }
+                    Some(DebuggerCommand::InjectFault(
+                        function.to_string(),
+                        errno,
+                        every,
                     ))
                 }
+            },
+            "who-writes" => {
+                if tokens.len() != 2 {
+                    println!("Usage: who-writes <variable>");
+                    None
+                } else {
+                    Some(DebuggerCommand::WhoWrites(tokens[1].to_string()))
+                }
+            }
+            "watch" => {
+                if tokens.len() != 2 {
+                    println!("Usage: watch <variable>");
+                    None
+                } else {
+                    Some(DebuggerCommand::Watch(tokens[1].to_string()))
+                }
             }
-            "n" | "next" => Some(DebuggerCommand::Next),
+            "b" | "break" => match parse_break(tokens) {
+                Some((location, options)) => Some(DebuggerCommand::Break(location, options)),
+                None => {
+                    println!(
+                        "Usage: b|break <location>|-sym <symbol> [@N] [if-caller <func>] [group <name>] [if <expr>]"
+                    );
+                    None
+                }
+            },
+            "n" | "next" => match parse_count(tokens) {
+                Some(count) => Some(DebuggerCommand::Next(count)),
+                None => {
+                    println!("Usage: n|next [N]");
+                    None
+                }
+            },
+            "s" | "step" => match parse_count(tokens) {
+                Some(count) => Some(DebuggerCommand::Step(count)),
+                None => {
+                    println!("Usage: s|step [N]");
+                    None
+                }
+            },
             "p" | "print" => {
                 if tokens.len() < 2 {
-                    println!("Usage: p|print <variable>");
+                    println!("Usage: p|print <variable>|<expr>");
                     None
                 } else {
-                    Some(DebuggerCommand::Print(tokens[1].to_string()))
+                    Some(DebuggerCommand::Print(tokens[1..].join(" ")))
                 }
             }
+            "llm" => match tokens.get(1).copied() {
+                Some("reload") if tokens.len() == 2 => Some(DebuggerCommand::LlmReload),
+                Some("status") if tokens.len() == 2 => Some(DebuggerCommand::LlmStatus),
+                _ => {
+                    println!("Usage: llm reload|status");
+                    None
+                }
+            },
             "nb" => {
                 if tokens.len() < 2 {
-                    println!("Usage: nb <自然语言描述>");
+                    println!("Usage: nb <自然语言描述> | nb --from-bug \"<错误报告>\"");
                     None
+                } else if tokens[1] == "--from-bug" {
+                    let report = tokens[2..].join(" ");
+                    let report = report.trim_matches(|c| c == '"' || c == '\'').trim();
+                    if report.is_empty() {
+                        println!("Usage: nb --from-bug \"<错误报告>\"");
+                        None
+                    } else {
+                        Some(DebuggerCommand::NaturalBreakFromBug(report.to_string()))
+                    }
                 } else {
                     let description = tokens[1..].join(" ");
                     Some(DebuggerCommand::NaturalBreak(description))
                 }
             }
+            "ib" => Some(DebuggerCommand::InfoBreakpoints),
+            "i" | "info" => match tokens.get(1).copied() {
+                Some("elf") => Some(DebuggerCommand::InfoElf),
+                Some("sections") => Some(DebuggerCommand::InfoSections),
+                Some("sharedlibrary") | Some("shared") => Some(DebuggerCommand::InfoSharedLibrary),
+                Some("frame") | Some("f") => Some(DebuggerCommand::InfoFrame),
+                Some("net") => Some(DebuggerCommand::InfoNet),
+                Some("threads") => Some(DebuggerCommand::InfoThreads),
+                Some("registers") | Some("reg") | Some("r") => {
+                    Some(DebuggerCommand::InfoRegisters(
+                        tokens[2..].iter().map(|name| name.to_string()).collect(),
+                    ))
+                }
+                Some("break") | Some("breakpoints") | Some("b") => {
+                    Some(DebuggerCommand::InfoBreakpoints)
+                }
+                Some("display") => Some(DebuggerCommand::InfoDisplay),
+                Some("signals") | Some("handle") => match tokens.get(2) {
+                    None => Some(DebuggerCommand::Handle(
+                        Signal::iterator().collect(),
+                        Vec::new(),
+                    )),
+                    Some(name) => match signals::parse_signal(name) {
+                        Some(signal) => Some(DebuggerCommand::Handle(vec![signal], Vec::new())),
+                        None => {
+                            println!("Unrecognized signal '{}'", name);
+                            None
+                        }
+                    },
+                },
+                _ => {
+                    println!("Usage: i|info elf|sections|sharedlibrary|frame|net|threads|registers|signals|break|display");
+                    None
+                }
+            },
+            "dump" => {
+                let range = tokens
+                    .get(3..)
+                    .map(crate::expr::split_arguments)
+                    .unwrap_or_default();
+                match (tokens.get(1).copied(), range.as_slice()) {
+                    (Some("memory"), [start, end]) => Some(DebuggerCommand::DumpMemory(
+                        tokens[2].to_string(),
+                        start.clone(),
+                        end.clone(),
+                    )),
+                    _ => {
+                        println!("Usage: dump memory <file> <start> <end>");
+                        None
+                    }
+                }
+            }
+            "restore" => {
+                if tokens.len() < 3 {
+                    println!("Usage: restore <file> <addr>");
+                    None
+                } else {
+                    Some(DebuggerCommand::Restore(
+                        tokens[1].to_string(),
+                        tokens[2..].join(" "),
+                    ))
+                }
+            }
+            "compare-sections" => Some(DebuggerCommand::CompareSections),
+            name if name == "x" || name.starts_with("x/") => Some(DebuggerCommand::Examine(
+                name.trim_start_matches('x')
+                    .trim_start_matches('/')
+                    .to_string(),
+                Some(tokens[1..].join(" ")).filter(|addr| !addr.is_empty()),
+            )),
+            "set" => match (tokens.get(1).copied(), tokens.len()) {
+                (Some("cwd"), 3) => Some(DebuggerCommand::SetCwd(tokens[2].to_string())),
+                (Some("print"), 4) if tokens[2] == "frame-arguments" => match tokens[3] {
+                    "all" => Some(DebuggerCommand::SetFrameArguments(FrameArguments::All)),
+                    "scalars" => Some(DebuggerCommand::SetFrameArguments(FrameArguments::Scalars)),
+                    "none" => Some(DebuggerCommand::SetFrameArguments(FrameArguments::None)),
+                    _ => {
+                        println!("Usage: set print frame-arguments all|scalars|none");
+                        None
+                    }
+                },
+                (Some("print"), 4) if tokens[2] == "deltas" => match tokens[3] {
+                    "on" => Some(DebuggerCommand::SetPrintDeltas(true)),
+                    "off" => Some(DebuggerCommand::SetPrintDeltas(false)),
+                    _ => {
+                        println!("Usage: set print deltas on|off");
+                        None
+                    }
+                },
+                (Some("context"), 3) => match tokens[2] {
+                    "on" => Some(DebuggerCommand::SetAutoContext(true)),
+                    "off" => Some(DebuggerCommand::SetAutoContext(false)),
+                    _ => {
+                        println!("Usage: set context on|off");
+                        None
+                    }
+                },
+                (Some("preload"), 3) if tokens[2] == "off" => {
+                    Some(DebuggerCommand::SetPreload(Vec::new()))
+                }
+                (Some("preload"), len) if len >= 3 => Some(DebuggerCommand::SetPreload(
+                    tokens[2..].iter().map(|lib| lib.to_string()).collect(),
+                )),
+                (Some("affinity"), 3) if tokens[2] == "off" => {
+                    Some(DebuggerCommand::SetAffinity(Vec::new()))
+                }
+                (Some("affinity"), 3) => {
+                    match tokens[2]
+                        .split(',')
+                        .map(str::parse)
+                        .collect::<Result<Vec<usize>, _>>()
+                    {
+                        Ok(cpus) => Some(DebuggerCommand::SetAffinity(cpus)),
+                        Err(_) => {
+                            println!("Usage: set affinity <cpu>[,<cpu>...] | set affinity off");
+                            None
+                        }
+                    }
+                }
+                (Some("scheduler"), _) => {
+                    let priority = tokens.get(3).and_then(|priority| priority.parse().ok());
+                    match (tokens.get(2).copied(), tokens.len(), priority) {
+                        (Some("off"), 3, _) => Some(DebuggerCommand::SetScheduler(None)),
+                        (Some("other"), 3, _) => {
+                            Some(DebuggerCommand::SetScheduler(Some(Scheduler::Other)))
+                        }
+                        (Some("batch"), 3, _) => {
+                            Some(DebuggerCommand::SetScheduler(Some(Scheduler::Batch)))
+                        }
+                        (Some("idle"), 3, _) => {
+                            Some(DebuggerCommand::SetScheduler(Some(Scheduler::Idle)))
+                        }
+                        (Some("fifo"), 4, Some(priority)) => Some(DebuggerCommand::SetScheduler(
+                            Some(Scheduler::Fifo(priority)),
+                        )),
+                        (Some("rr"), 4, Some(priority)) => Some(DebuggerCommand::SetScheduler(
+                            Some(Scheduler::RoundRobin(priority)),
+                        )),
+                        _ => {
+                            println!("Usage: set scheduler other|batch|idle|fifo <priority>|rr <priority>|off");
+                            None
+                        }
+                    }
+                }
+                (Some("reg") | Some("register"), len) if len >= 4 => {
+                    // 也接受 `set reg rax = 1`
+                    let value = tokens[3..].join(" ");
+                    let value = value.strip_prefix('=').unwrap_or(&value).trim();
+                    if value.is_empty() {
+                        println!("Usage: set reg <name> <value>");
+                        None
+                    } else {
+                        Some(DebuggerCommand::SetRegister(
+                            tokens[2].trim_start_matches('$').to_string(),
+                            value.to_string(),
+                        ))
+                    }
+                }
+                (Some("prompt"), _) => Some(DebuggerCommand::SetPrompt(tokens[2..].join(" "))),
+                (Some("confirm"), 3) => match tokens[2] {
+                    "on" => Some(DebuggerCommand::SetConfirm(true)),
+                    "off" => Some(DebuggerCommand::SetConfirm(false)),
+                    _ => {
+                        println!("Usage: set confirm on|off");
+                        None
+                    }
+                },
+                (Some("llm.confirm"), 3) => match tokens[2] {
+                    "on" => Some(DebuggerCommand::SetLlmConfirm(true)),
+                    "off" => Some(DebuggerCommand::SetLlmConfirm(false)),
+                    _ => {
+                        println!("Usage: set llm.confirm on|off");
+                        None
+                    }
+                },
+                (Some("llm.log"), 3) => match tokens[2] {
+                    "off" => Some(DebuggerCommand::SetLlmLog(None)),
+                    path => Some(DebuggerCommand::SetLlmLog(Some(path.to_string()))),
+                },
+                (Some("follow-fork-mode"), 3) => match tokens[2] {
+                    "parent" => Some(DebuggerCommand::SetFollowFork(FollowFork::Parent)),
+                    "child" => Some(DebuggerCommand::SetFollowFork(FollowFork::Child)),
+                    _ => {
+                        println!("Usage: set follow-fork-mode parent|child");
+                        None
+                    }
+                },
+                (Some("net-tracking"), 3) => match tokens[2] {
+                    "on" => Some(DebuggerCommand::SetNetTracking(true)),
+                    "off" => Some(DebuggerCommand::SetNetTracking(false)),
+                    _ => {
+                        println!("Usage: set net-tracking on|off");
+                        None
+                    }
+                },
+                (Some(name), _) if name.starts_with('$') => {
+                    let text = tokens[1..].join(" ");
+                    match text.split_once('=') {
+                        Some((name, expr))
+                            if is_convenience_name(name.trim()) && !expr.trim().is_empty() =>
+                        {
+                            Some(DebuggerCommand::SetConvenience(
+                                name.trim()[1..].to_string(),
+                                expr.trim().to_string(),
+                            ))
+                        }
+                        _ => {
+                            println!("Usage: set $<name> = <expr>");
+                            None
+                        }
+                    }
+                }
+                _ => {
+                    println!(
+                        "Usage: set cwd <dir> | set reg <name> <value> | set print frame-arguments all|scalars|none | set print deltas on|off | set context on|off | set prompt \"<text>\" | set confirm on|off | set llm.confirm on|off | set llm.log <file>|off | set net-tracking on|off | set preload <lib.so>...|off | set affinity <cpus>|off | set scheduler <policy>|off | set $<name> = <expr>"
+                    );
+                    None
+                }
+            },
+            "export" => {
+                if tokens.len() != 3 || tokens[1] != "timeline" {
+                    println!("Usage: export timeline <file>");
+                    None
+                } else {
+                    Some(DebuggerCommand::ExportTimeline(tokens[2].to_string()))
+                }
+            }
+            "journal" => match tokens.get(1).map(|n| n.parse::<usize>()) {
+                None => Some(DebuggerCommand::Journal(20)),
+                Some(Ok(n)) if n > 0 && tokens.len() == 2 => Some(DebuggerCommand::Journal(n)),
+                _ => {
+                    println!("Usage: journal [N]");
+                    None
+                }
+            },
+            "find-func" => {
+                if tokens.len() < 2 {
+                    println!("Usage: find-func <description>");
+                    None
+                } else {
+                    Some(DebuggerCommand::FindFunc(tokens[1..].join(" ")))
+                }
+            }
+            "ask" => {
+                if tokens.len() < 2 {
+                    println!("Usage: ask <question>");
+                    None
+                } else {
+                    Some(DebuggerCommand::Ask(tokens[1..].join(" ")))
+                }
+            }
+            "suggest-fix" => {
+                if tokens.len() > 1 {
+                    Some(DebuggerCommand::SuggestFix(Some(tokens[1..].join(" "))))
+                } else {
+                    Some(DebuggerCommand::SuggestFix(None))
+                }
+            }
+            "compare-runs" => {
+                let mut vars = Vec::new();
+                let mut rest = &tokens[1..];
+                while rest.len() >= 2 && rest[0] == "-p" {
+                    vars.push(rest[1].to_string());
+                    rest = &rest[2..];
+                }
+                match rest.iter().position(|t| *t == "--") {
+                    Some(split) => Some(DebuggerCommand::CompareRuns(
+                        vars,
+                        rest[..split].iter().map(|s| s.to_string()).collect(),
+                        rest[split + 1..].iter().map(|s| s.to_string()).collect(),
+                    )),
+                    None => {
+                        println!("Usage: compare-runs [-p <var>]... <args A> -- <args B>");
+                        None
+                    }
+                }
+            }
+            "target" => {
+                if tokens.len() < 4 || tokens[1] != "valgrind" || tokens[2] != "--" {
+                    println!("Usage: target valgrind -- <prog> [args]");
+                    None
+                } else {
+                    Some(DebuggerCommand::TargetValgrind(
+                        tokens[3].to_string(),
+                        tokens[4..].iter().map(|s| s.to_string()).collect(),
+                    ))
+                }
+            }
+            "handle" => {
+                let signals: Vec<Signal> = match tokens.get(1).copied() {
+                    None => Signal::iterator().collect(),
+                    // 与 gdb 相同，all 不包括调试器自己使用的 SIGTRAP 和 SIGINT
+                    Some("all") => Signal::iterator()
+                        .filter(|signal| *signal != Signal::SIGTRAP && *signal != Signal::SIGINT)
+                        .collect(),
+                    Some(name) => match signals::parse_signal(name) {
+                        Some(signal) => vec![signal],
+                        None => {
+                            println!("Unrecognized signal '{}'", name);
+                            println!("{}", HANDLE_USAGE);
+                            return None;
+                        }
+                    },
+                };
+                let mut actions = Vec::new();
+                for word in tokens.iter().skip(2) {
+                    match SignalAction::parse(word) {
+                        Some(action) => actions.push(action),
+                        None => {
+                            println!("Unrecognized action '{}'", word);
+                            println!("{}", HANDLE_USAGE);
+                            return None;
+                        }
+                    }
+                }
+                Some(DebuggerCommand::Handle(signals, actions))
+            }
+            "guard" => match crate::expr::split_arguments(&tokens[1..]).as_slice() {
+                [addr, len] => Some(DebuggerCommand::Guard(addr.clone(), len.clone())),
+                _ => {
+                    println!("Usage: guard <addr> <len>");
+                    None
+                }
+            },
+            "d" | "delete" | "enable" | "disable" => {
+                let mut ids = Vec::new();
+                for token in &tokens[1..] {
+                    match token.parse() {
+                        Ok(id) => ids.push(id),
+                        Err(_) => {
+                            println!("Invalid breakpoint number '{}'", token);
+                            return None;
+                        }
+                    }
+                }
+                match tokens[0] {
+                    "enable" => Some(DebuggerCommand::Enable(ids)),
+                    "disable" => Some(DebuggerCommand::Disable(ids)),
+                    _ => Some(DebuggerCommand::Delete(ids)),
+                }
+            }
+            "group" => match (tokens.get(1).copied(), tokens.get(2)) {
+                (Some("create"), Some(name)) => {
+                    Some(DebuggerCommand::GroupCreate(name.to_string()))
+                }
+                (Some("enable"), Some(name)) => {
+                    Some(DebuggerCommand::GroupEnable(name.to_string()))
+                }
+                (Some("disable"), Some(name)) => {
+                    Some(DebuggerCommand::GroupDisable(name.to_string()))
+                }
+                (Some("delete"), Some(name)) => {
+                    Some(DebuggerCommand::GroupDelete(name.to_string()))
+                }
+                (Some("list"), None) | (None, None) => Some(DebuggerCommand::GroupList),
+                _ => {
+                    println!("Usage: group create|enable|disable|delete <name> | group list");
+                    None
+                }
+            },
+            "history" => {
+                if tokens.len() > 1 {
+                    Some(DebuggerCommand::History(Some(tokens[1..].join(" "))))
+                } else {
+                    Some(DebuggerCommand::History(None))
+                }
+            }
+            "mt" | "maint" | "maintenance" => {
+                match (tokens.get(1).copied(), tokens.get(2).copied(), tokens.len()) {
+                    (Some("info"), Some("line-table"), 3) => {
+                        Some(DebuggerCommand::MaintLineTable(None))
+                    }
+                    (Some("info"), Some("line-table"), 4) => {
+                        Some(DebuggerCommand::MaintLineTable(Some(tokens[3].to_string())))
+                    }
+                    (Some("print"), Some("symbols"), 3) => Some(DebuggerCommand::MaintPrintSymbols),
+                    (Some("time"), Some("on"), 3) => Some(DebuggerCommand::MaintTime(true)),
+                    (Some("time"), Some("off"), 3) => Some(DebuggerCommand::MaintTime(false)),
+                    _ => {
+                        println!(
+                            "Usage: maint info line-table [file] | maint print symbols | maint time on|off"
+                        );
+                        None
+                    }
+                }
+            }
+            "context" => Some(DebuggerCommand::Context),
+            "display" => {
+                if tokens.len() > 1 {
+                    Some(DebuggerCommand::Display(Some(tokens[1..].join(" "))))
+                } else {
+                    Some(DebuggerCommand::Display(None))
+                }
+            }
+            "commands" => {
+                if tokens.len() > 1 {
+                    println!("Usage: commands (applies to the last breakpoint set)");
+                    None
+                } else {
+                    Some(DebuggerCommand::Commands(Vec::new()))
+                }
+            }
+            "hook-stop" | "hook-exited" => {
+                if tokens.len() > 1 {
+                    println!("Usage: {} (followed by the commands and `end`)", tokens[0]);
+                    None
+                } else if tokens[0] == "hook-stop" {
+                    Some(DebuggerCommand::HookStop(Vec::new()))
+                } else {
+                    Some(DebuggerCommand::HookExited(Vec::new()))
+                }
+            }
             // Default case:
             _ => None,
         }
     }
 }
+
+/// 解析 `break` 的位置和选项；选项从位置之后第一个 `@N` 或选项关键字开始，每个关键字带一个参数
+fn parse_break(tokens: &[&str]) -> Option<(String, BreakOptions)> {
+    let start = tokens
+        .iter()
+        .skip(2)
+        .position(|token| token.starts_with('@') || matches!(*token, "group" | "if-caller" | "if"))
+        .map_or(tokens.len(), |i| i + 2);
+    if start < 2 {
+        return None;
+    }
+    let mut options = BreakOptions::default();
+    let mut rest = &tokens[start..];
+    while let Some(option) = rest.first() {
+        if let Some(n) = option.strip_prefix('@') {
+            options.nth_hit = Some(n.parse::<usize>().ok().filter(|n| *n > 0)?);
+            rest = &rest[1..];
+            continue;
+        }
+        match rest {
+            // 表达式中可以有空格，取到行尾
+            ["if", expr @ ..] if !expr.is_empty() => {
+                options.condition = Some(expr.join(" "));
+                break;
+            }
+            ["group", name, ..] => options.group = Some(name.to_string()),
+            ["if-caller", func, ..] => options.caller = Some(func.to_string()),
+            _ => return None,
+        }
+        rest = &rest[2..];
+    }
+    Some((tokens[1..start].join(" "), options))
+}
+
+/// `$` 之后是字母或下划线开头的标识符
+fn is_convenience_name(name: &str) -> bool {
+    let mut chars = name.strip_prefix('$').unwrap_or_default().chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+const MONITOR_USAGE: &str =
+    "Usage: monitor <expr> every <n> stops|seconds, monitor, monitor off [n]";
+
+/// `monitor` 的参数；表达式中可以有空格，最后一个 `every` 之后是间隔
+fn parse_monitor(tokens: &[&str]) -> Option<DebuggerCommand> {
+    match tokens {
+        [_] => return Some(DebuggerCommand::MonitorList),
+        [_, "off"] => return Some(DebuggerCommand::MonitorOff(None)),
+        [_, "off", id] => match id.parse() {
+            Ok(id) => return Some(DebuggerCommand::MonitorOff(Some(id))),
+            Err(_) => {
+                println!("{}", MONITOR_USAGE);
+                return None;
+            }
+        },
+        _ => {}
+    }
+    let every = tokens.iter().rposition(|token| *token == "every");
+    let interval = match every.map(|every| &tokens[every + 1..]) {
+        Some([n, unit]) => match (n.parse::<usize>().ok().filter(|n| *n > 0), *unit) {
+            (Some(n), "stop" | "stops") => Some(Interval::Stops(n)),
+            (Some(n), "second" | "seconds" | "s") => Some(Interval::Seconds(n as u64)),
+            _ => None,
+        },
+        _ => None,
+    };
+    match (every, interval) {
+        (Some(every), Some(interval)) if every > 1 => Some(DebuggerCommand::Monitor(
+            tokens[1..every].join(" "),
+            interval,
+        )),
+        _ => {
+            println!("{}", MONITOR_USAGE);
+            None
+        }
+    }
+}
+
+/// 解析可选的重复次数参数，省略时为 1
+fn parse_count(tokens: &Vec<&str>) -> Option<usize> {
+    match tokens.get(1) {
+        Some(count) => count.parse::<usize>().ok().filter(|count| *count > 0),
+        None => Some(1),
+    }
+}
+
+/// Levenshtein 编辑距离
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + if ca == *cb { 0 } else { 1 };
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
@@ -0,0 +1,78 @@
+//! `set print deltas on`：每次停止时与上一次停止比较，列出改变了的寄存器和局部变量。
+//!
+//! 快照在每次显示停止位置时记录。局部变量只在两次停止位于同一帧（函数和 CFA 都相同）时比较：
+//! 进入或返回另一个函数后变量都是另一帧的，逐个报告没有意义。rip 每次都会改变，不参与比较。
+
+use crate::registers;
+use nix::libc::user_regs_struct;
+
+/// 参与比较的寄存器
+const COMPARED: &[&str] = &[
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "eflags",
+];
+
+/// 一次停止时的寄存器和局部变量
+pub struct Snapshot {
+    /// 所在函数和帧的 CFA，不在有调试信息的函数中时为 None
+    frame: Option<(String, u64)>,
+    registers: Vec<(&'static str, u64)>,
+    /// 局部变量名和显示的值（读取失败时是错误信息）
+    locals: Vec<(String, String)>,
+}
+
+impl Snapshot {
+    pub fn new(
+        regs: &user_regs_struct,
+        frame: Option<(String, u64)>,
+        locals: Vec<(String, String)>,
+    ) -> Snapshot {
+        let registers = COMPARED
+            .iter()
+            .map(|name| (*name, registers::get(regs, name).unwrap()))
+            .collect();
+        Snapshot {
+            frame,
+            registers,
+            locals,
+        }
+    }
+
+    /// 与上一次停止相比的变化，寄存器和局部变量各一行，都没有变化时为空
+    pub fn changes_since(&self, previous: &Snapshot) -> Vec<String> {
+        let mut lines = Vec::new();
+        let registers: Vec<String> = self
+            .registers
+            .iter()
+            .zip(&previous.registers)
+            .filter(|((_, new), (_, old))| new != old)
+            .map(|((name, new), (_, old))| format!("{} {:#x} -> {:#x}", name, old, new))
+            .collect();
+        if !registers.is_empty() {
+            lines.push(format!("Registers changed: {}", registers.join(", ")));
+        }
+        if self.frame.is_none() || self.frame != previous.frame {
+            return lines;
+        }
+        let locals: Vec<String> = self
+            .locals
+            .iter()
+            .filter_map(|(name, new)| {
+                match previous
+                    .locals
+                    .iter()
+                    .find(|(old_name, _)| old_name == name)
+                {
+                    Some((_, old)) if old != new => Some(format!("{} {} -> {}", name, old, new)),
+                    Some(_) => None,
+                    // 进入了内层作用域
+                    None => Some(format!("{} = {} (new)", name, new)),
+                }
+            })
+            .collect();
+        if !locals.is_empty() {
+            lines.push(format!("Locals changed: {}", locals.join(", ")));
+        }
+        lines
+    }
+}
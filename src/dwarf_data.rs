@@ -1,7 +1,10 @@
+use crate::expr::Value;
 use crate::gimli_wrapper;
+use crate::index_cache;
 use addr2line::Context;
-use object::Object;
+use object::{Object, ObjectSection, SectionFlags};
 use std::convert::TryInto;
+use std::time::{Duration, Instant};
 use std::{fmt, fs};
 
 #[derive(Debug)]
@@ -10,9 +13,29 @@ pub enum Error {
     DwarfFormatError(gimli_wrapper::Error),
 }
 
+/// x86-64 ELF 节标志：线程局部存储（.tdata / .tbss）
+const SHF_TLS: u64 = 0x400;
+
 pub struct DwarfData {
     files: Vec<File>,
+    /// 可执行文件的 TLS 块在线程指针（fs_base）之下的偏移，没有 TLS 时为 None
+    tls_offset: Option<usize>,
     addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    load_stats: LoadStats,
+    /// 被调试进程中的地址减去文件中的地址；PIE 程序被加载到随机的基址，非 PIE 程序为 0。
+    /// 按地址查询的方法接受和返回进程中的地址，`files()` 中的地址仍是文件中的地址
+    load_bias: usize,
+}
+
+/// 读取调试信息花费的时间，`maint print symbols` 显示
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadStats {
+    /// 解析 DWARF（或读取缓存）的耗时
+    pub index_time: Duration,
+    /// 建立 addr2line 上下文的耗时
+    pub addr2line_time: Duration,
+    /// 索引来自 `~/.kdb/cache`
+    pub from_cache: bool,
 }
 
 impl fmt::Debug for DwarfData {
@@ -43,19 +66,83 @@ impl DwarfData {
         } else {
             gimli::RunTimeEndian::Big
         };
+        let build_id = object
+            .build_id()
+            .map(|id| id.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+        let mut load_stats = LoadStats::default();
+        let started = Instant::now();
+        let files = match build_id.as_deref().and_then(index_cache::load) {
+            Some(files) => {
+                load_stats.from_cache = true;
+                files
+            }
+            None => {
+                let files = gimli_wrapper::load_file(&object, endian)?;
+                if let Some(build_id) = &build_id {
+                    index_cache::store(build_id, &files);
+                }
+                files
+            }
+        };
+        load_stats.index_time = started.elapsed();
+        let started = Instant::now();
+        let addr2line = Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?;
+        load_stats.addr2line_time = started.elapsed();
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
-            addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            files,
+            tls_offset: static_tls_offset(&object),
+            addr2line,
+            load_stats,
+            load_bias: 0,
         })
     }
 
-    #[allow(dead_code)]
-    fn get_target_file(&self, file: &str) -> Option<&File> {
-        self.files.iter().find(|f| {
-            f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
+    /// 只为调试文件建立 addr2line 上下文（不解析变量和类型），用于共享库的地址符号化
+    pub fn from_debug_file(path: &str) -> Result<DwarfData, Error> {
+        let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
+        let mmap = unsafe { memmap::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
+        let object = object::File::parse(&mmap)
+            .map_err(|e| gimli_wrapper::Error::ObjectError(e.to_string()))?;
+        Ok(DwarfData {
+            files: Vec::new(),
+            tls_offset: None,
+            addr2line: Context::new(&object).map_err(gimli_wrapper::Error::from)?,
+            load_stats: LoadStats::default(),
+            load_bias: 0,
         })
     }
 
+    pub fn load_stats(&self) -> LoadStats {
+        self.load_stats
+    }
+
+    pub fn load_bias(&self) -> usize {
+        self.load_bias
+    }
+
+    /// 被调试进程把程序加载到了新的基址
+    pub fn set_load_bias(&mut self, load_bias: usize) {
+        self.load_bias = load_bias;
+    }
+
+    /// 进程中的地址对应的文件中的地址，在加载基址之前时返回 None
+    pub fn file_addr(&self, addr: usize) -> Option<usize> {
+        addr.checked_sub(self.load_bias)
+    }
+
+    fn get_target_file(&self, file: &str) -> Option<&File> {
+        self.get_target_files(file).next()
+    }
+
+    /// 名字匹配 file 的全部源文件：完整路径相同，或者 file 不带目录时文件名相同
+    fn get_target_files(&self, file: &str) -> impl Iterator<Item = &File> + '_ {
+        let file = file.to_string();
+        let suffix = format!("/{}", file);
+        self.files
+            .iter()
+            .filter(move |f| f.name == file || (!file.contains("/") && f.name.ends_with(&suffix)))
+    }
+
     #[allow(dead_code)]
     pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
         let target_file = match file {
@@ -67,33 +154,102 @@ impl DwarfData {
                 .lines
                 .iter()
                 .find(|line| line.number >= line_number)?
-                .address,
+                .address
+                + self.load_bias,
         )
     }
 
-    #[allow(dead_code)]
-    pub fn get_addr_for_function(&self, file: Option<&str>, func_name: &str) -> Option<usize> {
-        match file {
-            Some(filename) => Some(
-                self.get_target_file(filename)?
-                    .functions
+    /// 源文件 file 中 line_number 行（或其后第一个有代码的行）的全部位置，每个同名源文件一组；
+    /// file 为 None 时只查第一个源文件。这一行的代码出现在多个函数中（内联展开）时每个函数各有一个位置，
+    /// 同一函数中的多段代码（for 的初始化和递增、展开的循环）与 gdb 相同只取地址最低的一段
+    pub fn get_line_candidates(&self, file: Option<&str>, line_number: usize) -> Vec<Vec<Line>> {
+        let files: Vec<&File> = match file {
+            Some(filename) => self.get_target_files(filename).collect(),
+            None => self.files.first().into_iter().collect(),
+        };
+        files
+            .into_iter()
+            .filter_map(|target_file| {
+                let number = target_file
+                    .lines
                     .iter()
-                    .find(|func| func.name == func_name)?
-                    .address,
-            ),
-            None => {
-                for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
-                        return Some(func.address);
+                    .map(|line| line.number)
+                    .filter(|number| *number >= line_number)
+                    .min()?;
+                let mut lines: Vec<&Line> = target_file
+                    .lines
+                    .iter()
+                    .filter(|line| line.number == number)
+                    .collect();
+                lines.sort_by_key(|line| line.address);
+                // 按所在的函数分组，不在任何函数中的行号表项算作一组
+                let mut functions = Vec::new();
+                let mut locations = Vec::new();
+                for line in lines {
+                    let function = self.function_at(line.address).map(|func| func.address);
+                    if functions.contains(&function) {
+                        continue;
                     }
+                    functions.push(function);
+                    locations.push(Line {
+                        address: line.address + self.load_bias,
+                        ..line.clone()
+                    });
+                }
+                Some(locations)
+            })
+            .collect()
+    }
+
+    #[allow(dead_code)]
+    /// 函数断点的地址：跳过函数序言，停在参数和局部变量已经可以读取的第一条语句上。
+    /// 需要精确停在函数入口时使用 `break *<addr>`
+    pub fn get_addr_for_function(&self, file: Option<&str>, func_name: &str) -> Option<usize> {
+        self.get_function_candidates(file, func_name)
+            .first()
+            .map(|line| line.address)
+    }
+
+    /// 名为 func_name 的全部函数定义（同名的 static 函数、C++ 重载），按源文件顺序排列。
+    /// 返回的行号是函数的声明行，地址已跳过函数序言；只有声明没有代码的函数不算在内
+    pub fn get_function_candidates(&self, file: Option<&str>, func_name: &str) -> Vec<Line> {
+        let files: Vec<&File> = match file {
+            Some(filename) => self.get_target_files(filename).collect(),
+            None => self.files.iter().collect(),
+        };
+        let mut candidates: Vec<Line> = Vec::new();
+        for file in files {
+            for func in &file.functions {
+                if func.name != func_name || func.text_length == 0 {
+                    continue;
+                }
+                let address = skip_prologue(file, func) + self.load_bias;
+                // 同一个函数可能出现在多个编译单元的调试信息中
+                if candidates.iter().all(|line| line.address != address) {
+                    candidates.push(Line {
+                        file: file.name.clone(),
+                        number: func.line_number,
+                        address,
+                    });
                 }
-                None
             }
         }
+        candidates
     }
 
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
+        let addr = self.file_addr(curr_addr)?;
+        let line = self
+            .addr2line_line(addr)
+            .or_else(|| self.table_line(addr))?;
+        Some(Line {
+            address: curr_addr,
+            ..line
+        })
+    }
+
+    fn addr2line_line(&self, curr_addr: usize) -> Option<Line> {
         let location = self
             .addr2line
             .find_location(curr_addr.try_into().unwrap())
@@ -105,24 +261,107 @@ impl DwarfData {
         })
     }
 
+    /// 按自己解析的行号表查找 addr 所在的行。addr2line 找不到编译单元时（例如 clang 的 DWARF 5 用
+    /// DW_FORM_addrx 表示地址）使用
+    fn table_line(&self, curr_addr: usize) -> Option<Line> {
+        let (lines, _) = self.function_lines(curr_addr)?;
+        let line = lines.iter().rev().find(|line| line.address <= curr_addr)?;
+        Some(Line {
+            file: line.file.clone(),
+            number: line.number,
+            address: curr_addr,
+        })
+    }
+
+    /// addr 所在函数的行号表项（按地址排序）和函数的结束地址
+    fn function_lines(&self, addr: usize) -> Option<(Vec<&Line>, usize)> {
+        for file in &self.files {
+            if let Some(func) = file
+                .functions
+                .iter()
+                .find(|func| addr >= func.address && addr < func.address + func.text_length)
+            {
+                let end = func.address + func.text_length;
+                let mut lines: Vec<&Line> = file
+                    .lines
+                    .iter()
+                    .filter(|line| line.address >= func.address && line.address < end)
+                    .collect();
+                lines.sort_by_key(|line| line.address);
+                return Some((lines, end));
+            }
+        }
+        None
+    }
+
+    /// addr 所在源码行的地址区间 [start, end)：相邻的同一行的行号表项合并在一起，最多到函数末尾。
+    /// addr 不在任何函数中或在函数的第一个行号表项之前时返回 None
+    pub fn get_line_range(&self, addr: usize) -> Option<(usize, usize)> {
+        let addr = self.file_addr(addr)?;
+        let (lines, func_end) = self.function_lines(addr)?;
+        let index = lines.iter().rposition(|line| line.address <= addr)?;
+        let number = lines[index].number;
+        let start = lines[..index]
+            .iter()
+            .rev()
+            .take_while(|line| line.number == number)
+            .last()
+            .unwrap_or(&lines[index])
+            .address;
+        let end = lines[index..]
+            .iter()
+            .find(|line| line.number != number)
+            .map(|line| line.address)
+            .unwrap_or(func_end);
+        Some((start + self.load_bias, end + self.load_bias))
+    }
+
+    /// addr 所在函数中每个行号表项的起始地址
+    pub fn get_function_line_addrs(&self, addr: usize) -> Vec<usize> {
+        let mut addrs: Vec<usize> = match self
+            .file_addr(addr)
+            .and_then(|addr| self.function_lines(addr))
+        {
+            Some((lines, _)) => lines
+                .iter()
+                .map(|line| line.address + self.load_bias)
+                .collect(),
+            None => Vec::new(),
+        };
+        addrs.dedup();
+        addrs
+    }
+
     #[allow(dead_code)]
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
-        let frame = self
+        let curr_addr = self.file_addr(curr_addr)?;
+        let name = self
             .addr2line
             .find_frames(curr_addr.try_into().unwrap())
-            .ok()?
-            .next()
-            .ok()??;
-        Some(frame.function?.raw_name().ok()?.to_string())
+            .ok()
+            .and_then(|mut frames| frames.next().ok().flatten())
+            .and_then(|frame| Some(frame.function?.raw_name().ok()?.to_string()));
+        name.or_else(|| Some(self.function_at(curr_addr)?.name.clone()))
+    }
+
+    /// 可执行文件 TLS 块相对线程指针的偏移：TLS 变量的地址为 fs_base - offset + 变量在块内的偏移
+    pub fn tls_offset(&self) -> Option<usize> {
+        self.tls_offset
     }
 
     #[allow(dead_code)]
     pub fn get_variable_by_name(&self, addr: usize, var_name: &str) -> Option<&Variable> {
         // 先在当前函数的局部变量中查找
-        for file in &self.files {
-            for func in &file.functions {
+        if let Some(addr) = self.file_addr(addr) {
+            for func in self.files.iter().flat_map(|file| &file.functions) {
                 if addr >= func.address && addr < func.address + func.text_length {
-                    if let Some(var) = func.variables.iter().find(|v| v.name == var_name) {
+                    // 内层词法块中的同名变量遮蔽外层的
+                    if let Some(var) = func
+                        .variables
+                        .iter()
+                        .filter(|v| v.name == var_name && v.in_scope(addr))
+                        .max_by_key(|v| v.scope_depth)
+                    {
                         return Some(var);
                     }
                 }
@@ -137,6 +376,92 @@ impl DwarfData {
         None
     }
 
+    /// 文件中的地址 addr 所在的函数
+    fn function_at(&self, addr: usize) -> Option<&Function> {
+        self.files
+            .iter()
+            .flat_map(|file| &file.functions)
+            .find(|func| addr >= func.address && addr < func.address + func.text_length)
+    }
+
+    /// addr 所在函数的地址区间 [start, end)
+    pub fn get_function_range(&self, addr: usize) -> Option<(usize, usize)> {
+        let func = self.function_at(self.file_addr(addr)?)?;
+        let start = func.address + self.load_bias;
+        Some((start, start + func.text_length))
+    }
+
+    /// 从 entry 进入函数时序言之后的地址，与函数断点的位置相同；entry 不是有调试信息的函数的入口时
+    /// 返回 None
+    pub fn get_prologue_end(&self, entry: usize) -> Option<usize> {
+        let addr = self.file_addr(entry)?;
+        self.files.iter().find_map(|file| {
+            file.functions
+                .iter()
+                .find(|func| func.address == addr && func.text_length > 0)
+                .map(|func| skip_prologue(file, func) + self.load_bias)
+        })
+    }
+
+    /// 代码地址的注解 `<函数+偏移 at 文件:行>`，偏移为 0 时省略；addr 不在有调试信息的函数中时返回 None
+    pub fn annotate_address(&self, addr: usize) -> Option<String> {
+        let file_addr = self.file_addr(addr)?;
+        let func = self.function_at(file_addr)?;
+        let offset = match file_addr - func.address {
+            0 => String::new(),
+            offset => format!("+{}", offset),
+        };
+        Some(match self.get_line_from_addr(addr) {
+            Some(line) => format!("<{}{} at {}>", func.name, offset, line),
+            None => format!("<{}{}>", func.name, offset),
+        })
+    }
+
+    /// addr 所在函数的返回值类型，void 函数或没有调试信息时返回 None
+    pub fn get_return_type(&self, addr: usize) -> Option<&Type> {
+        self.function_at(self.file_addr(addr)?)?
+            .return_type
+            .as_ref()
+    }
+
+    /// addr 处可见的参数和局部变量，按声明顺序；同名变量只保留最内层的
+    pub fn get_local_variables(&self, addr: usize) -> Vec<&Variable> {
+        let addr = match self.file_addr(addr) {
+            Some(addr) => addr,
+            None => return Vec::new(),
+        };
+        let func = match self.function_at(addr) {
+            Some(func) => func,
+            None => return Vec::new(),
+        };
+        let visible: Vec<&Variable> = func.variables.iter().filter(|v| v.in_scope(addr)).collect();
+        visible
+            .iter()
+            .filter(|v| {
+                !visible
+                    .iter()
+                    .any(|other| other.name == v.name && other.scope_depth > v.scope_depth)
+            })
+            .copied()
+            .collect()
+    }
+
+    /// addr 所在函数的形式参数，按声明顺序
+    pub fn get_function_parameters(&self, addr: usize) -> Vec<&Variable> {
+        self.file_addr(addr)
+            .and_then(|addr| self.function_at(addr))
+            .map(|func| func.variables.iter().filter(|v| v.is_parameter).collect())
+            .unwrap_or_default()
+    }
+
+    /// 在指定源文件（编译单元）的全局和 static 变量中查找，用于 `print file.c::var`
+    pub fn get_file_variable(&self, file: &str, var_name: &str) -> Option<&Variable> {
+        self.get_target_file(file)?
+            .global_variables
+            .iter()
+            .find(|v| v.name == var_name)
+    }
+
     #[allow(dead_code)]
     pub fn print(&self) {
         for file in &self.files {
@@ -172,12 +497,228 @@ impl DwarfData {
             }
         }
     }
+
+    /// 按地址顺序打印行号表，file 为 None 时打印所有文件。没有匹配的文件时返回 false
+    pub fn print_line_table(&self, file: Option<&str>) -> bool {
+        let files: Vec<&File> = match file {
+            Some(name) => self.get_target_files(name).collect(),
+            None => self.files.iter().collect(),
+        };
+        if files.is_empty() {
+            return false;
+        }
+        for file in files {
+            let mut lines: Vec<&Line> = file.lines.iter().collect();
+            lines.sort_by_key(|line| line.address);
+            println!("Line table for {} ({} entries):", file.name, lines.len());
+            println!("  {:<18} {:>6}  FUNCTION", "ADDRESS", "LINE");
+            for line in lines {
+                let function = self
+                    .function_at(line.address)
+                    .map(|func| func.name.as_str())
+                    .unwrap_or("??");
+                println!(
+                    "  {:<#18x} {:>6}  {}",
+                    line.address + self.load_bias,
+                    line.number,
+                    function
+                );
+            }
+        }
+        true
+    }
+}
+
+impl Member {
+    /// 把整个变量当作一个成员，用于统一处理 `print var` 和 `print var.member`
+    pub fn whole(member_type: &Type) -> Member {
+        Member {
+            name: String::new(),
+            bit_position: 0,
+            bit_size: None,
+            member_type: member_type.clone(),
+        }
+    }
+
+    /// 成员在所在结构体内容中的字节；超出读到的范围时返回 None
+    fn bytes_in<'a>(&self, struct_bytes: &'a [u8]) -> Option<&'a [u8]> {
+        let start = self.bit_position / 8;
+        struct_bytes.get(start..start + self.member_type.size.max(1))
+    }
+
+    /// 标量成员（包括位域）的值，结构体和联合成员返回 None
+    pub fn scalar_in(&self, struct_bytes: &[u8]) -> Option<u64> {
+        if !self.member_type.members.is_empty() {
+            return None;
+        }
+        match self.bit_size {
+            Some(width) => Some((0..width.min(64)).fold(0u64, |value, i| {
+                let bit = self.bit_position + i;
+                let set = struct_bytes
+                    .get(bit / 8)
+                    .map(|byte| (byte >> (bit % 8)) & 1)
+                    .unwrap_or(0);
+                value | ((set as u64) << i)
+            })),
+            None => self.bytes_in(struct_bytes).map(little_endian),
+        }
+    }
+
+    /// 有符号位域按位宽做符号扩展
+    fn sign_extend(&self, value: u64) -> u64 {
+        match self.bit_size {
+            Some(width) if self.member_type.encoding == Encoding::Signed => {
+                sign_extend(value, width) as u64
+            }
+            _ => value,
+        }
+    }
+
+    /// 标量成员用于表达式求值的数值：有符号整数做符号扩展，浮点数转换为 f64。
+    /// 结构体和联合成员返回 None
+    pub fn value_in(&self, struct_bytes: &[u8]) -> Option<Value> {
+        let raw = self.scalar_in(struct_bytes)?;
+        let member_type = &self.member_type;
+        Some(match member_type.encoding {
+            Encoding::Float => Value::Float(match member_type.size {
+                4 => f32::from_bits(raw as u32) as f64,
+                8 => f64::from_bits(raw),
+                _ => x87_extended(self.bytes_in(struct_bytes).filter(|b| b.len() >= 10)?),
+            }),
+            Encoding::Signed if self.bit_size.is_some() => Value::Int(self.sign_extend(raw) as i64),
+            Encoding::Signed => Value::Int(sign_extend(raw, member_type.size * 8)),
+            Encoding::Unsigned | Encoding::Address => Value::Int(raw as i64),
+        })
+    }
+
+    /// 从所在结构体的内容中取出这个成员并格式化
+    pub fn format_in(&self, struct_bytes: &[u8]) -> String {
+        if self.bit_size.is_some() {
+            if let Some(value) = self.scalar_in(struct_bytes) {
+                return self.member_type.format_value(self.sign_extend(value));
+            }
+        }
+        match self.bytes_in(struct_bytes) {
+            Some(bytes) => self.member_type.format_bytes(bytes),
+            None => "<unavailable>".to_string(),
+        }
+    }
+}
+
+/// 把 x87 80 位扩展精度浮点数（显式整数位的 64 位尾数、15 位指数和符号位）转换为 f64
+fn x87_extended(bytes: &[u8]) -> f64 {
+    let mantissa = little_endian(&bytes[..8]);
+    let sign_exponent = u16::from_le_bytes([bytes[8], bytes[9]]);
+    let sign = if sign_exponent & 0x8000 != 0 {
+        -1.0
+    } else {
+        1.0
+    };
+    let exponent = (sign_exponent & 0x7fff) as i32;
+    if exponent == 0x7fff {
+        // 尾数除整数位外全为 0 时是无穷大，否则是 NaN
+        return if mantissa << 1 == 0 {
+            sign * f64::INFINITY
+        } else {
+            f64::NAN
+        };
+    }
+    let exponent = if exponent == 0 {
+        1 - 16383
+    } else {
+        exponent - 16383
+    };
+    sign * (mantissa as f64) * 2f64.powi(exponent - 63)
+}
+
+/// 把低 bits 位的值按有符号数扩展到 64 位
+fn sign_extend(value: u64, bits: usize) -> i64 {
+    if bits == 0 || bits >= 64 {
+        return value as i64;
+    }
+    let shift = 64 - bits;
+    ((value << shift) as i64) >> shift
+}
+
+/// 最多 8 个字节的小端序整数
+fn little_endian(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .take(8)
+        .rev()
+        .fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+/// 按 glibc 在 x86-64 上的静态 TLS 布局（TLS 块位于线程指针之下）计算可执行文件的 TLS 块偏移。
+/// 块的起始地址、大小和对齐取自带 SHF_TLS 标志的节，与 PT_TLS 段一致
+fn static_tls_offset(object: &object::File) -> Option<usize> {
+    let mut start = u64::MAX;
+    let mut end = 0;
+    let mut align = 1;
+    for section in object.sections() {
+        let flags = match section.flags() {
+            SectionFlags::Elf { sh_flags } => sh_flags,
+            _ => 0,
+        };
+        if flags & SHF_TLS == 0 {
+            continue;
+        }
+        start = start.min(section.address());
+        end = end.max(section.address() + section.size());
+        align = align.max(section.align());
+    }
+    if start > end {
+        return None;
+    }
+    // 与 glibc 的 _dl_determine_tlsoffset 相同，保证块的起始地址按 p_vaddr 的方式对齐
+    let first_byte = start.wrapping_neg() & (align - 1);
+    let size = end - start;
+    let offset = ((size + align - 1 - first_byte) / align) * align + first_byte;
+    Some(offset as usize)
+}
+
+/// 与 GDB 相同，把行号表中函数入口之后的下一项当作序言的结束；
+/// 函数内只有一项时（例如没有序言的函数）返回函数入口
+fn skip_prologue(file: &File, func: &Function) -> usize {
+    file.lines
+        .iter()
+        .map(|line| line.address)
+        .filter(|addr| *addr > func.address && *addr < func.address + func.text_length)
+        .min()
+        .unwrap_or(func.address)
+}
+
+/// 基本类型的值如何解释（DW_AT_encoding）
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Encoding {
+    #[default]
+    Unsigned,
+    Signed,
+    Float,
+    /// 指针，按十六进制显示
+    Address,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct Type {
     pub name: String,
     pub size: usize,
+    pub encoding: Encoding,
+    /// 枚举类型的 (名字, 值)，其它类型为空
+    pub enumerators: Vec<(String, i64)>,
+    /// 结构体或联合的成员，其它类型为空
+    pub members: Vec<Member>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    /// 匿名的嵌套结构体或联合为空
+    pub name: String,
+    /// 相对结构体起始位置的位偏移
+    pub bit_position: usize,
+    /// 位域的宽度，普通成员为 None
+    pub bit_size: Option<usize>,
+    pub member_type: Type,
 }
 
 impl Type {
@@ -185,6 +726,81 @@ impl Type {
         Type {
             name: name,
             size: size,
+            encoding: Encoding::Unsigned,
+            enumerators: Vec::new(),
+            members: Vec::new(),
+        }
+    }
+
+    /// 按类型格式化从内存读出的值（小端序）：结构体显示为 `{成员 = 值, ...}`，联合显示每一种解释，
+    /// 位域只取它自己的位
+    pub fn format_bytes(&self, bytes: &[u8]) -> String {
+        if self.members.is_empty() {
+            // x87 的 long double 有 10 个有效字节，放不进 u64
+            if self.encoding == Encoding::Float && bytes.len() >= 10 {
+                return format!("{:?}", x87_extended(bytes));
+            }
+            return self.format_value(little_endian(bytes));
+        }
+        let fields: Vec<String> = self
+            .members
+            .iter()
+            .map(|member| match member.name.as_str() {
+                "" => member.format_in(bytes),
+                name => format!("{} = {}", name, member.format_in(bytes)),
+            })
+            .collect();
+        format!("{{{}}}", fields.join(", "))
+    }
+
+    /// 按名字查找成员，也查找匿名嵌套结构体和联合中的成员。
+    /// 返回的成员的位偏移相对这个类型的起始位置
+    pub fn find_member(&self, name: &str) -> Option<Member> {
+        for member in &self.members {
+            if member.name == name {
+                return Some(member.clone());
+            }
+            if member.name.is_empty() {
+                if let Some(inner) = member.member_type.find_member(name) {
+                    return Some(Member {
+                        bit_position: member.bit_position + inner.bit_position,
+                        ..inner
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// 按类型格式化已经截断到类型大小的值：枚举值显示为 `NAME (值)`，
+    /// 不对应任何枚举值（例如按位组合的标志）时只显示数值
+    pub fn format_value(&self, value: u64) -> String {
+        if self.encoding == Encoding::Float {
+            return match self.size {
+                4 => format!("{:?}", f32::from_bits(value as u32)),
+                8 => format!("{:?}", f64::from_bits(value)),
+                _ => value.to_string(),
+            };
+        }
+        let mask = match self.size {
+            1 => 0xff,
+            2 => 0xffff,
+            4 => 0xffff_ffff,
+            _ => u64::MAX,
+        };
+        if self.encoding == Encoding::Address {
+            return format!("{:#x}", value & mask);
+        }
+        if self.encoding == Encoding::Signed {
+            return sign_extend(value, mask.count_ones() as usize).to_string();
+        }
+        match self
+            .enumerators
+            .iter()
+            .find(|(_, enumerator)| *enumerator as u64 & mask == value)
+        {
+            Some((name, enumerator)) => format!("{} ({})", name, enumerator),
+            None => value.to_string(),
         }
     }
 }
@@ -193,13 +809,55 @@ impl Type {
 pub enum Location {
     Address(usize),
     FramePointerOffset(isize),
+    /// `__thread` 变量：在所属模块 TLS 块内的偏移
+    TlsOffset(usize),
+    /// 变量的值在 DWARF 编号为 n 的寄存器中
+    Register(u16),
+    /// 需要在读取时求值的位置表达式（DW_OP_breg*、DW_OP_stack_value、DW_OP_piece 等）
+    Expression(Vec<u8>, gimli::Encoding),
+    /// 位置列表：变量的位置随 pc 变化，不在任何一项范围内时变量不可用
+    List(Vec<LocationEntry>),
+    /// 变量被编译器优化掉了
+    OptimizedOut,
+}
+
+/// 位置列表中的一项，[begin, end) 内变量位于 location；表达式不受支持时 location 为 None
+#[derive(Clone)]
+pub struct LocationEntry {
+    pub begin: usize,
+    pub end: usize,
+    pub location: Option<Location>,
+}
+
+impl LocationEntry {
+    pub fn covers(&self, pc: usize) -> bool {
+        self.begin <= pc && pc < self.end
+    }
 }
 
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             Location::Address(addr) => write!(f, "Address({:#x})", addr),
             Location::FramePointerOffset(offset) => write!(f, "FramePointerOffset({})", offset),
+            Location::TlsOffset(offset) => write!(f, "TlsOffset({:#x})", offset),
+            Location::Register(register) => write!(f, "Register({})", register),
+            Location::Expression(bytes, _) => write!(f, "Expression({:02x?})", bytes),
+            Location::List(entries) => {
+                write!(f, "List[")?;
+                for (i, entry) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:#x}-{:#x}: ", entry.begin, entry.end)?;
+                    match &entry.location {
+                        Some(location) => write!(f, "{}", location)?,
+                        None => write!(f, "?")?,
+                    }
+                }
+                write!(f, "]")
+            }
+            Location::OptimizedOut => write!(f, "OptimizedOut"),
         }
     }
 }
@@ -217,6 +875,23 @@ pub struct Variable {
     pub entity_type: Type,
     pub location: Location,
     pub line_number: usize, // Line number in source file
+    /// 所在最内层词法块（DW_TAG_lexical_block）的地址范围，不在词法块中时为空
+    pub scope: Vec<(usize, usize)>,
+    /// 函数的形式参数（DW_TAG_formal_parameter）
+    pub is_parameter: bool,
+    /// 词法块的嵌套层数，为 0 时作用域是整个函数或文件；同名变量取当前 pc 处层数最大的那个
+    pub scope_depth: usize,
+}
+
+impl Variable {
+    /// 变量在 addr 处是否可见
+    pub fn in_scope(&self, addr: usize) -> bool {
+        self.scope_depth == 0
+            || self
+                .scope
+                .iter()
+                .any(|(low, high)| addr >= *low && addr < *high)
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -226,6 +901,8 @@ pub struct Function {
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
     pub variables: Vec<Variable>,
+    /// 返回值类型，void 函数为 None
+    pub return_type: Option<Type>,
 }
 
 #[derive(Debug, Default, Clone)]
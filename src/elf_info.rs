@@ -0,0 +1,179 @@
+//! ELF 文件结构信息（文件类型、入口点、解释器、节表），供 `info elf` / `info sections` 使用
+
+use object::{Object, ObjectSection, SectionFlags};
+use std::fs;
+
+const ET_REL: u16 = 1;
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+const ET_CORE: u16 = 4;
+
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+
+const DT_NULL: u64 = 0;
+const DT_FLAGS_1: u64 = 0x6fff_fffb;
+const DF_1_PIE: u64 = 0x0800_0000;
+/// Elf64_Dyn 的大小
+const DYN_SIZE: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct SectionInfo {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+    pub flags: u64,
+    /// 节内容在文件中的 (offset, size)，NOBITS 节（如 .bss）没有
+    pub file_range: Option<(u64, u64)>,
+}
+
+impl SectionInfo {
+    pub fn is_code(&self) -> bool {
+        self.flags & SHF_ALLOC != 0 && self.flags & SHF_EXECINSTR != 0
+    }
+
+    /// 与 readelf 相同的 W/A/X 标志缩写
+    pub fn flags_string(&self) -> String {
+        let mut s = String::new();
+        if self.flags & SHF_WRITE != 0 {
+            s.push('W');
+        }
+        if self.flags & SHF_ALLOC != 0 {
+            s.push('A');
+        }
+        if self.flags & SHF_EXECINSTR != 0 {
+            s.push('X');
+        }
+        s
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ElfSummary {
+    pub path: String,
+    pub elf_type: u16,
+    pub entry: u64,
+    pub interpreter: Option<String>,
+    /// ET_DYN 文件的 DT_FLAGS_1 是否带 DF_1_PIE；没有 DT_FLAGS_1 时无法判断，为 None
+    pub pie: Option<bool>,
+    pub sections: Vec<SectionInfo>,
+    /// 进程运行时该文件被映射的地址范围
+    pub mapped_range: Option<(usize, usize)>,
+}
+
+impl ElfSummary {
+    pub fn from_file(path: &str) -> Result<ElfSummary, String> {
+        let file = fs::File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mmap = unsafe { memmap::Mmap::map(&file).map_err(|e| format!("{}: {}", path, e))? };
+        let object = object::File::parse(&mmap).map_err(|e| format!("{}: {}", path, e))?;
+        if mmap.len() < 18 {
+            return Err(format!("{}: file too short", path));
+        }
+        // e_type 位于 ELF 头偏移 16 处，object crate 没有直接暴露
+        let elf_type = if object.is_little_endian() {
+            u16::from_le_bytes([mmap[16], mmap[17]])
+        } else {
+            u16::from_be_bytes([mmap[16], mmap[17]])
+        };
+        let interpreter = object.section_data_by_name(".interp").map(|data| {
+            String::from_utf8_lossy(&data)
+                .trim_end_matches('\0')
+                .to_string()
+        });
+        let pie = object.section_data_by_name(".dynamic").and_then(|dynamic| {
+            let word = |bytes: &[u8]| {
+                let mut word = [0u8; 8];
+                word.copy_from_slice(bytes);
+                match object.is_little_endian() {
+                    true => u64::from_le_bytes(word),
+                    false => u64::from_be_bytes(word),
+                }
+            };
+            dynamic
+                .chunks_exact(DYN_SIZE)
+                .map(|entry| (word(&entry[..8]), word(&entry[8..])))
+                .take_while(|(tag, _)| *tag != DT_NULL)
+                .find(|(tag, _)| *tag == DT_FLAGS_1)
+                .map(|(_, flags)| flags & DF_1_PIE != 0)
+        });
+        let sections = object
+            .sections()
+            .map(|section| SectionInfo {
+                name: section.name().unwrap_or("").to_string(),
+                address: section.address(),
+                size: section.size(),
+                flags: match section.flags() {
+                    SectionFlags::Elf { sh_flags } => sh_flags,
+                    _ => 0,
+                },
+                file_range: section.file_range(),
+            })
+            .collect();
+        Ok(ElfSummary {
+            path: path.to_string(),
+            elf_type,
+            entry: object.entry(),
+            interpreter,
+            pie,
+            sections,
+            mapped_range: None,
+        })
+    }
+
+    /// 从磁盘上读取某个节的原始内容
+    pub fn read_section(&self, section: &SectionInfo) -> Result<Vec<u8>, String> {
+        let (offset, size) = section
+            .file_range
+            .ok_or_else(|| format!("section {} has no file contents", section.name))?;
+        let data = fs::read(&self.path).map_err(|e| format!("{}: {}", self.path, e))?;
+        data.get(offset as usize..(offset + size) as usize)
+            .map(|bytes| bytes.to_vec())
+            .ok_or_else(|| format!("section {} is truncated in {}", section.name, self.path))
+    }
+
+    pub fn type_description(&self) -> &'static str {
+        match self.elf_type {
+            ET_REL => "REL (relocatable file)",
+            ET_EXEC => "EXEC (non-PIE executable)",
+            ET_DYN => match self.pie {
+                Some(true) => "DYN (PIE executable)",
+                Some(false) => "DYN (shared object)",
+                None => "DYN",
+            },
+            ET_CORE => "CORE (core file)",
+            _ => "unknown",
+        }
+    }
+
+    pub fn print_summary(&self) {
+        println!("{}:", self.path);
+        println!("  Type:        {}", self.type_description());
+        println!("  Entry point: {:#x}", self.entry);
+        match &self.interpreter {
+            Some(interp) => println!("  Interpreter: {}", interp),
+            None => println!("  Interpreter: (none)"),
+        }
+        if let Some((start, end)) = self.mapped_range {
+            println!("  Mapped at:   {:#x}-{:#x}", start, end);
+        }
+    }
+
+    pub fn print_sections(&self) {
+        println!("{}:", self.path);
+        println!("  Nr   Name                     Address            Size       Flags");
+        for (i, section) in self.sections.iter().enumerate() {
+            if section.name.is_empty() {
+                continue;
+            }
+            println!(
+                "  {:<4} {:<24} {:<#18x} {:<#10x} {}",
+                i,
+                section.name,
+                section.address,
+                section.size,
+                section.flags_string()
+            );
+        }
+    }
+}
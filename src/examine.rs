@@ -0,0 +1,201 @@
+//! `x/<count><format><size> <addr>`：按 gdb 的格式查看被调试进程的内存。
+//!
+//! 格式字母：x 十六进制、d 有符号十进制、u 无符号十进制、o 八进制、t 二进制、c 字符、s 字符串；
+//! 单位大小：b 1 字节、h 2 字节、w 4 字节、g 8 字节。省略的格式和大小沿用上一次 `x` 的设置，
+//! 最初是 4 字节的十六进制数。每行显示的单位数与 gdb 相同。
+
+/// 字符串最多显示的字符数，更长的截断并加上 `...`
+const MAX_STRING: usize = 200;
+/// 读取字符串时每次读取的字节数
+const STRING_CHUNK: usize = 64;
+/// 一次最多查看的字节数（字符串按每个最多 MAX_STRING 个字符计算）。内存是一次读出的，
+/// 过大的个数多半是输错了，不能让它耗尽调试器的内存
+const MAX_BYTES: usize = 1 << 20;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Hex,
+    Decimal,
+    Unsigned,
+    Octal,
+    Binary,
+    Char,
+    Str,
+}
+
+#[derive(Clone, Copy)]
+pub struct Spec {
+    pub count: usize,
+    pub format: Format,
+    /// 单位的字节数；字符串按字节读取，不使用它
+    pub size: usize,
+}
+
+impl Default for Spec {
+    fn default() -> Spec {
+        Spec {
+            count: 1,
+            format: Format::Hex,
+            size: 4,
+        }
+    }
+}
+
+impl Spec {
+    /// 解析 `x/` 之后的部分，例如 `16xb`。省略的格式和大小取 last 中的值，省略的个数为 1
+    pub fn parse(text: &str, last: &Spec) -> Result<Spec, String> {
+        let digits = text.chars().take_while(char::is_ascii_digit).count();
+        let count: usize = match &text[..digits] {
+            "" => 1,
+            count => count
+                .parse()
+                .map_err(|_| format!("Invalid count '{}'", count))?,
+        };
+        if count == 0 {
+            return Err("The count must be at least 1".to_string());
+        }
+        let mut format = last.format;
+        let mut size = None;
+        for letter in text[digits..].chars() {
+            match letter {
+                'x' => format = Format::Hex,
+                'd' => format = Format::Decimal,
+                'u' => format = Format::Unsigned,
+                'o' => format = Format::Octal,
+                't' => format = Format::Binary,
+                'c' => format = Format::Char,
+                's' => format = Format::Str,
+                'b' => size = Some(1),
+                'h' => size = Some(2),
+                'w' => size = Some(4),
+                'g' => size = Some(8),
+                _ => return Err(format!("Invalid format letter '{}'", letter)),
+            }
+        }
+        let size = match (size, format) {
+            (Some(size), _) => size,
+            // 与 gdb 相同，字符没有指定大小时按单个字节
+            (None, Format::Char) => 1,
+            (None, _) if last.format == Format::Char || last.format == Format::Str => 4,
+            (None, _) => last.size,
+        };
+        let unit = if format == Format::Str {
+            MAX_STRING
+        } else {
+            size
+        };
+        if count.checked_mul(unit).is_none_or(|len| len > MAX_BYTES) {
+            return Err(format!(
+                "The count {} is too large: at most {} units of this size can be examined at once",
+                count,
+                MAX_BYTES / unit
+            ));
+        }
+        Ok(Spec {
+            count,
+            format,
+            size,
+        })
+    }
+
+    /// 每行显示的单位数
+    fn per_row(&self) -> usize {
+        match (self.format, self.size) {
+            (Format::Char, _) | (_, 1) | (_, 2) => 8,
+            (_, 4) => 4,
+            _ => 2,
+        }
+    }
+}
+
+/// 把从 start 开始的 bytes 按 spec 分行，返回每行的起始地址和内容
+pub fn format_units(start: usize, bytes: &[u8], spec: &Spec) -> Vec<(usize, String)> {
+    let row_bytes = spec.per_row() * spec.size;
+    bytes
+        .chunks(row_bytes)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let units: Vec<String> = chunk
+                .chunks(spec.size)
+                .map(|unit| format_unit(unit, spec))
+                .collect();
+            (start + row * row_bytes, units.join("\t"))
+        })
+        .collect()
+}
+
+fn format_unit(unit: &[u8], spec: &Spec) -> String {
+    let mut word = [0u8; 8];
+    word[..unit.len()].copy_from_slice(unit);
+    let value = u64::from_le_bytes(word);
+    let bits = unit.len() * 8;
+    // 按单位宽度做符号扩展
+    let signed = ((value << (64 - bits)) as i64) >> (64 - bits);
+    match spec.format {
+        Format::Hex => format!("{:#0width$x}", value, width = unit.len() * 2 + 2),
+        Format::Decimal => signed.to_string(),
+        Format::Unsigned => value.to_string(),
+        Format::Octal if value == 0 => "0".to_string(),
+        Format::Octal => format!("0{:o}", value),
+        Format::Binary => format!("{:0width$b}", value, width = bits),
+        Format::Char => format!("{} '{}'", signed, escape(unit[0], '\'')),
+        Format::Str => unreachable!("strings are read by read_strings"),
+    }
+}
+
+/// 从 start 开始读取 count 个以 NUL 结尾的字符串，返回每个字符串的地址和带引号的内容，以及下一个
+/// 字符串的地址。read 读不到某个字符串的剩余部分时只显示已读到的部分
+pub fn read_strings<E>(
+    start: usize,
+    count: usize,
+    read: impl Fn(usize, usize) -> Result<Vec<u8>, E>,
+) -> Result<(Vec<(usize, String)>, usize), E> {
+    let mut strings = Vec::new();
+    let mut addr = start;
+    for _ in 0..count {
+        let mut bytes = Vec::new();
+        let mut terminated = false;
+        while !terminated && bytes.len() < MAX_STRING {
+            let chunk = match read(addr + bytes.len(), STRING_CHUNK) {
+                Ok(chunk) => chunk,
+                // 第一个字符串都读不到时报告错误，否则在不可读的位置结束
+                Err(e) if strings.is_empty() && bytes.is_empty() => return Err(e),
+                Err(_) => break,
+            };
+            for byte in chunk {
+                if byte == 0 {
+                    terminated = true;
+                    break;
+                }
+                bytes.push(byte);
+            }
+        }
+        let text: String = bytes
+            .iter()
+            .take(MAX_STRING)
+            .map(|byte| escape(*byte, '"'))
+            .collect();
+        let truncated = bytes.len() > MAX_STRING;
+        strings.push((
+            addr,
+            format!("\"{}\"{}", text, if truncated { "..." } else { "" }),
+        ));
+        // 没有读到结尾时下一个字符串从停下的位置开始
+        addr += bytes.len() + terminated as usize;
+    }
+    Ok((strings, addr))
+}
+
+/// 按 C 的写法转义一个字节，quote 是外面的引号
+fn escape(byte: u8, quote: char) -> String {
+    match byte {
+        b'\n' => "\\n".to_string(),
+        b'\t' => "\\t".to_string(),
+        b'\r' => "\\r".to_string(),
+        0 => "\\000".to_string(),
+        b'\\' => "\\\\".to_string(),
+        _ if byte as char == quote => format!("\\{}", quote),
+        0x20..=0x7e => (byte as char).to_string(),
+        _ => format!("\\{:03o}", byte),
+    }
+}
@@ -0,0 +1,409 @@
+//! 调试器命令中的表达式。
+//!
+//! 支持整数（十进制或 `0x` 十六进制）和浮点数字面量、变量（与 `print` 相同，可以带 `.成员` 和
+//! `file.c::` 前缀）、寄存器和便利变量（`$rsp`、`$base`）、取地址（`&buf`）、读内存（`*addr`，
+//! 读取一个 8 字节的字），以及 C 的算术、位、比较和逻辑运算符，优先级与 C 相同。表达式没有类型，
+//! 地址按字节计算，`&buf + 8` 不按元素大小缩放。变量的值和地址、内存由调用者实现的 [`Environment`]
+//! 读取，表达式本身不依赖被调试进程。
+
+use std::fmt;
+
+/// 表达式的值；整数和浮点数混合运算时按浮点数计算
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+}
+
+impl Value {
+    /// 按 C 的规则判断真假：非 0 为真
+    pub fn is_true(&self) -> bool {
+        match self {
+            Value::Int(value) => *value != 0,
+            Value::Float(value) => *value != 0.0,
+        }
+    }
+
+    fn as_f64(&self) -> f64 {
+        match self {
+            Value::Int(value) => *value as f64,
+            Value::Float(value) => *value,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+/// 求值时读取变量、寄存器和内存
+pub trait Environment {
+    /// 变量，或 `$` 开头的寄存器、便利变量的值
+    fn value_of(&self, name: &str) -> Result<Value, String>;
+    /// `&变量` 的值
+    fn address_of(&self, name: &str) -> Result<usize, String>;
+    /// `*地址` 的值：addr 处的 8 字节整数
+    fn read_word(&self, addr: usize) -> Result<i64, String>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    /// 变量或 `$` 开头的寄存器
+    Variable(String),
+    /// `&变量`
+    AddressOf(String),
+    /// `*地址`
+    Deref(Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+/// 二元运算符按优先级从低到高分组
+const PRECEDENCE: &[&[(&str, BinaryOp)]] = &[
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+    &[
+        ("<=", BinaryOp::Le),
+        (">=", BinaryOp::Ge),
+        ("<", BinaryOp::Lt),
+        (">", BinaryOp::Gt),
+    ],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Rem),
+    ],
+];
+
+/// 多字符的运算符排在它们的前缀之前
+const OPERATORS: &[&str] = &[
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "<", ">", "+", "-", "*", "/",
+    "%", "!", "~", "(", ")",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(Value),
+    Ident(String),
+    Op(&'static str),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                // 指数部分可以带符号，例如 1e-3
+                if (chars[i] == 'e' || chars[i] == 'E')
+                    && matches!(chars.get(i + 1), Some('+') | Some('-'))
+                    && !chars[start..i].iter().any(|c| *c == 'x' || *c == 'X')
+                {
+                    i += 1;
+                }
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            tokens.push(Token::Literal(parse_number(&literal)?));
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            // 变量路径：a.b.c 或 file.c::name；寄存器：$rsp
+            let start = i;
+            i += 1;
+            while i < chars.len() {
+                if chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.' {
+                    i += 1;
+                } else if chars[i] == ':' && chars.get(i + 1) == Some(&':') {
+                    i += 2;
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..].iter().collect();
+            match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(op) => {
+                    tokens.push(Token::Op(op));
+                    i += op.len();
+                }
+                None => return Err(format!("Unexpected character '{}' in expression", c)),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(literal: &str) -> Result<Value, String> {
+    let invalid = || format!("Invalid number '{}'", literal);
+    if let Some(hex) = literal
+        .strip_prefix("0x")
+        .or_else(|| literal.strip_prefix("0X"))
+    {
+        return u64::from_str_radix(hex, 16)
+            .map(|value| Value::Int(value as i64))
+            .map_err(|_| invalid());
+    }
+    if literal.contains(['.', 'e', 'E']) {
+        return literal
+            .parse::<f64>()
+            .map(Value::Float)
+            .map_err(|_| invalid());
+    }
+    literal
+        .parse::<u64>()
+        .map(|value| Value::Int(value as i64))
+        .map_err(|_| invalid())
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(&(_, op)) = self
+            .peek_op()
+            .and_then(|token| PRECEDENCE[level].iter().find(|(name, _)| *name == token))
+        {
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let op = match self.peek_op() {
+            Some("-") => Some(UnaryOp::Neg),
+            Some("!") => Some(UnaryOp::Not),
+            Some("~") => Some(UnaryOp::BitNot),
+            Some("+") => {
+                self.pos += 1;
+                return self.unary();
+            }
+            Some("&") => {
+                self.pos += 1;
+                let token = self.tokens.get(self.pos).cloned();
+                self.pos += 1;
+                return match token {
+                    Some(Token::Ident(name)) if !name.starts_with('$') => Ok(Expr::AddressOf(name)),
+                    _ => Err("Expected a variable after '&' in expression".to_string()),
+                };
+            }
+            Some("*") => {
+                self.pos += 1;
+                return Ok(Expr::Deref(Box::new(self.unary()?)));
+            }
+            _ => None,
+        };
+        match op {
+            Some(op) => {
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.unary()?)))
+            }
+            None => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Literal(value)) => Ok(Expr::Literal(value)),
+            Some(Token::Ident(name)) => Ok(Expr::Variable(name)),
+            Some(Token::Op("(")) => {
+                let inner = self.binary(0)?;
+                if self.peek_op() != Some(")") {
+                    return Err("Expected ')' in expression".to_string());
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Some(Token::Op(op)) => Err(format!("Unexpected '{}' in expression", op)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+/// 解析一个表达式
+pub fn parse(text: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        pos: 0,
+    };
+    if parser.tokens.is_empty() {
+        return Err("Empty expression".to_string());
+    }
+    let expr = parser.binary(0)?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(expr),
+        Some(Token::Op(op)) => Err(format!("Unexpected '{}' in expression", op)),
+        Some(_) => Err("Expected an operator in expression".to_string()),
+    }
+}
+
+/// 把按空白切开的命令参数重新拼成表达式：运算符两侧有空格时（`&buf + i*8`）前后的参数属于同一个
+/// 表达式，括号内的空格也不切开。`-`、`+`、`&`、`*` 紧贴在后一个参数前面时（`a -b`）当作一元运算符，
+/// 开始一个新的表达式
+pub fn split_arguments(tokens: &[&str]) -> Vec<String> {
+    const BINARY_ONLY: &[char] = &['/', '%', '<', '>', '=', '|', '^', ')'];
+    let is_operator = |c: char| OPERATORS.iter().any(|op| op.starts_with(c));
+    let mut arguments: Vec<String> = Vec::new();
+    let mut depth = 0i32;
+    let mut joined = false;
+    for token in tokens {
+        let operator_only = token.chars().all(is_operator) && !token.starts_with('(');
+        let continues = joined
+            || depth > 0
+            || operator_only
+            || token.starts_with(BINARY_ONLY)
+            || token.starts_with("&&")
+            || token.starts_with("!=");
+        match arguments.last_mut() {
+            Some(last) if continues => {
+                last.push(' ');
+                last.push_str(token);
+            }
+            _ => arguments.push(token.to_string()),
+        }
+        depth += token.matches('(').count() as i32 - token.matches(')').count() as i32;
+        // 以运算符或 `(` 结尾时下一个参数是它的操作数
+        joined = token.ends_with(|c: char| is_operator(c) && c != ')');
+    }
+    arguments
+}
+
+impl Expr {
+    /// 在 env 中求值。`&&` 和 `||` 短路求值
+    pub fn evaluate<E: Environment + ?Sized>(&self, env: &E) -> Result<Value, String> {
+        match self {
+            Expr::Literal(value) => Ok(*value),
+            Expr::Variable(name) => env.value_of(name),
+            Expr::AddressOf(name) => env.address_of(name).map(|addr| Value::Int(addr as i64)),
+            Expr::Deref(operand) => match operand.evaluate(env)? {
+                Value::Int(addr) => env.read_word(addr as usize).map(Value::Int),
+                Value::Float(_) => Err("Invalid operand of '*': floating-point value".to_string()),
+            },
+            Expr::Unary(op, operand) => {
+                let value = operand.evaluate(env)?;
+                match (op, value) {
+                    (UnaryOp::Neg, Value::Int(v)) => Ok(Value::Int(v.wrapping_neg())),
+                    (UnaryOp::Neg, Value::Float(v)) => Ok(Value::Float(-v)),
+                    (UnaryOp::Not, value) => Ok(Value::Int(!value.is_true() as i64)),
+                    (UnaryOp::BitNot, Value::Int(v)) => Ok(Value::Int(!v)),
+                    (UnaryOp::BitNot, Value::Float(_)) => {
+                        Err("Invalid operand of '~': floating-point value".to_string())
+                    }
+                }
+            }
+            Expr::Binary(BinaryOp::And, left, right) => Ok(Value::Int(
+                (left.evaluate(env)?.is_true() && right.evaluate(env)?.is_true()) as i64,
+            )),
+            Expr::Binary(BinaryOp::Or, left, right) => Ok(Value::Int(
+                (left.evaluate(env)?.is_true() || right.evaluate(env)?.is_true()) as i64,
+            )),
+            Expr::Binary(op, left, right) => binary(*op, left.evaluate(env)?, right.evaluate(env)?),
+        }
+    }
+}
+
+fn binary(op: BinaryOp, left: Value, right: Value) -> Result<Value, String> {
+    use BinaryOp::*;
+    let compare = |result: bool| Ok(Value::Int(result as i64));
+    match (left, right) {
+        (Value::Int(a), Value::Int(b)) => match op {
+            Mul => Ok(Value::Int(a.wrapping_mul(b))),
+            Div | Rem if b == 0 => Err("Division by zero".to_string()),
+            Div => Ok(Value::Int(a.wrapping_div(b))),
+            Rem => Ok(Value::Int(a.wrapping_rem(b))),
+            Add => Ok(Value::Int(a.wrapping_add(b))),
+            Sub => Ok(Value::Int(a.wrapping_sub(b))),
+            Shl => Ok(Value::Int(a.wrapping_shl(b as u32))),
+            Shr => Ok(Value::Int(a.wrapping_shr(b as u32))),
+            Lt => compare(a < b),
+            Le => compare(a <= b),
+            Gt => compare(a > b),
+            Ge => compare(a >= b),
+            Eq => compare(a == b),
+            Ne => compare(a != b),
+            BitAnd => Ok(Value::Int(a & b)),
+            BitXor => Ok(Value::Int(a ^ b)),
+            BitOr => Ok(Value::Int(a | b)),
+            And | Or => unreachable!("logical operators are evaluated lazily"),
+        },
+        _ => {
+            let (a, b) = (left.as_f64(), right.as_f64());
+            match op {
+                Mul => Ok(Value::Float(a * b)),
+                Div => Ok(Value::Float(a / b)),
+                Add => Ok(Value::Float(a + b)),
+                Sub => Ok(Value::Float(a - b)),
+                Lt => compare(a < b),
+                Le => compare(a <= b),
+                Gt => compare(a > b),
+                Ge => compare(a >= b),
+                Eq => compare(a == b),
+                Ne => compare(a != b),
+                _ => Err("Invalid operands: floating-point value".to_string()),
+            }
+        }
+    }
+}
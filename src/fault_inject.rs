@@ -0,0 +1,202 @@
+//! `inject-fault`：让共享库函数在选定的调用上直接失败返回，用来走到程序的错误处理路径。
+//!
+//! 与调用图相同，被注入的函数入口上的 int3 只在被调试进程运行期间存在。命中时若这次调用要失败，
+//! 调试器先在进程中调用 `__errno_location` 得到 errno 的地址并写入选定的值，再模拟 `ret`：
+//! 弹出返回地址，rax 设为失败的返回值（返回指针的函数为 NULL，其它为 -1）。函数本身不会执行。
+//! 设置时共享库往往还没有加载（`run` 停在 exec 之后），这时在程序入口写一个 int3，到达入口时
+//! 动态链接器已经加载完依赖库，再解析函数地址。
+
+use crate::inferior::Inferior;
+
+/// 失败时返回 NULL 而不是 -1 的函数
+const POINTER_FUNCTIONS: &[&str] = &[
+    "malloc", "calloc", "realloc", "strdup", "strndup", "fopen", "fdopen", "opendir",
+];
+
+/// 常用的 errno 名字
+const ERRNO_NAMES: &[(&str, i32)] = &[
+    ("EPERM", 1),
+    ("ENOENT", 2),
+    ("EINTR", 4),
+    ("EIO", 5),
+    ("EBADF", 9),
+    ("EAGAIN", 11),
+    ("ENOMEM", 12),
+    ("EACCES", 13),
+    ("EFAULT", 14),
+    ("EBUSY", 16),
+    ("EEXIST", 17),
+    ("EINVAL", 22),
+    ("ENFILE", 23),
+    ("EMFILE", 24),
+    ("ENOSPC", 28),
+    ("EPIPE", 32),
+    ("ECONNRESET", 104),
+    ("ETIMEDOUT", 110),
+    ("ECONNREFUSED", 111),
+];
+
+/// 解析 errno 的名字或数值
+pub fn parse_errno(text: &str) -> Option<i32> {
+    match ERRNO_NAMES.iter().find(|(name, _)| *name == text) {
+        Some((_, value)) => Some(*value),
+        None => text.parse().ok().filter(|value| *value > 0),
+    }
+}
+
+pub fn errno_name(errno: i32) -> String {
+    match ERRNO_NAMES.iter().find(|(_, value)| *value == errno) {
+        Some((name, _)) => name.to_string(),
+        None => errno.to_string(),
+    }
+}
+
+/// 没有指定 errno 时使用的值
+pub fn default_errno(function: &str) -> i32 {
+    if POINTER_FUNCTIONS.contains(&function) {
+        12 // ENOMEM
+    } else {
+        5 // EIO
+    }
+}
+
+/// 哪些调用会失败，用于提示
+pub fn describe_calls(every: usize) -> String {
+    if every == 1 {
+        "every call".to_string()
+    } else {
+        format!("calls {}, {}, {}, ...", every, every * 2, every * 3)
+    }
+}
+
+pub struct Fault {
+    pub function: String,
+    pub errno: i32,
+    /// 第 every、2*every……次调用失败
+    pub every: usize,
+    /// 本次运行中的调用次数
+    pub calls: usize,
+    /// 本次运行中注入失败的次数
+    pub injected: usize,
+    /// 函数入口地址，解析之前为 None
+    addr: Option<usize>,
+    /// 写入 int3 之前的字节；没有写入时为 None
+    orig_byte: Option<u8>,
+}
+
+impl Fault {
+    pub fn new(function: String, errno: i32, every: usize) -> Fault {
+        Fault {
+            function,
+            errno,
+            every,
+            calls: 0,
+            injected: 0,
+            addr: None,
+            orig_byte: None,
+        }
+    }
+
+    /// 这次调用失败时的返回值
+    pub fn failure_value(&self) -> u64 {
+        if POINTER_FUNCTIONS.contains(&self.function.as_str()) {
+            0
+        } else {
+            -1i64 as u64
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FaultInjector {
+    pub faults: Vec<Fault>,
+    /// 还需要停下来解析函数地址的程序入口；到达过之后为 None
+    entry: Option<usize>,
+    /// 程序入口处写入 int3 之前的字节；没有写入时为 None
+    entry_orig_byte: Option<u8>,
+}
+
+impl FaultInjector {
+    /// 新的进程映像：计数清零，共享库中的地址需要在到达 entry 之后重新解析
+    pub fn reset(&mut self, entry: Option<usize>) {
+        for fault in self.faults.iter_mut() {
+            fault.calls = 0;
+            fault.injected = 0;
+            fault.addr = None;
+            fault.orig_byte = None;
+        }
+        self.entry = entry;
+        self.entry_orig_byte = None;
+    }
+
+    /// 用 lookup 解析还没有地址的函数，返回仍解析不到的函数
+    pub fn resolve(&mut self, lookup: impl Fn(&str) -> Option<usize>) -> Vec<String> {
+        let mut unresolved = Vec::new();
+        for fault in self.faults.iter_mut().filter(|fault| fault.addr.is_none()) {
+            fault.addr = lookup(&fault.function);
+            if fault.addr.is_none() {
+                unresolved.push(fault.function.clone());
+            }
+        }
+        unresolved
+    }
+
+    /// 在已解析的函数入口写入 int3；还有函数没有解析且没到过程序入口时在入口也写一个。
+    /// skip 为 true 的地址（已有用户断点）不写
+    pub fn arm(&mut self, inferior: &mut Inferior, skip: impl Fn(usize) -> bool) {
+        for fault in self.faults.iter_mut() {
+            if let (Some(addr), None) = (fault.addr, fault.orig_byte) {
+                if !skip(addr) {
+                    fault.orig_byte = inferior.write_byte(addr, 0xcc).ok();
+                }
+            }
+        }
+        let unresolved = self.faults.iter().any(|fault| fault.addr.is_none());
+        if let Some(entry) = self.entry.filter(|entry| unresolved && !skip(*entry)) {
+            if self.entry_orig_byte.is_none() {
+                self.entry_orig_byte = inferior.write_byte(entry, 0xcc).ok();
+            }
+        }
+    }
+
+    /// 撤掉所有 int3
+    pub fn disarm(&mut self, inferior: &mut Inferior) {
+        for fault in self.faults.iter_mut() {
+            if let (Some(addr), Some(orig_byte)) = (fault.addr, fault.orig_byte.take()) {
+                let _ = inferior.write_byte(addr, orig_byte);
+            }
+        }
+        if let (Some(entry), Some(orig_byte)) = (self.entry, self.entry_orig_byte.take()) {
+            let _ = inferior.write_byte(entry, orig_byte);
+        }
+    }
+
+    /// 停在程序入口的 int3 上（addr 是 int3 的地址）时撤掉它并返回 true，之后不再在入口停下
+    pub fn reached_entry(&mut self, inferior: &mut Inferior, addr: usize) -> bool {
+        match (self.entry, self.entry_orig_byte) {
+            (Some(entry), Some(orig_byte)) if entry == addr => {
+                let _ = inferior.write_byte(entry, orig_byte);
+                self.entry = None;
+                self.entry_orig_byte = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 已写入 int3 的函数入口在 addr 处时返回它的下标和原来的字节
+    pub fn armed_at(&self, addr: usize) -> Option<(usize, u8)> {
+        self.faults
+            .iter()
+            .enumerate()
+            .find(|(_, fault)| fault.addr == Some(addr))
+            .and_then(|(index, fault)| Some((index, fault.orig_byte?)))
+    }
+
+    /// 按函数名删除，返回是否存在。只在被调试进程停下（int3 已撤掉）时调用
+    pub fn remove(&mut self, function: &str) -> bool {
+        let count = self.faults.len();
+        self.faults.retain(|fault| fault.function != function);
+        self.faults.len() != count
+    }
+}
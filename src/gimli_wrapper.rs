@@ -10,10 +10,13 @@ use gimli::{UnitOffset, UnitSectionOffset};
 use object::Object;
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    Encoding, File, Function, Line, Location, LocationEntry, Member, Type, Variable,
+};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{io, path};
 
 pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
@@ -39,219 +42,345 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     // Create `EndianSlice`s for all of the sections.
     let dwarf = dwarf_cow.borrow(&borrow_section);
 
-    // Define a mapping from type offsets to type structs
+    // 编译单元之间互不依赖，在所有 CPU 核上并行解析。先收集全部类型，变量和函数可能引用其它单元中的类型
+    let mut headers = Vec::new();
+    let mut iter = dwarf.units();
+    while let Some(header) = iter.next()? {
+        headers.push(header);
+    }
+    let progress = Progress::new(headers.len() * 2);
     let mut offset_to_type: HashMap<usize, Type> = HashMap::new();
+    for types in parallel_map(&headers, &progress, |header| {
+        let unit = dwarf.unit(*header)?;
+        let mut types = HashMap::new();
+        collect_types(&unit, &dwarf, &mut types)?;
+        Ok(types)
+    })? {
+        offset_to_type.extend(types);
+    }
+    let units = parallel_map(&headers, &progress, |header| {
+        parse_unit(&dwarf, *header, &offset_to_type)
+    })?;
+    progress.finish();
 
+    // 行号表项按文件名归到编译单元，可能归到之前的单元，所以按单元顺序依次合并
     let mut compilation_units: Vec<File> = Vec::new();
+    for (files, rows) in units {
+        compilation_units.extend(files);
+        for (path, number, address) in rows {
+            // 先找名字完全相同的文件，再找名字是路径后缀的文件（DW_AT_name 常是相对编译目录的路径），
+            // 有多个时取最近的单元，即这个行号表所属的单元；最后退回只比较文件名
+            let path_str = path.as_os_str().to_str().unwrap_or("");
+            let path_basename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            let index = compilation_units
+                .iter()
+                .position(|f| f.name == path_str)
+                .or_else(|| {
+                    compilation_units
+                        .iter()
+                        .rposition(|f| !f.name.is_empty() && path.ends_with(&f.name))
+                })
+                .or_else(|| {
+                    compilation_units.iter().position(|f| {
+                        let f_basename = std::path::Path::new(&f.name)
+                            .file_name()
+                            .and_then(|n| n.to_str())
+                            .unwrap_or("");
+                        !f_basename.is_empty() && f_basename == path_basename
+                    })
+                });
+            let file = index.map(|index| &mut compilation_units[index]);
+            if let Some(file) = file {
+                file.lines.push(Line {
+                    file: file.name.clone(),
+                    number,
+                    address,
+                });
+            }
+        }
+    }
+    Ok(compilation_units)
+}
 
-    // Iterate over the compilation units.
-    let mut iter = dwarf.units();
-    while let Some(header) = iter.next()? {
-        let unit = dwarf.unit(header)?;
-
-        // Iterate over the Debugging Information Entries (DIEs) in the unit.
-        let mut depth = 0;
-        let mut entries = unit.entries();
-        while let Some((delta_depth, entry)) = entries.next_dfs()? {
-            depth += delta_depth;
-            // Update the offset_to_type mapping for types
-            // Update the variable list for formal params/variables
-            match entry.tag() {
-                gimli::DW_TAG_compile_unit => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    compilation_units.push(File {
-                        name,
-                        global_variables: Vec::new(),
-                        functions: Vec::new(),
-                        lines: Vec::new(),
-                    });
-                }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
+/// 一个编译单元解析出的文件（函数、变量）和行号表项 (源文件路径, 行号, 地址)
+type UnitIndex = (Vec<File>, Vec<(path::PathBuf, usize, usize)>);
+
+fn parse_unit<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    header: gimli::CompilationUnitHeader<R>,
+    offset_to_type: &HashMap<usize, Type>,
+) -> Result<UnitIndex, Error> {
+    let unit = dwarf.unit(header)?;
+    let mut compilation_units: Vec<File> = Vec::new();
+
+    // Iterate over the Debugging Information Entries (DIEs) in the unit.
+    let mut depth = 0;
+    // 当前所在的词法块：(DIE 深度, 地址范围)
+    let mut blocks: Vec<(isize, Vec<(usize, usize)>)> = Vec::new();
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        while blocks.last().map(|(d, _)| *d >= depth).unwrap_or(false) {
+            blocks.pop();
+        }
+        // Update the offset_to_type mapping for types
+        // Update the variable list for formal params/variables
+        match entry.tag() {
+            gimli::DW_TAG_compile_unit => {
+                let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
+                    if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, dwarf) {
+                        name
                     } else {
                         "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
+                    }
+                } else {
+                    "<unknown>".to_string()
+                };
+                compilation_units.push(File {
+                    name,
+                    global_variables: Vec::new(),
+                    functions: Vec::new(),
+                    lines: Vec::new(),
+                });
+            }
+            gimli::DW_TAG_subprogram => {
+                let mut func: Function = Default::default();
+                // DW_AT_high_pc 是地址（而不是长度）时的函数结束地址
+                let mut high_pc_addr = None;
+                let mut attrs = entry.attrs();
+                while let Some(attr) = attrs.next()? {
+                    let val = get_attr_value(&attr, &unit, dwarf);
+                    //println!("   {}: {:?}", attr.name(), val);
+                    match attr.name() {
+                        gimli::DW_AT_name => {
+                            if let Ok(DebugValue::Str(name)) = val {
+                                func.name = name;
+                            }
                         }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
-                gimli::DW_TAG_subprogram => {
-                    let mut func: Function = Default::default();
-                    let mut attrs = entry.attrs();
-                    while let Some(attr) = attrs.next()? {
-                        let val = get_attr_value(&attr, &unit, &dwarf);
-                        //println!("   {}: {:?}", attr.name(), val);
-                        match attr.name() {
-                            gimli::DW_AT_name => {
-                                if let Ok(DebugValue::Str(name)) = val {
-                                    func.name = name;
+                        gimli::DW_AT_high_pc => {
+                            if let Ok(DebugValue::Uint(high_pc)) = val {
+                                match attr.value() {
+                                    gimli::AttributeValue::Addr(_)
+                                    | gimli::AttributeValue::DebugAddrIndex(_) => {
+                                        high_pc_addr = Some(high_pc as usize)
+                                    }
+                                    _ => func.text_length = high_pc.try_into().unwrap(),
                                 }
                             }
-                            gimli::DW_AT_high_pc => {
-                                if let Ok(DebugValue::Uint(high_pc)) = val {
-                                    func.text_length = high_pc.try_into().unwrap();
-                                }
+                        }
+                        gimli::DW_AT_low_pc => {
+                            //println!("low pc {:?}", attr.value());
+                            if let Ok(DebugValue::Uint(low_pc)) = val {
+                                func.address = low_pc.try_into().unwrap();
                             }
-                            gimli::DW_AT_low_pc => {
-                                //println!("low pc {:?}", attr.value());
-                                if let Ok(DebugValue::Uint(low_pc)) = val {
-                                    func.address = low_pc.try_into().unwrap();
-                                }
+                        }
+                        gimli::DW_AT_decl_line => {
+                            if let Ok(DebugValue::Uint(line_number)) = val {
+                                func.line_number = line_number.try_into().unwrap();
                             }
-                            gimli::DW_AT_decl_line => {
-                                if let Ok(DebugValue::Uint(line_number)) = val {
-                                    func.line_number = line_number.try_into().unwrap();
-                                }
+                        }
+                        gimli::DW_AT_type => {
+                            if let Ok(DebugValue::Size(offset)) = val {
+                                func.return_type = offset_to_type.get(&offset).cloned();
                             }
-                            _ => {}
                         }
+                        _ => {}
                     }
-                    compilation_units.last_mut().unwrap().functions.push(func);
                 }
-                gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
-                    let mut name = String::new();
-                    let mut entity_type: Option<Type> = None;
-                    let mut location: Option<Location> = None;
-                    let mut line_number = 0;
-                    let mut attrs = entry.attrs();
-                    while let Some(attr) = attrs.next()? {
-                        let val = get_attr_value(&attr, &unit, &dwarf);
-                        //println!("   {}: {:?}", attr.name(), val);
-                        match attr.name() {
-                            gimli::DW_AT_name => {
-                                if let Ok(DebugValue::Str(attr_name)) = val {
-                                    name = attr_name;
-                                }
-                            }
-                            gimli::DW_AT_type => {
-                                if let Ok(DebugValue::Size(offset)) = val {
-                                    if let Some(dtype) = offset_to_type.get(&offset).clone() {
-                                        entity_type = Some(dtype.clone());
-                                    }
-                                }
+                if let Some(end) = high_pc_addr {
+                    func.text_length = end.saturating_sub(func.address);
+                }
+                compilation_units.last_mut().unwrap().functions.push(func);
+            }
+            gimli::DW_TAG_lexical_block => {
+                blocks.push((depth, entry_ranges(entry, &unit, dwarf)?));
+            }
+            gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
+                let mut name = String::new();
+                let mut entity_type: Option<Type> = None;
+                let mut location: Option<Location> = None;
+                let mut line_number = 0;
+                let mut declaration = false;
+                let mut attrs = entry.attrs();
+                while let Some(attr) = attrs.next()? {
+                    let val = get_attr_value(&attr, &unit, dwarf);
+                    //println!("   {}: {:?}", attr.name(), val);
+                    match attr.name() {
+                        gimli::DW_AT_name => {
+                            if let Ok(DebugValue::Str(attr_name)) = val {
+                                name = attr_name;
                             }
-                            gimli::DW_AT_location => {
-                                if let Some(loc) = get_location(&attr, &unit) {
-                                    location = Some(loc);
+                        }
+                        gimli::DW_AT_type => {
+                            if let Ok(DebugValue::Size(offset)) = val {
+                                if let Some(dtype) = offset_to_type.get(&offset).clone() {
+                                    entity_type = Some(dtype.clone());
                                 }
                             }
-                            gimli::DW_AT_decl_line => {
-                                if let Ok(DebugValue::Uint(num)) = val {
-                                    line_number = num;
-                                }
+                        }
+                        gimli::DW_AT_location => {
+                            if let Some(loc) = get_location(&attr, &unit, dwarf) {
+                                location = Some(loc);
                             }
-                            _ => {}
                         }
-                    }
-                    if entity_type.is_some() && location.is_some() {
-                        let var = Variable {
-                            name,
-                            entity_type: entity_type.unwrap(),
-                            location: location.unwrap(),
-                            line_number: line_number.try_into().unwrap(),
-                        };
-                        if depth == 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .global_variables
-                                .push(var);
-                        } else if depth > 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .functions
-                                .last_mut()
-                                .unwrap()
-                                .variables
-                                .push(var);
+                        gimli::DW_AT_decl_line => {
+                            if let Ok(DebugValue::Uint(num)) = val {
+                                line_number = num;
+                            }
                         }
+                        gimli::DW_AT_declaration => declaration = true,
+                        _ => {}
                     }
                 }
-                // NOTE: :You may consider supporting other types by extending this
-                // match statement
-                _ => {}
-            }
-        }
-
-        // Get line numbers
-        if let Some(program) = unit.line_program.clone() {
-            // Iterate over the line program rows.
-            let mut rows = program.rows();
-            while let Some((header, row)) = rows.next_row()? {
-                if !row.end_sequence() {
-                    // Determine the path. Real applications should cache this for performance.
-                    let mut path = path::PathBuf::new();
-                    if let Some(file) = row.file(header) {
-                        if let Some(dir) = file.directory(header) {
-                            path.push(dwarf.attr_string(&unit, dir)?.to_string_lossy().as_ref());
-                        }
-                        path.push(
-                            dwarf
-                                .attr_string(&unit, file.path_name())?
-                                .to_string_lossy()
-                                .as_ref(),
-                        );
+                // 没有 DW_AT_location 的局部变量（外部变量的声明除外）被编译器优化掉了
+                if location.is_none() && depth > 1 && !declaration && !name.is_empty() {
+                    location = Some(Location::OptimizedOut);
+                }
+                if entity_type.is_some() && location.is_some() {
+                    let var = Variable {
+                        name,
+                        entity_type: entity_type.unwrap(),
+                        location: location.unwrap(),
+                        line_number: line_number.try_into().unwrap(),
+                        scope: blocks
+                            .last()
+                            .map(|(_, ranges)| ranges.clone())
+                            .unwrap_or_default(),
+                        is_parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
+                        scope_depth: blocks.len(),
+                    };
+                    if depth == 1 {
+                        compilation_units
+                            .last_mut()
+                            .unwrap()
+                            .global_variables
+                            .push(var);
+                    } else if depth > 1 {
+                        compilation_units
+                            .last_mut()
+                            .unwrap()
+                            .functions
+                            .last_mut()
+                            .unwrap()
+                            .variables
+                            .push(var);
                     }
+                }
+            }
+            // NOTE: :You may consider supporting other types by extending this
+            // match statement
+            _ => {}
+        }
+    }
 
-                    // Get the File - use basename matching to handle path differences
-                    let path_str = path.as_os_str().to_str().unwrap_or("");
-                    let file = compilation_units.iter_mut().find(|f| {
-                        // Try exact match first
-                        if f.name == path_str {
-                            return true;
-                        }
-                        // Fall back to basename match
-                        let f_basename = std::path::Path::new(&f.name)
-                            .file_name()
-                            .and_then(|n| n.to_str())
-                            .unwrap_or("");
-                        let path_basename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-                        !f_basename.is_empty() && f_basename == path_basename
-                    });
-
-                    // Determine line/column. DWARF line/column is never 0, so we use that
-                    // but other applications may want to display this differently.
-                    let line = row.line().unwrap_or(0);
-
-                    if let Some(file) = file {
-                        file.lines.push(Line {
-                            file: file.name.clone(),
-                            number: line.try_into().unwrap(),
-                            address: row.address().try_into().unwrap(),
-                        });
+    // Get line numbers
+    let mut lines = Vec::new();
+    if let Some(program) = unit.line_program.clone() {
+        // Iterate over the line program rows.
+        let mut rows = program.rows();
+        while let Some((header, row)) = rows.next_row()? {
+            if !row.end_sequence() {
+                // Determine the path. Real applications should cache this for performance.
+                let mut path = path::PathBuf::new();
+                if let Some(file) = row.file(header) {
+                    if let Some(dir) = file.directory(header) {
+                        path.push(dwarf.attr_string(&unit, dir)?.to_string_lossy()?.as_ref());
                     }
+                    path.push(
+                        dwarf
+                            .attr_string(&unit, file.path_name())?
+                            .to_string_lossy()?
+                            .as_ref(),
+                    );
                 }
+
+                // Determine line/column. DWARF line/column is never 0, so we use that
+                // but other applications may want to display this differently.
+                let line = row.line().unwrap_or(0);
+                lines.push((
+                    path,
+                    line.try_into().unwrap(),
+                    row.address().try_into().unwrap(),
+                ));
             }
         }
     }
-    Ok(compilation_units)
+    Ok((compilation_units, lines))
+}
+
+/// 解析的编译单元超过这个数量时才显示进度
+const PROGRESS_MIN_UNITS: usize = 64;
+
+/// 在 stderr 上显示解析进度，多个线程共用
+struct Progress {
+    total: usize,
+    done: AtomicUsize,
+}
+
+impl Progress {
+    fn new(total: usize) -> Progress {
+        Progress {
+            total,
+            done: AtomicUsize::new(0),
+        }
+    }
+
+    fn visible(&self) -> bool {
+        self.total >= PROGRESS_MIN_UNITS * 2
+    }
+
+    fn advance(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        // 每 1% 刷新一次
+        if self.visible() && (done * 100 / self.total) != ((done - 1) * 100 / self.total) {
+            eprint!("\rReading debug info... {}%", done * 100 / self.total);
+        }
+    }
+
+    fn finish(&self) {
+        if self.visible() {
+            eprintln!("\rReading debug info... done");
+        }
+    }
+}
+
+/// 用所有 CPU 核并行处理 items，结果按原来的顺序返回；出错时返回排在最前面的错误
+fn parallel_map<T, U, F>(items: &[T], progress: &Progress, f: F) -> Result<Vec<U>, Error>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> Result<U, Error> + Sync,
+{
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(items.len())
+        .max(1);
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, Result<U, Error>)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        if index >= items.len() {
+                            break;
+                        }
+                        results.push((index, f(&items[index])));
+                        progress.advance();
+                    }
+                    results
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
 }
 
 #[derive(Debug, Clone)]
@@ -302,25 +431,489 @@ impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
 
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
-fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
+/// DIE 在 .debug_info 中的偏移，与 get_attr_value 返回的 DW_AT_type 引用一致
+fn section_offset<R: Reader>(offset: UnitOffset, unit: &gimli::Unit<R>) -> usize {
+    match offset.to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(offset) => offset.0,
+        UnitSectionOffset::DebugTypesOffset(offset) => offset.0,
+    }
+}
+
+fn attr_string<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    match get_attr_value(&entry.attr(name).ok()??, unit, dwarf) {
+        Ok(DebugValue::Str(value)) => Some(value),
+        _ => None,
+    }
+}
+
+/// 解析类型引用时的最大嵌套深度，防止损坏的调试信息造成无限递归
+const MAX_TYPE_DEPTH: usize = 16;
+
+/// 第一遍收集的类型 DIE；typedef、指针和成员引用的类型在第二遍解析
+struct RawType {
+    name: Option<String>,
+    size: usize,
+    kind: RawKind,
+}
+
+enum RawKind {
+    Base(Encoding),
+    Enum(Vec<(String, i64)>),
+    Typedef(usize),
+    /// 指向的类型，void * 为 None
+    Pointer(Option<usize>),
+    /// const / volatile 修饰的类型
+    Qualified(&'static str, Option<usize>),
+    /// 结构体或联合（联合的所有成员偏移都是 0）
+    Struct(Vec<RawMember>),
+}
+
+struct RawMember {
+    name: String,
+    type_ref: usize,
+    bit_position: usize,
+    bit_size: Option<usize>,
+}
+
+fn attr_type_ref<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<usize> {
+    match get_attr_value(&entry.attr(gimli::DW_AT_type).ok()??, unit, dwarf) {
+        Ok(DebugValue::Size(offset)) => Some(offset),
+        _ => None,
+    }
+}
+
+fn attr_udata<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+) -> Option<usize> {
+    entry
+        .attr_value(name)
+        .ok()??
+        .udata_value()
+        .map(|value| value as usize)
+}
+
+/// 成员的字节偏移：DWARF 4 起是常量，更早的版本是 `DW_OP_plus_uconst` 表达式
+fn member_location<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+) -> usize {
+    match entry.attr_value(gimli::DW_AT_data_member_location) {
+        Ok(Some(gimli::AttributeValue::Exprloc(expr))) => {
+            let mut pc = expr.0;
+            match gimli::Operation::parse(&mut pc, unit.encoding()) {
+                Ok(gimli::Operation::PlusConstant { value }) => value as usize,
+                _ => 0,
+            }
+        }
+        Ok(Some(value)) => value.udata_value().unwrap_or(0) as usize,
+        _ => 0,
+    }
+}
+
+/// 收集单元中的类型（基本类型、枚举、结构体、typedef、指针和 const / volatile）。
+/// DIE 之间可以前向引用，所以先记录所有类型 DIE，再逐个解析成 `Type`
+fn collect_types<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    offset_to_type: &mut HashMap<usize, Type>,
+) -> Result<(), Error> {
+    let mut raw_types: HashMap<usize, RawType> = HashMap::new();
+    // 正在收集子项（枚举值、成员）的类型及其深度
+    let mut parents: Vec<(usize, isize)> = Vec::new();
+    let mut depth = 0;
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        while parents.last().map(|(_, d)| *d >= depth).unwrap_or(false) {
+            parents.pop();
+        }
+        let parent = parents
+            .last()
+            .filter(|(_, d)| *d + 1 == depth)
+            .map(|(offset, _)| *offset);
+        let type_offset = section_offset(entry.offset(), unit);
+        let name = attr_string(entry, gimli::DW_AT_name, unit, dwarf);
+        let size = attr_udata(entry, gimli::DW_AT_byte_size).unwrap_or(0);
+        let type_ref = attr_type_ref(entry, unit, dwarf);
+        let raw = match entry.tag() {
+            gimli::DW_TAG_base_type => RawType {
+                name,
+                size,
+                kind: RawKind::Base(match entry.attr_value(gimli::DW_AT_encoding)? {
+                    Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_float)) => Encoding::Float,
+                    Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_signed))
+                    | Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_signed_char)) => {
+                        Encoding::Signed
+                    }
+                    _ => Encoding::Unsigned,
+                }),
+            },
+            gimli::DW_TAG_enumeration_type => {
+                parents.push((type_offset, depth));
+                RawType {
+                    name: Some(format!("enum {}", name.as_deref().unwrap_or("<anonymous>"))),
+                    size,
+                    kind: RawKind::Enum(Vec::new()),
+                }
+            }
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_union_type => {
+                parents.push((type_offset, depth));
+                RawType {
+                    name: Some(format!(
+                        "{} {}",
+                        if entry.tag() == gimli::DW_TAG_union_type {
+                            "union"
+                        } else {
+                            "struct"
+                        },
+                        name.as_deref().unwrap_or("<anonymous>")
+                    )),
+                    size,
+                    kind: RawKind::Struct(Vec::new()),
+                }
+            }
+            gimli::DW_TAG_typedef => match type_ref {
+                Some(target) => RawType {
+                    name,
+                    size,
+                    kind: RawKind::Typedef(target),
+                },
+                None => continue,
+            },
+            gimli::DW_TAG_pointer_type => RawType {
+                name: None,
+                size,
+                kind: RawKind::Pointer(type_ref),
+            },
+            gimli::DW_TAG_const_type | gimli::DW_TAG_volatile_type => RawType {
+                name: None,
+                size,
+                kind: RawKind::Qualified(
+                    if entry.tag() == gimli::DW_TAG_const_type {
+                        "const"
+                    } else {
+                        "volatile"
+                    },
+                    type_ref,
+                ),
+            },
+            gimli::DW_TAG_enumerator => {
+                let value = entry
+                    .attr_value(gimli::DW_AT_const_value)?
+                    .and_then(|value| {
+                        value
+                            .sdata_value()
+                            .or_else(|| value.udata_value().map(|v| v as i64))
+                    });
+                if let (Some(RawKind::Enum(enumerators)), Some(name), Some(value)) = (
+                    parent
+                        .and_then(|p| raw_types.get_mut(&p))
+                        .map(|t| &mut t.kind),
+                    name,
+                    value,
+                ) {
+                    enumerators.push((name, value));
+                }
+                continue;
+            }
+            gimli::DW_TAG_member => {
+                let byte_position = member_location(entry, unit) * 8;
+                let bit_size = attr_udata(entry, gimli::DW_AT_bit_size);
+                let bit_position = match (
+                    bit_size,
+                    attr_udata(entry, gimli::DW_AT_data_bit_offset),
+                    attr_udata(entry, gimli::DW_AT_bit_offset),
+                ) {
+                    (Some(_), Some(data_bit_offset), _) => data_bit_offset,
+                    // DWARF 2/3：从存储单元的最高位开始计数
+                    (Some(bits), None, Some(bit_offset)) => {
+                        byte_position + (size * 8).saturating_sub(bit_offset + bits)
+                    }
+                    _ => byte_position,
+                };
+                if let (Some(RawKind::Struct(members)), Some(type_ref)) = (
+                    parent
+                        .and_then(|p| raw_types.get_mut(&p))
+                        .map(|t| &mut t.kind),
+                    type_ref,
+                ) {
+                    members.push(RawMember {
+                        name: name.unwrap_or_default(),
+                        type_ref,
+                        bit_position,
+                        bit_size,
+                    });
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        raw_types.insert(type_offset, raw);
+    }
+
+    for offset in raw_types.keys() {
+        if let Some(resolved) = resolve_type(&raw_types, *offset, 0) {
+            offset_to_type.insert(*offset, resolved);
+        }
+    }
+    Ok(())
+}
+
+/// 类型的显示名，只需要名字时（例如指针指向的类型）不解析成员，自引用的结构体也不会递归
+fn type_name(raw_types: &HashMap<usize, RawType>, offset: Option<usize>, depth: usize) -> String {
+    let raw = match offset.and_then(|offset| raw_types.get(&offset)) {
+        Some(raw) if depth < MAX_TYPE_DEPTH => raw,
+        Some(_) => return "<unknown>".to_string(),
+        None => return "void".to_string(),
+    };
+    match &raw.kind {
+        RawKind::Pointer(target) => format!("{} *", type_name(raw_types, *target, depth + 1)),
+        RawKind::Qualified(qualifier, target) => {
+            format!("{} {}", qualifier, type_name(raw_types, *target, depth + 1))
+        }
+        _ => raw.name.clone().unwrap_or_else(|| "<unknown>".to_string()),
+    }
+}
+
+fn resolve_type(raw_types: &HashMap<usize, RawType>, offset: usize, depth: usize) -> Option<Type> {
+    if depth >= MAX_TYPE_DEPTH {
+        return None;
+    }
+    let raw = raw_types.get(&offset)?;
+    let name = type_name(raw_types, Some(offset), depth);
+    match &raw.kind {
+        RawKind::Base(encoding) => Some(Type {
+            encoding: *encoding,
+            ..Type::new(name, raw.size)
+        }),
+        RawKind::Pointer(_) => Some(Type {
+            encoding: Encoding::Address,
+            ..Type::new(name, raw.size)
+        }),
+        RawKind::Enum(enumerators) => Some(Type {
+            enumerators: enumerators.clone(),
+            ..Type::new(name, raw.size)
+        }),
+        RawKind::Typedef(target) => Some(Type {
+            name,
+            ..resolve_type(raw_types, *target, depth + 1)?
+        }),
+        RawKind::Qualified(_, target) => Some(Type {
+            name,
+            ..resolve_type(raw_types, (*target)?, depth + 1)?
+        }),
+        RawKind::Struct(members) => Some(Type {
+            // 无法解析类型的成员（例如数组）不显示
+            members: members
+                .iter()
+                .filter_map(|member| {
+                    Some(Member {
+                        name: member.name.clone(),
+                        bit_position: member.bit_position,
+                        bit_size: member.bit_size,
+                        member_type: resolve_type(raw_types, member.type_ref, depth + 1)?,
+                    })
+                })
+                .collect(),
+            ..Type::new(name, raw.size)
+        }),
+    }
+}
+
+fn get_location<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<Location> {
     if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
-        let encoding = unit.encoding();
-        let mut pc = data.0.clone();
-        if pc.len() > 0 {
-            if let Ok(op) = gimli::Operation::parse(&mut pc, encoding) {
-                match op {
-                    gimli::Operation::FrameOffset { offset } => {
-                        return Some(Location::FramePointerOffset(offset.try_into().unwrap()));
+        return expression_location(data, unit.encoding());
+    }
+    // 位置列表：DW_FORM_sec_offset（DWARF 4 的 .debug_loc）或 DW_FORM_loclistx（DWARF 5）
+    let offset = dwarf.attr_locations_offset(unit, attr.value()).ok()??;
+    let mut iter = dwarf.locations(unit, offset).ok()?;
+    let mut entries = Vec::new();
+    while let Ok(Some(entry)) = iter.next() {
+        entries.push(LocationEntry {
+            begin: entry.range.begin as usize,
+            end: entry.range.end as usize,
+            location: expression_location(&entry.data, unit.encoding()),
+        });
+    }
+    Some(Location::List(entries))
+}
+
+/// 解析一个位置表达式；空表达式表示变量被优化掉了，常见的单个操作直接解析，其余的表达式保存下来，
+/// 读取变量时再求值
+fn expression_location<R: Reader>(
+    data: &gimli::Expression<R>,
+    encoding: gimli::Encoding,
+) -> Option<Location> {
+    let mut pc = data.0.clone();
+    if pc.len() == 0 {
+        return Some(Location::OptimizedOut);
+    }
+    if let Ok(op) = gimli::Operation::parse(&mut pc, encoding) {
+        match op {
+            gimli::Operation::FrameOffset { offset } if pc.len() == 0 => {
+                return Some(Location::FramePointerOffset(offset.try_into().unwrap()));
+            }
+            gimli::Operation::Address { address } if pc.len() == 0 => {
+                return Some(Location::Address(address.try_into().unwrap()));
+            }
+            gimli::Operation::Register { register } if pc.len() == 0 => {
+                return Some(Location::Register(register.0));
+            }
+            // DW_OP_const*u <offset>; DW_OP_form_tls_address（或 GNU 的 push_tls_address）
+            gimli::Operation::UnsignedConstant { value } => {
+                if let Ok(gimli::Operation::TLS) = gimli::Operation::parse(&mut pc, encoding) {
+                    if pc.len() == 0 {
+                        return Some(Location::TlsOffset(value.try_into().unwrap()));
                     }
-                    gimli::Operation::Address { address } => {
-                        return Some(Location::Address(address.try_into().unwrap()));
+                }
+            }
+            _ => {}
+        }
+    }
+    let bytes = data.0.to_slice().ok()?.into_owned();
+    Some(Location::Expression(bytes, encoding))
+}
+
+/// DIE 的地址范围，来自 DW_AT_low_pc / DW_AT_high_pc 或 DW_AT_ranges。gimli 的 `die_ranges` 不认识
+/// DWARF 5 的 DW_FORM_addrx（clang 默认用它表示 DW_AT_low_pc），这里自己解析
+fn entry_ranges<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Result<Vec<(usize, usize)>, Error> {
+    let mut ranges = Vec::new();
+    let mut low_pc = None;
+    let mut high_pc = None;
+    let mut length = None;
+    let mut attrs = entry.attrs();
+    while let Some(attr) = attrs.next()? {
+        match (attr.name(), attr.value()) {
+            (gimli::DW_AT_low_pc, gimli::AttributeValue::Addr(addr)) => low_pc = Some(addr),
+            (gimli::DW_AT_low_pc, gimli::AttributeValue::DebugAddrIndex(index)) => {
+                low_pc = Some(dwarf.address(unit, index)?)
+            }
+            (gimli::DW_AT_high_pc, gimli::AttributeValue::Addr(addr)) => high_pc = Some(addr),
+            (gimli::DW_AT_high_pc, gimli::AttributeValue::DebugAddrIndex(index)) => {
+                high_pc = Some(dwarf.address(unit, index)?)
+            }
+            (gimli::DW_AT_high_pc, gimli::AttributeValue::Udata(size)) => length = Some(size),
+            (gimli::DW_AT_ranges, value) => {
+                if let Some(mut iter) = dwarf.attr_ranges(unit, value)? {
+                    while let Some(range) = iter.next()? {
+                        ranges.push((range.begin as usize, range.end as usize));
                     }
-                    _ => {}
                 }
             }
+            _ => {}
         }
     }
-    None
+    if let Some(begin) = low_pc {
+        if let Some(end) = length.map(|length| begin + length).or(high_pc) {
+            ranges.push((begin as usize, end as usize));
+        }
+    }
+    Ok(ranges)
+}
+
+/// 位置表达式求值时需要的进程状态
+pub struct ExpressionContext<'a> {
+    /// DWARF 寄存器号对应寄存器的内容（小端），不可用时为 None
+    pub register: &'a dyn Fn(u16) -> Option<Vec<u8>>,
+    /// 读取 [addr, addr + len) 的内存
+    pub read_memory: &'a dyn Fn(usize, usize) -> Option<Vec<u8>>,
+    /// 所在帧的 CFA，同时作为 DW_OP_fbreg 的帧基址
+    pub cfa: u64,
+}
+
+fn le_word(bytes: &[u8]) -> u64 {
+    let mut word = [0u8; 8];
+    let len = bytes.len().min(8);
+    word[..len].copy_from_slice(&bytes[..len]);
+    u64::from_le_bytes(word)
+}
+
+/// 对 `Location::Expression` 求值，返回变量开头的 size 个字节。变量（或它的某一部分）在这里被优化掉、
+/// 或者依赖无法恢复的函数入口值（DW_OP_entry_value）时返回 Ok(None)
+pub fn evaluate_location(
+    bytes: &[u8],
+    encoding: gimli::Encoding,
+    size: usize,
+    ctx: &ExpressionContext,
+) -> Result<Option<Vec<u8>>, String> {
+    let expression =
+        gimli::Expression(gimli::EndianSlice::new(bytes, gimli::RunTimeEndian::Little));
+    let mut evaluation = expression.evaluation(encoding);
+    let mut result = evaluation.evaluate();
+    loop {
+        result = match result.map_err(|e| format!("Invalid location expression: {}", e))? {
+            gimli::EvaluationResult::Complete => break,
+            gimli::EvaluationResult::RequiresMemory { address, size, .. } => {
+                let data = (ctx.read_memory)(address as usize, size as usize)
+                    .ok_or_else(|| format!("Cannot access memory at {:#x}", address))?;
+                evaluation.resume_with_memory(gimli::Value::Generic(le_word(&data)))
+            }
+            gimli::EvaluationResult::RequiresRegister { register, .. } => {
+                let data = (ctx.register)(register.0)
+                    .ok_or_else(|| format!("Register {} is not available", register.0))?;
+                evaluation.resume_with_register(gimli::Value::Generic(le_word(&data)))
+            }
+            gimli::EvaluationResult::RequiresFrameBase => {
+                evaluation.resume_with_frame_base(ctx.cfa)
+            }
+            gimli::EvaluationResult::RequiresCallFrameCfa => {
+                evaluation.resume_with_call_frame_cfa(ctx.cfa)
+            }
+            gimli::EvaluationResult::RequiresRelocatedAddress(address) => {
+                evaluation.resume_with_relocated_address(address)
+            }
+            gimli::EvaluationResult::RequiresEntryValue(_) => return Ok(None),
+            _ => return Err("Unsupported operation in location expression".to_string()),
+        };
+    }
+
+    // DW_OP_piece 把变量拆成几段，每段各有位置；没有 DW_OP_piece 时只有一段
+    let mut value = Vec::with_capacity(size);
+    for piece in evaluation.result() {
+        let piece_size = piece
+            .size_in_bits
+            .map(|bits| (bits as usize).div_ceil(8))
+            .unwrap_or(size);
+        let mut data = match piece.location {
+            gimli::Location::Empty => return Ok(None),
+            gimli::Location::Register { register } => (ctx.register)(register.0)
+                .ok_or_else(|| format!("Register {} is not available", register.0))?,
+            gimli::Location::Address { address } => (ctx.read_memory)(address as usize, piece_size)
+                .ok_or_else(|| format!("Cannot access memory at {:#x}", address))?,
+            // DW_OP_stack_value：表达式算出的就是变量的值
+            gimli::Location::Value { value } => match value {
+                gimli::Value::F32(v) => v.to_le_bytes().to_vec(),
+                gimli::Value::F64(v) => v.to_le_bytes().to_vec(),
+                v => v.to_u64(!0).unwrap_or_default().to_le_bytes().to_vec(),
+            },
+            gimli::Location::Bytes { value } => value.to_vec(),
+            gimli::Location::ImplicitPointer { .. } => {
+                return Err("Implicit pointers are not supported".to_string())
+            }
+        };
+        data.resize(piece_size, 0);
+        value.extend(data);
+    }
+    value.resize(size, 0);
+    Ok(Some(value))
 }
 
 // based on dwarf_dump.rs
@@ -378,6 +971,10 @@ fn get_attr_value<R: Reader>(
         }
         gimli::AttributeValue::Sdata(data) => Ok(DebugValue::Int(data)),
         gimli::AttributeValue::Addr(data) => Ok(DebugValue::Uint(data)),
+        // DWARF5 DW_FORM_addrx：.debug_addr 中的地址
+        gimli::AttributeValue::DebugAddrIndex(index) => {
+            Ok(DebugValue::Uint(dwarf.address(unit, index)?))
+        }
         gimli::AttributeValue::Udata(data) => Ok(DebugValue::Uint(data)),
 
         gimli::AttributeValue::String(s) => {
@@ -398,13 +995,14 @@ fn dump_file_index<R: Reader, W: Write>(
     unit: &gimli::Unit<R>,
     dwarf: &gimli::Dwarf<R>,
 ) -> Result<(), Error> {
-    if file == 0 {
-        return Ok(());
-    }
     let header = match unit.line_program {
         Some(ref program) => program.header(),
         None => return Ok(()),
     };
+    // DWARF 5 的文件编号从 0 开始，0 是编译单元的主文件
+    if file == 0 && header.version() <= 4 {
+        return Ok(());
+    }
     let file = match header.file(file) {
         Some(header) => header,
         None => {
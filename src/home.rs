@@ -0,0 +1,59 @@
+//! 调试器自己的文件（命令历史、LLM 配置、索引缓存、插件）所在的目录。
+//!
+//! 平时是 HOME。容器和 systemd 服务中常常没有设置 HOME，这时改用 `$XDG_DATA_HOME/kdb`，
+//! 再没有时用 `/tmp/kdb-<uid>`；目录不能创建时调用者只是不读写这些文件，不影响调试。
+//!
+//! `/tmp` 是所有用户共享的，别的用户可以抢先建好 `/tmp/kdb-<uid>` 并放进插件或 LLM 配置，
+//! 因此只使用属于当前用户、权限为 0700 的真实目录，否则不使用任何目录（插件、配置、缓存和
+//! 历史都关闭）。
+
+use nix::unistd::getuid;
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::io::ErrorKind;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::PathBuf;
+
+/// 设置了（非空的绝对路径）HOME 时返回它
+pub fn home_dir() -> Option<PathBuf> {
+    absolute_var("HOME")
+}
+
+/// 存放调试器文件的目录，其下的文件名与放在 HOME 中时相同。`/tmp` 下的目录不安全时返回 None
+pub fn base_dir() -> Option<PathBuf> {
+    if let Some(home) = home_dir() {
+        return Some(home);
+    }
+    if let Some(data) = absolute_var("XDG_DATA_HOME") {
+        let dir = data.join("kdb");
+        let _ = DirBuilder::new().recursive(true).mode(0o700).create(&dir);
+        return Some(dir);
+    }
+    let dir = temp_dir();
+    match DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+        Err(_) => return None,
+    }
+    // 不跟随符号链接：链接本身可以由别人创建
+    let metadata = fs::symlink_metadata(&dir).ok()?;
+    let private = metadata.file_type().is_dir()
+        && metadata.uid() == getuid().as_raw()
+        && metadata.mode() & 0o777 == 0o700;
+    if private {
+        Some(dir)
+    } else {
+        None
+    }
+}
+
+/// 没有 HOME 和 XDG_DATA_HOME 时使用的目录
+pub fn temp_dir() -> PathBuf {
+    env::temp_dir().join(format!("kdb-{}", getuid()))
+}
+
+fn absolute_var(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
@@ -0,0 +1,312 @@
+//! 符号索引的磁盘缓存。
+//!
+//! 从 DWARF 解析出的源文件、函数、变量和行号表按可执行文件的 build-id 保存为
+//! `~/.kdb/cache/<build-id>.json`，再次调试同一个程序时直接读取，不用重新解析。程序重新编译后
+//! build-id 随之改变，旧的缓存不会再被用到；没有 build-id 的程序不缓存。
+
+use crate::dwarf_data::{
+    Encoding, File, Function, Line, Location, LocationEntry, Member, Type, Variable,
+};
+
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+/// 缓存格式的版本，修改下面的 JSON 结构或 `dwarf_data` 中的索引结构时递增
+const CACHE_FORMAT_VERSION: u64 = 2;
+
+fn cache_path(build_id: &str) -> Option<PathBuf> {
+    Some(
+        crate::home::base_dir()?
+            .join(".kdb/cache")
+            .join(format!("{}.json", build_id)),
+    )
+}
+
+/// 读取 build-id 对应的缓存；没有缓存、格式版本不同或内容损坏时返回 None
+pub fn load(build_id: &str) -> Option<Vec<File>> {
+    let text = std::fs::read_to_string(cache_path(build_id)?).ok()?;
+    let cache: Value = serde_json::from_str(&text).ok()?;
+    if cache.get("version")?.as_u64()? != CACHE_FORMAT_VERSION
+        || cache.get("build_id")?.as_str()? != build_id
+    {
+        return None;
+    }
+    cache
+        .get("files")?
+        .as_array()?
+        .iter()
+        .map(file_from_json)
+        .collect()
+}
+
+/// 保存缓存。写入失败（例如 HOME 不可写）不影响调试，只是下次仍要重新解析
+pub fn store(build_id: &str, files: &[File]) {
+    let path = match cache_path(build_id) {
+        Some(path) => path,
+        None => return,
+    };
+    let cache = json!({
+        "version": CACHE_FORMAT_VERSION,
+        "build_id": build_id,
+        "files": files.iter().map(file_to_json).collect::<Vec<_>>(),
+    });
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    // 先写临时文件再改名，避免同时启动的另一个会话读到写了一半的缓存
+    let tmp = path.with_extension(format!("json.{}", std::process::id()));
+    if std::fs::write(&tmp, cache.to_string()).is_ok() && std::fs::rename(&tmp, &path).is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+}
+
+fn usize_field(value: &Value, key: &str) -> Option<usize> {
+    value.get(key)?.as_u64().map(|n| n as usize)
+}
+
+fn string_field(value: &Value, key: &str) -> Option<String> {
+    value.get(key)?.as_str().map(str::to_string)
+}
+
+fn list_field<T>(value: &Value, key: &str, from_json: fn(&Value) -> Option<T>) -> Option<Vec<T>> {
+    value.get(key)?.as_array()?.iter().map(from_json).collect()
+}
+
+fn file_to_json(file: &File) -> Value {
+    json!({
+        "name": file.name,
+        "globals": file.global_variables.iter().map(variable_to_json).collect::<Vec<_>>(),
+        "functions": file.functions.iter().map(function_to_json).collect::<Vec<_>>(),
+        // 行号表项的文件名总是所属文件的名字，只保存行号和地址
+        "lines": file.lines.iter().map(|line| json!([line.number, line.address])).collect::<Vec<_>>(),
+    })
+}
+
+fn file_from_json(value: &Value) -> Option<File> {
+    let name = string_field(value, "name")?;
+    let lines = value
+        .get("lines")?
+        .as_array()?
+        .iter()
+        .map(|line| {
+            Some(Line {
+                file: name.clone(),
+                number: line.get(0)?.as_u64()? as usize,
+                address: line.get(1)?.as_u64()? as usize,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(File {
+        global_variables: list_field(value, "globals", variable_from_json)?,
+        functions: list_field(value, "functions", function_from_json)?,
+        lines,
+        name,
+    })
+}
+
+fn function_to_json(function: &Function) -> Value {
+    json!({
+        "name": function.name,
+        "address": function.address,
+        "length": function.text_length,
+        "line": function.line_number,
+        "variables": function.variables.iter().map(variable_to_json).collect::<Vec<_>>(),
+        "return_type": function.return_type.as_ref().map(type_to_json),
+    })
+}
+
+fn function_from_json(value: &Value) -> Option<Function> {
+    let return_type = match value.get("return_type")? {
+        Value::Null => None,
+        return_type => Some(type_from_json(return_type)?),
+    };
+    Some(Function {
+        name: string_field(value, "name")?,
+        address: usize_field(value, "address")?,
+        text_length: usize_field(value, "length")?,
+        line_number: usize_field(value, "line")?,
+        variables: list_field(value, "variables", variable_from_json)?,
+        return_type,
+    })
+}
+
+fn variable_to_json(variable: &Variable) -> Value {
+    json!({
+        "name": variable.name,
+        "type": type_to_json(&variable.entity_type),
+        "location": location_to_json(&variable.location),
+        "line": variable.line_number,
+        "scope": variable.scope.iter().map(|(begin, end)| json!([begin, end])).collect::<Vec<_>>(),
+        "parameter": variable.is_parameter,
+        "scope_depth": variable.scope_depth,
+    })
+}
+
+fn variable_from_json(value: &Value) -> Option<Variable> {
+    let scope = value
+        .get("scope")?
+        .as_array()?
+        .iter()
+        .map(|range| {
+            Some((
+                range.get(0)?.as_u64()? as usize,
+                range.get(1)?.as_u64()? as usize,
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(Variable {
+        name: string_field(value, "name")?,
+        entity_type: type_from_json(value.get("type")?)?,
+        location: location_from_json(value.get("location")?)?,
+        line_number: usize_field(value, "line")?,
+        scope,
+        is_parameter: value.get("parameter")?.as_bool()?,
+        scope_depth: usize_field(value, "scope_depth")?,
+    })
+}
+
+fn encoding_name(encoding: Encoding) -> &'static str {
+    match encoding {
+        Encoding::Unsigned => "unsigned",
+        Encoding::Signed => "signed",
+        Encoding::Float => "float",
+        Encoding::Address => "address",
+    }
+}
+
+fn type_to_json(entity_type: &Type) -> Value {
+    json!({
+        "name": entity_type.name,
+        "size": entity_type.size,
+        "encoding": encoding_name(entity_type.encoding),
+        "enumerators": entity_type.enumerators.iter().map(|(name, value)| json!([name, value])).collect::<Vec<_>>(),
+        "members": entity_type.members.iter().map(member_to_json).collect::<Vec<_>>(),
+    })
+}
+
+fn type_from_json(value: &Value) -> Option<Type> {
+    let encoding = match value.get("encoding")?.as_str()? {
+        "unsigned" => Encoding::Unsigned,
+        "signed" => Encoding::Signed,
+        "float" => Encoding::Float,
+        "address" => Encoding::Address,
+        _ => return None,
+    };
+    let enumerators = value
+        .get("enumerators")?
+        .as_array()?
+        .iter()
+        .map(|enumerator| {
+            Some((
+                enumerator.get(0)?.as_str()?.to_string(),
+                enumerator.get(1)?.as_i64()?,
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(Type {
+        name: string_field(value, "name")?,
+        size: usize_field(value, "size")?,
+        encoding,
+        enumerators,
+        members: list_field(value, "members", member_from_json)?,
+    })
+}
+
+fn member_to_json(member: &Member) -> Value {
+    json!({
+        "name": member.name,
+        "bit_position": member.bit_position,
+        "bit_size": member.bit_size,
+        "type": type_to_json(&member.member_type),
+    })
+}
+
+fn member_from_json(value: &Value) -> Option<Member> {
+    let bit_size = match value.get("bit_size")? {
+        Value::Null => None,
+        bit_size => Some(bit_size.as_u64()? as usize),
+    };
+    Some(Member {
+        name: string_field(value, "name")?,
+        bit_position: usize_field(value, "bit_position")?,
+        bit_size,
+        member_type: type_from_json(value.get("type")?)?,
+    })
+}
+
+fn location_to_json(location: &Location) -> Value {
+    match location {
+        Location::Address(addr) => json!({ "address": addr }),
+        Location::FramePointerOffset(offset) => json!({ "frame_offset": offset }),
+        Location::TlsOffset(offset) => json!({ "tls_offset": offset }),
+        Location::Register(register) => json!({ "register": register }),
+        Location::Expression(bytes, encoding) => json!({
+            "expression": bytes,
+            "address_size": encoding.address_size,
+            "dwarf64": encoding.format == gimli::Format::Dwarf64,
+            "version": encoding.version,
+        }),
+        Location::List(entries) => json!({
+            "list": entries.iter().map(|entry| json!({
+                "begin": entry.begin,
+                "end": entry.end,
+                "location": entry.location.as_ref().map(location_to_json),
+            })).collect::<Vec<_>>(),
+        }),
+        Location::OptimizedOut => json!({ "optimized_out": true }),
+    }
+}
+
+fn location_from_json(value: &Value) -> Option<Location> {
+    if let Some(addr) = usize_field(value, "address") {
+        return Some(Location::Address(addr));
+    }
+    if let Some(offset) = value.get("frame_offset") {
+        return Some(Location::FramePointerOffset(offset.as_i64()? as isize));
+    }
+    if let Some(offset) = usize_field(value, "tls_offset") {
+        return Some(Location::TlsOffset(offset));
+    }
+    if let Some(register) = usize_field(value, "register") {
+        return Some(Location::Register(register as u16));
+    }
+    if let Some(bytes) = value.get("expression") {
+        let bytes = bytes
+            .as_array()?
+            .iter()
+            .map(|byte| byte.as_u64().map(|byte| byte as u8))
+            .collect::<Option<Vec<_>>>()?;
+        let encoding = gimli::Encoding {
+            address_size: usize_field(value, "address_size")? as u8,
+            format: if value.get("dwarf64")?.as_bool()? {
+                gimli::Format::Dwarf64
+            } else {
+                gimli::Format::Dwarf32
+            },
+            version: usize_field(value, "version")? as u16,
+        };
+        return Some(Location::Expression(bytes, encoding));
+    }
+    if let Some(entries) = value.get("list") {
+        let entries = entries
+            .as_array()?
+            .iter()
+            .map(|entry| {
+                let location = match entry.get("location")? {
+                    Value::Null => None,
+                    location => Some(location_from_json(location)?),
+                };
+                Some(LocationEntry {
+                    begin: usize_field(entry, "begin")?,
+                    end: usize_field(entry, "end")?,
+                    location,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        return Some(Location::List(entries));
+    }
+    if value.get("optimized_out").is_some() {
+        return Some(Location::OptimizedOut);
+    }
+    None
+}
@@ -1,25 +1,48 @@
-use crate::dwarf_data::DwarfData;
+mod fork;
+mod hw_breakpoint;
 
+pub use fork::FollowFork;
+pub use hw_breakpoint::{watch_ranges, WatchScope, Watchpoint, WatchpointManager};
+
+use crate::backend::{Backend, PtraceBackend, SigInfo};
+use crate::breakpoints::BreakpointManager;
+use crate::cfi::{CallFrameInfo, UnwindRegs};
+use crate::dwarf_data::{DwarfData, Encoding, Line, Location, Type, Variable};
+use crate::gimli_wrapper::{evaluate_location, ExpressionContext};
+use crate::net::NetTraffic;
+use crate::remote::RemoteBackend;
+use crate::symbols::{FrameSymbol, SharedLibraries};
+use crate::syscall_trace::SyscallTracer;
+
+use nix::libc::{user_fpregs_struct, user_regs_struct};
 use nix::sys::ptrace;
 use nix::sys::signal;
-use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::sys::wait::WaitPidFlag;
 use nix::unistd::Pid;
+use std::collections::HashMap;
+use std::fmt;
 use std::mem::size_of;
 
 use std::os::unix::process::CommandExt;
-use std::process::Child;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 fn align_addr_to_word(addr: usize) -> usize {
     addr & (-(size_of::<usize>() as isize) as usize)
 }
 
-use std::collections::HashMap;
-
-#[derive(Clone, Debug)]
-pub struct Breakpoint {
-    pub addr: usize,
-    pub orig_byte: u8,
+/// 把 backend 的进程中 addr 处的字节改为 val，返回原来的字节
+fn write_byte_in(backend: &mut dyn Backend, addr: usize, val: u8) -> Result<u8, nix::Error> {
+    let aligned_addr = align_addr_to_word(addr);
+    let byte_offset = addr - aligned_addr;
+    let word = backend.read_word(aligned_addr)?;
+    let orig_byte = (word >> 8 * byte_offset) & 0xff;
+    let masked_word = word & !(0xff << 8 * byte_offset);
+    let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
+    backend.write_word(aligned_addr, updated_word)?;
+    Ok(orig_byte as u8)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -34,6 +57,150 @@ pub enum Status {
     /// Indicates the inferior exited due to a signal. Contains the signal that killed the
     /// process.
     Signaled(signal::Signal),
+
+    /// Indicates the inferior called execve and is stopped at the entry point of the new program.
+    /// Contains the new instruction pointer.
+    Exec(usize),
+
+    /// 停在一次系统调用的入口或出口（用 PTRACE_SYSCALL 恢复运行时）。只在 `Inferior` 内部出现，
+    /// `continue_run` 记录后会自动继续
+    Syscall,
+
+    /// 调用了 fork（vfork 时为 true）后停在返回之前，包含已被自动跟踪的子进程。只在 `Inferior`
+    /// 内部出现，`wait` 按 `set follow-fork-mode` 处理后自动继续
+    Fork(Pid, bool),
+
+    /// vfork 出的子进程已经 exec 或退出，不再与父进程共享内存。只在 `Inferior` 内部出现
+    VforkDone,
+}
+
+/// 恢复运行的方式，fork 事件处理完之后以同样的方式继续
+#[derive(Clone, Copy)]
+enum Resume {
+    Continue,
+    Step,
+    Syscall,
+}
+
+// SIGTRAP 的 si_code：x86 上 int3 报告为 SI_KERNEL，部分架构和内核使用 TRAP_BRKPT
+const SI_KERNEL: i32 = 0x80;
+const TRAP_BRKPT: i32 = 1;
+
+/// `syscall` 指令
+const SYSCALL_INSN: [u8; 2] = [0x0f, 0x05];
+
+/// `set scheduler` 选择的调度策略，实时策略带优先级
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scheduler {
+    Other,
+    Batch,
+    Idle,
+    Fifo(i32),
+    RoundRobin(i32),
+}
+
+impl Scheduler {
+    pub fn policy(&self) -> i32 {
+        match self {
+            Scheduler::Other => libc::SCHED_OTHER,
+            Scheduler::Batch => libc::SCHED_BATCH,
+            Scheduler::Idle => libc::SCHED_IDLE,
+            Scheduler::Fifo(_) => libc::SCHED_FIFO,
+            Scheduler::RoundRobin(_) => libc::SCHED_RR,
+        }
+    }
+
+    /// 非实时策略的优先级总是 0
+    pub fn priority(&self) -> i32 {
+        match self {
+            Scheduler::Fifo(priority) | Scheduler::RoundRobin(priority) => *priority,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Scheduler::Other => write!(f, "SCHED_OTHER"),
+            Scheduler::Batch => write!(f, "SCHED_BATCH"),
+            Scheduler::Idle => write!(f, "SCHED_IDLE"),
+            Scheduler::Fifo(priority) => write!(f, "SCHED_FIFO priority {}", priority),
+            Scheduler::RoundRobin(priority) => write!(f, "SCHED_RR priority {}", priority),
+        }
+    }
+}
+
+/// 调试器自己可以使用的 CPU；被调试进程只能绑定到其中的 CPU 上
+pub fn available_cpus() -> Vec<usize> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    if unsafe { libc::sched_getaffinity(0, size_of::<libc::cpu_set_t>(), &mut set) } != 0 {
+        return Vec::new();
+    }
+    (0..libc::CPU_SETSIZE as usize)
+        .filter(|cpu| unsafe { libc::CPU_ISSET(*cpu, &set) })
+        .collect()
+}
+
+/// 启动被调试进程时对它的环境的额外设置
+#[derive(Debug, Clone, Default)]
+pub struct LaunchOptions {
+    /// `set preload` 通过 LD_PRELOAD 注入的共享库
+    pub preload: Vec<PathBuf>,
+    /// `set affinity` 绑定的 CPU，为空时不限制
+    pub affinity: Vec<usize>,
+    /// `set scheduler` 设置的调度策略
+    pub scheduler: Option<Scheduler>,
+}
+
+impl LaunchOptions {
+    pub fn apply(&self, cmd: &mut Command) {
+        if !self.affinity.is_empty() || self.scheduler.is_some() {
+            // 在子进程 exec 之前设置，exec 之后的程序和它创建的线程都继承这些设置
+            let affinity = self.affinity.clone();
+            let scheduler = self.scheduler;
+            unsafe {
+                cmd.pre_exec(move || set_scheduling(&affinity, scheduler));
+            }
+        }
+        if self.preload.is_empty() {
+            return;
+        }
+        // 调试器自身环境中的 LD_PRELOAD 仍然保留，排在注入的库之后
+        let mut preload: Vec<String> = self
+            .preload
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        if let Ok(inherited) = std::env::var("LD_PRELOAD") {
+            if !inherited.is_empty() {
+                preload.push(inherited);
+            }
+        }
+        cmd.env("LD_PRELOAD", preload.join(":"));
+    }
+}
+
+/// 在 fork 出的子进程中调用，不能分配内存
+fn set_scheduling(affinity: &[usize], scheduler: Option<Scheduler>) -> Result<(), std::io::Error> {
+    if !affinity.is_empty() {
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        for cpu in affinity {
+            unsafe { libc::CPU_SET(*cpu, &mut set) };
+        }
+        if unsafe { libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    if let Some(scheduler) = scheduler {
+        let param = libc::sched_param {
+            sched_priority: scheduler.priority(),
+        };
+        if unsafe { libc::sched_setscheduler(0, scheduler.policy(), &param) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
 }
 
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
@@ -46,117 +213,937 @@ fn child_traceme() -> Result<(), std::io::Error> {
 }
 
 pub struct Inferior {
-    child: Child,
+    backend: Box<dyn Backend>,
+    /// `trace syscalls` 打开时记录每次系统调用
+    syscall_tracer: Option<SyscallTracer>,
+    /// `set net-tracking on` 时统计套接字流量
+    net_traffic: Option<NetTraffic>,
+    /// `monitor ... every N seconds` 的定时线程发出了 SIGSTOP、还没有送达时为 true
+    timer_interrupt: Option<Arc<AtomicBool>>,
+    /// 用 `attach` 跟踪的已有进程，不是调试器启动的
+    attached: bool,
+    /// 已经停止跟踪，进程不再属于调试器，丢弃时不能结束它
+    detached: bool,
+    /// `set follow-fork-mode`
+    follow_fork: FollowFork,
+    /// 写入了 int3 的地址和原来的字节。fork 出的进程中有同样的 int3，停止跟踪它之前要恢复
+    planted: HashMap<usize, u8>,
+    last_resume: Resume,
+    /// 跟随 vfork 的子进程时仍然停着的父进程，子进程 exec 或退出之前它们共享内存
+    vfork_parent: Option<Box<dyn Backend>>,
 }
 
 impl Inferior {
     /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
-    /// an error is encountered.
+    /// an error is encountered. 断点要等调用者知道程序的加载偏移之后再用 `arm_breakpoints` 写入
     pub fn new(
         target: &str,
         args: &Vec<String>,
-        breakpoints: &mut HashMap<usize, Breakpoint>,
+        cwd: Option<&Path>,
+        launch: &LaunchOptions,
     ) -> Option<Inferior> {
         // TODO: implement me!
         let mut cmd = Command::new(target);
         unsafe {
             cmd.pre_exec(child_traceme);
         }
+        if let Some(cwd) = cwd {
+            cmd.current_dir(cwd);
+        }
+        launch.apply(&mut cmd);
 
-        let child = cmd.args(args).spawn().ok().unwrap();
+        let child = match cmd.args(args).spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                println!("{}: {}", target, e);
+                return None;
+            }
+        };
 
-        let mut inferior = Inferior { child };
+        let mut inferior = Inferior::with_backend(Box::new(PtraceBackend::new(child)));
 
-        for (addr, bp) in breakpoints.iter_mut() {
-            match inferior.write_byte(*addr, 0xcc) {
+        // 子进程在 exec 之后停下才能写入它的内存
+        match inferior.wait(None) {
+            Ok(Status::Stopped(signal::Signal::SIGTRAP, _)) => (),
+            _ => return None,
+        }
+        inferior.set_trace_options();
+        Some(inferior)
+    }
+
+    /// 跟踪已经在运行的进程 pid：PTRACE_ATTACH 后等它因 SIGSTOP 停下。断点要等调用者
+    /// 加载了这个进程的符号之后再用 `arm_breakpoints` 写入
+    pub fn attach(pid: Pid) -> Option<Inferior> {
+        let backend = match PtraceBackend::attach(pid) {
+            Ok(backend) => backend,
+            Err(e) => {
+                println!("Could not attach to process {}: {}", pid, e);
+                if e == nix::Error::Sys(nix::errno::Errno::EPERM) {
+                    println!("Check /proc/sys/kernel/yama/ptrace_scope or run as the same user with CAP_SYS_PTRACE");
+                }
+                return None;
+            }
+        };
+        let mut inferior = Inferior::with_backend(Box::new(backend));
+        inferior.attached = true;
+        match inferior.wait(None) {
+            Ok(Status::Stopped(..)) => (),
+            _ => return None,
+        }
+        inferior.set_trace_options();
+        Some(inferior)
+    }
+
+    /// 之后的 execve 报告为 Status::Exec，而不是普通的 SIGTRAP；系统调用停止报告为 SIGTRAP | 0x80，
+    /// 与断点区分开；fork 和 vfork 出的子进程自动被跟踪，由 `wait` 决定跟随哪一个
+    fn set_trace_options(&self) {
+        let options = ptrace::Options::PTRACE_O_TRACEEXEC
+            | ptrace::Options::PTRACE_O_TRACESYSGOOD
+            | ptrace::Options::PTRACE_O_TRACEFORK
+            | ptrace::Options::PTRACE_O_TRACEVFORK
+            | ptrace::Options::PTRACE_O_TRACEVFORKDONE;
+        if let Err(e) = ptrace::setoptions(self.pid(), options) {
+            println!("Error tracing exec in the inferior: {}", e);
+        }
+    }
+
+    /// 是否是 `attach` 上的已有进程
+    pub fn attached(&self) -> bool {
+        self.attached
+    }
+
+    /// 撤掉所有断点的 int3 后停止跟踪，进程继续运行。停在断点上时先把 rip 退回断点地址，
+    /// 让原来的指令被执行
+    pub fn detach(&mut self, breakpoints: &BreakpointManager) -> Result<(), nix::Error> {
+        if let Some(addr) = self.trapped_breakpoint(breakpoints)? {
+            let mut regs = self.getregs()?;
+            regs.rip = addr as u64;
+            self.setregs(regs)?;
+        }
+        for bp in breakpoints.iter().filter(|bp| bp.enabled) {
+            self.write_byte(bp.addr, bp.orig_byte)?;
+        }
+        self.backend.detach()?;
+        self.detached = true;
+        if let Some(parent) = self.vfork_parent.take() {
+            self.release_parent(parent, "vfork");
+        }
+        Ok(())
+    }
+
+    /// 在新进程中写入所有已启用断点的 int3。原始字节总是从这个进程中重新读取：
+    /// 上一次运行记录的 orig_byte 可能已经过时，也可能来自一次失败的写入
+    pub fn arm_breakpoints(&mut self, breakpoints: &mut BreakpointManager) {
+        for bp in breakpoints.iter_mut() {
+            bp.orig_byte = 0;
+            if !bp.enabled {
+                continue;
+            }
+            match self.write_byte(bp.addr, 0xcc) {
                 Ok(byte) => bp.orig_byte = byte,
-                Err(e) => println!("Error setting breakpoint at {:#x}: {}", addr, e),
+                Err(e) => println!("Error setting breakpoint at {:#x}: {}", bp.addr, e),
             }
         }
+    }
 
+    /// 在 Valgrind 的 gdbserver 下启动 target，停在第一条指令前。
+    /// Valgrind 不会看到直接写入代码的 int3，因此这种模式下不写入断点
+    pub fn under_valgrind(
+        target: &str,
+        args: &[String],
+        cwd: Option<&Path>,
+        launch: &LaunchOptions,
+    ) -> Option<Inferior> {
+        let backend = match RemoteBackend::launch_valgrind(target, args, cwd, launch) {
+            Ok(backend) => backend,
+            Err(e) => {
+                println!("{}", e);
+                return None;
+            }
+        };
+        let mut inferior = Inferior::with_backend(Box::new(backend));
         match inferior.wait(None) {
             Ok(Status::Stopped(signal::Signal::SIGTRAP, _)) => Some(inferior),
-            _ => None,
+            _ => {
+                let _ = inferior.kill();
+                None
+            }
+        }
+    }
+
+    /// 在任意 backend 上构造 inferior，测试时可以传入 `MockBackend`
+    pub fn with_backend(backend: Box<dyn Backend>) -> Inferior {
+        Inferior {
+            backend,
+            syscall_tracer: None,
+            net_traffic: None,
+            timer_interrupt: None,
+            attached: false,
+            detached: false,
+            follow_fork: FollowFork::Parent,
+            planted: HashMap::new(),
+            last_resume: Resume::Continue,
+            vfork_parent: None,
         }
     }
 
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.backend.pid()
     }
 
     /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
     /// after the waitpid call.
-    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
-            WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
-            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
-            WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
-                Status::Stopped(signal, regs.rip as usize)
-            }
-            other => panic!("waitpid returned unexpected status: {:?}", other),
-        })
+    /// fork 事件在这里处理，处理完后以上一次的方式继续运行跟随的进程
+    pub fn wait(&mut self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
+        loop {
+            match self.backend.wait(options)? {
+                Status::Fork(child, vfork) => self.follow_fork(child, vfork)?,
+                Status::VforkDone => self.replant(),
+                status @ (Status::Exec(_) | Status::Exited(_) | Status::Signaled(_)) => {
+                    if let Some(parent) = self.vfork_parent.take() {
+                        self.release_parent(parent, "vfork");
+                    }
+                    if let Status::Exec(_) = status {
+                        // 新的程序映像里没有调试器写入的 int3
+                        self.planted.clear();
+                    }
+                    return Ok(status);
+                }
+                status => return Ok(status),
+            }
+            self.resume(self.last_resume, None)?;
+        }
     }
 
-    pub fn continue_run(&self, signal: Option<signal::Signal>) -> Result<Status, nix::Error> {
-        ptrace::cont(self.pid(), signal)?;
-        self.wait(None)
+    fn resume(&mut self, how: Resume, signal: Option<signal::Signal>) -> Result<(), nix::Error> {
+        self.last_resume = how;
+        match how {
+            Resume::Continue => self.backend.cont(signal),
+            Resume::Step => self.backend.step(signal),
+            Resume::Syscall => self.backend.syscall(signal),
+        }
     }
 
-    pub fn step(&self) -> Result<Status, nix::Error> {
-        ptrace::step(self.pid(), None)?;
-        self.wait(None)
+    /// 恢复运行直到下一次停止；跟踪系统调用或统计流量时途中的每次系统调用都记录下来，不算停止
+    pub fn continue_run(&mut self, signal: Option<signal::Signal>) -> Result<Status, nix::Error> {
+        if self.syscall_tracer.is_none() && self.net_traffic.is_none() {
+            self.resume(Resume::Continue, signal)?;
+            return self.wait(None);
+        }
+        self.resume(Resume::Syscall, signal)?;
+        loop {
+            match self.wait(None)? {
+                Status::Syscall => {
+                    let regs = self.getregs()?;
+                    if let Some(mut tracer) = self.syscall_tracer.take() {
+                        tracer.on_stop(&regs, |addr, len| self.read_memory(addr, len).ok());
+                        self.syscall_tracer = Some(tracer);
+                    }
+                    let pid = self.pid();
+                    if let Some(traffic) = self.net_traffic.as_mut() {
+                        traffic.on_stop(pid, &regs);
+                    }
+                    self.resume(Resume::Syscall, None)?;
+                }
+                status => return Ok(status),
+            }
+        }
+    }
+
+    /// 开始或停止跟踪系统调用
+    pub fn set_syscall_tracer(&mut self, tracer: Option<SyscallTracer>) {
+        self.syscall_tracer = tracer;
+    }
+
+    /// 开始或停止统计套接字流量；重复打开时保留已有的计数
+    pub fn set_net_tracking(&mut self, on: bool) {
+        if on {
+            self.net_traffic.get_or_insert_with(NetTraffic::default);
+        } else {
+            self.net_traffic = None;
+        }
+    }
+
+    /// 统计流量时返回上次清零以来的计数
+    pub fn net_traffic(&mut self) -> Option<&mut NetTraffic> {
+        self.net_traffic.as_mut()
+    }
+
+    pub fn step(&mut self, signal: Option<signal::Signal>) -> Result<Status, nix::Error> {
+        self.resume(Resume::Step, signal)?;
+        loop {
+            match self.wait(None)? {
+                // 定时器的 SIGSTOP 在单步之前送达，这条指令还没有执行
+                Status::Stopped(signal::Signal::SIGSTOP, _) if self.take_timer_interrupt() => {
+                    self.resume(Resume::Step, None)?
+                }
+                status => return Ok(status),
+            }
+        }
+    }
+
+    /// 设置 `monitor` 定时线程的标志，单步时透明地越过它发出的 SIGSTOP
+    pub fn set_timer_interrupt(&mut self, flag: Arc<AtomicBool>) {
+        self.timer_interrupt = Some(flag);
+    }
+
+    /// 停在 SIGSTOP 上时调用：它是定时线程发出的时返回 true 并清除标志
+    pub fn take_timer_interrupt(&self) -> bool {
+        self.timer_interrupt
+            .as_ref()
+            .is_some_and(|flag| flag.swap(false, Ordering::SeqCst))
     }
 
     pub fn kill(&mut self) -> Result<(), std::io::Error> {
-        self.child.kill()?;
+        self.backend.kill()?;
         self.wait(None)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
         Ok(())
     }
 
-    pub fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
+    pub fn getregs(&self) -> Result<user_regs_struct, nix::Error> {
+        self.backend.getregs()
+    }
+
+    pub fn setregs(&mut self, regs: user_regs_struct) -> Result<(), nix::Error> {
+        self.backend.setregs(regs)
+    }
+
+    pub fn getfpregs(&self) -> Result<user_fpregs_struct, nix::Error> {
+        self.backend.getfpregs()
+    }
+
+    /// 按 DWARF 寄存器号（x86-64 psABI）读取当前的寄存器：0-16 是通用寄存器和返回地址（rip），
+    /// 17-32 是 xmm0-xmm15。不支持的寄存器返回 None
+    pub fn dwarf_register(&self, register: u16) -> Option<Vec<u8>> {
+        use gimli::X86_64;
+        let regs = self.getregs().ok()?;
+        let value = match gimli::Register(register) {
+            X86_64::RAX => regs.rax,
+            X86_64::RDX => regs.rdx,
+            X86_64::RCX => regs.rcx,
+            X86_64::RBX => regs.rbx,
+            X86_64::RSI => regs.rsi,
+            X86_64::RDI => regs.rdi,
+            X86_64::RBP => regs.rbp,
+            X86_64::RSP => regs.rsp,
+            X86_64::R8 => regs.r8,
+            X86_64::R9 => regs.r9,
+            X86_64::R10 => regs.r10,
+            X86_64::R11 => regs.r11,
+            X86_64::R12 => regs.r12,
+            X86_64::R13 => regs.r13,
+            X86_64::R14 => regs.r14,
+            X86_64::R15 => regs.r15,
+            X86_64::RA => regs.rip,
+            _ if (X86_64::XMM0.0..=X86_64::XMM15.0).contains(&register) => {
+                let index = (register - X86_64::XMM0.0) as usize;
+                let xmm = &self.getfpregs().ok()?.xmm_space[index * 4..index * 4 + 4];
+                return Some(xmm.iter().flat_map(|word| word.to_le_bytes()).collect());
+            }
+            _ => return None,
+        };
+        Some(value.to_le_bytes().to_vec())
+    }
+
+    /// 按 System V x86-64 调用约定取出刚返回的函数的返回值：整数和指针在 rax，float / double 在 xmm0，
+    /// long double 在 st0；不超过 16 字节的结构体每 8 字节为一组，全是浮点成员的组依次放在 xmm0、xmm1，
+    /// 其它依次放在 rax、rdx。更大的结构体由调用者分配内存，rax 中是它的地址
+    pub fn return_value(&self, return_type: &Type) -> Result<Vec<u8>, nix::Error> {
+        let size = return_type.size;
+        let regs = self.getregs()?;
+        if size > 16 {
+            return self.read_memory(regs.rax as usize, size);
+        }
+        if return_type.members.is_empty() && return_type.encoding == Encoding::Float && size > 8 {
+            let st0 = &self.getfpregs()?.st_space[..4];
+            let mut bytes: Vec<u8> = st0.iter().flat_map(|word| word.to_le_bytes()).collect();
+            bytes.truncate(size);
+            return Ok(bytes);
+        }
+        let mut leaves = Vec::new();
+        scalar_leaves(return_type, 0, &mut leaves);
+        let is_sse = |eightbyte: usize| {
+            let (start, end) = (eightbyte * 64, eightbyte * 64 + 64);
+            let overlapping: Vec<_> = leaves
+                .iter()
+                .filter(|(bit, width, _)| *bit < end && bit + width > start)
+                .collect();
+            !overlapping.is_empty() && overlapping.iter().all(|(_, _, float)| *float)
+        };
+        let eightbytes = size.div_ceil(8);
+        let fpregs = if (0..eightbytes).any(is_sse) {
+            Some(self.getfpregs()?)
+        } else {
+            None
+        };
+        let mut integer = vec![regs.rax, regs.rdx].into_iter();
+        let mut sse = (0..2).map(|index| {
+            let xmm = &fpregs.as_ref().unwrap().xmm_space[index * 4..];
+            xmm[0] as u64 | (xmm[1] as u64) << 32
+        });
+        let mut bytes = Vec::with_capacity(eightbytes * 8);
+        for eightbyte in 0..eightbytes {
+            let word = if is_sse(eightbyte) {
+                sse.next()
+            } else {
+                integer.next()
+            };
+            bytes.extend_from_slice(&word.unwrap_or(0).to_le_bytes());
+        }
+        bytes.truncate(size);
+        Ok(bytes)
+    }
+
+    /// 如果进程是因为执行了某个已启用断点的 int3 而停下，返回该断点的地址。
+    /// 通过 siginfo 区分断点、单步（TRAP_TRACE）、硬件断点以及程序自己发出的 SIGTRAP，
+    /// 而不是只猜测 rip - 1 处有没有断点
+    pub fn trapped_breakpoint(
+        &self,
+        breakpoints: &BreakpointManager,
+    ) -> Result<Option<usize>, nix::Error> {
+        let info = match self.backend.siginfo() {
+            Ok(info) => info,
+            // 不是信号停止（例如进程刚退出）
+            Err(_) => return Ok(None),
+        };
+        if info.signo != signal::Signal::SIGTRAP as i32
+            || (info.code != SI_KERNEL && info.code != TRAP_BRKPT)
+        {
+            return Ok(None);
+        }
+        let bp_addr = self.getregs()?.rip as usize - 1;
+        Ok(breakpoints.get_enabled(bp_addr).map(|bp| bp.addr))
+    }
+
+    /// 恢复运行前需要越过的断点地址：刚执行了它的 int3，或者 rip 正好在一个已写入 int3 的断点上
+    /// （单步停在那里，或者停下之后才在这里设置、重新启用断点）。后一种情况不越过的话，继续运行时
+    /// 会立即在原地再次停下
+    pub fn breakpoint_to_step_over(
+        &self,
+        breakpoints: &BreakpointManager,
+    ) -> Result<Option<usize>, nix::Error> {
+        if let Some(addr) = self.trapped_breakpoint(breakpoints)? {
+            return Ok(Some(addr));
+        }
+        let rip = self.getregs()?.rip as usize;
+        if breakpoints.get_enabled(rip).is_none() {
+            return Ok(None);
+        }
+        // Valgrind 下断点由 gdbserver 实现，内存中没有 int3
+        let armed = self.read_memory(rip, 1).ok() == Some(vec![0xcc]);
+        Ok(if armed { Some(rip) } else { None })
+    }
+
+    pub fn siginfo(&self) -> Result<SigInfo, nix::Error> {
+        self.backend.siginfo()
+    }
+
+    /// 在被调试进程中执行一次系统调用并返回 rax。
+    /// 临时把当前指令替换为 `syscall` 单步执行，之后恢复原指令和全部寄存器
+    pub fn inject_syscall(&mut self, number: u64, args: &[u64]) -> Result<i64, nix::Error> {
+        let saved_regs = self.getregs()?;
+        let pc = saved_regs.rip as usize;
+        let orig_code = self.read_memory(pc, SYSCALL_INSN.len())?;
+
+        let mut regs = saved_regs;
+        regs.rax = number;
+        // 避免内核把它当成被中断的系统调用重新启动
+        regs.orig_rax = u64::MAX;
+        let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+        regs.rdi = arg(0);
+        regs.rsi = arg(1);
+        regs.rdx = arg(2);
+        regs.r10 = arg(3);
+        regs.r8 = arg(4);
+        regs.r9 = arg(5);
+
+        self.write_memory(pc, &SYSCALL_INSN)?;
+        self.setregs(regs)?;
+        let status = self.step(None);
+        let result = self.getregs().map(|regs| regs.rax as i64);
+        self.write_memory(pc, &orig_code)?;
+        self.setregs(saved_regs)?;
+        status?;
+        result
+    }
+
+    /// 读取 addr 所在的 word，addr 不必对齐
+    pub fn read_word(&self, addr: usize) -> Result<u64, nix::Error> {
         let aligned_addr = align_addr_to_word(addr);
-        let byte_offset = addr - aligned_addr;
-        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
-        let orig_byte = (word >> 8 * byte_offset) & 0xff;
-        let masked_word = word & !(0xff << 8 * byte_offset);
-        let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
-        ptrace::write(
-            self.pid(),
-            aligned_addr as ptrace::AddressType,
-            updated_word as *mut std::ffi::c_void,
-        )?;
-        Ok(orig_byte as u8)
+        if aligned_addr == addr {
+            return self.backend.read_word(addr);
+        }
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&self.read_memory(addr, size_of::<u64>())?);
+        Ok(u64::from_le_bytes(word))
     }
 
-    pub fn print_backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error> {
-        let regs = ptrace::getregs(self.pid())?;
-        let mut instruction_ptr = regs.rip;
-        let mut base_ptr = regs.rbp;
-        loop {
-            let line_num = debug_data
-                .get_line_from_addr(instruction_ptr as usize)
-                .unwrap();
-            let fun_name = debug_data
-                .get_function_from_addr(instruction_ptr as usize)
-                .unwrap();
-            println!("{}: {}", fun_name, line_num);
-            if fun_name == "main" {
-                break;
+    pub fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
+        let orig_byte = write_byte_in(self.backend.as_mut(), addr, val)?;
+        if val == 0xcc {
+            self.planted.entry(addr).or_insert(orig_byte);
+        } else {
+            self.planted.remove(&addr);
+        }
+        Ok(orig_byte)
+    }
+
+    /// 读取 [addr, addr + len) 范围内的内存，按 word 批量读取
+    pub fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let end = addr + len;
+        let mut bytes = Vec::with_capacity(len);
+        let mut word_addr = align_addr_to_word(addr);
+        while word_addr < end {
+            let word = self.backend.read_word(word_addr)?;
+            for (i, byte) in word.to_le_bytes().iter().enumerate() {
+                if word_addr + i >= addr && word_addr + i < end {
+                    bytes.push(*byte);
+                }
             }
-            instruction_ptr =
-                ptrace::read(self.pid(), (base_ptr + 8) as ptrace::AddressType)? as u64;
-            base_ptr = ptrace::read(self.pid(), base_ptr as ptrace::AddressType)? as u64;
+            word_addr += size_of::<usize>();
         }
+        Ok(bytes)
+    }
 
+    /// 把 data 写入 addr 开始的内存，首尾不对齐的 word 会先读出再合并
+    pub fn write_memory(&mut self, addr: usize, data: &[u8]) -> Result<(), nix::Error> {
+        let end = addr + data.len();
+        let mut word_addr = align_addr_to_word(addr);
+        while word_addr < end {
+            let word = self.backend.read_word(word_addr)?;
+            let mut bytes = word.to_le_bytes();
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                if word_addr + i >= addr && word_addr + i < end {
+                    *byte = data[word_addr + i - addr];
+                }
+            }
+            self.backend
+                .write_word(word_addr, u64::from_le_bytes(bytes))?;
+            word_addr += size_of::<usize>();
+        }
         Ok(())
     }
+
+    pub fn print_backtrace(
+        &self,
+        debug_data: &DwarfData,
+        target_cfi: Option<&CallFrameInfo>,
+        libraries: &mut SharedLibraries,
+        breakpoints: &BreakpointManager,
+        frame_arguments: FrameArguments,
+    ) -> Result<(), nix::Error> {
+        let regs = self.unwind_regs(breakpoints)?;
+        print_frames(
+            debug_data,
+            target_cfi,
+            Some(libraries),
+            regs,
+            frame_arguments,
+            |addr| self.read_word(addr),
+        )
+    }
+
+    /// 与 print_backtrace 相同，但返回各帧而不是打印
+    pub fn backtrace(
+        &self,
+        debug_data: &DwarfData,
+        target_cfi: Option<&CallFrameInfo>,
+        libraries: &mut SharedLibraries,
+        breakpoints: &BreakpointManager,
+        frame_arguments: FrameArguments,
+    ) -> Result<Vec<Frame>, nix::Error> {
+        let frames = self.frames(
+            debug_data,
+            target_cfi,
+            libraries,
+            breakpoints,
+            frame_arguments,
+        )?;
+        Ok(frames.into_iter().map(|(frame, _)| frame).collect())
+    }
+
+    /// 与 backtrace 相同，同时返回每一帧回溯出的寄存器（`frame` / `up` 选中的帧据此读取变量）
+    pub fn frames(
+        &self,
+        debug_data: &DwarfData,
+        target_cfi: Option<&CallFrameInfo>,
+        libraries: &mut SharedLibraries,
+        breakpoints: &BreakpointManager,
+        frame_arguments: FrameArguments,
+    ) -> Result<Vec<(Frame, UnwindRegs)>, nix::Error> {
+        let regs = self.unwind_regs(breakpoints)?;
+        let mut frames = Vec::new();
+        walk_frames(
+            debug_data,
+            target_cfi,
+            Some(libraries),
+            regs,
+            frame_arguments,
+            |addr| self.read_word(addr),
+            |frame, regs| frames.push((frame, *regs)),
+        )?;
+        Ok(frames)
+    }
+
+    /// 回溯的起点寄存器
+    pub fn unwind_regs(&self, breakpoints: &BreakpointManager) -> Result<UnwindRegs, nix::Error> {
+        let mut regs = self.getregs()?;
+        // 刚命中断点时 rip 位于 int3 之后，按断点地址查 CFI 才能得到正确的调用者
+        if let Some(bp_addr) = self.trapped_breakpoint(breakpoints)? {
+            regs.rip = bp_addr as u64;
+        }
+        Ok(UnwindRegs {
+            rip: regs.rip,
+            rsp: regs.rsp,
+            rbp: regs.rbp,
+        })
+    }
+}
+
+/// 防止损坏的栈导致无限回溯
+const MAX_FRAMES: usize = 256;
+
+/// __restore_rt 的指令序列 `mov $0xf, %rax; syscall` 的前 8 个字节，没有 CFI 时用它识别信号帧
+const SIGRETURN_CODE: u64 = 0x0f00_0000_0fc0_c748;
+
+// 信号帧中 ucontext_t 里保存的寄存器偏移（uc_mcontext.gregs 从偏移 40 开始）
+const UCONTEXT_RBP: usize = 40 + 10 * 8;
+const UCONTEXT_RSP: usize = 40 + 15 * 8;
+const UCONTEXT_RIP: usize = 40 + 16 * 8;
+
+/// 回溯并打印调用栈。`read_word` 负责读取目标内存，活动进程和 minidump 共用这套逻辑。
+///
+/// 每一帧优先用 CFI（目标程序的 `target_cfi`，或 `libraries` 中对应库的 .eh_frame）计算调用者，
+/// 这样停在函数序言或不维护帧指针的叶子函数中时也能得到正确的返回地址；没有 CFI 时退回 rbp 链。
+/// 目标程序之外的帧交给 `libraries` 按共享库符号化。
+/// 遇到信号处理返回跳板时，从内核保存在栈上的 ucontext 中恢复被中断的上下文继续回溯
+pub fn print_frames<E, F>(
+    debug_data: &DwarfData,
+    target_cfi: Option<&CallFrameInfo>,
+    libraries: Option<&mut SharedLibraries>,
+    regs: UnwindRegs,
+    frame_arguments: FrameArguments,
+    read_word: F,
+) -> Result<(), E>
+where
+    F: Fn(usize) -> Result<u64, E>,
+{
+    walk_frames(
+        debug_data,
+        target_cfi,
+        libraries,
+        regs,
+        frame_arguments,
+        read_word,
+        |frame, _| println!("{}", frame),
+    )
+}
+
+/// 回溯时显示哪些参数的值（`set print frame-arguments`）
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FrameArguments {
+    All,
+    /// 结构体和联合参数显示为 `...`
+    #[default]
+    Scalars,
+    None,
+}
+
+/// 回溯中的一帧
+pub enum Frame {
+    /// 信号处理函数返回到的 __restore_rt
+    SignalHandler,
+    /// 目标程序中有调试信息的帧；不显示参数时 args 为 None，否则是 `名字=值` 的列表
+    Source {
+        function: String,
+        args: Option<Vec<String>>,
+        line: Line,
+    },
+    /// 共享库中的帧
+    Library(FrameSymbol),
+    /// vDSO 或 vsyscall 页中的帧：(区域名, 函数名)
+    KernelStub(&'static str, String),
+    /// 没有任何符号信息的帧，回溯到此为止
+    Unknown(u64),
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Frame::SignalHandler => write!(f, "<signal handler called>"),
+            Frame::Source {
+                function,
+                args: None,
+                line,
+            } => write!(f, "{}: {}", function, line),
+            Frame::Source {
+                function,
+                args: Some(args),
+                line,
+            } => write!(f, "{}({}): {}", function, args.join(", "), line),
+            Frame::Library(symbol) => write!(f, "{}", symbol),
+            Frame::KernelStub(area, function) => write!(f, "<{}: {}>", area, function),
+            Frame::Unknown(rip) => write!(f, "{:#x}: ??", rip),
+        }
+    }
+}
+
+/// 从 regs 开始逐帧回溯，把每一帧和它的寄存器交给 visit，到 main 或无法继续时停止
+pub fn walk_frames<E, F, V>(
+    debug_data: &DwarfData,
+    target_cfi: Option<&CallFrameInfo>,
+    mut libraries: Option<&mut SharedLibraries>,
+    regs: UnwindRegs,
+    frame_arguments: FrameArguments,
+    read_word: F,
+    mut visit: V,
+) -> Result<(), E>
+where
+    F: Fn(usize) -> Result<u64, E>,
+    V: FnMut(Frame, &UnwindRegs),
+{
+    let mut regs = regs;
+    // 最内层帧（以及信号帧之后的帧）的 rip 就是当前指令，其它帧的 rip 是返回地址
+    let mut exact_pc = true;
+    for _ in 0..MAX_FRAMES {
+        let in_trampoline = match libraries
+            .as_mut()
+            .and_then(|libs| libs.is_signal_trampoline(regs.rip as usize))
+        {
+            Some(result) => result,
+            None => read_word(regs.rip as usize)
+                .map(|word| word == SIGRETURN_CODE)
+                .unwrap_or(false),
+        };
+        if in_trampoline {
+            // 信号处理函数返回到 __restore_rt 时，rsp 正好指向 ucontext
+            visit(Frame::SignalHandler, &regs);
+            let ucontext = regs.rsp as usize;
+            regs = UnwindRegs {
+                rip: read_word(ucontext + UCONTEXT_RIP)?,
+                rsp: read_word(ucontext + UCONTEXT_RSP)?,
+                rbp: read_word(ucontext + UCONTEXT_RBP)?,
+            };
+            exact_pc = true;
+            continue;
+        }
+
+        // 返回地址可能正好落在下一个函数的开头（调用 noreturn 函数时），也可能属于 call 的下一行，
+        // 查 CFI、源代码行和函数名时都用 call 指令内的地址
+        let lookup_pc = if exact_pc { regs.rip } else { regs.rip - 1 };
+        // CFI 和位置列表使用文件中的地址
+        let bias = debug_data.load_bias();
+        let file_pc = lookup_pc - bias as u64;
+        let line = debug_data.get_line_from_addr(lookup_pc as usize);
+        let fun_name = debug_data.get_function_from_addr(lookup_pc as usize);
+        match (fun_name, line) {
+            (Some(fun_name), Some(line)) => {
+                let is_main = fun_name == "main";
+                let args = if frame_arguments == FrameArguments::None {
+                    None
+                } else {
+                    // 参数的 DW_OP_fbreg 相对这一帧的 CFA，也就是返回后调用者的 rsp
+                    let cfa = target_cfi
+                        .and_then(|cfi| cfi.unwind(file_pc, &regs, &read_word).ok().flatten())
+                        .map(|caller| caller.rsp)
+                        .unwrap_or(regs.rbp + 16);
+                    Some(
+                        debug_data
+                            .get_function_parameters(lookup_pc as usize)
+                            .into_iter()
+                            .map(|param| {
+                                format_argument(
+                                    param,
+                                    file_pc as usize,
+                                    bias,
+                                    &regs,
+                                    cfa,
+                                    frame_arguments,
+                                    &read_word,
+                                )
+                            })
+                            .collect(),
+                    )
+                };
+                visit(
+                    Frame::Source {
+                        function: fun_name,
+                        args,
+                        line,
+                    },
+                    &regs,
+                );
+                if is_main {
+                    break;
+                }
+            }
+            _ => match libraries.as_mut().and_then(|libs| {
+                let rip = lookup_pc as usize;
+                match libs.kernel_stub(rip) {
+                    Some((area, function)) => Some(Frame::KernelStub(area, function)),
+                    None => libs.symbolize(rip).map(Frame::Library),
+                }
+            }) {
+                Some(frame) => visit(frame, &regs),
+                None => {
+                    // 没有任何符号信息的帧，无法继续可靠地回溯
+                    visit(Frame::Unknown(regs.rip), &regs);
+                    break;
+                }
+            },
+        }
+
+        let mut caller = match target_cfi {
+            Some(cfi) => cfi.unwind(file_pc, &regs, &read_word)?,
+            None => None,
+        };
+        if caller.is_none() {
+            if let Some(libs) = libraries.as_mut() {
+                caller = libs.unwind(lookup_pc as usize, &regs, &read_word)?;
+            }
+        }
+        regs = match caller {
+            Some(caller) => caller,
+            None => {
+                if regs.rbp == 0 {
+                    break;
+                }
+                // 返回后调用者的 rsp 位于保存的 rbp 和返回地址之后
+                UnwindRegs {
+                    rip: read_word(regs.rbp as usize + 8)?,
+                    rsp: regs.rbp + 16,
+                    rbp: read_word(regs.rbp as usize)?,
+                }
+            }
+        };
+        // CFI 标记返回地址未定义，说明已经到达最外层帧
+        if regs.rip == 0 {
+            break;
+        }
+        exact_pc = false;
+    }
+
+    Ok(())
+}
+
+/// 把类型展开成标量：(起始位, 位宽, 是否为浮点数)
+fn scalar_leaves(scalar_type: &Type, base: usize, leaves: &mut Vec<(usize, usize, bool)>) {
+    if scalar_type.members.is_empty() {
+        leaves.push((
+            base,
+            scalar_type.size * 8,
+            scalar_type.encoding == Encoding::Float,
+        ));
+        return;
+    }
+    for member in &scalar_type.members {
+        match member.bit_size {
+            Some(width) => leaves.push((base + member.bit_position, width, false)),
+            None => scalar_leaves(&member.member_type, base + member.bit_position, leaves),
+        }
+    }
+}
+
+/// 按 `名字=值` 格式化一个参数，读取失败时值为 `<unavailable>`。pc 是文件中的地址
+fn format_argument<E, F>(
+    param: &Variable,
+    pc: usize,
+    load_bias: usize,
+    regs: &UnwindRegs,
+    cfa: u64,
+    frame_arguments: FrameArguments,
+    read_word: &F,
+) -> String
+where
+    F: Fn(usize) -> Result<u64, E>,
+{
+    let entity_type = &param.entity_type;
+    if frame_arguments == FrameArguments::Scalars && !entity_type.members.is_empty() {
+        return format!("{}=...", param.name);
+    }
+    let location = match &param.location {
+        Location::List(entries) => match entries.iter().find(|entry| entry.covers(pc)) {
+            Some(entry) => match &entry.location {
+                Some(location) => location,
+                None => return format!("{}=<unavailable>", param.name),
+            },
+            None => return format!("{}=<optimized out>", param.name),
+        },
+        location => location,
+    };
+    // 大小未知的类型按一个 word 读取
+    let size = if entity_type.size == 0 {
+        size_of::<usize>()
+    } else {
+        entity_type.size
+    };
+    let bytes = match *location {
+        Location::Address(addr) => read_bytes(read_word, addr + load_bias, size),
+        Location::FramePointerOffset(offset) => {
+            read_bytes(read_word, (cfa as i64 + offset as i64) as usize, size)
+        }
+        Location::Register(register) => unwind_register(regs, register),
+        Location::Expression(ref expression, encoding) => {
+            let ctx = ExpressionContext {
+                register: &|register| unwind_register(regs, register),
+                read_memory: &|addr, len| read_bytes(read_word, addr, len),
+                cfa,
+            };
+            match evaluate_location(expression, encoding, size, &ctx) {
+                Ok(Some(bytes)) => Some(bytes),
+                Ok(None) => return format!("{}=<optimized out>", param.name),
+                Err(_) => None,
+            }
+        }
+        Location::TlsOffset(_) | Location::List(_) => None,
+        Location::OptimizedOut => return format!("{}=<optimized out>", param.name),
+    };
+    match bytes {
+        Some(mut bytes) => {
+            bytes.resize(size, 0);
+            format!("{}={}", param.name, entity_type.format_bytes(&bytes))
+        }
+        None => format!("{}=<unavailable>", param.name),
+    }
+}
+
+/// 逐 word 读取 [addr, addr + size) 的内存，任何一个 word 读取失败时返回 None
+fn read_bytes<E, F>(read_word: &F, addr: usize, size: usize) -> Option<Vec<u8>>
+where
+    F: Fn(usize) -> Result<u64, E>,
+{
+    let mut bytes = Vec::with_capacity(size);
+    let mut word_addr = align_addr_to_word(addr);
+    while word_addr < addr + size {
+        let word = read_word(word_addr).ok()?;
+        for (i, byte) in word.to_le_bytes().iter().enumerate() {
+            if word_addr + i >= addr && word_addr + i < addr + size {
+                bytes.push(*byte);
+            }
+        }
+        word_addr += size_of::<usize>();
+    }
+    Some(bytes)
+}
+
+/// 回溯时每一帧只知道 rip、rsp 和 rbp，其它寄存器不可用
+pub fn unwind_register(regs: &UnwindRegs, register: u16) -> Option<Vec<u8>> {
+    let value = match gimli::Register(register) {
+        gimli::X86_64::RBP => regs.rbp,
+        gimli::X86_64::RSP => regs.rsp,
+        gimli::X86_64::RA => regs.rip,
+        _ => return None,
+    };
+    Some(value.to_le_bytes().to_vec())
 }
 
 impl Drop for Inferior {
     fn drop(&mut self) {
+        if self.detached {
+            return;
+        }
         let _ = self.kill();
     }
 }
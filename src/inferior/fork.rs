@@ -0,0 +1,117 @@
+//! `set follow-fork-mode parent|child`：被调试进程 fork 时跟随父进程还是子进程。
+//!
+//! PTRACE_O_TRACEFORK / TRACEVFORK 让内核自动跟踪新的子进程，它以 SIGSTOP 停在 fork 返回之前。
+//! fork 复制整个地址空间，子进程中也有调试器写入的全部 int3，调试寄存器则不会被继承：不跟随的进程
+//! 要先恢复 int3 处原来的字节、清空调试寄存器再停止跟踪，否则它执行到断点时会因 SIGTRAP 而终止；
+//! 跟随子进程时它已经有全部断点，只需要把父进程的调试寄存器（硬件监视点）复制过去。
+//!
+//! vfork 的子进程与父进程共享内存，直到子进程 exec 或退出。跟随父进程时先撤掉 int3 再放开子进程，
+//! 父进程报告 PTRACE_EVENT_VFORK_DONE 后重新写入；跟随子进程时父进程一直停着，
+//! 子进程 exec 或退出之后才恢复父进程内存中的字节并放开它。
+
+use super::hw_breakpoint::{DR7, WATCH_SLOTS};
+use super::{write_byte_in, Inferior};
+use crate::backend::{Backend, PtraceBackend};
+
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FollowFork {
+    Parent,
+    Child,
+}
+
+impl fmt::Display for FollowFork {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FollowFork::Parent => write!(f, "parent"),
+            FollowFork::Child => write!(f, "child"),
+        }
+    }
+}
+
+impl Inferior {
+    pub fn set_follow_fork(&mut self, mode: FollowFork) {
+        self.follow_fork = mode;
+    }
+
+    /// 处理 fork / vfork 事件：放开不跟随的进程，跟随子进程时换成它的 backend
+    pub(super) fn follow_fork(&mut self, child: Pid, vfork: bool) -> Result<(), nix::Error> {
+        let call = if vfork { "vfork" } else { "fork" };
+        // 子进程的第一次停止可能比父进程的事件晚到
+        match waitpid(child, Some(WaitPidFlag::__WALL))? {
+            WaitStatus::Stopped(..) => {}
+            // 子进程还没有开始运行就被结束了
+            _ => return Ok(()),
+        }
+        let mut child_backend: Box<dyn Backend> = Box::new(PtraceBackend::forked(child));
+        match self.follow_fork {
+            FollowFork::Parent => {
+                // vfork 时这同时撤掉了父进程中的 int3，等 VFORK_DONE 之后再写回
+                self.restore_planted(child_backend.as_mut());
+                child_backend.detach()?;
+                println!("[Detaching after {} from child process {}]", call, child);
+            }
+            FollowFork::Child => {
+                copy_debug_registers(self.backend.as_ref(), child_backend.as_mut());
+                let parent = std::mem::replace(&mut self.backend, child_backend);
+                println!(
+                    "[Attaching after process {} {} to child process {}]",
+                    parent.pid(),
+                    call,
+                    child
+                );
+                if vfork {
+                    self.vfork_parent = Some(parent);
+                } else {
+                    self.release_parent(parent, call);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 跟随子进程后放开父进程：恢复它内存中 int3 处的字节、清空调试寄存器后停止跟踪。
+    /// call 是父进程调用的 `fork` 或 `vfork`
+    pub(super) fn release_parent(&self, mut parent: Box<dyn Backend>, call: &str) {
+        self.restore_planted(parent.as_mut());
+        let _ = parent.set_debug_register(DR7, 0);
+        if parent.detach().is_ok() {
+            println!(
+                "[Detaching after {} from parent process {}]",
+                call,
+                parent.pid()
+            );
+        }
+    }
+
+    /// 跟随父进程时，vfork 的子进程不再共享内存之后重新写入放开它之前撤掉的 int3
+    pub(super) fn replant(&mut self) {
+        for addr in self.planted.keys() {
+            let _ = write_byte_in(self.backend.as_mut(), *addr, 0xcc);
+        }
+    }
+
+    /// 在另一个进程（fork 出的副本）中恢复调试器写入 int3 之前的字节
+    fn restore_planted(&self, other: &mut dyn Backend) {
+        for (addr, orig_byte) in &self.planted {
+            let _ = write_byte_in(other, *addr, *orig_byte);
+        }
+    }
+}
+
+/// 把 DR0-DR3 和 DR7 从 from 复制到 to，使硬件监视点在跟随的子进程中继续生效
+fn copy_debug_registers(from: &dyn Backend, to: &mut dyn Backend) {
+    let dr7 = match from.debug_register(DR7) {
+        Ok(dr7) if dr7 != 0 => dr7,
+        _ => return,
+    };
+    for index in 0..WATCH_SLOTS {
+        if let Ok(value) = from.debug_register(index) {
+            let _ = to.set_debug_register(index, value);
+        }
+    }
+    let _ = to.set_debug_register(DR7, dr7);
+}
@@ -0,0 +1,238 @@
+//! 硬件监视点：用 x86-64 的调试寄存器 DR0-DR3 监视内存写入（`watch`、`who-writes`）。
+//!
+//! 调试寄存器通过 PTRACE_POKEUSER 写入 user 区的 u_debugreg：DR0-DR3 是被监视的地址，DR7 中每个
+//! slot 有启用位和 RW/LEN 字段，触发后 DR6 中对应的位被置上，进程以 SIGTRAP 停在写入指令之后。
+//! 一个 slot 只能监视按自身长度对齐的 1、2、4 或 8 字节，较大或不对齐的变量拆成几段，各占一个 slot。
+//!
+//! 局部变量的监视点记录所在帧的 CFA 和返回地址。被调试进程运行期间在返回地址写一个 int3，
+//! 停在那里且 rsp 已经回到 CFA 之上时说明帧已经返回，变量不再有效，监视点随之删除。
+
+use super::Inferior;
+use crate::dwarf_data::Member;
+
+/// 可用作硬件监视点的调试寄存器 DR0-DR3
+pub const WATCH_SLOTS: usize = 4;
+const DR6: usize = 6;
+pub(super) const DR7: usize = 7;
+/// DR7 中 RW 字段的值：只在写入时触发
+const DR7_RW_WRITE: u64 = 0b01;
+
+/// 局部变量所在的帧
+pub struct WatchScope {
+    pub frame_cfa: usize,
+    pub return_addr: usize,
+    /// 返回地址处写入 int3 之前的字节；没有写入时为 None
+    orig_byte: Option<u8>,
+}
+
+impl WatchScope {
+    pub fn new(frame_cfa: usize, return_addr: usize) -> WatchScope {
+        WatchScope {
+            frame_cfa,
+            return_addr,
+            orig_byte: None,
+        }
+    }
+}
+
+/// `watch` 设置的监视点
+pub struct Watchpoint {
+    /// 与断点共用编号
+    pub id: usize,
+    /// `watch` 的参数
+    pub expression: String,
+    /// 变量（或选中的成员）的类型和位置，用于格式化值
+    pub member: Member,
+    /// 所在变量的起始地址和大小，成员的值从整个变量的字节中取出
+    pub var_addr: usize,
+    pub var_size: usize,
+    /// 监视的字节范围；位字段按所在的字节监视
+    pub addr: usize,
+    pub len: usize,
+    /// 占用的调试寄存器，被调试进程没有运行时为空
+    pub slots: Vec<usize>,
+    /// 上次停止时所在变量的字节
+    pub value: Vec<u8>,
+    /// 本次运行中值改变的次数
+    pub hits: usize,
+    /// 局部变量所在的帧，全局变量为 None
+    pub scope: Option<WatchScope>,
+}
+
+impl Watchpoint {
+    pub fn format(&self, bytes: &[u8]) -> String {
+        self.member.format_in(bytes)
+    }
+}
+
+#[derive(Default)]
+pub struct WatchpointManager {
+    pub watchpoints: Vec<Watchpoint>,
+}
+
+impl WatchpointManager {
+    /// 没有被监视点占用的调试寄存器
+    pub fn free_slots(&self) -> Vec<usize> {
+        (0..WATCH_SLOTS)
+            .filter(|slot| {
+                !self
+                    .watchpoints
+                    .iter()
+                    .any(|watchpoint| watchpoint.slots.contains(slot))
+            })
+            .collect()
+    }
+
+    /// 所有监视点占用的调试寄存器
+    pub fn used_slots(&self) -> Vec<usize> {
+        self.watchpoints
+            .iter()
+            .flat_map(|watchpoint| watchpoint.slots.iter().copied())
+            .collect()
+    }
+
+    pub fn find_id(&self, id: usize) -> Option<&Watchpoint> {
+        self.watchpoints
+            .iter()
+            .find(|watchpoint| watchpoint.id == id)
+    }
+
+    /// 把 watchpoint 写入空闲的调试寄存器并加入列表
+    pub fn insert(
+        &mut self,
+        inferior: &mut Inferior,
+        mut watchpoint: Watchpoint,
+    ) -> Result<(), String> {
+        let ranges = watch_ranges(watchpoint.addr, watchpoint.len);
+        let free = self.free_slots();
+        if ranges.len() > free.len() {
+            return Err(format!(
+                "{} bytes need {} debug registers, {} available",
+                watchpoint.len,
+                ranges.len(),
+                free.len()
+            ));
+        }
+        for (slot, (addr, size)) in free.iter().zip(&ranges) {
+            if let Err(e) = inferior.set_write_watch(*slot, *addr, *size) {
+                for slot in &watchpoint.slots {
+                    let _ = inferior.clear_watch(*slot);
+                }
+                return Err(e.to_string());
+            }
+            watchpoint.slots.push(*slot);
+        }
+        self.watchpoints.push(watchpoint);
+        Ok(())
+    }
+
+    /// 按编号删除并撤掉它的调试寄存器。只在被调试进程停下（int3 已撤掉）时调用
+    pub fn remove(&mut self, inferior: Option<&mut Inferior>, id: usize) -> Option<Watchpoint> {
+        let index = self
+            .watchpoints
+            .iter()
+            .position(|watchpoint| watchpoint.id == id)?;
+        let watchpoint = self.watchpoints.remove(index);
+        if let Some(inferior) = inferior {
+            for slot in &watchpoint.slots {
+                let _ = inferior.clear_watch(*slot);
+            }
+        }
+        Some(watchpoint)
+    }
+
+    /// 在局部变量监视点的返回地址写入 int3。skip 为 true 的地址（已有用户断点）不写
+    pub fn arm(&mut self, inferior: &mut Inferior, skip: impl Fn(usize) -> bool) {
+        for scope in self
+            .watchpoints
+            .iter_mut()
+            .filter_map(|watchpoint| watchpoint.scope.as_mut())
+        {
+            if scope.orig_byte.is_none() && !skip(scope.return_addr) {
+                scope.orig_byte = inferior.write_byte(scope.return_addr, 0xcc).ok();
+            }
+        }
+    }
+
+    /// 撤掉返回地址上的 int3。同一个返回地址可能被几个监视点写入，按写入的相反顺序恢复
+    pub fn disarm(&mut self, inferior: &mut Inferior) {
+        for scope in self
+            .watchpoints
+            .iter_mut()
+            .rev()
+            .filter_map(|watchpoint| watchpoint.scope.as_mut())
+        {
+            if let Some(orig_byte) = scope.orig_byte.take() {
+                let _ = inferior.write_byte(scope.return_addr, orig_byte);
+            }
+        }
+    }
+
+    /// 已写入 int3 的返回地址在 addr 处时返回原来的字节（最先写入的监视点记录的那个）
+    pub fn armed_at(&self, addr: usize) -> Option<u8> {
+        self.watchpoints
+            .iter()
+            .filter_map(|watchpoint| watchpoint.scope.as_ref())
+            .filter(|scope| scope.return_addr == addr)
+            .find_map(|scope| scope.orig_byte)
+    }
+}
+
+impl Inferior {
+    /// 用调试寄存器 slot 监视 [addr, addr + len) 的写入；len 为 1、2、4 或 8，addr 按 len 对齐
+    pub fn set_write_watch(
+        &mut self,
+        slot: usize,
+        addr: usize,
+        len: usize,
+    ) -> Result<(), nix::Error> {
+        // DR7 的 LEN 字段：1 字节 00、2 字节 01、8 字节 10、4 字节 11
+        let len_bits: u64 = match len {
+            1 => 0b00,
+            2 => 0b01,
+            8 => 0b10,
+            _ => 0b11,
+        };
+        self.backend.set_debug_register(slot, addr as u64)?;
+        let mut dr7 = self.backend.debug_register(DR7)?;
+        dr7 &= !(0b11 << (2 * slot) | 0b1111 << (16 + 4 * slot));
+        dr7 |= 1 << (2 * slot) | (DR7_RW_WRITE | len_bits << 2) << (16 + 4 * slot);
+        self.backend.set_debug_register(DR7, dr7)
+    }
+
+    /// 撤掉调试寄存器 slot 上的监视点
+    pub fn clear_watch(&mut self, slot: usize) -> Result<(), nix::Error> {
+        let dr7 = self.backend.debug_register(DR7)?;
+        self.backend
+            .set_debug_register(DR7, dr7 & !(0b11 << (2 * slot) | 0b1111 << (16 + 4 * slot)))?;
+        self.backend.set_debug_register(slot, 0)
+    }
+
+    /// 如果进程是因为 slots 中的硬件监视点停下，返回触发的 slot，并清除 DR6 中这些 slot 的记录。
+    /// `watch` 和 `who-writes` 各自只认领自己的 slot
+    pub fn triggered_watch(&mut self, slots: &[usize]) -> Result<Option<usize>, nix::Error> {
+        let dr6 = self.backend.debug_register(DR6)?;
+        let slot = slots.iter().copied().find(|slot| dr6 & (1 << slot) != 0);
+        if slot.is_some() {
+            let mask = slots.iter().fold(0u64, |mask, slot| mask | 1 << slot);
+            self.backend.set_debug_register(DR6, dr6 & !mask)?;
+        }
+        Ok(slot)
+    }
+}
+
+/// 把 [addr, addr + len) 拆成硬件监视点能覆盖的若干段：每段 1、2、4 或 8 字节，并按自身长度对齐
+pub fn watch_ranges(addr: usize, len: usize) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let (mut addr, end) = (addr, addr + len);
+    while addr < end {
+        let size = [8, 4, 2, 1]
+            .iter()
+            .copied()
+            .find(|size| addr % size == 0 && addr + size <= end)
+            .unwrap();
+        ranges.push((addr, size));
+        addr += size;
+    }
+    ranges
+}
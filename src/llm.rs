@@ -1,8 +1,15 @@
 use crate::dwarf_data::DwarfData;
+use crate::semantic::SymbolIndex;
 use std::collections::HashMap;
 use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
 use std::path::Path;
 use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// 没有 LLM 配置时，本地语义匹配的相似度至少为多少才采用
+const SEMANTIC_THRESHOLD: f32 = 0.2;
 
 /// LLM 返回的断点解析结果
 #[derive(Debug, Clone)]
@@ -16,10 +23,25 @@ pub enum BreakpointSpec {
 }
 
 /// LLM API 配置
-struct LlmConfig {
+pub struct LlmConfig {
+    /// 读取的配置文件
+    pub path: String,
     api_key: String,
-    api_base: String,
-    model: String,
+    pub api_base: String,
+    pub model: String,
+}
+
+impl LlmConfig {
+    /// 只显示 api_key 的前后几个字符
+    pub fn masked_key(&self) -> String {
+        let chars: Vec<char> = self.api_key.chars().collect();
+        if chars.len() <= 8 {
+            return "*".repeat(chars.len());
+        }
+        let head: String = chars[..3].iter().collect();
+        let tail: String = chars[chars.len() - 4..].iter().collect();
+        format!("{}...{}", head, tail)
+    }
 }
 
 // ======================== 响应缓存 ========================
@@ -43,6 +65,13 @@ impl Cache {
     fn insert(&mut self, key: String, value: BreakpointSpec) {
         self.map.insert(key, value);
     }
+
+    /// 清空缓存，返回清掉的条数
+    fn clear(&mut self) -> usize {
+        let count = self.map.len();
+        self.map.clear();
+        count
+    }
 }
 
 /// 使用 Once + 指针实现线程安全的全局缓存（无需 lazy_static）
@@ -61,18 +90,89 @@ fn get_cache() -> &'static Mutex<Cache> {
     unsafe { &*CACHE_PTR }
 }
 
+// ======================== 请求日志 ========================
+
+/// `set llm.log <file>` 设置的日志文件，None 表示不记录
+static LOG_PATH: Mutex<Option<String>> = Mutex::new(None);
+
+/// 之后的每次请求以一行 JSON 追加到 path（None 时停止记录）。先以追加方式打开一次，确认文件可写
+pub fn set_log(path: Option<String>) -> Result<(), String> {
+    if let Some(path) = &path {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("无法打开 LLM 日志文件 {}: {}", path, e))?;
+    }
+    *LOG_PATH.lock().unwrap() = path;
+    Ok(())
+}
+
+/// 当前的日志文件
+pub fn log_path() -> Option<String> {
+    LOG_PATH.lock().unwrap().clone()
+}
+
+/// 追加一条请求记录：发出的提示词、模型的回答（或错误）、耗时和接口返回的 token 用量
+fn log_request(
+    config: &LlmConfig,
+    request: &serde_json::Value,
+    result: &Result<(String, serde_json::Value), String>,
+    latency: Duration,
+) {
+    let path = match log_path() {
+        Some(path) => path,
+        None => return,
+    };
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs_f64())
+        .unwrap_or_default();
+    let (response, usage, error) = match result {
+        Ok((content, usage)) => (
+            serde_json::json!(content),
+            usage.clone(),
+            serde_json::Value::Null,
+        ),
+        Err(e) => (
+            serde_json::Value::Null,
+            serde_json::Value::Null,
+            serde_json::json!(e),
+        ),
+    };
+    let record = serde_json::json!({
+        "time": time,
+        "api_base": config.api_base,
+        "model": config.model,
+        "messages": request["messages"],
+        "max_tokens": request["max_tokens"],
+        "response": response,
+        "error": error,
+        "latency_ms": latency.as_millis() as u64,
+        "usage": usage,
+    });
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", record));
+    if let Err(e) = written {
+        println!("写入 LLM 日志 {} 失败: {}", path, e);
+    }
+}
+
 // ======================== 配置加载 ========================
 
-/// 从配置文件加载 LLM 配置
+/// 从配置文件加载 LLM 配置。每次请求都重新读取，修改配置文件后不需要重启调试器
 /// 查找顺序: ./llm_config.json -> ~/.deet_llm_config.json
-fn load_config() -> Result<LlmConfig, String> {
-    let config_paths = vec![
-        "llm_config.json".to_string(),
-        format!(
-            "{}/.deet_llm_config.json",
-            std::env::var("HOME").unwrap_or_default()
-        ),
-    ];
+pub fn load_config() -> Result<LlmConfig, String> {
+    let config_paths: Vec<String> = std::iter::once("llm_config.json".to_string())
+        .chain(crate::home::base_dir().map(|dir| {
+            dir.join(".deet_llm_config.json")
+                .to_string_lossy()
+                .into_owned()
+        }))
+        .collect();
 
     let mut config_content = None;
     let mut used_path = String::new();
@@ -125,12 +225,39 @@ fn load_config() -> Result<LlmConfig, String> {
     let model = json["model"].as_str().unwrap_or("gpt-4o-mini").to_string();
 
     Ok(LlmConfig {
+        path: used_path,
         api_key,
         api_base,
         model,
     })
 }
 
+/// `llm reload`：清空自然语言断点的缓存（缓存命中时不会读取配置，配置的错误会被掩盖），
+/// 返回清掉的条数和重新读取的配置
+pub fn reload() -> (usize, Result<LlmConfig, String>) {
+    let cleared = get_cache().lock().map(|mut c| c.clear()).unwrap_or(0);
+    (cleared, load_config())
+}
+
+/// 用一次不消耗 token 的请求（列出可用模型）验证 api_base 和 api_key，返回可用模型的数量
+pub fn ping(config: &LlmConfig) -> Result<usize, String> {
+    let url = format!("{}/models", config.api_base.trim_end_matches('/'));
+    let response = ureq::get(&url)
+        .set("Authorization", &format!("Bearer {}", config.api_key))
+        .timeout(Duration::from_secs(10))
+        .call()
+        .map_err(|e| format!("LLM API 请求失败: {}", e))?;
+    let response_text = response
+        .into_string()
+        .map_err(|e| format!("读取 LLM 响应失败: {}", e))?;
+    let response_json: serde_json::Value = serde_json::from_str(&response_text)
+        .map_err(|e| format!("解析 LLM 响应 JSON 失败: {}", e))?;
+    response_json["data"]
+        .as_array()
+        .map(|models| models.len())
+        .ok_or_else(|| format!("LLM 响应格式异常: {}", response_text))
+}
+
 // ======================== DWARF 上下文构建 ========================
 
 /// 从 DWARF 数据中收集调试上下文，作为 LLM 的 system prompt 上下文
@@ -300,38 +427,10 @@ fn parse_natural_breakpoint(
 用户："在地址0x4005b8设断点" -> {{"type": "address", "addr": "0x4005b8"}}"#
     );
 
-    let request_body = serde_json::json!({
-        "model": config.model,
-        "messages": [
-            {"role": "system", "content": system_prompt},
-            {"role": "user", "content": natural_text}
-        ],
-        "temperature": 0.0,
-        "max_tokens": 150
-    });
-
-    let url = format!("{}/chat/completions", config.api_base.trim_end_matches('/'));
-
-    let response = ureq::post(&url)
-        .set("Authorization", &format!("Bearer {}", config.api_key))
-        .set("Content-Type", "application/json")
-        .send_string(&request_body.to_string())
-        .map_err(|e| format!("LLM API 请求失败: {}", e))?;
-
-    let response_text = response
-        .into_string()
-        .map_err(|e| format!("读取 LLM 响应失败: {}", e))?;
-
-    let response_json: serde_json::Value = serde_json::from_str(&response_text)
-        .map_err(|e| format!("解析 LLM 响应 JSON 失败: {}", e))?;
-
-    // 提取 LLM 返回的内容
-    let content = response_json["choices"][0]["message"]["content"]
-        .as_str()
-        .ok_or_else(|| format!("LLM 响应格式异常: {}", response_text))?;
+    let content = chat_completion(&config, &system_prompt, natural_text, 150)?;
 
     // 尝试从内容中提取 JSON（LLM 可能会用 ```json ``` 包裹）
-    let json_str = extract_json(content);
+    let json_str = extract_json(&content);
 
     let parsed: serde_json::Value = serde_json::from_str(&json_str)
         .map_err(|e| format!("解析 LLM 返回的断点 JSON 失败: {} (原文: {})", e, content))?;
@@ -368,6 +467,250 @@ fn parse_natural_breakpoint(
     }
 }
 
+/// 调用 chat completions 接口，返回模型回答的文本
+fn chat_completion(
+    config: &LlmConfig,
+    system_prompt: &str,
+    user_prompt: &str,
+    max_tokens: u32,
+) -> Result<String, String> {
+    let request_body = serde_json::json!({
+        "model": config.model,
+        "messages": [
+            {"role": "system", "content": system_prompt},
+            {"role": "user", "content": user_prompt}
+        ],
+        "temperature": 0.0,
+        "max_tokens": max_tokens
+    });
+
+    let start = Instant::now();
+    let result = send_completion(config, &request_body);
+    log_request(config, &request_body, &result, start.elapsed());
+    result.map(|(content, _)| content)
+}
+
+/// 发出一次 chat completions 请求，返回回答的文本和响应中的 `usage`（没有时为 null）
+fn send_completion(
+    config: &LlmConfig,
+    request_body: &serde_json::Value,
+) -> Result<(String, serde_json::Value), String> {
+    let url = format!("{}/chat/completions", config.api_base.trim_end_matches('/'));
+
+    let response = ureq::post(&url)
+        .set("Authorization", &format!("Bearer {}", config.api_key))
+        .set("Content-Type", "application/json")
+        .send_string(&request_body.to_string())
+        .map_err(|e| format!("LLM API 请求失败: {}", e))?;
+
+    let response_text = response
+        .into_string()
+        .map_err(|e| format!("读取 LLM 响应失败: {}", e))?;
+
+    let response_json: serde_json::Value = serde_json::from_str(&response_text)
+        .map_err(|e| format!("解析 LLM 响应 JSON 失败: {}", e))?;
+
+    // 提取 LLM 返回的内容
+    let content = response_json["choices"][0]["message"]["content"]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| format!("LLM 响应格式异常: {}", response_text))?;
+    Ok((content, response_json["usage"].clone()))
+}
+
+// ======================== 两次运行的对比总结 ========================
+
+/// 请 LLM 总结两次运行记录之间的行为差异（compare-runs）
+pub fn summarize_run_differences(first: &str, second: &str) -> Result<String, String> {
+    let config = load_config()?;
+
+    let system_prompt = "你是一个调试助手。用户会给出同一个程序两次运行的记录，\
+                         包括每次停在断点时的位置、观察的变量值以及最终的退出状态。\
+                         请用几句话总结两次运行在行为上的差异：从哪里开始分叉、哪些变量的值不同、\
+                         结果有什么不同，并推测可能的原因。不要逐条复述记录。";
+    let user_prompt = format!("运行 A：\n{}\n运行 B：\n{}", first, second);
+
+    chat_completion(&config, system_prompt, &user_prompt, 500)
+}
+
+// ======================== 根据错误报告建议断点 ========================
+
+/// `nb --from-bug` 建议的一项
+pub enum Suggestion {
+    /// 断点，location 是 `break` 能接受的位置（函数名、`文件:行`、行号）
+    Break { location: String, reason: String },
+    /// 监视变量的写入
+    Watch { variable: String, reason: String },
+}
+
+/// 最多建议几项
+const MAX_SUGGESTIONS: usize = 8;
+
+/// 程序中有代码的函数（带参数名）和全局变量，供模型选择断点和监视的对象
+fn build_symbol_inventory(debug_data: &DwarfData) -> String {
+    let mut inventory = String::from("## 函数:\n");
+    for file in debug_data.files() {
+        for func in file.functions.iter().filter(|func| func.address != 0) {
+            let parameters: Vec<&str> = func
+                .variables
+                .iter()
+                .filter(|var| var.is_parameter)
+                .map(|var| var.name.as_str())
+                .collect();
+            inventory.push_str(&format!(
+                "- `{}({})` ({}:{})\n",
+                func.name,
+                parameters.join(", "),
+                file.name,
+                func.line_number
+            ));
+        }
+    }
+    inventory.push_str("\n## 全局变量:\n");
+    for file in debug_data.files() {
+        for var in &file.global_variables {
+            inventory.push_str(&format!(
+                "- `{}` ({}, {}:{})\n",
+                var.name, var.entity_type.name, file.name, var.line_number
+            ));
+        }
+    }
+    inventory
+}
+
+/// 请 LLM 根据用户粘贴的错误报告建议断点和监视的变量，按可能性从高到低排列
+pub fn suggest_breakpoints(
+    report: &str,
+    debug_data: &DwarfData,
+) -> Result<Vec<Suggestion>, String> {
+    let config = load_config()?;
+
+    let system_prompt = format!(
+        r#"你是一个调试助手。用户会粘贴一份错误报告（崩溃现象、日志、复现步骤等），你需要根据报告和程序的符号表，
+建议最有助于定位问题的断点和需要监视写入的变量，按可能性从高到低排列，最多 {max} 项。
+
+程序的符号表：
+{inventory}
+你必须返回且只返回一个 JSON 对象（不要包含任何其他文字），格式为：
+{{"suggestions": [
+  {{"type": "break", "location": "函数名 或 文件名:行号", "reason": "一句话理由"}},
+  {{"type": "watch", "variable": "全局变量名", "reason": "一句话理由"}}
+]}}
+
+注意：
+- 只使用符号表中出现的函数、文件和变量
+- location 优先用函数名；只有报告中明确提到某一行时才用 文件名:行号"#,
+        max = MAX_SUGGESTIONS,
+        inventory = build_symbol_inventory(debug_data)
+    );
+
+    let content = chat_completion(&config, &system_prompt, report, 800)?;
+    let parsed: serde_json::Value = serde_json::from_str(&extract_json(&content))
+        .map_err(|e| format!("解析 LLM 返回的建议 JSON 失败: {} (原文: {})", e, content))?;
+    let items = parsed["suggestions"]
+        .as_array()
+        .ok_or_else(|| format!("LLM 响应中没有 suggestions (原文: {})", content))?;
+
+    let mut suggestions = Vec::new();
+    for item in items.iter().take(MAX_SUGGESTIONS) {
+        let reason = item["reason"].as_str().unwrap_or_default().to_string();
+        // 格式不对的项直接跳过，不影响其它建议
+        match (
+            item["type"].as_str(),
+            item["location"].as_str(),
+            item["variable"].as_str(),
+        ) {
+            (Some("break"), Some(location), _) => suggestions.push(Suggestion::Break {
+                location: location.to_string(),
+                reason,
+            }),
+            (Some("watch"), _, Some(variable)) => suggestions.push(Suggestion::Watch {
+                variable: variable.to_string(),
+                reason,
+            }),
+            _ => {}
+        }
+    }
+    Ok(suggestions)
+}
+
+// ======================== 对停止的进程提问 ========================
+
+/// `ask` 的回答
+pub struct Answer {
+    pub text: String,
+    /// 模型引用的依据，是提供给它的上下文中的原文
+    pub evidence: Vec<String>,
+}
+
+/// 请 LLM 根据停止时的局部变量、内存和源代码回答关于程序状态的问题
+pub fn ask(context: &str, question: &str) -> Result<Answer, String> {
+    let config = load_config()?;
+
+    let system_prompt = r#"你是一个调试助手。用户会给出程序停止时的位置、附近的源代码（带行号）、当前帧的局部变量，
+以及指针变量指向的内存（十六进制），并提出一个关于程序状态的问题。
+只根据给出的数据回答；数据不足以下结论时直接说明还需要查看什么。
+你必须返回且只返回一个 JSON 对象（不要包含任何其他文字）：
+{"answer": "回答", "evidence": ["支撑回答的数据，原样摘自给出的上下文（例如 `len = 3 (int)`）", ...]}"#;
+    let user_prompt = format!("{}\n问题: {}", context, question);
+
+    let content = chat_completion(&config, system_prompt, &user_prompt, 600)?;
+    let parsed: serde_json::Value = match serde_json::from_str(&extract_json(&content)) {
+        Ok(parsed) => parsed,
+        // 模型没有按格式回答时把原文当作回答
+        Err(_) => {
+            return Ok(Answer {
+                text: content.trim().to_string(),
+                evidence: Vec::new(),
+            })
+        }
+    };
+    let text = parsed["answer"]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| format!("LLM 响应中没有 answer (原文: {})", content))?;
+    let evidence = parsed["evidence"]
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item.as_str())
+                .map(|item| item.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(Answer { text, evidence })
+}
+
+// ======================== 修复建议 ========================
+
+/// 请 LLM 针对当前停止的位置给出修复补丁（suggest-fix），返回 unified diff 文本
+pub fn suggest_fix(context: &str) -> Result<String, String> {
+    let config = load_config()?;
+
+    let system_prompt = "你是一个调试助手。用户会给出程序停止的原因、调用栈和出问题附近的源代码（带行号）。\
+                         请给出一个具体、最小的修复，只修改与问题直接相关的行，\
+                         以 unified diff 格式返回（--- a/<文件> 与 +++ b/<文件> 使用给出的源文件路径），\
+                         放在 ```diff 代码块中，代码块之后可以用一两句话说明原因。";
+
+    let content = chat_completion(&config, system_prompt, context, 800)?;
+    Ok(extract_diff(&content))
+}
+
+/// 取出 ```diff ... ``` 代码块中的补丁，没有代码块时返回原文
+fn extract_diff(content: &str) -> String {
+    let trimmed = content.trim();
+    for marker in &["```diff", "```"] {
+        if let Some(start) = trimmed.find(marker) {
+            let after_marker = &trimmed[start + marker.len()..];
+            if let Some(end) = after_marker.find("```") {
+                return after_marker[..end].trim_matches('\n').to_string();
+            }
+        }
+    }
+    trimmed.to_string()
+}
+
 // ======================== 对外接口（带 Fallback + 缓存） ========================
 
 /// 解析自然语言断点描述，带离线 fallback 和缓存
@@ -375,8 +718,9 @@ fn parse_natural_breakpoint(
 /// 解析策略：
 /// 1. 查缓存 → 命中则直接返回
 /// 2. 尝试简单模式匹配（离线，不依赖 API）
-/// 3. 回退到 LLM API 调用
-/// 4. 将结果写入缓存
+/// 3. 没有 LLM 配置时在本地语义索引中查找最相似的函数
+/// 4. 回退到 LLM API 调用
+/// 5. 将结果写入缓存
 pub fn parse_with_fallback(
     natural_text: &str,
     debug_data: &DwarfData,
@@ -400,7 +744,26 @@ pub fn parse_with_fallback(
         return Ok(spec);
     }
 
-    // 3. 回退到 LLM API
+    // 3. 没有 LLM 配置时用本地语义索引
+    if load_config().is_err() {
+        let index = SymbolIndex::build(debug_data);
+        if let Some((entry, score)) = index
+            .search(natural_text, 1)
+            .into_iter()
+            .find(|(_, score)| *score >= SEMANTIC_THRESHOLD)
+        {
+            println!("[本地语义匹配: {} (相似度 {:.2})]", entry.name, score);
+            let spec = BreakpointSpec::Function {
+                name: entry.name.clone(),
+            };
+            if let Ok(mut c) = cache.lock() {
+                c.insert(natural_text.to_string(), spec.clone());
+            }
+            return Ok(spec);
+        }
+    }
+
+    // 4. 回退到 LLM API
     println!("[调用 LLM API ...]");
     let spec = parse_natural_breakpoint(natural_text, debug_data)?;
 
@@ -1,25 +1,59 @@
+mod backend;
+mod breakpoints;
+mod callgraph;
+mod cfi;
 mod debugger;
 mod debugger_command;
+mod deltas;
 mod dwarf_data;
+mod elf_info;
+mod examine;
+mod expr;
+mod fault_inject;
 mod gimli_wrapper;
+mod home;
+mod index_cache;
 mod inferior;
 mod llm;
+mod minidump;
+mod monitor;
+mod net;
+mod output_pipe;
+mod plt_catch;
+mod plugins;
+mod proc_maps;
+mod registers;
+mod remote;
+mod run_compare;
+mod semantic;
+mod signals;
+mod symbols;
+mod syscall_trace;
+mod threads;
+mod timeline;
 
 use crate::debugger::Debugger;
 use nix::sys::signal::{signal, SigHandler, Signal};
 use std::env;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
+    let mut args: Vec<String> = env::args().collect();
+    // --batch：从标准输入读取命令，断言失败时以非零状态退出，供 CI 使用
+    let batch = args.get(1).map(|arg| arg.as_str()) == Some("--batch");
+    if batch {
+        args.remove(1);
+    }
+    if args.len() != 2 && args.len() != 3 {
+        println!("Usage: {} [--batch] <target program> [minidump]", args[0]);
         std::process::exit(1);
     }
     let target = &args[1];
+    let core = args.get(2).map(|s| s.as_str());
 
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    let status = Debugger::new(target, core, batch).run();
+    std::process::exit(status);
 }
@@ -0,0 +1,224 @@
+//! Minidump（Breakpad/Crashpad 格式）解析，用于事后调试。
+//!
+//! 只解析回溯所需的部分：线程列表及其 x86-64 寄存器上下文、异常流（崩溃线程和信号）、
+//! 以及 MemoryList / Memory64List 中保存的内存区域。
+
+use nix::sys::signal::Signal;
+use std::convert::{TryFrom, TryInto};
+use std::fs;
+
+const MINIDUMP_SIGNATURE: u32 = 0x504d_444d; // "MDMP"
+
+const THREAD_LIST_STREAM: u32 = 3;
+const MEMORY_LIST_STREAM: u32 = 5;
+const EXCEPTION_STREAM: u32 = 6;
+const MEMORY64_LIST_STREAM: u32 = 9;
+
+// CONTEXT_AMD64 结构中各寄存器的偏移
+const CONTEXT_AMD64_RSP: usize = 0x98;
+const CONTEXT_AMD64_RBP: usize = 0xa0;
+const CONTEXT_AMD64_RIP: usize = 0xf8;
+const CONTEXT_AMD64_SIZE: usize = 0x100;
+
+#[derive(Debug)]
+pub enum Error {
+    ErrorOpeningFile,
+    FormatError(String),
+}
+
+/// 线程在 dump 时刻的寄存器（只保留回溯需要的几个）
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadContext {
+    pub rip: u64,
+    pub rsp: u64,
+    pub rbp: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MinidumpThread {
+    pub thread_id: u32,
+    pub context: ThreadContext,
+}
+
+/// 异常流中记录的崩溃信息。Linux 上 Breakpad 把信号编号写在 ExceptionCode 里
+#[derive(Debug, Clone)]
+pub struct CrashInfo {
+    pub thread_id: u32,
+    pub signal: Option<Signal>,
+    pub context: ThreadContext,
+}
+
+struct MemoryRegion {
+    start: u64,
+    offset: usize,
+    size: usize,
+}
+
+pub struct Minidump {
+    data: Vec<u8>,
+    threads: Vec<MinidumpThread>,
+    regions: Vec<MemoryRegion>,
+    crash: Option<CrashInfo>,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, Error> {
+    let bytes = offset
+        .checked_add(4)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| Error::FormatError(format!("truncated data at offset {:#x}", offset)))?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, Error> {
+    let bytes = offset
+        .checked_add(8)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| Error::FormatError(format!("truncated data at offset {:#x}", offset)))?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// 读取 MINIDUMP_LOCATION_DESCRIPTOR（DataSize, Rva）
+fn read_location(data: &[u8], offset: usize) -> Result<(usize, usize), Error> {
+    Ok((
+        read_u32(data, offset)? as usize,
+        read_u32(data, offset + 4)? as usize,
+    ))
+}
+
+/// 文件中的地址和大小不可信：结束地址溢出的内存区域直接丢弃
+fn push_region(regions: &mut Vec<MemoryRegion>, start: u64, offset: usize, size: usize) {
+    if start.checked_add(size as u64).is_some() {
+        regions.push(MemoryRegion {
+            start,
+            offset,
+            size,
+        });
+    }
+}
+
+fn parse_context(data: &[u8], size: usize, rva: usize) -> Result<ThreadContext, Error> {
+    if size < CONTEXT_AMD64_SIZE {
+        return Err(Error::FormatError(format!(
+            "unsupported thread context of {} bytes (only x86-64 is supported)",
+            size
+        )));
+    }
+    Ok(ThreadContext {
+        rip: read_u64(data, rva + CONTEXT_AMD64_RIP)?,
+        rsp: read_u64(data, rva + CONTEXT_AMD64_RSP)?,
+        rbp: read_u64(data, rva + CONTEXT_AMD64_RBP)?,
+    })
+}
+
+impl Minidump {
+    pub fn from_file(path: &str) -> Result<Minidump, Error> {
+        let data = fs::read(path).or(Err(Error::ErrorOpeningFile))?;
+        if read_u32(&data, 0)? != MINIDUMP_SIGNATURE {
+            return Err(Error::FormatError("not a minidump file".to_string()));
+        }
+        let stream_count = read_u32(&data, 8)? as usize;
+        let directory_rva = read_u32(&data, 12)? as usize;
+
+        let mut dump = Minidump {
+            data: Vec::new(),
+            threads: Vec::new(),
+            regions: Vec::new(),
+            crash: None,
+        };
+
+        for i in 0..stream_count {
+            let entry = directory_rva + i * 12;
+            let stream_type = read_u32(&data, entry)?;
+            let (_, rva) = read_location(&data, entry + 4)?;
+            match stream_type {
+                THREAD_LIST_STREAM => {
+                    let count = read_u32(&data, rva)? as usize;
+                    for t in 0..count {
+                        // MINIDUMP_THREAD 占 48 字节，上下文描述符位于偏移 40
+                        let thread = rva + 4 + t * 48;
+                        let thread_id = read_u32(&data, thread)?;
+                        let (size, context_rva) = read_location(&data, thread + 40)?;
+                        dump.threads.push(MinidumpThread {
+                            thread_id,
+                            context: parse_context(&data, size, context_rva)?,
+                        });
+                    }
+                }
+                MEMORY_LIST_STREAM => {
+                    let count = read_u32(&data, rva)? as usize;
+                    for m in 0..count {
+                        let desc = rva + 4 + m * 16;
+                        let start = read_u64(&data, desc)?;
+                        let (size, offset) = read_location(&data, desc + 8)?;
+                        push_region(&mut dump.regions, start, offset, size);
+                    }
+                }
+                MEMORY64_LIST_STREAM => {
+                    // Memory64List 的数据连续存放在 BaseRva 之后
+                    let count = read_u64(&data, rva)? as usize;
+                    let mut offset = read_u64(&data, rva + 8)? as usize;
+                    for m in 0..count {
+                        let desc = rva + 16 + m * 16;
+                        let start = read_u64(&data, desc)?;
+                        let size = read_u64(&data, desc + 8)? as usize;
+                        push_region(&mut dump.regions, start, offset, size);
+                        offset = offset.checked_add(size).ok_or_else(|| {
+                            Error::FormatError("memory descriptor out of range".to_string())
+                        })?;
+                    }
+                }
+                EXCEPTION_STREAM => {
+                    let thread_id = read_u32(&data, rva)?;
+                    let code = read_u32(&data, rva + 8)?;
+                    // MINIDUMP_EXCEPTION 占 152 字节，紧随其后的是上下文描述符
+                    let (size, context_rva) = read_location(&data, rva + 8 + 152)?;
+                    dump.crash = Some(CrashInfo {
+                        thread_id,
+                        signal: Signal::try_from(code as i32).ok(),
+                        context: parse_context(&data, size, context_rva)?,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        dump.data = data;
+        Ok(dump)
+    }
+
+    pub fn threads(&self) -> &[MinidumpThread] {
+        &self.threads
+    }
+
+    pub fn crash(&self) -> Option<&CrashInfo> {
+        self.crash.as_ref()
+    }
+
+    /// 返回崩溃线程的上下文；没有异常流时退回第一个线程
+    pub fn crash_context(&self) -> Option<ThreadContext> {
+        match &self.crash {
+            Some(crash) => Some(crash.context),
+            None => self.threads.first().map(|t| t.context),
+        }
+    }
+
+    /// 从 dump 保存的内存区域中读取一个 word
+    pub fn read_word(&self, addr: usize) -> Result<u64, String> {
+        let addr = addr as u64;
+        for region in &self.regions {
+            let end = region.start + region.size as u64;
+            if addr >= region.start && addr.checked_add(8).is_some_and(|last| last <= end) {
+                let truncated = || format!("memory at {:#x} is truncated in the minidump", addr);
+                let offset = region
+                    .offset
+                    .checked_add((addr - region.start) as usize)
+                    .ok_or_else(truncated)?;
+                return read_u64(&self.data, offset).map_err(|_| truncated());
+            }
+        }
+        Err(format!(
+            "memory at {:#x} is not present in the minidump",
+            addr
+        ))
+    }
+}
@@ -0,0 +1,186 @@
+//! `monitor <expr> every <n> stops|seconds`：被调试进程运行期间定期打印表达式的值，不需要停下来交给用户。
+//!
+//! 按停止计数的监视在 `continue` 途中每停下 n 次（包括调试器自动越过的停止，例如条件不满足的断点）
+//! 求值一次。按时间计数的监视由一个定时线程每隔 n 秒向进程发送 SIGSTOP，调试器认出这次停止是
+//! 定时器造成的，求值后不转交信号直接继续运行。定时线程只在 `continue` 期间存在。
+
+use crate::expr::{Expr, Value};
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// 定时线程检查是否该结束的间隔
+const TICK: Duration = Duration::from_millis(20);
+
+#[derive(Clone, Copy)]
+pub enum Interval {
+    Stops(usize),
+    Seconds(u64),
+}
+
+impl Interval {
+    pub fn describe(&self) -> String {
+        match self {
+            Interval::Stops(n) => format!("every {} stop(s)", n),
+            Interval::Seconds(n) => format!("every {} second(s)", n),
+        }
+    }
+}
+
+pub struct Monitor {
+    pub id: usize,
+    pub expression: String,
+    pub expr: Expr,
+    pub interval: Interval,
+    /// 上次求值以来的停止次数
+    stops: usize,
+    /// 上次求值的时间
+    sampled_at: Instant,
+    /// 上次求值的结果
+    pub last: Option<Value>,
+}
+
+impl Monitor {
+    /// 一次采样的输出：值，以及与上次相比的变化
+    pub fn describe_sample(&self, value: &Value) -> String {
+        match (self.last, value) {
+            (Some(Value::Int(old)), Value::Int(new)) if old != *new => {
+                format!("{} ({:+})", new, new.wrapping_sub(old))
+            }
+            (Some(old), new) if old != *new => format!("{} (was {})", new, old),
+            (Some(_), new) => format!("{} (unchanged)", new),
+            (None, new) => new.to_string(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Monitors {
+    pub monitors: Vec<Monitor>,
+    next_id: usize,
+    /// 定时线程发出了 SIGSTOP，进程还没有因它停下。跨越多次 `continue` 保留：信号可能在进程
+    /// 因别的原因停下之后才发出，要到下一次继续运行时才送达
+    interrupt_pending: Arc<AtomicBool>,
+}
+
+impl Monitors {
+    pub fn add(&mut self, expression: String, expr: Expr, interval: Interval) -> usize {
+        self.next_id += 1;
+        self.monitors.push(Monitor {
+            id: self.next_id,
+            expression,
+            expr,
+            interval,
+            stops: 0,
+            sampled_at: Instant::now(),
+            last: None,
+        });
+        self.next_id
+    }
+
+    /// 按编号删除，返回是否存在
+    pub fn remove(&mut self, id: usize) -> bool {
+        let count = self.monitors.len();
+        self.monitors.retain(|monitor| monitor.id != id);
+        self.monitors.len() != count
+    }
+
+    /// 记一次停止，返回到了求值时候的监视的下标
+    pub fn count_stop(&mut self) -> Vec<usize> {
+        let mut due = Vec::new();
+        for (index, monitor) in self.monitors.iter_mut().enumerate() {
+            if let Interval::Stops(n) = monitor.interval {
+                monitor.stops += 1;
+                if monitor.stops >= n {
+                    monitor.stops = 0;
+                    due.push(index);
+                }
+            }
+        }
+        due
+    }
+
+    /// 定时器的停止中到了求值时候的监视的下标
+    pub fn due_by_time(&mut self) -> Vec<usize> {
+        let mut due = Vec::new();
+        for (index, monitor) in self.monitors.iter_mut().enumerate() {
+            if let Interval::Seconds(n) = monitor.interval {
+                // 定时线程按最短的间隔发信号，留出一个 TICK 的误差
+                if monitor.sampled_at.elapsed() + TICK >= Duration::from_secs(n) {
+                    monitor.sampled_at = Instant::now();
+                    due.push(index);
+                }
+            }
+        }
+        due
+    }
+
+    /// 有按时间计数的监视时启动定时线程，返回的 MonitorTimer 被丢弃时线程结束
+    pub fn start_timer(&mut self, pid: Pid) -> Option<MonitorTimer> {
+        let period = self
+            .monitors
+            .iter()
+            .filter_map(|monitor| match monitor.interval {
+                Interval::Seconds(n) => Some(n),
+                Interval::Stops(_) => None,
+            })
+            .min()?;
+        let now = Instant::now();
+        for monitor in self.monitors.iter_mut() {
+            monitor.sampled_at = now;
+        }
+        let done = Arc::new(AtomicBool::new(false));
+        let pending = self.interrupt_pending.clone();
+        let thread = {
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut started = Instant::now();
+                while !done.load(Ordering::SeqCst) {
+                    thread::sleep(TICK);
+                    if started.elapsed() < Duration::from_secs(period) {
+                        continue;
+                    }
+                    started = Instant::now();
+                    if !done.load(Ordering::SeqCst) && !pending.swap(true, Ordering::SeqCst) {
+                        let _ = signal::kill(pid, Signal::SIGSTOP);
+                    }
+                }
+            })
+        };
+        Some(MonitorTimer {
+            done,
+            thread: Some(thread),
+        })
+    }
+
+    /// 定时线程发出 SIGSTOP 时置上的标志，由 Inferior 在 SIGSTOP 停止时检查并清除
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        self.interrupt_pending.clone()
+    }
+
+    /// 新的被调试进程：重新开始计数，上一个进程的信号不会再送达
+    pub fn reset(&mut self) {
+        self.interrupt_pending.store(false, Ordering::SeqCst);
+        for monitor in self.monitors.iter_mut() {
+            monitor.stops = 0;
+            monitor.last = None;
+        }
+    }
+}
+
+pub struct MonitorTimer {
+    done: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for MonitorTimer {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
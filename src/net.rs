@@ -0,0 +1,197 @@
+//! `info net`：被调试进程打开的套接字及其流量。
+//!
+//! 套接字来自 /proc/<pid>/fd 中 `socket:[inode]` 形式的链接，再按 inode 在进程所在网络命名空间的
+//! /proc/<pid>/net/{tcp,tcp6,udp,udp6,unix} 中找到协议、本端和对端地址。`set net-tracking on` 时
+//! 被调试进程以 PTRACE_SYSCALL 运行，在 read/write/send*/recv* 等调用的出口按返回值累计每个套接字
+//! 收发的字节数；计数在每次继续运行时清零，因此显示的是上次停止以来的流量。
+
+use nix::libc::user_regs_struct;
+use nix::unistd::Pid;
+use std::collections::HashMap;
+use std::fs;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// 统计流量的调用：(调用号, 是否是发送)。readv/writev 的返回值同样是字节数
+const TRAFFIC_SYSCALLS: &[(u64, bool)] = &[
+    (0, false),  // read
+    (1, true),   // write
+    (19, false), // readv
+    (20, true),  // writev
+    (44, true),  // sendto
+    (45, false), // recvfrom
+    (46, true),  // sendmsg
+    (47, false), // recvmsg
+];
+
+const ENOSYS: i64 = 38;
+
+const TCP_STATES: &[&str] = &[
+    "",
+    "ESTABLISHED",
+    "SYN_SENT",
+    "SYN_RECV",
+    "FIN_WAIT1",
+    "FIN_WAIT2",
+    "TIME_WAIT",
+    "CLOSE",
+    "CLOSE_WAIT",
+    "LAST_ACK",
+    "LISTEN",
+    "CLOSING",
+];
+
+/// 一个套接字
+pub struct Socket {
+    pub fd: i32,
+    pub inode: u64,
+    pub protocol: &'static str,
+    pub local: String,
+    pub peer: String,
+    pub state: String,
+}
+
+/// 上次停止以来每个套接字（按 inode）发送和接收的字节数
+#[derive(Default)]
+pub struct NetTraffic {
+    bytes: HashMap<u64, (u64, u64)>,
+}
+
+impl NetTraffic {
+    pub fn reset(&mut self) {
+        self.bytes.clear();
+    }
+
+    /// (发送, 接收) 的字节数
+    pub fn get(&self, inode: u64) -> (u64, u64) {
+        self.bytes.get(&inode).copied().unwrap_or_default()
+    }
+
+    /// 在一次系统调用的入口或出口停下时调用，只处理出口
+    pub fn on_stop(&mut self, pid: Pid, regs: &user_regs_struct) {
+        let ret = regs.rax as i64;
+        if ret == -ENOSYS || ret <= 0 {
+            return;
+        }
+        let sent = match TRAFFIC_SYSCALLS.iter().find(|(n, _)| *n == regs.orig_rax) {
+            Some((_, sent)) => *sent,
+            None => return,
+        };
+        // rdi 在系统调用前后保持不变，仍是描述符
+        let inode = match socket_inode(pid, regs.rdi as i32) {
+            Some(inode) => inode,
+            None => return,
+        };
+        let bytes = self.bytes.entry(inode).or_default();
+        if sent {
+            bytes.0 += ret as u64;
+        } else {
+            bytes.1 += ret as u64;
+        }
+    }
+}
+
+/// 描述符指向套接字时返回它的 inode
+fn socket_inode(pid: Pid, fd: i32) -> Option<u64> {
+    let link = fs::read_link(format!("/proc/{}/fd/{}", pid, fd)).ok()?;
+    link.to_str()?
+        .strip_prefix("socket:[")?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
+
+/// 列出进程打开的所有套接字，按描述符排序。/proc/net 中找不到的套接字（例如 netlink）协议显示为 `?`
+pub fn read_sockets(pid: Pid) -> Result<Vec<Socket>, std::io::Error> {
+    let mut sockets = Vec::new();
+    for entry in fs::read_dir(format!("/proc/{}/fd", pid))? {
+        let fd = match entry?
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(fd) => fd,
+            None => continue,
+        };
+        if let Some(inode) = socket_inode(pid, fd) {
+            sockets.push(Socket {
+                fd,
+                inode,
+                protocol: "?",
+                local: "-".to_string(),
+                peer: "-".to_string(),
+                state: String::new(),
+            });
+        }
+    }
+    sockets.sort_by_key(|socket| socket.fd);
+    for protocol in &["tcp", "tcp6", "udp", "udp6"] {
+        let table =
+            fs::read_to_string(format!("/proc/{}/net/{}", pid, protocol)).unwrap_or_default();
+        for line in table.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let inode = match fields.get(9).and_then(|inode| inode.parse::<u64>().ok()) {
+                Some(inode) => inode,
+                None => continue,
+            };
+            for socket in sockets.iter_mut().filter(|socket| socket.inode == inode) {
+                socket.protocol = protocol;
+                socket.local = parse_address(fields[1]).unwrap_or_else(|| fields[1].to_string());
+                socket.peer = parse_address(fields[2]).unwrap_or_else(|| fields[2].to_string());
+                // UDP 没有连接状态，表中的 07 只是占位
+                if protocol.starts_with("tcp") {
+                    socket.state = usize::from_str_radix(fields[3], 16)
+                        .ok()
+                        .and_then(|state| TCP_STATES.get(state))
+                        .map(|state| state.to_string())
+                        .unwrap_or_else(|| fields[3].to_string());
+                }
+            }
+        }
+    }
+    let unix = fs::read_to_string(format!("/proc/{}/net/unix", pid)).unwrap_or_default();
+    for line in unix.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let inode = match fields.get(6).and_then(|inode| inode.parse::<u64>().ok()) {
+            Some(inode) => inode,
+            None => continue,
+        };
+        for socket in sockets.iter_mut().filter(|socket| socket.inode == inode) {
+            socket.protocol = "unix";
+            if let Some(path) = fields.get(7) {
+                socket.local = path.to_string();
+            }
+            // 对端在 /proc 中不可见。Flags 带 __SO_ACCEPTCON 的是监听套接字，St 为 03 表示已连接
+            socket.state = match (fields[3], fields[5]) {
+                (_, "03") => "CONNECTED",
+                ("00010000", _) => "LISTEN",
+                _ => "UNCONNECTED",
+            }
+            .to_string();
+        }
+    }
+    Ok(sockets)
+}
+
+/// 把 /proc/net/tcp 中的 `0100007F:1F90` 解析为 `127.0.0.1:8080`。地址按 32 位一组以主机字节序
+/// 存放，端口是大端的十六进制
+fn parse_address(text: &str) -> Option<String> {
+    let (addr, port) = text.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let words = (0..addr.len() / 8)
+        .map(|i| u32::from_str_radix(&addr[i * 8..i * 8 + 8], 16).ok())
+        .collect::<Option<Vec<u32>>>()?;
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    match bytes.len() {
+        4 => Some(format!(
+            "{}:{}",
+            Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]),
+            port
+        )),
+        16 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&bytes);
+            Some(format!("[{}]:{}", Ipv6Addr::from(octets), port))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,121 @@
+//! 把调试器命令的输出交给 shell 管道处理，例如 `backtrace | grep alloc`。
+//!
+//! 命令执行期间把标准输出（fd 1）重定向到 `sh -c <pipeline>` 的标准输入，
+//! `OutputPipe` 被 drop 时恢复原来的标准输出并等待管道结束。
+
+use nix::fcntl::{fcntl, FcntlArg};
+use nix::unistd::{close, dup2};
+use std::io::Write;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::process::{Child, Command, Stdio};
+
+const STDOUT_FILENO: RawFd = 1;
+
+/// 参数是表达式的命令：其中的 `|` 和 `||` 是运算符，整行都不拆分管道
+const EXPRESSION_COMMANDS: &[&str] = &["p", "print", "until-expr", "monitor", "display", "set"];
+
+/// 把一行输入拆成命令和 shell 管道。管道符是两边都有空格的 ` | `，`a||b`、`a|b` 不算；
+/// 参数是表达式的命令和带 `if` 条件的 `break` 不拆分。行尾的 ` |` 得到空的管道命令
+pub fn split_pipeline(line: &str) -> (&str, Option<&str>) {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let conditional_break = matches!(name, "b" | "break") && words.any(|word| word == "if");
+    if EXPRESSION_COMMANDS.contains(&name) || conditional_break {
+        return (line, None);
+    }
+    if let Some(idx) = line.find(" | ") {
+        return (&line[..idx], Some(line[idx + 3..].trim()));
+    }
+    match line.trim_end().strip_suffix(" |") {
+        Some(command) => (command, Some("")),
+        None => (line, None),
+    }
+}
+
+pub struct OutputPipe {
+    child: Child,
+    saved_stdout: RawFd,
+}
+
+impl OutputPipe {
+    pub fn start(pipeline: &str) -> Result<OutputPipe, String> {
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(pipeline)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to start shell: {}", e))?;
+        let pipe_fd = child.stdin.as_ref().unwrap().as_raw_fd();
+
+        let _ = std::io::stdout().flush();
+        // 保存的 fd 带 CLOEXEC，之后启动的子进程不会继承它
+        let saved_stdout = fcntl(STDOUT_FILENO, FcntlArg::F_DUPFD_CLOEXEC(0))
+            .map_err(|e| format!("failed to save stdout: {}", e))?;
+        if let Err(e) = dup2(pipe_fd, STDOUT_FILENO) {
+            let _ = close(saved_stdout);
+            return Err(format!("failed to redirect stdout: {}", e));
+        }
+        Ok(OutputPipe {
+            child,
+            saved_stdout,
+        })
+    }
+}
+
+impl Drop for OutputPipe {
+    fn drop(&mut self) {
+        let _ = std::io::stdout().flush();
+        let _ = dup2(self.saved_stdout, STDOUT_FILENO);
+        let _ = close(self.saved_stdout);
+        // 关闭写端后管道才能读到 EOF
+        drop(self.child.stdin.take());
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_pipeline;
+    use crate::debugger_command::DebuggerCommand;
+
+    #[test]
+    fn splits_spaced_pipe() {
+        assert_eq!(
+            split_pipeline("backtrace | grep alloc"),
+            ("backtrace", Some("grep alloc"))
+        );
+        assert_eq!(
+            split_pipeline("info sections |"),
+            ("info sections", Some(""))
+        );
+        assert_eq!(split_pipeline("backtrace"), ("backtrace", None));
+    }
+
+    #[test]
+    fn refuses_commands_that_start_processes() {
+        let starts_process = |line: &str| {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            DebuggerCommand::from_tokens(&tokens)
+                .unwrap()
+                .starts_process()
+        };
+        assert!(starts_process("run"));
+        assert!(starts_process("run --until-fail 3"));
+        assert!(starts_process("target valgrind -- ./a.out"));
+        assert!(starts_process("compare-runs 1 -- 2"));
+        assert!(!starts_process("backtrace"));
+        assert!(!starts_process("info break"));
+    }
+
+    #[test]
+    fn keeps_operators_in_expressions() {
+        let condition = "break func2 if a == 1 || b == 5";
+        assert_eq!(split_pipeline(condition), (condition, None));
+        assert_eq!(split_pipeline("b 10 if a | b"), ("b 10 if a | b", None));
+        assert_eq!(split_pipeline("print a | b"), ("print a | b", None));
+        assert_eq!(split_pipeline("until-expr x||y"), ("until-expr x||y", None));
+        assert_eq!(split_pipeline("set $x = a | 4"), ("set $x = a | 4", None));
+        // 没有空格的 `|` 不是管道
+        assert_eq!(split_pipeline("x/4xg a|b"), ("x/4xg a|b", None));
+    }
+}
@@ -0,0 +1,192 @@
+//! `catch plt <name>`：目标程序第一次调用一个延迟绑定的库函数、动态链接器解析它的地址时停下。
+//!
+//! 延迟绑定时 .got.plt 中函数的槽位最初指向 PLT 中的解析桩（push 重定位下标后跳到 PLT0），
+//! 第一次调用经过这个桩进入 `_dl_runtime_resolve`，动态链接器把真实地址写回槽位，之后的调用
+//! 直接跳到函数，不再经过桩。因此在桩上写一个 int3：它只在第一次调用时执行，这时函数的真实地址
+//! 还不存在。槽位来自目标程序 .rela.plt 中的 JUMP_SLOT 重定位，桩的地址是磁盘上槽位的初始内容。
+//! 与故障注入相同，int3 只在被调试进程运行期间存在。
+
+use crate::inferior::Inferior;
+use object::{Object, ObjectSection};
+use std::fs;
+
+const R_X86_64_JUMP_SLOT: u32 = 7;
+const DT_NULL: u64 = 0;
+const DT_BIND_NOW: u64 = 24;
+const DT_FLAGS: u64 = 30;
+const DT_FLAGS_1: u64 = 0x6fff_fffb;
+const DF_BIND_NOW: u64 = 0x8;
+const DF_1_NOW: u64 = 0x1;
+/// Elf64_Rela、Elf64_Sym 和 Elf64_Dyn 的大小
+const RELA_SIZE: usize = 24;
+const SYM_SIZE: usize = 24;
+const DYN_SIZE: usize = 16;
+
+/// 一个延迟绑定的函数在目标程序中的位置
+#[derive(Clone, Copy)]
+pub struct PltSlot {
+    /// .got.plt 中的槽位
+    pub got: usize,
+    /// 槽位初始指向的解析桩
+    pub stub: usize,
+}
+
+impl PltSlot {
+    /// 整个映像移动 delta 之后的位置
+    pub fn shifted(self, delta: usize) -> PltSlot {
+        PltSlot {
+            got: self.got.wrapping_add(delta),
+            stub: self.stub.wrapping_add(delta),
+        }
+    }
+}
+
+/// 在 path 的 .rela.plt 中查找 name 的 JUMP_SLOT 重定位，返回文件中的地址。没有这个函数时返回 Ok(None)
+pub fn find_slot(path: &str, name: &str) -> Result<Option<PltSlot>, String> {
+    let file = fs::File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mmap = unsafe { memmap::Mmap::map(&file).map_err(|e| format!("{}: {}", path, e))? };
+    let object = object::File::parse(&mmap).map_err(|e| format!("{}: {}", path, e))?;
+    let (relocations, symbols, strings) = match (
+        object.section_data_by_name(".rela.plt"),
+        object.section_data_by_name(".dynsym"),
+        object.section_data_by_name(".dynstr"),
+    ) {
+        (Some(relocations), Some(symbols), Some(strings)) => (relocations, symbols, strings),
+        _ => return Ok(None),
+    };
+    for rela in relocations.chunks_exact(RELA_SIZE) {
+        let info = u64_at(rela, 8);
+        if info as u32 != R_X86_64_JUMP_SLOT {
+            continue;
+        }
+        let symbol = (info >> 32) as usize * SYM_SIZE;
+        let name_offset = match symbols.get(symbol..symbol + 4) {
+            Some(bytes) => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize,
+            None => continue,
+        };
+        let symbol_name = strings
+            .get(name_offset..)
+            .and_then(|bytes| bytes.split(|byte| *byte == 0).next())
+            .unwrap_or_default();
+        if symbol_name != name.as_bytes() {
+            continue;
+        }
+        let got = u64_at(rela, 0);
+        let stub = object
+            .sections()
+            .find_map(|section| section.data_range(got, 8))
+            .ok_or_else(|| format!("{}: no contents for the GOT slot at {:#x}", path, got))?;
+        return Ok(Some(PltSlot {
+            got: got as usize,
+            stub: u64_at(stub, 0) as usize,
+        }));
+    }
+    Ok(None)
+}
+
+/// path 是否在启动时就绑定所有符号（`-z now`），这时解析桩不会执行
+pub fn binds_now(path: &str) -> bool {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(_) => return false,
+    };
+    let object = match object::File::parse(&data) {
+        Ok(object) => object,
+        Err(_) => return false,
+    };
+    let dynamic = match object.section_data_by_name(".dynamic") {
+        Some(dynamic) => dynamic,
+        None => return false,
+    };
+    for entry in dynamic.chunks_exact(DYN_SIZE) {
+        let (tag, value) = (u64_at(entry, 0), u64_at(entry, 8));
+        match tag {
+            DT_NULL => break,
+            DT_BIND_NOW => return true,
+            DT_FLAGS if value & DF_BIND_NOW != 0 => return true,
+            DT_FLAGS_1 if value & DF_1_NOW != 0 => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    let mut word = [0u8; 8];
+    word.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(word)
+}
+
+pub struct PltCatch {
+    pub name: String,
+    pub slot: PltSlot,
+    /// 本次运行中第一次调用的返回地址；还没有调用时为 None
+    pub first_call: Option<usize>,
+    /// 解析桩处写入 int3 之前的字节；没有写入时为 None
+    orig_byte: Option<u8>,
+}
+
+impl PltCatch {
+    pub fn new(name: String, slot: PltSlot) -> PltCatch {
+        PltCatch {
+            name,
+            slot,
+            first_call: None,
+            orig_byte: None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PltCatches {
+    pub catches: Vec<PltCatch>,
+}
+
+impl PltCatches {
+    /// 新的进程：每个函数重新等待第一次调用
+    pub fn reset(&mut self) {
+        for catch in self.catches.iter_mut() {
+            catch.first_call = None;
+            catch.orig_byte = None;
+        }
+    }
+
+    /// 在还没有被调用过的函数的解析桩上写入 int3。skip 为 true 的地址（已有用户断点）不写
+    pub fn arm(&mut self, inferior: &mut Inferior, skip: impl Fn(usize) -> bool) {
+        for catch in self.catches.iter_mut() {
+            if catch.first_call.is_none() && catch.orig_byte.is_none() && !skip(catch.slot.stub) {
+                catch.orig_byte = inferior.write_byte(catch.slot.stub, 0xcc).ok();
+            }
+        }
+    }
+
+    /// 撤掉所有 int3
+    pub fn disarm(&mut self, inferior: &mut Inferior) {
+        for catch in self.catches.iter_mut() {
+            if let Some(orig_byte) = catch.orig_byte.take() {
+                let _ = inferior.write_byte(catch.slot.stub, orig_byte);
+            }
+        }
+    }
+
+    /// 已写入 int3 的解析桩在 addr 处时返回它的下标
+    pub fn armed_at(&self, addr: usize) -> Option<usize> {
+        self.catches
+            .iter()
+            .position(|catch| catch.slot.stub == addr && catch.orig_byte.is_some())
+    }
+
+    /// 目标程序的加载偏移改变了 delta：只在 int3 没有写入时调用
+    pub fn shift(&mut self, delta: usize) {
+        for catch in self.catches.iter_mut() {
+            catch.slot = catch.slot.shifted(delta);
+        }
+    }
+
+    /// 按函数名删除，返回是否存在。只在被调试进程停下（int3 已撤掉）时调用
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.catches.len();
+        self.catches.retain(|catch| catch.name != name);
+        self.catches.len() != count
+    }
+}
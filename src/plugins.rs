@@ -0,0 +1,307 @@
+//! 通过动态加载的共享库扩展调试器。
+//!
+//! 启动时加载 `~/.kdb/plugins/*.so`。每个插件导出 C 函数 `kdb_plugin_v1`，返回一个静态的
+//! `KdbPlugin` 描述，其中可以提供三类钩子：新命令、被调试进程停止时的观察者、按类型名格式化变量值的
+//! pretty-printer。接口只使用 C ABI 类型，插件可以用 C 或任何能导出 C 函数的语言编写，
+//! 结构定义见 `plugin/kdb_plugin.h`。
+
+use crate::inferior::Inferior;
+
+use nix::libc::{self, c_char, c_int, c_void};
+use std::ffi::{CStr, CString, OsStr};
+use std::io::Write;
+use std::path::Path;
+
+/// 插件接口版本，修改 `KdbPlugin` 的布局时递增
+pub const KDB_PLUGIN_ABI_VERSION: u32 = 1;
+
+/// 插件入口函数的名字
+const ENTRY_SYMBOL: &[u8] = b"kdb_plugin_v1\0";
+
+/// pretty-printer 输出缓冲区的大小
+const PRETTY_BUFFER_SIZE: usize = 256;
+
+pub const KDB_STOP_STOPPED: c_int = 0;
+pub const KDB_STOP_EXITED: c_int = 1;
+pub const KDB_STOP_SIGNALED: c_int = 2;
+
+/// 传给插件命令和停止观察者的调试器上下文；没有被调试进程时 pid 为 0，read_memory 总是失败
+#[repr(C)]
+pub struct KdbContext {
+    pub pid: c_int,
+    /// 读取被调试进程 [addr, addr + len) 的内存到 buf，成功返回 0
+    pub read_memory:
+        extern "C" fn(ctx: *const KdbContext, addr: u64, buf: *mut u8, len: usize) -> c_int,
+    /// 调试器内部使用
+    pub inferior: *const c_void,
+}
+
+/// 一次停止事件
+#[repr(C)]
+pub struct KdbStopEvent {
+    /// KDB_STOP_STOPPED / KDB_STOP_EXITED / KDB_STOP_SIGNALED
+    pub kind: c_int,
+    /// 停止或终止进程的信号；正常退出时为 0
+    pub signal: c_int,
+    pub exit_code: c_int,
+    pub rip: u64,
+    /// 停止位置所在的函数和源码位置，没有调试信息时为 NULL / 0
+    pub function: *const c_char,
+    pub file: *const c_char,
+    pub line: u32,
+}
+
+/// 插件导出的描述，插件加载期间必须一直有效
+#[repr(C)]
+pub struct KdbPlugin {
+    /// 必须等于 KDB_PLUGIN_ABI_VERSION
+    pub abi_version: u32,
+    pub name: *const c_char,
+    /// 插件提供的命令名，以 NULL 结尾；不能覆盖内置命令
+    pub commands: *const *const c_char,
+    /// 执行插件命令，args 是命令名之后的文本
+    pub run_command: Option<
+        extern "C" fn(command: *const c_char, args: *const c_char, ctx: *const KdbContext) -> c_int,
+    >,
+    pub on_stop: Option<extern "C" fn(event: *const KdbStopEvent, ctx: *const KdbContext)>,
+    /// 把 type_name 类型的值格式化到 buf（以 NUL 结尾），处理了该类型时返回非 0
+    pub pretty_print: Option<
+        extern "C" fn(type_name: *const c_char, value: u64, buf: *mut c_char, len: usize) -> c_int,
+    >,
+}
+
+/// 停止事件中的源码位置
+pub struct StopLocation {
+    pub function: String,
+    pub file: String,
+    pub line: usize,
+}
+
+/// 调试器传给插件的停止事件
+pub enum StopKind {
+    Stopped(c_int, u64),
+    Exited(c_int),
+    Signaled(c_int),
+}
+
+struct LoadedPlugin {
+    /// dlopen 返回的句柄，插件在整个会话期间保持加载
+    _handle: *mut c_void,
+    api: &'static KdbPlugin,
+    name: String,
+    commands: Vec<String>,
+}
+
+pub struct PluginManager {
+    plugins: Vec<LoadedPlugin>,
+}
+
+extern "C" fn read_memory(ctx: *const KdbContext, addr: u64, buf: *mut u8, len: usize) -> c_int {
+    let inferior = unsafe { ((*ctx).inferior as *const Inferior).as_ref() };
+    match inferior.map(|inferior| inferior.read_memory(addr as usize, len)) {
+        Some(Ok(bytes)) => {
+            unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), buf, len) };
+            0
+        }
+        _ => -1,
+    }
+}
+
+fn context(inferior: Option<&Inferior>) -> KdbContext {
+    KdbContext {
+        pid: inferior
+            .map(|inferior| inferior.pid().as_raw())
+            .unwrap_or(0),
+        read_memory,
+        inferior: inferior
+            .map(|inferior| inferior as *const Inferior as *const c_void)
+            .unwrap_or(std::ptr::null()),
+    }
+}
+
+/// 插件通过 C 的 stdio 输出，调用前后都刷新缓冲区，保证与调试器自己的输出顺序一致
+fn with_flushed_output<T>(call: impl FnOnce() -> T) -> T {
+    let _ = std::io::stdout().flush();
+    let result = call();
+    unsafe { libc::fflush(std::ptr::null_mut()) };
+    result
+}
+
+fn dl_error() -> String {
+    let err = unsafe { libc::dlerror() };
+    if err.is_null() {
+        "unknown error".to_string()
+    } else {
+        unsafe { CStr::from_ptr(err) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// 加载一个插件并检查接口版本
+fn load_plugin(path: &Path) -> Result<LoadedPlugin, String> {
+    let c_path = CString::new(path.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+    let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        return Err(dl_error());
+    }
+    let close = |err: String| {
+        unsafe { libc::dlclose(handle) };
+        Err(err)
+    };
+    let entry = unsafe { libc::dlsym(handle, ENTRY_SYMBOL.as_ptr() as *const c_char) };
+    if entry.is_null() {
+        return close("missing kdb_plugin_v1".to_string());
+    }
+    let entry: extern "C" fn() -> *const KdbPlugin = unsafe { std::mem::transmute(entry) };
+    let api = match unsafe { entry().as_ref() } {
+        Some(api) => api,
+        None => return close("kdb_plugin_v1 returned NULL".to_string()),
+    };
+    if api.abi_version != KDB_PLUGIN_ABI_VERSION {
+        return close(format!(
+            "plugin ABI version {} is not supported (expected {})",
+            api.abi_version, KDB_PLUGIN_ABI_VERSION
+        ));
+    }
+
+    let name = if api.name.is_null() {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    } else {
+        unsafe { CStr::from_ptr(api.name) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    let mut commands = Vec::new();
+    if !api.commands.is_null() {
+        let mut command = api.commands;
+        while unsafe { !(*command).is_null() } {
+            commands.push(
+                unsafe { CStr::from_ptr(*command) }
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+            command = unsafe { command.add(1) };
+        }
+    }
+    Ok(LoadedPlugin {
+        _handle: handle,
+        api,
+        name,
+        commands,
+    })
+}
+
+impl PluginManager {
+    /// 按文件名顺序加载 `~/.kdb/plugins/*.so`，加载失败的插件只打印错误
+    pub fn load() -> PluginManager {
+        let mut plugins = Vec::new();
+        // 没有可信的目录时不加载任何插件
+        let dir = crate::home::base_dir().map(|dir| dir.join(".kdb/plugins"));
+        let mut paths: Vec<_> = match dir.map(std::fs::read_dir) {
+            Some(Ok(entries)) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension() == Some(OsStr::new("so")))
+                .collect(),
+            _ => Vec::new(),
+        };
+        paths.sort();
+        for path in paths {
+            match load_plugin(&path) {
+                Ok(plugin) => {
+                    println!("Loaded plugin {} from {}", plugin.name, path.display());
+                    plugins.push(plugin);
+                }
+                Err(e) => println!("Error loading plugin {}: {}", path.display(), e),
+            }
+        }
+        PluginManager { plugins }
+    }
+
+    pub fn has_command(&self, name: &str) -> bool {
+        self.plugins
+            .iter()
+            .any(|plugin| plugin.commands.iter().any(|command| command == name))
+    }
+
+    /// 执行插件命令；由第一个提供该命令的插件处理
+    pub fn run_command(&self, name: &str, args: &str, inferior: Option<&Inferior>) {
+        let plugin = match self.plugins.iter().find(|plugin| {
+            plugin.api.run_command.is_some() && plugin.commands.iter().any(|c| c == name)
+        }) {
+            Some(plugin) => plugin,
+            None => return,
+        };
+        let (c_name, c_args) = match (CString::new(name), CString::new(args)) {
+            (Ok(c_name), Ok(c_args)) => (c_name, c_args),
+            _ => return,
+        };
+        let ctx = context(inferior);
+        let run_command = plugin.api.run_command.unwrap();
+        let ret = with_flushed_output(|| run_command(c_name.as_ptr(), c_args.as_ptr(), &ctx));
+        if ret != 0 {
+            println!("{}: command {} failed ({})", plugin.name, name, ret);
+        }
+    }
+
+    /// 把停止事件通知给所有插件
+    pub fn notify_stop(
+        &self,
+        kind: StopKind,
+        location: Option<StopLocation>,
+        inferior: Option<&Inferior>,
+    ) {
+        if self
+            .plugins
+            .iter()
+            .all(|plugin| plugin.api.on_stop.is_none())
+        {
+            return;
+        }
+        let function = location
+            .as_ref()
+            .and_then(|l| CString::new(l.function.as_str()).ok());
+        let file = location
+            .as_ref()
+            .and_then(|l| CString::new(l.file.as_str()).ok());
+        let (kind, signal, exit_code, rip) = match kind {
+            StopKind::Stopped(signal, rip) => (KDB_STOP_STOPPED, signal, 0, rip),
+            StopKind::Exited(code) => (KDB_STOP_EXITED, 0, code, 0),
+            StopKind::Signaled(signal) => (KDB_STOP_SIGNALED, signal, 0, 0),
+        };
+        let event = KdbStopEvent {
+            kind,
+            signal,
+            exit_code,
+            rip,
+            function: function.as_ref().map_or(std::ptr::null(), |f| f.as_ptr()),
+            file: file.as_ref().map_or(std::ptr::null(), |f| f.as_ptr()),
+            line: location.as_ref().map_or(0, |l| l.line as u32),
+        };
+        let ctx = context(inferior);
+        for plugin in &self.plugins {
+            if let Some(on_stop) = plugin.api.on_stop {
+                with_flushed_output(|| on_stop(&event, &ctx));
+            }
+        }
+    }
+
+    /// 让插件格式化 type_name 类型的值，没有插件处理该类型时返回 None
+    pub fn pretty_print(&self, type_name: &str, value: u64) -> Option<String> {
+        let c_type = CString::new(type_name).ok()?;
+        for plugin in &self.plugins {
+            if let Some(pretty_print) = plugin.api.pretty_print {
+                let mut buf = vec![0 as c_char; PRETTY_BUFFER_SIZE];
+                if pretty_print(c_type.as_ptr(), value, buf.as_mut_ptr(), buf.len()) != 0 {
+                    // 防止插件没有写入结尾的 NUL
+                    buf[PRETTY_BUFFER_SIZE - 1] = 0;
+                    let text = unsafe { CStr::from_ptr(buf.as_ptr()) };
+                    return Some(text.to_string_lossy().into_owned());
+                }
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,91 @@
+//! 解析 /proc/<pid>/maps，获取被调试进程的内存映射（加载的库、匿名映射等）
+
+use nix::unistd::Pid;
+use std::fs;
+
+#[derive(Debug, Clone)]
+pub struct MapEntry {
+    pub start: usize,
+    pub end: usize,
+    pub perms: String,
+    pub path: Option<String>,
+}
+
+/// 读取并解析指定进程的内存映射表
+pub fn read_maps(pid: Pid) -> Result<Vec<MapEntry>, std::io::Error> {
+    let contents = fs::read_to_string(format!("/proc/{}/maps", pid))?;
+    Ok(contents.lines().filter_map(parse_line).collect())
+}
+
+/// 返回没有对应文件的可执行映射（JIT 代码通常位于这里）
+pub fn anonymous_executable(maps: &[MapEntry]) -> Vec<(usize, usize)> {
+    maps.iter()
+        .filter(|e| e.perms.contains('x'))
+        .filter(|e| match &e.path {
+            None => true,
+            Some(path) => path.starts_with("[anon"),
+        })
+        .map(|e| (e.start, e.end))
+        .collect()
+}
+
+/// 内核提供的特殊映射（`[vdso]`、`[vsyscall]`）的地址范围
+pub fn special_mapping(maps: &[MapEntry], name: &str) -> Option<(usize, usize)> {
+    maps.iter()
+        .find(|e| e.path.as_deref() == Some(name))
+        .map(|e| (e.start, e.end))
+}
+
+/// 一个被映射进进程的文件及其占据的地址范围
+#[derive(Debug, Clone)]
+pub struct MappedFile {
+    pub path: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// 返回所有带可执行映射的文件（即已加载的可执行文件和共享库），保持出现顺序
+pub fn mapped_files(maps: &[MapEntry]) -> Vec<MappedFile> {
+    let mut files: Vec<MappedFile> = Vec::new();
+    for entry in maps {
+        let path = match &entry.path {
+            Some(path) if path.starts_with('/') => path,
+            _ => continue,
+        };
+        match files.iter_mut().find(|f| &f.path == path) {
+            Some(file) => {
+                file.start = file.start.min(entry.start);
+                file.end = file.end.max(entry.end);
+            }
+            None => files.push(MappedFile {
+                path: path.clone(),
+                start: entry.start,
+                end: entry.end,
+            }),
+        }
+    }
+    files.retain(|file| {
+        maps.iter()
+            .any(|e| e.path.as_ref() == Some(&file.path) && e.perms.contains('x'))
+    });
+    files
+}
+
+// 每行格式: start-end perms offset dev inode [path]
+fn parse_line(line: &str) -> Option<MapEntry> {
+    let mut fields = line.split_whitespace();
+    let mut range = fields.next()?.split('-');
+    let start = usize::from_str_radix(range.next()?, 16).ok()?;
+    let end = usize::from_str_radix(range.next()?, 16).ok()?;
+    let perms = fields.next()?.to_string();
+    let _offset = fields.next()?;
+    let _dev = fields.next()?;
+    let _inode = fields.next()?;
+    let path = fields.collect::<Vec<&str>>().join(" ");
+    Some(MapEntry {
+        start,
+        end,
+        perms,
+        path: if path.is_empty() { None } else { Some(path) },
+    })
+}
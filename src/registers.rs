@@ -0,0 +1,88 @@
+//! 按名字读写 PTRACE_GETREGS 得到的寄存器快照（`info registers`、`set reg`、表达式中的 `$rax`）。
+//!
+//! 名字与 gdb 相同；`pc`、`sp`、`fp` 是 rip、rsp、rbp 的别名。读写都经过 `slot` 中的同一张表，
+//! 新增寄存器时只需要改这一处。
+
+use nix::libc::user_regs_struct;
+
+/// `info registers` 显示的寄存器，按显示的顺序
+pub const DISPLAYED: &[&str] = &[
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es", "fs", "gs", "fs_base", "gs_base",
+];
+
+/// eflags 中 `info registers` 显示的标志位
+const FLAGS: &[(u64, &str)] = &[
+    (1 << 0, "CF"),
+    (1 << 2, "PF"),
+    (1 << 4, "AF"),
+    (1 << 6, "ZF"),
+    (1 << 7, "SF"),
+    (1 << 8, "TF"),
+    (1 << 9, "IF"),
+    (1 << 10, "DF"),
+    (1 << 11, "OF"),
+];
+
+/// 名字对应的字段，不认识的名字返回 None
+fn slot<'a>(regs: &'a mut user_regs_struct, name: &str) -> Option<&'a mut u64> {
+    Some(match name {
+        "rax" => &mut regs.rax,
+        "rbx" => &mut regs.rbx,
+        "rcx" => &mut regs.rcx,
+        "rdx" => &mut regs.rdx,
+        "rsi" => &mut regs.rsi,
+        "rdi" => &mut regs.rdi,
+        "rbp" | "fp" => &mut regs.rbp,
+        "rsp" | "sp" => &mut regs.rsp,
+        "r8" => &mut regs.r8,
+        "r9" => &mut regs.r9,
+        "r10" => &mut regs.r10,
+        "r11" => &mut regs.r11,
+        "r12" => &mut regs.r12,
+        "r13" => &mut regs.r13,
+        "r14" => &mut regs.r14,
+        "r15" => &mut regs.r15,
+        "rip" | "pc" => &mut regs.rip,
+        "eflags" => &mut regs.eflags,
+        "cs" => &mut regs.cs,
+        "ss" => &mut regs.ss,
+        "ds" => &mut regs.ds,
+        "es" => &mut regs.es,
+        "fs" => &mut regs.fs,
+        "gs" => &mut regs.gs,
+        "fs_base" => &mut regs.fs_base,
+        "gs_base" => &mut regs.gs_base,
+        _ => return None,
+    })
+}
+
+pub fn is_register(name: &str) -> bool {
+    DISPLAYED.contains(&name) || matches!(name, "pc" | "sp" | "fp")
+}
+
+pub fn get(regs: &user_regs_struct, name: &str) -> Option<u64> {
+    let mut copy = *regs;
+    slot(&mut copy, name).map(|value| *value)
+}
+
+/// 修改快照中名为 name 的寄存器，不认识的名字返回 false
+pub fn set(regs: &mut user_regs_struct, name: &str, value: u64) -> bool {
+    match slot(regs, name) {
+        Some(slot) => {
+            *slot = value;
+            true
+        }
+        None => false,
+    }
+}
+
+/// 与 gdb 相同的 eflags 显示：`[ ZF PF IF ]`
+pub fn format_eflags(eflags: u64) -> String {
+    let set: Vec<&str> = FLAGS
+        .iter()
+        .filter(|(bit, _)| eflags & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    format!("[ {} ]", set.join(" "))
+}
@@ -0,0 +1,458 @@
+//! 通过 GDB 远程串行协议（RSP）控制被调试进程，用于在 Valgrind 的 gdbserver 下调试。
+//!
+//! Valgrind 以 `--vgdb=yes` 启动目标程序后，由 `vgdb` 在标准输入输出上转发协议数据包。
+//! 寄存器、内存和停止事件都换成对应的数据包（`g` / `G`、`m` / `M`、`c` / `s` 与停止应答），
+//! 因此 `Inferior` 的回溯等逻辑可以原样使用。Memcheck 发现错误时 gdbserver 会以 SIGTRAP 报告停止。
+
+use crate::backend::{Backend, SigInfo};
+use crate::inferior::{LaunchOptions, Status};
+
+use nix::errno::Errno;
+use nix::libc::{user_fpregs_struct, user_regs_struct};
+use nix::sys::signal::Signal;
+use nix::sys::wait::WaitPidFlag;
+use nix::unistd::Pid;
+use std::cell::RefCell;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// GDB 协议中的信号编号与 Linux 信号的对应关系（两者在 7 以后不一致）
+const GDB_SIGNALS: &[(u8, Signal)] = &[
+    (1, Signal::SIGHUP),
+    (2, Signal::SIGINT),
+    (3, Signal::SIGQUIT),
+    (4, Signal::SIGILL),
+    (5, Signal::SIGTRAP),
+    (6, Signal::SIGABRT),
+    (8, Signal::SIGFPE),
+    (9, Signal::SIGKILL),
+    (10, Signal::SIGBUS),
+    (11, Signal::SIGSEGV),
+    (12, Signal::SIGSYS),
+    (13, Signal::SIGPIPE),
+    (14, Signal::SIGALRM),
+    (15, Signal::SIGTERM),
+    (16, Signal::SIGURG),
+    (17, Signal::SIGSTOP),
+    (18, Signal::SIGTSTP),
+    (19, Signal::SIGCONT),
+    (20, Signal::SIGCHLD),
+    (21, Signal::SIGTTIN),
+    (22, Signal::SIGTTOU),
+    (23, Signal::SIGIO),
+    (24, Signal::SIGXCPU),
+    (25, Signal::SIGXFSZ),
+    (26, Signal::SIGVTALRM),
+    (27, Signal::SIGPROF),
+    (28, Signal::SIGWINCH),
+    (30, Signal::SIGUSR1),
+    (31, Signal::SIGUSR2),
+];
+
+/// `g` 应答中 rax..rip 这 17 个 8 字节寄存器之后是 eflags 和 6 个段寄存器，各 4 字节
+const GPR_COUNT: usize = 17;
+/// 段寄存器之后是 st0-st7（各 10 字节）、8 个 4 字节的 x87 控制寄存器，然后是 xmm0-xmm15（各 16 字节）
+const ST_START: usize = 16 * GPR_COUNT + 8 * 7;
+const XMM_START: usize = ST_START + 2 * (8 * 10 + 8 * 4);
+
+fn protocol_error() -> nix::Error {
+    nix::Error::Sys(Errno::EIO)
+}
+
+fn signal_from_gdb(number: u8) -> Option<Signal> {
+    GDB_SIGNALS
+        .iter()
+        .find(|(n, _)| *n == number)
+        .map(|(_, signal)| *signal)
+}
+
+fn signal_to_gdb(signal: Signal) -> Option<u8> {
+    GDB_SIGNALS
+        .iter()
+        .find(|(_, s)| *s == signal)
+        .map(|(n, _)| *n)
+}
+
+/// 解析小端序的十六进制字节串；gdbserver 用 `xx` 表示取不到的寄存器，按 0 处理
+fn parse_le_hex(hex: &str) -> Option<u64> {
+    let mut value = 0u64;
+    for (i, pair) in hex.as_bytes().chunks(2).enumerate() {
+        let pair = std::str::from_utf8(pair).ok()?;
+        let byte = if pair == "xx" {
+            0
+        } else {
+            u8::from_str_radix(pair, 16).ok()?
+        };
+        value |= (byte as u64) << (8 * i);
+    }
+    Some(value)
+}
+
+fn to_le_hex(value: u64, bytes: usize) -> String {
+    (0..bytes)
+        .map(|i| format!("{:02x}", (value >> (8 * i)) as u8))
+        .collect()
+}
+
+/// 与 gdbserver 之间的数据包收发
+struct Connection {
+    reader: BufReader<ChildStdout>,
+    writer: ChildStdin,
+}
+
+pub struct RemoteBackend {
+    valgrind: Child,
+    vgdb: Child,
+    /// 读内存和寄存器的接口只拿到 &self，但收发数据包需要可变访问
+    conn: RefCell<Connection>,
+    /// 最近一次停止应答中的信号（Linux 编号）
+    last_signal: i32,
+}
+
+impl RemoteBackend {
+    /// 在 Valgrind 下启动 target，并通过 vgdb 连接它内置的 gdbserver。
+    /// `--vgdb-error=0` 让程序在第一条指令前停下，之后每个 Memcheck 错误都会停止一次
+    pub fn launch_valgrind(
+        target: &str,
+        args: &[String],
+        cwd: Option<&Path>,
+        launch: &LaunchOptions,
+    ) -> Result<RemoteBackend, String> {
+        let mut cmd = Command::new("valgrind");
+        if let Some(cwd) = cwd {
+            cmd.current_dir(cwd);
+        }
+        // Valgrind 把 LD_PRELOAD 原样交给被调试程序，排在它自己的 vgpreload 库之后；
+        // CPU 绑定和调度策略由 Valgrind 进程继承
+        launch.apply(&mut cmd);
+        // Valgrind 和被调试程序继承调试器的标准输出；输出被管道重定向时会让管道无法结束，
+        // 所以 `target valgrind` 和 `run` 一样不接受 ` | `
+        let mut valgrind = cmd
+            .arg("--vgdb=yes")
+            .arg("--vgdb-error=0")
+            .arg(target)
+            .args(args)
+            .spawn()
+            .map_err(|e| format!("failed to start valgrind: {}", e))?;
+        let vgdb = Command::new("vgdb")
+            .arg(format!("--pid={}", valgrind.id()))
+            // vgdb 需要等 Valgrind 的 gdbserver 准备好
+            .arg("--wait=10")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn();
+        let mut vgdb = match vgdb {
+            Ok(vgdb) => vgdb,
+            Err(e) => {
+                let _ = valgrind.kill();
+                let _ = valgrind.wait();
+                return Err(format!("failed to start vgdb: {}", e));
+            }
+        };
+        let conn = Connection {
+            reader: BufReader::new(vgdb.stdout.take().unwrap()),
+            writer: vgdb.stdin.take().unwrap(),
+        };
+        let backend = RemoteBackend {
+            valgrind,
+            vgdb,
+            conn: RefCell::new(conn),
+            last_signal: 0,
+        };
+        // `?` 的应答就是当前的停止原因，留给第一次 wait 读取
+        backend
+            .conn
+            .borrow_mut()
+            .send("?")
+            .map_err(|e| format!("failed to connect to valgrind gdbserver: {}", e))?;
+        Ok(backend)
+    }
+
+    fn request(&self, data: &str) -> Result<String, nix::Error> {
+        self.conn.borrow_mut().request(data)
+    }
+
+    fn expect_ok(&self, data: &str) -> Result<(), nix::Error> {
+        match self.request(data)?.as_str() {
+            "OK" => Ok(()),
+            _ => Err(nix::Error::Sys(Errno::EFAULT)),
+        }
+    }
+
+    /// 发送 `c` / `s`，需要投递信号时换成 `C` / `S`
+    fn resume(&mut self, command: char, signal: Option<Signal>) -> Result<(), nix::Error> {
+        let packet = match signal {
+            Some(signal) => {
+                let number = signal_to_gdb(signal).ok_or(nix::Error::Sys(Errno::EINVAL))?;
+                format!("{}{:02x}", command.to_ascii_uppercase(), number)
+            }
+            None => command.to_string(),
+        };
+        self.conn.borrow_mut().send(&packet)
+    }
+}
+
+impl Connection {
+    fn read_byte(&mut self) -> Result<u8, nix::Error> {
+        let mut byte = [0u8; 1];
+        match self.reader.read(&mut byte) {
+            Ok(1) => Ok(byte[0]),
+            _ => Err(protocol_error()),
+        }
+    }
+
+    /// 发送一个数据包并等待对方确认（`+`），收到 `-` 时重发
+    fn send(&mut self, data: &str) -> Result<(), nix::Error> {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        let packet = format!("${}#{:02x}", data, checksum);
+        loop {
+            self.writer
+                .write_all(packet.as_bytes())
+                .and_then(|_| self.writer.flush())
+                .map_err(|_| protocol_error())?;
+            match self.read_byte()? {
+                b'+' => return Ok(()),
+                b'-' => continue,
+                _ => return Err(protocol_error()),
+            }
+        }
+    }
+
+    /// 接收一个数据包并回复确认，返回去掉转义和游程编码后的内容
+    fn recv(&mut self) -> Result<String, nix::Error> {
+        loop {
+            while self.read_byte()? != b'$' {}
+            let mut raw = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => raw.push(byte),
+                }
+            }
+            let expected = [self.read_byte()?, self.read_byte()?];
+            let expected = std::str::from_utf8(&expected)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            let checksum = raw.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+            if expected != Some(checksum) {
+                self.writer.write_all(b"-").map_err(|_| protocol_error())?;
+                continue;
+            }
+            self.writer.write_all(b"+").map_err(|_| protocol_error())?;
+
+            let mut data = Vec::new();
+            let mut bytes = raw.into_iter();
+            while let Some(byte) = bytes.next() {
+                match byte {
+                    b'}' => data.push(bytes.next().ok_or_else(protocol_error)? ^ 0x20),
+                    // `X*n`：把上一个字符再重复 n - 29 次
+                    b'*' => {
+                        let count = bytes.next().ok_or_else(protocol_error)?;
+                        let last = *data.last().ok_or_else(protocol_error)?;
+                        for _ in 0..count.saturating_sub(29) {
+                            data.push(last);
+                        }
+                    }
+                    byte => data.push(byte),
+                }
+            }
+            return String::from_utf8(data).map_err(|_| protocol_error());
+        }
+    }
+
+    fn request(&mut self, data: &str) -> Result<String, nix::Error> {
+        self.send(data)?;
+        self.recv()
+    }
+}
+
+impl Backend for RemoteBackend {
+    /// Valgrind 在自己的进程里模拟执行目标程序，因此这也是目标程序的 pid
+    fn pid(&self) -> Pid {
+        Pid::from_raw(self.valgrind.id() as i32)
+    }
+
+    fn cont(&mut self, signal: Option<Signal>) -> Result<(), nix::Error> {
+        self.resume('c', signal)
+    }
+
+    fn step(&mut self, signal: Option<Signal>) -> Result<(), nix::Error> {
+        self.resume('s', signal)
+    }
+
+    /// 远程协议没有停在系统调用上的恢复方式
+    fn syscall(&mut self, _signal: Option<Signal>) -> Result<(), nix::Error> {
+        Err(nix::Error::Sys(Errno::EOPNOTSUPP))
+    }
+
+    fn wait(&mut self, _options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
+        loop {
+            let reply = self.conn.borrow_mut().recv()?;
+            let code = reply
+                .get(1..3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match (reply.chars().next(), code) {
+                (Some('S'), Some(number)) | (Some('T'), Some(number)) => {
+                    let signal = signal_from_gdb(number).ok_or(nix::Error::Sys(Errno::EINVAL))?;
+                    self.last_signal = signal as i32;
+                    let rip = self.getregs()?.rip as usize;
+                    return Ok(Status::Stopped(signal, rip));
+                }
+                (Some('W'), Some(exit_code)) => return Ok(Status::Exited(exit_code as i32)),
+                (Some('X'), Some(number)) => {
+                    let signal = signal_from_gdb(number).ok_or(nix::Error::Sys(Errno::EINVAL))?;
+                    return Ok(Status::Signaled(signal));
+                }
+                // 运行期间 gdbserver 转发的控制台输出
+                (Some('O'), _) => {
+                    let text: Vec<u8> = reply.as_bytes()[1..]
+                        .chunks(2)
+                        .filter_map(|pair| std::str::from_utf8(pair).ok())
+                        .filter_map(|pair| u8::from_str_radix(pair, 16).ok())
+                        .collect();
+                    print!("{}", String::from_utf8_lossy(&text));
+                }
+                _ => return Err(protocol_error()),
+            }
+        }
+    }
+
+    fn kill(&mut self) -> Result<(), std::io::Error> {
+        let _ = self.conn.borrow_mut().send("k");
+        let _ = self.vgdb.kill();
+        let _ = self.vgdb.wait();
+        self.valgrind.kill()?;
+        self.valgrind.wait().map(|_| ())
+    }
+
+    /// 进程运行在 Valgrind 的模拟器里，离开 gdbserver 之后也不能脱离 Valgrind 继续运行
+    fn detach(&mut self) -> Result<(), nix::Error> {
+        Err(nix::Error::Sys(Errno::EOPNOTSUPP))
+    }
+
+    fn read_word(&self, addr: usize) -> Result<u64, nix::Error> {
+        let reply = self.request(&format!("m{:x},8", addr))?;
+        if reply.len() != 16 {
+            // `Exx`：地址不可读
+            return Err(nix::Error::Sys(Errno::EFAULT));
+        }
+        parse_le_hex(&reply).ok_or_else(protocol_error)
+    }
+
+    fn write_word(&mut self, addr: usize, word: u64) -> Result<(), nix::Error> {
+        self.expect_ok(&format!("M{:x},8:{}", addr, to_le_hex(word, 8)))
+    }
+
+    fn getregs(&self) -> Result<user_regs_struct, nix::Error> {
+        let block = self.request("g")?;
+        let field = |offset: usize, bytes: usize| {
+            block
+                .get(offset..offset + 2 * bytes)
+                .and_then(parse_le_hex)
+                .ok_or_else(protocol_error)
+        };
+        let gpr = |index: usize| field(16 * index, 8);
+        let segment = |index: usize| field(16 * GPR_COUNT + 8 * (index + 1), 4);
+        // user_regs_struct 只包含整数字段，全 0 是合法的值
+        let mut regs: user_regs_struct = unsafe { std::mem::zeroed() };
+        regs.rax = gpr(0)?;
+        regs.rbx = gpr(1)?;
+        regs.rcx = gpr(2)?;
+        regs.rdx = gpr(3)?;
+        regs.rsi = gpr(4)?;
+        regs.rdi = gpr(5)?;
+        regs.rbp = gpr(6)?;
+        regs.rsp = gpr(7)?;
+        regs.r8 = gpr(8)?;
+        regs.r9 = gpr(9)?;
+        regs.r10 = gpr(10)?;
+        regs.r11 = gpr(11)?;
+        regs.r12 = gpr(12)?;
+        regs.r13 = gpr(13)?;
+        regs.r14 = gpr(14)?;
+        regs.r15 = gpr(15)?;
+        regs.rip = gpr(16)?;
+        regs.eflags = field(16 * GPR_COUNT, 4)?;
+        regs.cs = segment(0)?;
+        regs.ss = segment(1)?;
+        regs.ds = segment(2)?;
+        regs.es = segment(3)?;
+        regs.fs = segment(4)?;
+        regs.gs = segment(5)?;
+        regs.orig_rax = u64::MAX;
+        Ok(regs)
+    }
+
+    fn setregs(&mut self, regs: user_regs_struct) -> Result<(), nix::Error> {
+        // `G` 需要完整的寄存器块，先取回当前值再替换通用寄存器部分
+        let mut block = self.request("g")?;
+        let values = [
+            regs.rax, regs.rbx, regs.rcx, regs.rdx, regs.rsi, regs.rdi, regs.rbp, regs.rsp,
+            regs.r8, regs.r9, regs.r10, regs.r11, regs.r12, regs.r13, regs.r14, regs.r15, regs.rip,
+        ];
+        let mut prefix: String = values.iter().map(|v| to_le_hex(*v, 8)).collect();
+        prefix.push_str(&to_le_hex(regs.eflags, 4));
+        if block.len() < prefix.len() {
+            return Err(protocol_error());
+        }
+        block.replace_range(..prefix.len(), &prefix);
+        self.expect_ok(&format!("G{}", block))
+    }
+
+    /// 远程协议只报告信号编号，没有 si_code 和出错地址
+    fn getfpregs(&self) -> Result<user_fpregs_struct, nix::Error> {
+        let block = self.request("g")?;
+        let bytes = |offset: usize, len: usize| -> Result<Vec<u8>, nix::Error> {
+            let hex = block
+                .get(offset..offset + 2 * len)
+                .ok_or_else(protocol_error)?;
+            hex.as_bytes()
+                .chunks(2)
+                .map(|pair| parse_le_hex(std::str::from_utf8(pair).unwrap_or("")).map(|b| b as u8))
+                .collect::<Option<Vec<u8>>>()
+                .ok_or_else(protocol_error)
+        };
+        // user_fpregs_struct 只包含整数字段，全 0 是合法的值
+        let mut regs: user_fpregs_struct = unsafe { std::mem::zeroed() };
+        // st_space 和 xmm_space 中每个寄存器占 16 字节
+        let store = |space: &mut [u32], index: usize, value: &[u8]| {
+            for (i, byte) in value.iter().enumerate() {
+                let word = &mut space[index * 4 + i / 4];
+                *word |= (*byte as u32) << (8 * (i % 4));
+            }
+        };
+        for index in 0..8 {
+            store(
+                &mut regs.st_space,
+                index,
+                &bytes(ST_START + 20 * index, 10)?,
+            );
+        }
+        for index in 0..16 {
+            store(
+                &mut regs.xmm_space,
+                index,
+                &bytes(XMM_START + 32 * index, 16)?,
+            );
+        }
+        Ok(regs)
+    }
+
+    fn siginfo(&self) -> Result<SigInfo, nix::Error> {
+        Ok(SigInfo {
+            signo: self.last_signal,
+            code: 0,
+            addr: 0,
+        })
+    }
+
+    /// Valgrind 模拟执行目标程序，没有可用的调试寄存器
+    fn debug_register(&self, _index: usize) -> Result<u64, nix::Error> {
+        Err(nix::Error::Sys(Errno::EOPNOTSUPP))
+    }
+
+    fn set_debug_register(&mut self, _index: usize, _value: u64) -> Result<(), nix::Error> {
+        Err(nix::Error::Sys(Errno::EOPNOTSUPP))
+    }
+}
@@ -0,0 +1,95 @@
+//! `compare-runs`：记录两次运行的行为并找出差异。
+//!
+//! 每次运行记录依次命中的断点位置、每次停下时观察的变量值，以及最终结果（退出码或导致停止的信号）。
+//! 两份记录交给 LLM 总结；没有可用的 LLM 时按结构逐项对比。
+
+/// 运行中的一次断点停止
+pub struct StopRecord {
+    pub location: String,
+    /// `print` 格式的变量值，读取失败时是错误信息
+    pub values: Vec<String>,
+}
+
+pub struct RunRecord {
+    pub args: Vec<String>,
+    pub stops: Vec<StopRecord>,
+    pub outcome: String,
+}
+
+impl RunRecord {
+    pub fn new(args: &[String]) -> RunRecord {
+        RunRecord {
+            args: args.to_vec(),
+            stops: Vec::new(),
+            outcome: String::new(),
+        }
+    }
+
+    /// 给 LLM 阅读的文本形式
+    pub fn transcript(&self) -> String {
+        let mut text = format!("args: {}\n", self.args.join(" "));
+        for (i, stop) in self.stops.iter().enumerate() {
+            text.push_str(&format!("stop {}: {}\n", i + 1, stop.location));
+            for value in &stop.values {
+                text.push_str(&format!("    {}\n", value));
+            }
+        }
+        text.push_str(&format!("result: {}\n", self.outcome));
+        text
+    }
+}
+
+/// 按结构对比两次运行：停止序列第一次分叉的位置、相同停止处不同的变量值、结果是否相同
+pub fn structural_diff(first: &RunRecord, second: &RunRecord) -> Vec<String> {
+    let mut differences = Vec::new();
+    let common = first
+        .stops
+        .iter()
+        .zip(&second.stops)
+        .take_while(|(a, b)| a.location == b.location)
+        .count();
+
+    for (i, (a, b)) in first
+        .stops
+        .iter()
+        .zip(&second.stops)
+        .take(common)
+        .enumerate()
+    {
+        for (value_a, value_b) in a.values.iter().zip(&b.values) {
+            if value_a != value_b {
+                differences.push(format!(
+                    "stop {} at {}: A has {}, B has {}",
+                    i + 1,
+                    a.location,
+                    value_a,
+                    value_b
+                ));
+            }
+        }
+    }
+
+    if common < first.stops.len() || common < second.stops.len() {
+        let describe = |run: &RunRecord| match run.stops.get(common) {
+            Some(stop) => format!("stops at {}", stop.location),
+            None => "has no more stops".to_string(),
+        };
+        differences.push(format!(
+            "runs diverge after {} common stop(s): A {}, B {}",
+            common,
+            describe(first),
+            describe(second)
+        ));
+    }
+    if first.stops.len() != second.stops.len() {
+        differences.push(format!(
+            "A stopped {} time(s), B stopped {} time(s)",
+            first.stops.len(),
+            second.stops.len()
+        ));
+    }
+    if first.outcome != second.outcome {
+        differences.push(format!("A {}, B {}", first.outcome, second.outcome));
+    }
+    differences
+}
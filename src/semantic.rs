@@ -0,0 +1,298 @@
+//! 本地的函数语义索引：`find-func <描述>`，以及没有 LLM 配置时的 `nb`。
+//!
+//! 每个函数的文本由函数名（按 `_` 和驼峰拆成单词）、参数名和源文件中紧挨着函数定义之前的注释
+//! （一行摘要）组成。向量用 fastText 式的子词哈希：每个单词和它的字符三元组哈希到固定维数的
+//! 向量中再归一化，查询与函数按余弦相似度排序，拼写不完全一致（`parse` 和 `parser`）时也能匹配。
+//! 中文描述先用一张小的词表翻译成常见的英文标识符单词，其余的汉字按相邻两个字切分，
+//! 可以匹配中文注释。整个过程不需要模型文件，也不访问网络。
+
+use crate::dwarf_data::DwarfData;
+use std::collections::HashMap;
+use std::fs;
+
+/// 向量的维数
+const DIMENSIONS: usize = 512;
+/// 相似度低于它的函数视为不相关；子词哈希的碰撞会让无关的文本也有很小的相似度
+const MIN_SIMILARITY: f32 = 0.1;
+/// 读取函数定义之前最多几行注释作为摘要
+const SUMMARY_LINES: usize = 3;
+
+/// 不参与匹配的常见词
+const STOP_WORDS: &[&str] = &[
+    "a",
+    "an",
+    "the",
+    "of",
+    "in",
+    "at",
+    "on",
+    "to",
+    "for",
+    "and",
+    "or",
+    "is",
+    "that",
+    "which",
+    "where",
+    "when",
+    "function",
+    "func",
+    "break",
+    "breakpoint",
+    "代码",
+    "函数",
+    "地方",
+    "断点",
+    "的",
+    "在",
+    "打",
+    "设",
+];
+
+/// 中文描述中的常见词和对应的标识符单词
+const SYNONYMS: &[(&str, &[&str])] = &[
+    ("崩溃", &["crash", "abort", "panic", "fault"]),
+    ("错误", &["error", "err", "fail"]),
+    ("失败", &["fail", "error"]),
+    ("解析", &["parse", "parser"]),
+    ("读取", &["read", "load"]),
+    ("加载", &["load", "read"]),
+    ("写入", &["write", "save", "store"]),
+    ("保存", &["save", "store", "write"]),
+    ("初始化", &["init", "setup", "create"]),
+    ("创建", &["create", "new", "alloc"]),
+    ("分配", &["alloc", "malloc", "new"]),
+    ("释放", &["free", "release", "destroy"]),
+    ("销毁", &["destroy", "free", "cleanup"]),
+    ("打开", &["open"]),
+    ("关闭", &["close"]),
+    ("连接", &["connect", "conn"]),
+    ("发送", &["send", "write"]),
+    ("接收", &["recv", "receive", "read"]),
+    ("排序", &["sort"]),
+    ("查找", &["find", "search", "lookup"]),
+    ("搜索", &["search", "find"]),
+    ("计算", &["compute", "calc", "sum"]),
+    ("求和", &["sum", "add", "total"]),
+    ("打印", &["print", "show", "dump"]),
+    ("输出", &["print", "output", "write"]),
+    ("输入", &["input", "read"]),
+    ("处理", &["handle", "process"]),
+    ("检查", &["check", "validate", "verify"]),
+    ("校验", &["validate", "verify", "check"]),
+    ("比较", &["compare", "cmp"]),
+    ("复制", &["copy", "clone", "dup"]),
+    ("删除", &["delete", "remove"]),
+    ("插入", &["insert", "add", "push"]),
+    ("更新", &["update", "set"]),
+    ("交换", &["swap"]),
+    ("递归", &["recursive", "recurse"]),
+    ("循环", &["loop", "iterate"]),
+    ("配置", &["config", "conf", "settings"]),
+    ("文件", &["file"]),
+    ("内存", &["memory", "mem", "alloc"]),
+    ("缓冲区", &["buffer", "buf"]),
+    ("字符串", &["string", "str"]),
+    ("数组", &["array", "arr"]),
+    ("链表", &["list", "node"]),
+    ("节点", &["node"]),
+    ("树", &["tree", "node"]),
+    ("哈希", &["hash"]),
+    ("队列", &["queue"]),
+    ("栈", &["stack"]),
+    ("线程", &["thread"]),
+    ("信号", &["signal"]),
+    ("退出", &["exit", "quit"]),
+    ("主函数", &["main"]),
+    ("入口", &["main", "entry", "start"]),
+];
+
+/// 一个函数在索引中的条目
+pub struct Entry {
+    pub name: String,
+    pub file: String,
+    pub line: usize,
+    /// 函数定义之前的注释，没有时为空
+    pub summary: String,
+    vector: Vec<f32>,
+}
+
+pub struct SymbolIndex {
+    entries: Vec<Entry>,
+}
+
+impl SymbolIndex {
+    /// 为目标程序中所有有代码的函数建立索引
+    pub fn build(debug_data: &DwarfData) -> SymbolIndex {
+        let mut sources: HashMap<String, Option<Vec<String>>> = HashMap::new();
+        let mut entries = Vec::new();
+        for file in debug_data.files() {
+            for func in file.functions.iter().filter(|func| func.address != 0) {
+                let lines = sources.entry(file.name.clone()).or_insert_with(|| {
+                    fs::read_to_string(&file.name)
+                        .ok()
+                        .map(|source| source.lines().map(str::to_string).collect())
+                });
+                let summary = lines
+                    .as_ref()
+                    .map(|lines| comment_before(lines, func.line_number))
+                    .unwrap_or_default();
+                let mut text = identifier_words(&func.name).join(" ");
+                for variable in &func.variables {
+                    text.push(' ');
+                    text.push_str(&identifier_words(&variable.name).join(" "));
+                }
+                text.push(' ');
+                text.push_str(&summary);
+                entries.push(Entry {
+                    name: func.name.clone(),
+                    file: file.name.clone(),
+                    line: func.line_number,
+                    summary,
+                    vector: embed(&text),
+                });
+            }
+        }
+        SymbolIndex { entries }
+    }
+
+    /// 与 query 最相似的 count 个函数和相似度（0 到 1），不相关的不返回
+    pub fn search(&self, query: &str, count: usize) -> Vec<(&Entry, f32)> {
+        let query = embed(query);
+        let mut scored: Vec<(&Entry, f32)> = self
+            .entries
+            .iter()
+            .map(|entry| (entry, cosine(&query, &entry.vector)))
+            .filter(|(_, score)| *score >= MIN_SIMILARITY)
+            .collect();
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        scored.truncate(count);
+        scored
+    }
+}
+
+/// 紧挨着第 line 行（从 1 开始）之前的 `//` 或 `/* */` 注释，去掉注释符号后拼成一行
+fn comment_before(lines: &[String], line: usize) -> String {
+    let mut comment = Vec::new();
+    let mut index = line.saturating_sub(1);
+    while index > 0 && comment.len() < SUMMARY_LINES {
+        index -= 1;
+        let text = lines.get(index).map(|text| text.trim()).unwrap_or_default();
+        let is_comment = text.starts_with("//")
+            || text.starts_with("/*")
+            || text.starts_with('*')
+            || text.ends_with("*/");
+        if !is_comment {
+            break;
+        }
+        let text = text
+            .trim_start_matches('/')
+            .trim_start_matches('*')
+            .trim_end_matches('/')
+            .trim_end_matches('*')
+            .trim();
+        if !text.is_empty() {
+            comment.push(text.to_string());
+        }
+    }
+    comment.reverse();
+    comment.join(" ")
+}
+
+/// 把标识符按 `_` 和驼峰拆成小写单词：`parseConfigFile` -> parse config file
+fn identifier_words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if !c.is_alphanumeric() {
+            prev_lower = false;
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        if c.is_uppercase() && prev_lower && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// 把一段描述切成单词：英文按标识符拆分，中文先替换词表中的词，其余汉字按相邻两个字切分
+fn words(text: &str) -> Vec<String> {
+    let mut text = text.to_string();
+    let mut words = Vec::new();
+    for (chinese, english) in SYNONYMS {
+        if text.contains(chinese) {
+            words.extend(english.iter().map(|word| word.to_string()));
+            text = text.replace(chinese, " ");
+        }
+    }
+    for stop_word in STOP_WORDS.iter().filter(|word| !word.is_ascii()) {
+        text = text.replace(stop_word, " ");
+    }
+    // 末尾补一个空格，让最后一段连续的汉字也被切分
+    let mut han = Vec::new();
+    for c in text.chars().chain(std::iter::once(' ')) {
+        if is_han(c) {
+            han.push(c);
+            continue;
+        }
+        if han.len() == 1 {
+            words.push(han[0].to_string());
+        }
+        words.extend(han.windows(2).map(|pair| pair.iter().collect::<String>()));
+        han.clear();
+    }
+    let ascii: String = text
+        .chars()
+        .map(|c| if is_han(c) { ' ' } else { c })
+        .collect();
+    for token in ascii.split_whitespace() {
+        words.extend(identifier_words(token));
+    }
+    words.retain(|word| !STOP_WORDS.contains(&word.as_str()));
+    words
+}
+
+fn is_han(c: char) -> bool {
+    ('\u{4e00}'..='\u{9fff}').contains(&c)
+}
+
+/// 子词哈希向量：每个单词计 1，单词（带边界标记）的每个字符三元组计 0.5，最后归一化
+fn embed(text: &str) -> Vec<f32> {
+    let mut vector = vec![0f32; DIMENSIONS];
+    for word in words(text) {
+        vector[bucket(&word)] += 1.0;
+        let chars: Vec<char> = format!("<{}>", word).chars().collect();
+        for trigram in chars.windows(3) {
+            vector[bucket(&trigram.iter().collect::<String>())] += 0.5;
+        }
+    }
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+    vector
+}
+
+/// FNV-1a 哈希到向量的某一维
+fn bucket(feature: &str) -> usize {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in feature.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    (hash % DIMENSIONS as u64) as usize
+}
+
+/// 两个已归一化向量的余弦相似度
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
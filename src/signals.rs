@@ -0,0 +1,158 @@
+//! 被调试进程收到信号时的处理策略（`handle <signal> stop|nostop|print|noprint|pass|nopass`）。
+//!
+//! 每个信号有三个开关：是否停下来交给用户（stop）、是否打印提示（print）、继续运行时是否把信号
+//! 转交给进程（pass）。与 gdb 相同，默认停下、打印并在继续运行时转交；SIGINT（Ctrl+C）和
+//! SIGSTOP 是用来打断进程的，默认不转交。SIGWINCH、SIGPIPE、SIGCHLD 这类常见且无害的信号默认
+//! 静默转交，不会打断 `continue`。
+
+use nix::libc;
+use nix::sys::signal::Signal;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SignalPolicy {
+    pub stop: bool,
+    pub print: bool,
+    pub pass: bool,
+}
+
+/// 没有单独配置的信号：停下、打印，继续运行时转交给进程
+const DEFAULT_POLICY: SignalPolicy = SignalPolicy {
+    stop: true,
+    print: true,
+    pass: true,
+};
+
+/// 静默转交给进程、不打断调试的信号
+const QUIET_POLICY: SignalPolicy = SignalPolicy {
+    stop: false,
+    print: false,
+    pass: true,
+};
+
+/// 用来打断进程的信号：停下，但不转交
+const INTERRUPT_POLICY: SignalPolicy = SignalPolicy {
+    stop: true,
+    print: true,
+    pass: false,
+};
+
+/// `handle` 命令中的一个动作
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignalAction {
+    Stop,
+    NoStop,
+    Print,
+    NoPrint,
+    Pass,
+    NoPass,
+}
+
+impl SignalAction {
+    /// `ignore` / `noignore` 与 gdb 相同，是 `nopass` / `pass` 的别名
+    pub fn parse(word: &str) -> Option<SignalAction> {
+        Some(match word {
+            "stop" => SignalAction::Stop,
+            "nostop" => SignalAction::NoStop,
+            "print" => SignalAction::Print,
+            "noprint" => SignalAction::NoPrint,
+            "pass" | "noignore" => SignalAction::Pass,
+            "nopass" | "ignore" => SignalAction::NoPass,
+            _ => return None,
+        })
+    }
+}
+
+/// 按名字（`SIGSEGV`、`segv`）或编号（`11`）解析信号
+pub fn parse_signal(name: &str) -> Option<Signal> {
+    if let Ok(number) = name.parse::<i32>() {
+        return Signal::try_from(number).ok();
+    }
+    let name = name.to_uppercase();
+    if name.starts_with("SIG") {
+        Signal::from_str(&name).ok()
+    } else {
+        Signal::from_str(&format!("SIG{}", name)).ok()
+    }
+}
+
+/// libc 对信号的描述，例如 SIGSEGV 的 "Segmentation fault"
+fn describe(signal: Signal) -> String {
+    let description = unsafe { libc::strsignal(signal as libc::c_int) };
+    if description.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(description) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+pub struct SignalTable {
+    policies: HashMap<Signal, SignalPolicy>,
+}
+
+impl SignalTable {
+    pub fn new() -> SignalTable {
+        let mut policies = HashMap::new();
+        for signal in &[Signal::SIGWINCH, Signal::SIGPIPE, Signal::SIGCHLD] {
+            policies.insert(*signal, QUIET_POLICY);
+        }
+        for signal in &[Signal::SIGINT, Signal::SIGSTOP] {
+            policies.insert(*signal, INTERRUPT_POLICY);
+        }
+        SignalTable { policies }
+    }
+
+    pub fn policy(&self, signal: Signal) -> SignalPolicy {
+        // SIGTRAP 是断点和单步使用的信号，必须停下，也不能交给进程
+        if signal == Signal::SIGTRAP {
+            return INTERRUPT_POLICY;
+        }
+        self.policies
+            .get(&signal)
+            .copied()
+            .unwrap_or(DEFAULT_POLICY)
+    }
+
+    /// 按顺序执行 actions。与 gdb 相同，`stop` 意味着 `print`，`noprint` 意味着 `nostop`
+    pub fn apply(&mut self, signal: Signal, actions: &[SignalAction]) {
+        let mut policy = self.policy(signal);
+        for action in actions {
+            match action {
+                SignalAction::Stop => {
+                    policy.stop = true;
+                    policy.print = true;
+                }
+                SignalAction::NoStop => policy.stop = false,
+                SignalAction::Print => policy.print = true,
+                SignalAction::NoPrint => {
+                    policy.print = false;
+                    policy.stop = false;
+                }
+                SignalAction::Pass => policy.pass = true,
+                SignalAction::NoPass => policy.pass = false,
+            }
+        }
+        self.policies.insert(signal, policy);
+    }
+
+    /// 按 gdb `info signals` 的格式列出 signals 的策略
+    pub fn print(&self, signals: &[Signal]) {
+        let yes_no = |on: bool| if on { "Yes" } else { "No" };
+        println!("Signal        Stop\tPrint\tPass to program\tDescription");
+        for signal in signals {
+            let policy = self.policy(*signal);
+            println!(
+                "{:<14}{}\t{}\t{}\t\t{}",
+                signal.as_str(),
+                yes_no(policy.stop),
+                yes_no(policy.print),
+                yes_no(policy.pass),
+                describe(*signal)
+            );
+        }
+    }
+}
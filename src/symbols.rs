@@ -0,0 +1,521 @@
+//! 共享库符号索引。
+//!
+//! 进程停下时根据 /proc/<pid>/maps 记录每个已加载库的地址范围、装载偏移和 build-id；
+//! 第一次需要符号化某个库内的地址时，才去 /usr/lib/debug/.build-id/ 下查找对应的调试文件并加载，
+//! 找不到调试文件时退回到库自身的 ELF 符号表，至少能显示函数名。
+//!
+//! 位于匿名可执行映射中的地址（JIT 生成的代码）则查询 JIT 写出的 /tmp/perf-<pid>.map。
+//!
+//! 内核映射进每个进程的 vDSO（`clock_gettime`、`gettimeofday` 等）不对应磁盘上的文件，
+//! 它的 ELF 映像从被调试进程的内存中读出，之后与普通的库一样符号化和按 .eh_frame 回溯。
+//! 旧的 vsyscall 页没有符号表，按内核固定的入口位置识别。
+
+use crate::cfi::{CallFrameInfo, UnwindRegs};
+use crate::dwarf_data::{DwarfData, Line};
+use crate::proc_maps::{self, MapEntry};
+use nix::unistd::Pid;
+use object::{Object, ObjectSection, ObjectSegment, SectionKind, SymbolKind};
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+const DEBUG_BUILD_ID_DIR: &str = "/usr/lib/debug/.build-id";
+
+/// vsyscall 页中各入口相对页首的偏移，由内核 ABI 固定
+const VSYSCALL_ENTRIES: &[(usize, &str)] =
+    &[(0x0, "gettimeofday"), (0x400, "time"), (0x800, "getcpu")];
+/// vsyscall 页中每个入口占的字节数
+const VSYSCALL_ENTRY_SIZE: usize = 0x400;
+
+/// 某个库内地址的符号化结果
+#[derive(Debug, Clone)]
+pub struct FrameSymbol {
+    pub function: String,
+    pub line: Option<Line>,
+    pub library: String,
+}
+
+impl fmt::Display for FrameSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.line {
+            Some(line) => write!(f, "{}: {} ({})", self.function, line, self.library),
+            None => write!(f, "{} ({})", self.function, self.library),
+        }
+    }
+}
+
+/// 库的符号信息，首次使用时才加载
+struct LibrarySymbols {
+    debug_file: Option<String>,
+    dwarf: Option<DwarfData>,
+    /// ELF 符号表中的函数 (地址, 大小, 名字)，按地址排序
+    functions: Vec<(u64, u64, String)>,
+}
+
+pub struct SharedLibrary {
+    pub path: String,
+    pub start: usize,
+    pub end: usize,
+    /// 运行时地址减去文件中记录的虚拟地址
+    pub load_bias: usize,
+    pub build_id: Option<String>,
+    /// 不对应磁盘文件的 ELF 映像（vDSO），符号表和 CFI 从这里读取
+    image: Option<Vec<u8>>,
+    symbols: Option<LibrarySymbols>,
+    /// .eh_frame，首次需要时读取；内层 None 表示该库没有 CFI
+    cfi: Option<Option<CallFrameInfo>>,
+}
+
+impl SharedLibrary {
+    fn from_mapping(path: &str, start: usize, end: usize) -> Option<SharedLibrary> {
+        let file = fs::File::open(path).ok()?;
+        let mmap = unsafe { memmap::Mmap::map(&file).ok()? };
+        let object = object::File::parse(&mmap).ok()?;
+        Some(SharedLibrary::new(path, start, end, &object))
+    }
+
+    /// 从被调试进程的内存中读出的映像，path 是 /proc/<pid>/maps 中的名字（`[vdso]`）
+    fn from_image(path: &str, start: usize, end: usize, image: Vec<u8>) -> Option<SharedLibrary> {
+        let object = object::File::parse(&image).ok()?;
+        let library = SharedLibrary::new(path, start, end, &object);
+        Some(SharedLibrary {
+            image: Some(image),
+            ..library
+        })
+    }
+
+    fn new(path: &str, start: usize, end: usize, object: &object::File) -> SharedLibrary {
+        let build_id = object
+            .build_id()
+            .map(|id| id.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+        SharedLibrary {
+            path: path.to_string(),
+            start,
+            end,
+            load_bias: image_bias(object, start),
+            build_id,
+            image: None,
+            symbols: None,
+            cfi: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        Path::new(&self.path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(&self.path)
+    }
+
+    /// build-id 对应的独立调试文件路径（不保证存在）
+    pub fn debug_file_path(&self) -> Option<String> {
+        let id = self.build_id.as_ref()?;
+        if id.len() < 3 {
+            return None;
+        }
+        Some(format!(
+            "{}/{}/{}.debug",
+            DEBUG_BUILD_ID_DIR,
+            &id[..2],
+            &id[2..]
+        ))
+    }
+
+    /// 调试信息的加载状态，供 `info sharedlibrary` 显示
+    pub fn symbols_status(&self) -> String {
+        match &self.symbols {
+            None => "(not loaded)".to_string(),
+            Some(LibrarySymbols {
+                debug_file: Some(path),
+                dwarf: Some(_),
+                ..
+            }) => path.clone(),
+            Some(symbols) if !symbols.functions.is_empty() => "(ELF symbols only)".to_string(),
+            Some(_) => "(no symbols)".to_string(),
+        }
+    }
+
+    fn load_symbols(&mut self) -> &LibrarySymbols {
+        if self.symbols.is_none() {
+            let debug_file = self.debug_file_path().filter(|p| Path::new(p).exists());
+            let dwarf = debug_file
+                .as_ref()
+                .and_then(|p| DwarfData::from_debug_file(p).ok());
+            self.symbols = Some(LibrarySymbols {
+                debug_file,
+                dwarf,
+                functions: function_symbols(match &self.image {
+                    Some(image) => text_symbols(image),
+                    None => read_text_symbols(&self.path),
+                }),
+            });
+        }
+        self.symbols.as_ref().unwrap()
+    }
+
+    fn load_cfi(&mut self) -> Option<&CallFrameInfo> {
+        if self.cfi.is_none() {
+            self.cfi = Some(match &self.image {
+                Some(image) => CallFrameInfo::from_image(image),
+                None => CallFrameInfo::from_file(&self.path),
+            });
+        }
+        self.cfi.as_ref().unwrap().as_ref()
+    }
+
+    /// 文件地址 file_addr 所在的函数，先查调试信息，再查 ELF 符号表
+    fn function_at(&mut self, file_addr: usize) -> Option<String> {
+        let symbols = self.load_symbols();
+        if let Some(function) = symbols
+            .dwarf
+            .as_ref()
+            .and_then(|dwarf| dwarf.get_function_from_addr(file_addr))
+        {
+            return Some(function);
+        }
+        symbols
+            .functions
+            .iter()
+            .find(|(start, size, _)| {
+                file_addr as u64 >= *start && (file_addr as u64) < start + size
+            })
+            .map(|(_, _, name)| name.clone())
+    }
+
+    /// vDSO 中 addr 所在函数的名字。导出的符号有时只是一条跳到内部实现的 `jmp`，内部实现没有符号，
+    /// 这时按 .eh_frame 找到 addr 所在函数的起点，再找跳到这个起点的导出符号
+    fn vdso_function(&mut self, addr: usize) -> Option<String> {
+        let file_addr = addr - self.load_bias;
+        if let Some(function) = self.function_at(file_addr) {
+            return Some(function);
+        }
+        let start = self.load_cfi()?.function_start(file_addr as u64)?;
+        let image = self.image.as_ref()?;
+        // 映像从 self.start 开始，文件地址在映像中的偏移
+        let offset =
+            |file_addr: u64| (file_addr as usize + self.load_bias).wrapping_sub(self.start);
+        self.symbols
+            .as_ref()?
+            .functions
+            .iter()
+            .find(|(symbol, _, _)| jump_target(image, offset(*symbol)) == Some(offset(start)))
+            .map(|(_, _, name)| name.clone())
+    }
+
+    /// 根据 CFI 判断 addr 是否位于信号处理返回跳板中；库没有 CFI 时返回 None
+    fn is_signal_trampoline(&mut self, addr: usize) -> Option<bool> {
+        let file_addr = (addr - self.load_bias) as u64;
+        Some(self.load_cfi()?.is_signal_frame(file_addr))
+    }
+
+    fn symbolize(&mut self, addr: usize) -> Option<FrameSymbol> {
+        let file_addr = addr - self.load_bias;
+        let library = self.name().to_string();
+        let line = self
+            .load_symbols()
+            .dwarf
+            .as_ref()
+            .and_then(|dwarf| dwarf.get_line_from_addr(file_addr));
+        let function = self.function_at(file_addr);
+        Some(FrameSymbol {
+            function: function.unwrap_or_else(|| format!("{:#x}", addr)),
+            line,
+            library,
+        })
+    }
+}
+
+/// 代码符号中有大小的函数，按地址排序
+fn function_symbols(symbols: Vec<(u64, u64, String)>) -> Vec<(u64, u64, String)> {
+    let mut functions: Vec<(u64, u64, String)> = symbols
+        .into_iter()
+        .filter(|(_, size, _)| *size > 0)
+        .collect();
+    functions.sort();
+    functions.dedup_by_key(|(addr, _, _)| *addr);
+    functions
+}
+
+/// .symtab 和 .dynsym 中所有代码符号的 (地址, 大小, 名字)，包括汇编中没有 `.size` 或 `.type` 的标签
+fn read_text_symbols(path: &str) -> Vec<(u64, u64, String)> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(_) => return Vec::new(),
+    };
+    match unsafe { memmap::Mmap::map(&file) } {
+        Ok(mmap) => text_symbols(&mmap),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// 与 read_text_symbols 相同，ELF 映像已经在内存中
+fn text_symbols(image: &[u8]) -> Vec<(u64, u64, String)> {
+    let object = match object::File::parse(image) {
+        Ok(object) => object,
+        Err(_) => return Vec::new(),
+    };
+    object
+        .symbols()
+        .chain(object.dynamic_symbols())
+        .filter(|(_, sym)| {
+            // 汇编中没有 `.type @function` 的标签是 STT_NOTYPE，按所在的节判断是否为代码
+            let in_text = || {
+                sym.section_index()
+                    .and_then(|index| object.section_by_index(index))
+                    .map(|section| section.kind() == SectionKind::Text)
+                    .unwrap_or(false)
+            };
+            sym.address() != 0
+                && (sym.kind() == SymbolKind::Text
+                    || sym.kind() == SymbolKind::Unknown && in_text())
+        })
+        .filter_map(|(_, sym)| Some((sym.address(), sym.size(), sym.name()?.to_string())))
+        .collect()
+}
+
+/// ELF 头被映射到 start 时文件的加载偏移：运行时地址减去文件中的虚拟地址
+fn image_bias(object: &object::File, start: usize) -> usize {
+    let min_vaddr = object.segments().map(|s| s.address()).min().unwrap_or(0) as usize;
+    start.wrapping_sub(min_vaddr & !0xfff)
+}
+
+/// path 从 start 开始映射时的加载偏移。非 PIE 的可执行文件按链接地址加载，偏移为 0
+pub fn load_bias(path: &str, start: usize) -> Option<usize> {
+    let file = fs::File::open(path).ok()?;
+    let mmap = unsafe { memmap::Mmap::map(&file).ok()? };
+    let object = object::File::parse(&mmap).ok()?;
+    Some(image_bias(&object, start))
+}
+
+/// 按原始（未 demangle 的）名字在 path 的 ELF 符号表中查找代码符号的地址
+pub fn lookup_text_symbol(path: &str, name: &str) -> Option<usize> {
+    read_text_symbols(path)
+        .into_iter()
+        .find(|(_, _, symbol)| symbol == name)
+        .map(|(addr, _, _)| addr as usize)
+}
+
+/// perf map 文件中的 JIT 符号，每行格式为 `START SIZE name`（十六进制）
+struct PerfMap {
+    entries: Vec<(usize, usize, String)>,
+}
+
+impl PerfMap {
+    fn load(pid: Pid) -> Option<PerfMap> {
+        let contents = fs::read_to_string(format!("/tmp/perf-{}.map", pid)).ok()?;
+        let entries = contents
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, ' ');
+                let start = parse_hex(fields.next()?)?;
+                let size = parse_hex(fields.next()?)?;
+                Some((start, size, fields.next()?.trim().to_string()))
+            })
+            .collect();
+        Some(PerfMap { entries })
+    }
+
+    /// JIT 可能在同一地址重新生成代码，后写入的条目优先
+    fn lookup(&self, addr: usize) -> Option<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(start, size, _)| addr >= *start && addr < start + size)
+            .map(|(_, _, name)| name.as_str())
+    }
+}
+
+fn parse_hex(s: &str) -> Option<usize> {
+    let s = s.trim_start_matches("0x").trim_start_matches("0X");
+    usize::from_str_radix(s, 16).ok()
+}
+
+/// 当前进程中所有已加载的共享库
+pub struct SharedLibraries {
+    libraries: Vec<SharedLibrary>,
+    /// 匿名可执行映射的地址范围
+    jit_ranges: Vec<(usize, usize)>,
+    perf_map: Option<PerfMap>,
+    /// 从进程内存读出的 vDSO，不在 libraries 中（`info sharedlibrary` 不列出它）
+    vdso: Option<SharedLibrary>,
+    /// vsyscall 页的地址范围
+    vsyscall: Option<(usize, usize)>,
+}
+
+impl SharedLibraries {
+    pub fn new() -> SharedLibraries {
+        SharedLibraries {
+            libraries: Vec::new(),
+            jit_ranges: Vec::new(),
+            perf_map: None,
+            vdso: None,
+            vsyscall: None,
+        }
+    }
+
+    pub fn libraries(&self) -> &[SharedLibrary] {
+        &self.libraries
+    }
+
+    pub fn clear(&mut self) {
+        self.libraries.clear();
+        self.jit_ranges.clear();
+        self.perf_map = None;
+        self.vdso = None;
+        self.vsyscall = None;
+    }
+
+    /// 根据最新的内存映射更新索引：新出现的库读取 build-id，已卸载的库移除。
+    /// 目标程序本身由 DwarfData 负责，不放进索引。JIT 会不断追加 perf map，所以每次都重新读取
+    pub fn refresh(&mut self, pid: Pid, maps: &[MapEntry], target: &str) {
+        self.jit_ranges = proc_maps::anonymous_executable(maps);
+        self.perf_map = PerfMap::load(pid);
+        self.vsyscall = proc_maps::special_mapping(maps, "[vsyscall]");
+        match proc_maps::special_mapping(maps, "[vdso]") {
+            Some((start, _)) if self.vdso.as_ref().map(|vdso| vdso.start) == Some(start) => {}
+            Some((start, end)) => self.vdso = read_vdso(pid, start, end),
+            None => self.vdso = None,
+        }
+        let target = fs::canonicalize(target).ok();
+        let mapped = proc_maps::mapped_files(maps);
+        self.libraries.retain(|lib| {
+            mapped
+                .iter()
+                .any(|m| m.path == lib.path && m.start == lib.start)
+        });
+        for file in mapped {
+            if target.as_deref() == Some(Path::new(&file.path)) {
+                continue;
+            }
+            if self
+                .libraries
+                .iter()
+                .any(|lib| lib.path == file.path && lib.start == file.start)
+            {
+                continue;
+            }
+            if let Some(lib) = SharedLibrary::from_mapping(&file.path, file.start, file.end) {
+                self.libraries.push(lib);
+            }
+        }
+    }
+
+    /// 按名字在已加载的共享库中查找函数的运行时地址。不查动态链接器自己的符号表：
+    /// 它带有 malloc 等函数的私有实现，程序调用的是其它库中的版本
+    pub fn lookup_function(&self, name: &str) -> Option<usize> {
+        self.libraries
+            .iter()
+            .filter(|lib| !lib.path.contains("/ld-linux"))
+            .find_map(|lib| lookup_text_symbol(&lib.path, name).map(|addr| lib.start + addr))
+    }
+
+    /// addr 所在的库，包括 vDSO
+    fn containing(&mut self, addr: usize) -> Option<&mut SharedLibrary> {
+        self.libraries
+            .iter_mut()
+            .chain(self.vdso.as_mut())
+            .find(|lib| addr >= lib.start && addr < lib.end)
+    }
+
+    /// addr 位于 vDSO 或 vsyscall 页中时返回 (区域名, 函数名)，例如 `("vdso", "clock_gettime")`
+    pub fn kernel_stub(&mut self, addr: usize) -> Option<(&'static str, String)> {
+        if let Some((start, end)) = self.vsyscall {
+            if addr >= start && addr < end {
+                let offset = (addr - start) / VSYSCALL_ENTRY_SIZE * VSYSCALL_ENTRY_SIZE;
+                let name = VSYSCALL_ENTRIES
+                    .iter()
+                    .find(|(entry, _)| *entry == offset)
+                    .map(|(_, name)| name.to_string())
+                    .unwrap_or_else(|| format!("{:#x}", addr));
+                return Some(("vsyscall", name));
+            }
+        }
+        let vdso = self.vdso.as_mut()?;
+        if addr < vdso.start || addr >= vdso.end {
+            return None;
+        }
+        // vDSO 同时导出 `clock_gettime` 和 `__vdso_clock_gettime`，显示不带前缀的名字
+        let name = match vdso.vdso_function(addr) {
+            Some(function) => function.trim_start_matches("__vdso_").to_string(),
+            None => format!("{:#x}", addr),
+        };
+        Some(("vdso", name))
+    }
+
+    /// 符号化共享库或 JIT 代码中的地址，两者都不是时返回 None
+    pub fn symbolize(&mut self, addr: usize) -> Option<FrameSymbol> {
+        if let Some(lib) = self.containing(addr) {
+            return lib.symbolize(addr);
+        }
+        if self
+            .jit_ranges
+            .iter()
+            .any(|(start, end)| addr >= *start && addr < *end)
+        {
+            let name = self.perf_map.as_ref().and_then(|map| map.lookup(addr));
+            return Some(FrameSymbol {
+                function: name
+                    .map(|n| n.to_string())
+                    .unwrap_or_else(|| format!("{:#x}", addr)),
+                line: None,
+                library: "JIT".to_string(),
+            });
+        }
+        None
+    }
+
+    /// 判断 addr 是否位于某个库的信号处理返回跳板中，无法判断时返回 None
+    pub fn is_signal_trampoline(&mut self, addr: usize) -> Option<bool> {
+        self.containing(addr)?.is_signal_trampoline(addr)
+    }
+
+    /// 用 pc 所在库的 CFI 计算调用者寄存器，见 `CallFrameInfo::unwind`
+    pub fn unwind<E, F>(
+        &mut self,
+        pc: usize,
+        regs: &UnwindRegs,
+        read_word: &F,
+    ) -> Result<Option<UnwindRegs>, E>
+    where
+        F: Fn(usize) -> Result<u64, E>,
+    {
+        if let Some((start, end)) = self.vsyscall {
+            // vsyscall 入口只是 `mov $nr, %rax; syscall; ret`，返回地址位于栈顶
+            if pc >= start && pc < end {
+                return Ok(Some(UnwindRegs {
+                    rip: read_word(regs.rsp as usize)?,
+                    rsp: regs.rsp + 8,
+                    rbp: regs.rbp,
+                }));
+            }
+        }
+        let lib = match self.containing(pc) {
+            Some(lib) => lib,
+            None => return Ok(None),
+        };
+        let file_pc = (pc - lib.load_bias) as u64;
+        match lib.load_cfi() {
+            Some(cfi) => cfi.unwind(file_pc, regs, read_word),
+            None => Ok(None),
+        }
+    }
+}
+
+/// 映像中 offset 处是 `jmp rel32` 时返回跳转目标在映像中的偏移
+fn jump_target(image: &[u8], offset: usize) -> Option<usize> {
+    let bytes = image.get(offset..offset + 5)?;
+    if bytes[0] != 0xe9 {
+        return None;
+    }
+    let rel = i32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+    Some((offset + 5).wrapping_add(rel as isize as usize))
+}
+
+/// 从 /proc/<pid>/mem 读出映射在 [start, end) 的 vDSO 映像
+fn read_vdso(pid: Pid, start: usize, end: usize) -> Option<SharedLibrary> {
+    let mem = fs::File::open(format!("/proc/{}/mem", pid)).ok()?;
+    let mut image = vec![0u8; end - start];
+    mem.read_exact_at(&mut image, start as u64).ok()?;
+    SharedLibrary::from_image("[vdso]", start, end, image)
+}
@@ -0,0 +1,400 @@
+//! `trace syscalls`：像 strace 一样记录被调试进程的每次系统调用。
+//!
+//! 进程用 PTRACE_SYSCALL 恢复运行，在每次系统调用的入口和出口各停一次。入口处 rax 为 -ENOSYS，
+//! 此时按调用号解码参数（路径、写出的缓冲区、标志位）；出口处得到返回值，再解码进程读入的缓冲区，
+//! 然后输出一行 `openat(AT_FDCWD, "/etc/hosts", O_RDONLY|O_CLOEXEC) = 3`。只认识 x86_64 的调用号，
+//! 表中没有的调用按编号显示六个原始参数。
+
+use nix::errno::Errno;
+use nix::libc::user_regs_struct;
+use std::fs::File;
+use std::io::Write;
+
+/// 缓冲区最多显示的字节数
+const PREVIEW_BYTES: usize = 32;
+/// 路径最多读取的字节数
+const MAX_PATH: usize = 4096;
+const ENOSYS: i64 = 38;
+const AT_FDCWD: i64 = -100;
+
+/// 参数的解码方式
+#[derive(Clone, Copy)]
+enum Arg {
+    Int,
+    Hex,
+    /// 文件描述符
+    Fd,
+    /// openat 等调用的目录描述符，AT_FDCWD 显示为名字
+    DirFd,
+    /// 以 NUL 结尾的路径
+    Path,
+    /// 进程写出的缓冲区，长度是第 n 个参数
+    BufIn(usize),
+    /// 进程读入的缓冲区，长度是返回值
+    BufOut,
+    OpenFlags,
+    /// 八进制的权限位
+    Mode,
+    Prot,
+    MmapFlags,
+}
+
+use Arg::*;
+
+/// (调用号, 名字, 参数)
+const SYSCALLS: &[(u64, &str, &[Arg])] = &[
+    (0, "read", &[Fd, BufOut, Int]),
+    (1, "write", &[Fd, BufIn(2), Int]),
+    (2, "open", &[Path, OpenFlags, Mode]),
+    (3, "close", &[Fd]),
+    (4, "stat", &[Path, Hex]),
+    (5, "fstat", &[Fd, Hex]),
+    (6, "lstat", &[Path, Hex]),
+    (7, "poll", &[Hex, Int, Int]),
+    (8, "lseek", &[Fd, Int, Int]),
+    (9, "mmap", &[Hex, Int, Prot, MmapFlags, Fd, Hex]),
+    (10, "mprotect", &[Hex, Int, Prot]),
+    (11, "munmap", &[Hex, Int]),
+    (12, "brk", &[Hex]),
+    (13, "rt_sigaction", &[Int, Hex, Hex, Int]),
+    (14, "rt_sigprocmask", &[Int, Hex, Hex, Int]),
+    (16, "ioctl", &[Fd, Hex, Hex]),
+    (17, "pread64", &[Fd, BufOut, Int, Int]),
+    (18, "pwrite64", &[Fd, BufIn(2), Int, Int]),
+    (19, "readv", &[Fd, Hex, Int]),
+    (20, "writev", &[Fd, Hex, Int]),
+    (21, "access", &[Path, Int]),
+    (22, "pipe", &[Hex]),
+    (24, "sched_yield", &[]),
+    (28, "madvise", &[Hex, Int, Int]),
+    (32, "dup", &[Fd]),
+    (33, "dup2", &[Fd, Fd]),
+    (35, "nanosleep", &[Hex, Hex]),
+    (39, "getpid", &[]),
+    (41, "socket", &[Int, Int, Int]),
+    (42, "connect", &[Fd, Hex, Int]),
+    (43, "accept", &[Fd, Hex, Hex]),
+    (44, "sendto", &[Fd, BufIn(2), Int, Hex, Hex, Int]),
+    (45, "recvfrom", &[Fd, BufOut, Int, Hex, Hex, Hex]),
+    (49, "bind", &[Fd, Hex, Int]),
+    (50, "listen", &[Fd, Int]),
+    (56, "clone", &[Hex, Hex, Hex, Hex, Hex]),
+    (57, "fork", &[]),
+    (58, "vfork", &[]),
+    (59, "execve", &[Path, Hex, Hex]),
+    (60, "exit", &[Int]),
+    (61, "wait4", &[Int, Hex, Int, Hex]),
+    (62, "kill", &[Int, Int]),
+    (63, "uname", &[Hex]),
+    (72, "fcntl", &[Fd, Int, Hex]),
+    (74, "fsync", &[Fd]),
+    (77, "ftruncate", &[Fd, Int]),
+    (79, "getcwd", &[Hex, Int]),
+    (80, "chdir", &[Path]),
+    (82, "rename", &[Path, Path]),
+    (83, "mkdir", &[Path, Mode]),
+    (84, "rmdir", &[Path]),
+    (87, "unlink", &[Path]),
+    (89, "readlink", &[Path, BufOut, Int]),
+    (90, "chmod", &[Path, Mode]),
+    (96, "gettimeofday", &[Hex, Hex]),
+    (97, "getrlimit", &[Int, Hex]),
+    (102, "getuid", &[]),
+    (104, "getgid", &[]),
+    (107, "geteuid", &[]),
+    (108, "getegid", &[]),
+    (110, "getppid", &[]),
+    (158, "arch_prctl", &[Hex, Hex]),
+    (186, "gettid", &[]),
+    (201, "time", &[Hex]),
+    (202, "futex", &[Hex, Int, Int, Hex, Hex, Int]),
+    (217, "getdents64", &[Fd, Hex, Int]),
+    (218, "set_tid_address", &[Hex]),
+    (228, "clock_gettime", &[Int, Hex]),
+    (230, "clock_nanosleep", &[Int, Int, Hex, Hex]),
+    (231, "exit_group", &[Int]),
+    (257, "openat", &[DirFd, Path, OpenFlags, Mode]),
+    (262, "newfstatat", &[DirFd, Path, Hex, Hex]),
+    (263, "unlinkat", &[DirFd, Path, Int]),
+    (273, "set_robust_list", &[Hex, Int]),
+    (293, "pipe2", &[Hex, OpenFlags]),
+    (302, "prlimit64", &[Int, Int, Hex, Hex]),
+    (318, "getrandom", &[Hex, Int, Int]),
+    (332, "statx", &[DirFd, Path, Hex, Hex, Hex]),
+    (334, "rseq", &[Hex, Int, Int, Hex]),
+    (435, "clone3", &[Hex, Int]),
+];
+
+const OPEN_FLAGS: &[(u64, &str)] = &[
+    (0o100, "O_CREAT"),
+    (0o200, "O_EXCL"),
+    (0o400, "O_NOCTTY"),
+    (0o1000, "O_TRUNC"),
+    (0o2000, "O_APPEND"),
+    (0o4000, "O_NONBLOCK"),
+    (0o200000, "O_DIRECTORY"),
+    (0o400000, "O_NOFOLLOW"),
+    (0o2000000, "O_CLOEXEC"),
+];
+
+const PROT_FLAGS: &[(u64, &str)] = &[(1, "PROT_READ"), (2, "PROT_WRITE"), (4, "PROT_EXEC")];
+
+const MMAP_FLAGS: &[(u64, &str)] = &[
+    (0x1, "MAP_SHARED"),
+    (0x2, "MAP_PRIVATE"),
+    (0x10, "MAP_FIXED"),
+    (0x20, "MAP_ANONYMOUS"),
+    (0x800, "MAP_DENYWRITE"),
+    (0x4000, "MAP_NORESERVE"),
+    (0x8000, "MAP_POPULATE"),
+    (0x20000, "MAP_STACK"),
+];
+
+/// name 是否是表中的调用名，或表外调用的 `syscall_<n>` 写法
+pub fn is_known(name: &str) -> bool {
+    SYSCALLS.iter().any(|(_, n, _)| *n == name)
+        || name
+            .strip_prefix("syscall_")
+            .is_some_and(|number| number.parse::<u64>().is_ok())
+}
+
+/// 调用号对应的名字，表中没有时为 `syscall_<n>`
+fn syscall_name(number: u64) -> String {
+    match SYSCALLS.iter().find(|(n, _, _)| *n == number) {
+        Some((_, name, _)) => name.to_string(),
+        None => format!("syscall_{}", number),
+    }
+}
+
+/// 把标志位显示为 `A|B|0x..`，none 是值为 0 时的名字
+fn format_flags(value: u64, flags: &[(u64, &str)], mut names: Vec<String>, none: &str) -> String {
+    let mut rest = value;
+    for (bit, name) in flags {
+        if rest & bit != 0 {
+            names.push(name.to_string());
+            rest &= !bit;
+        }
+    }
+    if rest != 0 {
+        names.push(format!("{:#x}", rest));
+    }
+    if names.is_empty() {
+        none.to_string()
+    } else {
+        names.join("|")
+    }
+}
+
+/// 按 C 字符串字面量的写法转义
+fn escape(bytes: &[u8]) -> String {
+    let mut text = String::from("\"");
+    for byte in bytes {
+        match byte {
+            b'\n' => text.push_str("\\n"),
+            b'\t' => text.push_str("\\t"),
+            b'\r' => text.push_str("\\r"),
+            b'"' => text.push_str("\\\""),
+            b'\\' => text.push_str("\\\\"),
+            0x20..=0x7e => text.push(*byte as char),
+            _ => text.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    text.push('"');
+    text
+}
+
+/// 缓冲区的前 PREVIEW_BYTES 个字节，total 超过它时后面显示 `...`
+fn format_buffer(bytes: &[u8], total: usize) -> String {
+    let mut text = escape(&bytes[..bytes.len().min(PREVIEW_BYTES)]);
+    if total > PREVIEW_BYTES {
+        text.push_str("...");
+    }
+    text
+}
+
+/// 返回值；-4095 到 -1 是 errno
+fn format_return(value: u64) -> String {
+    let signed = value as i64;
+    if (-4095..0).contains(&signed) {
+        let errno = Errno::from_i32(-signed as i32);
+        format!("-1 {:?} ({})", errno, errno.desc())
+    } else if signed < 0 || value > 0xffff_ffff {
+        format!("{:#x}", value)
+    } else {
+        signed.to_string()
+    }
+}
+
+/// 一次系统调用的入口，等出口时补全
+struct PendingCall {
+    number: u64,
+    args: [u64; 6],
+    /// 入口处已经解码的参数；进程读入的缓冲区要等出口时才有内容，先为 None
+    decoded: Vec<Option<String>>,
+}
+
+pub struct SyscallTracer {
+    /// 只记录这些调用；为空时记录全部
+    filter: Vec<String>,
+    output: Option<File>,
+    pending: Option<PendingCall>,
+}
+
+impl SyscallTracer {
+    /// filter 为空时记录全部调用；output 为 None 时输出到控制台
+    pub fn new(filter: Vec<String>, output: Option<File>) -> SyscallTracer {
+        SyscallTracer {
+            filter,
+            output,
+            pending: None,
+        }
+    }
+
+    fn emit(&mut self, line: &str) {
+        match self.output.as_mut() {
+            Some(file) => {
+                let _ = writeln!(file, "{}", line);
+            }
+            None => println!("{}", line),
+        }
+    }
+
+    /// 在一次系统调用的入口或出口停下；read_memory 读取被调试进程的内存
+    pub fn on_stop<F>(&mut self, regs: &user_regs_struct, read_memory: F)
+    where
+        F: Fn(usize, usize) -> Option<Vec<u8>>,
+    {
+        let number = regs.orig_rax;
+        let name = syscall_name(number);
+        if !self.filter.is_empty() && !self.filter.contains(&name) {
+            self.pending = None;
+            return;
+        }
+        let entry = regs.rax as i64 == -ENOSYS;
+        if entry {
+            let args = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
+            let kinds = arg_kinds(number);
+            let decoded = kinds
+                .iter()
+                .enumerate()
+                .map(|(i, kind)| match kind {
+                    BufOut => None,
+                    kind => Some(decode(*kind, args[i], &args, &read_memory)),
+                })
+                .collect();
+            let call = PendingCall {
+                number,
+                args,
+                decoded,
+            };
+            // 这两个调用不会返回
+            if number == 60 || number == 231 {
+                let line = format!("{}({}) = ?", name, join_args(&call, None, &read_memory));
+                self.emit(&line);
+            } else {
+                self.pending = Some(call);
+            }
+            return;
+        }
+        // 开始跟踪时进程可能正在一次系统调用中，这时只有出口没有入口
+        let call = match self.pending.take() {
+            Some(call) if call.number == number => call,
+            _ => return,
+        };
+        let line = format!(
+            "{}({}) = {}",
+            name,
+            join_args(&call, Some(regs.rax), &read_memory),
+            format_return(regs.rax)
+        );
+        self.emit(&line);
+    }
+}
+
+fn arg_kinds(number: u64) -> &'static [Arg] {
+    match SYSCALLS.iter().find(|(n, _, _)| *n == number) {
+        Some((_, _, args)) => args,
+        None => &[Hex, Hex, Hex, Hex, Hex, Hex],
+    }
+}
+
+/// 拼出参数列表；ret 为出口处的返回值，用于解码进程读入的缓冲区
+fn join_args<F>(call: &PendingCall, ret: Option<u64>, read_memory: &F) -> String
+where
+    F: Fn(usize, usize) -> Option<Vec<u8>>,
+{
+    call.decoded
+        .iter()
+        .enumerate()
+        .map(|(i, decoded)| match decoded {
+            Some(text) => text.clone(),
+            None => match ret.map(|ret| ret as i64) {
+                Some(len) if len >= 0 => {
+                    let len = len as usize;
+                    match read_memory(call.args[i] as usize, len.min(PREVIEW_BYTES)) {
+                        Some(bytes) => format_buffer(&bytes, len),
+                        None => format!("{:#x}", call.args[i]),
+                    }
+                }
+                _ => format!("{:#x}", call.args[i]),
+            },
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn decode<F>(kind: Arg, value: u64, args: &[u64; 6], read_memory: &F) -> String
+where
+    F: Fn(usize, usize) -> Option<Vec<u8>>,
+{
+    match kind {
+        Int => (value as i64).to_string(),
+        Hex => format!("{:#x}", value),
+        DirFd if value as i32 as i64 == AT_FDCWD => "AT_FDCWD".to_string(),
+        // 描述符是 int，高 32 位可能是残留的垃圾
+        Fd | DirFd => (value as i32).to_string(),
+        Path => match read_c_string(value as usize, read_memory) {
+            Some(path) => escape(&path),
+            None => format!("{:#x}", value),
+        },
+        BufIn(len_arg) => {
+            let len = args[len_arg] as usize;
+            match read_memory(value as usize, len.min(PREVIEW_BYTES)) {
+                Some(bytes) => format_buffer(&bytes, len),
+                None => format!("{:#x}", value),
+            }
+        }
+        BufOut => format!("{:#x}", value),
+        OpenFlags => {
+            let access = match value & 0o3 {
+                0 => "O_RDONLY",
+                1 => "O_WRONLY",
+                2 => "O_RDWR",
+                _ => "O_ACCMODE",
+            };
+            format_flags(value & !0o3, OPEN_FLAGS, vec![access.to_string()], "")
+        }
+        Mode => format!("{:#o}", value),
+        Prot => format_flags(value, PROT_FLAGS, Vec::new(), "PROT_NONE"),
+        MmapFlags => format_flags(value, MMAP_FLAGS, Vec::new(), "0"),
+    }
+}
+
+/// 按 word 读取以 NUL 结尾的字符串，最多 MAX_PATH 字节
+fn read_c_string<F>(addr: usize, read_memory: &F) -> Option<Vec<u8>>
+where
+    F: Fn(usize, usize) -> Option<Vec<u8>>,
+{
+    let mut bytes = Vec::new();
+    while bytes.len() < MAX_PATH {
+        let chunk = read_memory(addr + bytes.len(), 8)?;
+        match chunk.iter().position(|byte| *byte == 0) {
+            Some(end) => {
+                bytes.extend_from_slice(&chunk[..end]);
+                return Some(bytes);
+            }
+            None => bytes.extend_from_slice(&chunk),
+        }
+    }
+    Some(bytes)
+}
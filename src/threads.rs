@@ -0,0 +1,81 @@
+//! 从 /proc/<pid>/task 读取被调试进程的线程：名字、内核状态和当前指令地址（`info threads`）。
+//!
+//! 调试器只跟踪它启动的那个线程，其它线程不受它控制，因此它们的指令地址只能从
+//! /proc/<pid>/task/<tid>/syscall 得到：线程阻塞在系统调用中时这里有用户态的 pc，正在运行时没有。
+
+use nix::unistd::Pid;
+use std::fs;
+
+pub struct ThreadInfo {
+    pub tid: i32,
+    /// /proc/<pid>/task/<tid>/comm，没有用 pthread_setname_np 设置时是程序名
+    pub name: String,
+    /// /proc/<pid>/task/<tid>/stat 中的状态字母
+    pub state: char,
+    /// 线程阻塞在系统调用中时的用户态指令地址
+    pub pc: Option<usize>,
+}
+
+impl ThreadInfo {
+    /// 状态字母的含义
+    pub fn state_description(&self) -> &'static str {
+        match self.state {
+            'R' => "running",
+            'S' => "sleeping",
+            'D' => "disk sleep",
+            'T' => "stopped",
+            't' => "tracing stop",
+            'Z' => "zombie",
+            'X' => "dead",
+            'I' => "idle",
+            _ => "unknown",
+        }
+    }
+}
+
+/// 列出进程的所有线程，按线程号排序。线程在读取期间退出时跳过它
+pub fn read_threads(pid: Pid) -> Result<Vec<ThreadInfo>, std::io::Error> {
+    let mut threads = Vec::new();
+    for entry in fs::read_dir(format!("/proc/{}/task", pid))? {
+        let tid: i32 = match entry?
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(tid) => tid,
+            None => continue,
+        };
+        let dir = format!("/proc/{}/task/{}", pid, tid);
+        let (name, stat) = match (
+            fs::read_to_string(format!("{}/comm", dir)),
+            fs::read_to_string(format!("{}/stat", dir)),
+        ) {
+            (Ok(name), Ok(stat)) => (name, stat),
+            _ => continue,
+        };
+        // 名字可能包含空格和括号，状态在最后一个 `)` 之后
+        let state = stat
+            .rfind(')')
+            .and_then(|end| stat[end + 1..].split_whitespace().next())
+            .and_then(|state| state.chars().next())
+            .unwrap_or('?');
+        let pc = fs::read_to_string(format!("{}/syscall", dir))
+            .ok()
+            .and_then(|syscall| parse_syscall_pc(&syscall));
+        threads.push(ThreadInfo {
+            tid,
+            name: name.trim_end().to_string(),
+            state,
+            pc,
+        });
+    }
+    threads.sort_by_key(|thread| thread.tid);
+    Ok(threads)
+}
+
+/// /proc/<pid>/task/<tid>/syscall 的内容是 `调用号 六个参数 sp pc`（不在系统调用中时调用号为 -1，
+/// 只有 sp 和 pc），正在运行时是 `running`
+fn parse_syscall_pc(syscall: &str) -> Option<usize> {
+    let pc = syscall.split_whitespace().last()?;
+    usize::from_str_radix(pc.strip_prefix("0x")?, 16).ok()
+}
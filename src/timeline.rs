@@ -0,0 +1,206 @@
+//! 调试会话的时间线：记录被调试进程每次运行和停止的时间，用 `export timeline <file>` 导出。
+//!
+//! 时间线由交替的区间组成：从继续运行或单步到下一次停止是一个运行区间，从停止到再次运行是一个
+//! 停止区间；断点命中、信号和退出等停止原因同时记录为瞬时事件。导出格式为 Chrome trace
+//! （chrome://tracing、Perfetto）或 speedscope（文件名以 `.speedscope.json` 结尾时）。
+//!
+//! 另外按时间顺序保存一份日志：输入的命令、运行和停止、断点的设置与删除等，用 `journal [N]` 查看。
+
+use serde_json::json;
+use std::time::Instant;
+
+/// 时间线上的一个区间，时间为相对会话开始的微秒数
+struct Span {
+    name: String,
+    category: &'static str,
+    start: u64,
+    end: Option<u64>,
+}
+
+/// 停止原因等瞬时事件
+struct Event {
+    name: String,
+    at: u64,
+}
+
+/// 日志中的一条记录
+struct JournalEntry {
+    at: u64,
+    kind: &'static str,
+    text: String,
+}
+
+pub struct Timeline {
+    origin: Instant,
+    spans: Vec<Span>,
+    events: Vec<Event>,
+    journal: Vec<JournalEntry>,
+}
+
+impl Timeline {
+    pub fn new() -> Timeline {
+        Timeline {
+            origin: Instant::now(),
+            spans: Vec::new(),
+            events: Vec::new(),
+            journal: Vec::new(),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.origin.elapsed().as_micros() as u64
+    }
+
+    /// 结束当前打开的区间
+    fn close_span(&mut self, at: u64) {
+        if let Some(span) = self.spans.last_mut() {
+            if span.end.is_none() {
+                span.end = Some(at);
+            }
+        }
+    }
+
+    fn open_span(&mut self, name: String, category: &'static str, at: u64) {
+        self.close_span(at);
+        self.spans.push(Span {
+            name,
+            category,
+            start: at,
+            end: None,
+        });
+    }
+
+    fn log(&mut self, at: u64, kind: &'static str, text: String) {
+        self.journal.push(JournalEntry { at, kind, text });
+    }
+
+    /// 记录一个瞬时事件，例如启动了一次新的运行
+    pub fn event(&mut self, name: String) {
+        let at = self.now();
+        self.log(at, "event", name.clone());
+        self.events.push(Event { name, at });
+    }
+
+    /// 被调试进程开始运行（continue、step 等）
+    pub fn resumed(&mut self, name: &str) {
+        let at = self.now();
+        self.log(at, "resume", name.to_string());
+        self.open_span(name.to_string(), "running", at);
+    }
+
+    /// 被调试进程停止，kind 是日志中的类别（breakpoint、signal、exit 等）；exited 为 true 时
+    /// 进程已经结束，不再打开停止区间
+    pub fn stopped(&mut self, kind: &'static str, reason: String, exited: bool) {
+        let at = self.now();
+        self.log(at, kind, reason.clone());
+        self.events.push(Event {
+            name: reason.clone(),
+            at,
+        });
+        if exited {
+            self.close_span(at);
+        } else {
+            self.open_span(format!("stopped: {}", reason), "stopped", at);
+        }
+    }
+
+    /// 用户输入的一条命令，只进入日志
+    pub fn command(&mut self, line: &str) {
+        let at = self.now();
+        self.log(at, "command", line.to_string());
+    }
+
+    /// 断点的设置、启用、禁用和删除，只进入日志
+    pub fn breakpoint(&mut self, text: String) {
+        let at = self.now();
+        self.log(at, "breakpoint", text);
+    }
+
+    /// 打印日志中最近的 count 条记录，时间为相对会话开始的秒数
+    pub fn print_journal(&self, count: usize) {
+        if self.journal.is_empty() {
+            println!("The journal is empty");
+            return;
+        }
+        let skip = self.journal.len().saturating_sub(count);
+        for entry in &self.journal[skip..] {
+            println!(
+                "[{:>10.3}s] {:<10} {}",
+                entry.at as f64 / 1e6,
+                entry.kind,
+                entry.text
+            );
+        }
+    }
+
+    /// 按文件名选择格式导出，仍未结束的区间截止到当前时间
+    pub fn export(&self, path: &str) -> std::io::Result<()> {
+        let now = self.now();
+        let contents = if path.ends_with(".speedscope.json") {
+            self.to_speedscope(now)
+        } else {
+            self.to_chrome_trace(now)
+        };
+        std::fs::write(path, contents.to_string())
+    }
+
+    fn to_chrome_trace(&self, now: u64) -> serde_json::Value {
+        let mut trace_events = Vec::new();
+        for span in &self.spans {
+            let end = span.end.unwrap_or(now);
+            trace_events.push(json!({
+                "name": span.name,
+                "cat": span.category,
+                "ph": "X",
+                "ts": span.start,
+                "dur": end - span.start,
+                "pid": 1,
+                "tid": 1,
+            }));
+        }
+        for event in &self.events {
+            trace_events.push(json!({
+                "name": event.name,
+                "cat": "event",
+                "ph": "i",
+                "s": "g",
+                "ts": event.at,
+                "pid": 1,
+                "tid": 1,
+            }));
+        }
+        json!({ "traceEvents": trace_events, "displayTimeUnit": "ms" })
+    }
+
+    /// speedscope 的 evented 格式只有区间，瞬时事件体现在停止区间的名字里
+    fn to_speedscope(&self, now: u64) -> serde_json::Value {
+        let mut frames: Vec<&str> = Vec::new();
+        let mut events = Vec::new();
+        for span in &self.spans {
+            let frame = match frames.iter().position(|name| *name == span.name) {
+                Some(index) => index,
+                None => {
+                    frames.push(&span.name);
+                    frames.len() - 1
+                }
+            };
+            events.push(json!({ "type": "O", "frame": frame, "at": span.start }));
+            events.push(json!({ "type": "C", "frame": frame, "at": span.end.unwrap_or(now) }));
+        }
+        let frames: Vec<serde_json::Value> =
+            frames.iter().map(|name| json!({ "name": name })).collect();
+        json!({
+            "$schema": "https://www.speedscope.app/file-format-schema.json",
+            "name": "deet session",
+            "shared": { "frames": frames },
+            "profiles": [{
+                "type": "evented",
+                "name": "deet session",
+                "unit": "microseconds",
+                "startValue": 0,
+                "endValue": now,
+                "events": events,
+            }],
+        })
+    }
+}